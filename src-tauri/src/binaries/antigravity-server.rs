@@ -3,13 +3,30 @@
 /// This binary runs the Antigravity Manager as a headless server without Tauri/GUI dependencies.
 /// Designed for Docker/VPS deployments.
 ///
-/// Required Environment Variables:
+/// Required Environment Variables (one of):
 /// - ANTIGRAVITY_MASTER_KEY: Master encryption key (min 16 chars, recommend 32+)
+/// - ANTIGRAVITY_MASTER_KEY_FILE: File containing the master key (e.g. /run/secrets/antigravity_master_key)
+/// - ANTIGRAVITY_MASTER_PASSPHRASE: Passphrase the master key is derived from (PBKDF2, salt kept in the data dir)
 ///
 /// Optional Environment Variables:
 /// - ANTIGRAVITY_DATA_DIR: Custom data directory (default: ~/.antigravity_tools)
 /// - ANTIGRAVITY_WEB_ADMIN_PORT: Web Admin port (default: 8046)
 /// - ANTIGRAVITY_PROXY_PORT: Proxy service port (default: 8045)
+/// - ANTIGRAVITY_PUBLIC_URL: Public Web Admin URL, used as the OAuth callback for adding accounts
+/// - ANTIGRAVITY_PREVIOUS_MASTER_KEYS: Comma-separated retired master keys still accepted for decryption
+/// - ANTIGRAVITY_ACCOUNT_STORE: Account storage backend, `file` or `sqlite` (default: sqlite if accounts.db exists)
+///
+/// Maintenance commands (run instead of the server):
+/// - `antigravity-server rotate-key`: Re-encrypt all account tokens with the current master key.
+///   To rotate, set the new ANTIGRAVITY_MASTER_KEY, list the old one in
+///   ANTIGRAVITY_PREVIOUS_MASTER_KEYS, run this, then drop the old key.
+/// - `antigravity-server encryption-status`: Show how account tokens are encrypted
+/// - `antigravity-server encrypt-accounts`: Encrypt every plain-text account with the current master key
+/// - `antigravity-server decrypt-accounts`: Decrypt every account back to plain text
+///   (refused while token encryption is mandatory)
+/// - `antigravity-server migrate-account-store`: Move file-based accounts into accounts.db (SQLite)
+/// - `antigravity-server replay-trace <file>...`: Re-run captured protocol traces through the current
+///   mappers and print any differences (no master key required; exits non-zero on differences)
 
 use std::sync::Arc;
 use tracing::{info, error};
@@ -17,7 +34,10 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // Re-use the library components
 use antigravity_tools_lib::commands::proxy::ProxyServiceState;
+use antigravity_tools_lib::modules::account_store;
+use antigravity_tools_lib::modules::crypto::rotation;
 use antigravity_tools_lib::modules::web_admin;
+use antigravity_tools_lib::proxy::trace_capture;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -27,9 +47,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("🚀 Antigravity Manager - Server Mode");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
+    // Offline debugging tool, needs neither the master key nor the data directory
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay-trace") {
+        return replay_traces(&args[2..]).await;
+    }
+
     // Validate required environment variables
     validate_environment()?;
 
+    if let Some(command) = std::env::args().nth(1) {
+        return run_maintenance_command(&command);
+    }
+
     // Initialize proxy service state (TODO: use in Phase 3 auto-start)
     let _proxy_state = Arc::new(ProxyServiceState::new());
 
@@ -69,13 +99,111 @@ fn init_logging() {
         .init();
 }
 
+/// Run a one-shot maintenance command and exit
+fn run_maintenance_command(command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        "rotate-key" => {
+            let report = rotation::migrate_accounts(rotation::MigrationMode::Rotate)?;
+            info!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.failed.is_empty() {
+                return Err(format!(
+                    "{} account(s) failed to migrate; keep ANTIGRAVITY_PREVIOUS_MASTER_KEYS set and retry",
+                    report.failed.len()
+                )
+                .into());
+            }
+        }
+        "encrypt-accounts" | "decrypt-accounts" => {
+            let mode = if command == "encrypt-accounts" {
+                rotation::MigrationMode::Encrypt
+            } else {
+                rotation::MigrationMode::Decrypt
+            };
+            let report = rotation::migrate_accounts(mode)?;
+            info!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.failed.is_empty() {
+                return Err(format!(
+                    "{} account(s) failed to migrate; the pre-migration backup was kept",
+                    report.failed.len()
+                )
+                .into());
+            }
+        }
+        "encryption-status" => {
+            let status = rotation::encryption_status()?;
+            info!("{}", serde_json::to_string_pretty(&status)?);
+        }
+        "migrate-account-store" => {
+            let data_dir = antigravity_tools_lib::modules::account::get_data_dir()?;
+            let report = account_store::migrate_to_sqlite(&data_dir)?;
+            info!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.unreadable.is_empty() {
+                tracing::warn!(
+                    "{} unreadable account file(s) were skipped and left in the accounts directory",
+                    report.unreadable.len()
+                );
+            }
+        }
+        other => {
+            return Err(format!(
+                "Unknown command '{}'. Available commands: rotate-key, encrypt-accounts, decrypt-accounts, encryption-status, migrate-account-store",
+                other
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Replay captured traces and report mapper differences
+async fn replay_traces(paths: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if paths.is_empty() {
+        return Err("Usage: antigravity-server replay-trace <trace.json>...".into());
+    }
+
+    let mut changed = 0;
+    for path in paths {
+        let trace = trace_capture::TraceFile::load(std::path::Path::new(path))?;
+        let report = trace_capture::replay(&trace).await?;
+        if report.is_clean() {
+            info!("✅ {} ({}): no differences", path, trace.kind.as_str());
+            continue;
+        }
+        changed += 1;
+        info!("❌ {} ({}): mapper output changed", path, trace.kind.as_str());
+        if !report.request_diff.is_empty() {
+            println!("--- upstream request (recorded)\n+++ upstream request (replayed)");
+            report.request_diff.iter().for_each(|line| println!("{}", line));
+        }
+        if !report.response_diff.is_empty() {
+            println!("--- client output (recorded)\n+++ client output (replayed)");
+            report.response_diff.iter().for_each(|line| println!("{}", line));
+        }
+    }
+
+    if changed > 0 {
+        return Err(format!("{} of {} trace(s) differ", changed, paths.len()).into());
+    }
+    Ok(())
+}
+
 /// Validate required environment variables
 fn validate_environment() -> Result<(), Box<dyn std::error::Error>> {
+    // A key file or passphrase replaces ANTIGRAVITY_MASTER_KEY; their stores validate on first use
+    if std::env::var("ANTIGRAVITY_MASTER_KEY_FILE").is_ok()
+        || std::env::var("ANTIGRAVITY_MASTER_PASSPHRASE").is_ok()
+    {
+        antigravity_tools_lib::modules::crypto::get_master_key()?;
+        info!("✅ Environment validation passed");
+        return Ok(());
+    }
+
     // Check for master key (required in server mode)
     if std::env::var("ANTIGRAVITY_MASTER_KEY").is_err() {
         return Err(
             "ANTIGRAVITY_MASTER_KEY environment variable is required in server mode.\n\
-            Please set it to a strong, random passphrase (32+ characters recommended).\n\
+            Please set it to a strong, random passphrase (32+ characters recommended),\n\
+            or point ANTIGRAVITY_MASTER_KEY_FILE at a mounted secret.\n\
             Example: ANTIGRAVITY_MASTER_KEY=your-secure-random-passphrase-here"
                 .into(),
         );
@@ -205,116 +205,25 @@ pub async fn fetch_account_quota(
 }
 
 #[cfg(feature = "desktop")]
-#[derive(serde::Serialize)]
-pub struct RefreshStats {
-    total: usize,
-    success: usize,
-    failed: usize,
-    details: Vec<String>,
-}
+pub use crate::modules::account::RefreshStats;
 
 /// 刷新所有账号配额
 #[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn refresh_all_quotas(app: tauri::AppHandle) -> Result<RefreshStats, String> {
-    use futures::future::join_all;
-    use std::sync::Arc;
-    use tokio::sync::Semaphore;
-
-    const MAX_CONCURRENT: usize = 5;
-    let start = std::time::Instant::now();
-
-    modules::logger::log_info(&format!(
-        "开始批量刷新所有账号配额 (并发模式, 最大并发: {})",
-        MAX_CONCURRENT
-    ));
-    let accounts = modules::list_accounts()?;
-
-    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT));
-    let app_clone = app.clone();
+    let stats = modules::account::refresh_all_quotas().await?;
 
-    let tasks: Vec<_> = accounts
-        .into_iter()
-        .filter(|account| {
-            if account.disabled {
-                modules::logger::log_info(&format!("  - Skipping {} (Disabled)", account.email));
-                return false;
-            }
-            if let Some(ref q) = account.quota {
-                if q.is_forbidden {
-                    modules::logger::log_info(&format!("  - Skipping {} (Forbidden)", account.email));
-                    return false;
-                }
-            }
-            true
-        })
-        .map(|mut account| {
-            let email = account.email.clone();
-            let account_id = account.id.clone();
-            let permit = semaphore.clone();
-            let app_inner = app_clone.clone();
-            async move {
-                let _guard = permit.acquire().await.unwrap();
-                modules::logger::log_info(&format!("  - Processing {}", email));
-                match modules::account::fetch_quota_with_retry(&mut account).await {
-                    Ok(quota) => {
-                        if let Err(e) = modules::update_account_quota(&account_id, quota.clone()) {
-                            let msg = format!("Account {}: Save quota failed - {}", email, e);
-                            modules::logger::log_error(&msg);
-                            Err(msg)
-                        } else {
-                            // 【新增】同步到正在运行的 TokenManager
-                            if let Some(proxy_state) = app_inner.try_state::<crate::commands::proxy::ProxyServiceState>() {
-                                let instance_lock = proxy_state.instance.read().await;
-                                if let Some(instance) = instance_lock.as_ref() {
-                                    instance.token_manager.update_token_quota(&account_id, &quota);
-                                }
-                            }
-                            modules::logger::log_info(&format!("    ✅ {} Success", email));
-                            Ok(())
-                        }
-                    }
-                    Err(e) => {
-                        let msg = format!("Account {}: Fetch quota failed - {}", email, e);
-                        modules::logger::log_error(&msg);
-                        Err(msg)
-                    }
-                }
-            }
-        })
-        .collect();
-
-    let total = tasks.len();
-    let results = join_all(tasks).await;
-
-    let mut success = 0;
-    let mut failed = 0;
-    let mut details = Vec::new();
-
-    for result in results {
-        match result {
-            Ok(()) => success += 1,
-            Err(msg) => {
-                failed += 1;
-                details.push(msg);
+    // 【新增】同步到正在运行的 TokenManager
+    if let Some(proxy_state) = app.try_state::<crate::commands::proxy::ProxyServiceState>() {
+        let instance_lock = proxy_state.instance.read().await;
+        if let Some(instance) = instance_lock.as_ref() {
+            for (account_id, quota) in &stats.updated {
+                instance.token_manager.update_token_quota(account_id, quota);
             }
         }
     }
 
-    let elapsed = start.elapsed();
-    modules::logger::log_info(&format!(
-        "批量刷新完成: {} 成功, {} 失败, 耗时: {}ms",
-        success,
-        failed,
-        elapsed.as_millis()
-    ));
-
-    Ok(RefreshStats {
-        total,
-        success,
-        failed,
-        details,
-    })
+    Ok(stats)
 }
 
 /// 加载配置
@@ -338,23 +247,7 @@ pub async fn save_config(
     let _ = app.emit("config://updated", ());
 
     // 热更新正在运行的服务
-    let instance_lock = proxy_state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        // 更新完整配置 (包括 log_stream_content 等新增字段)
-        instance.axum_server.update_config(&config.proxy).await;
-        // 更新模型映射
-        instance.axum_server.update_mapping(&config.proxy).await;
-        // 更新上游代理
-        instance
-            .axum_server
-            .update_proxy(config.proxy.upstream_proxy.clone())
-            .await;
-        // 更新安全策略 (auth)
-        instance.axum_server.update_security(&config.proxy).await;
-        // 更新 z.ai 配置
-        instance.axum_server.update_zai(&config.proxy).await;
-        tracing::debug!("已同步热更新反代服务配置");
-    }
+    proxy_state.apply_config(&config.proxy).await;
 
     Ok(())
 }
@@ -597,6 +490,103 @@ pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Accoun
     Ok(Some(account))
 }
 
+// --- 账号包命令 ---
+
+/// 导出加密账号包到指定文件
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn export_account_bundle(
+    path: String,
+    passphrase: String,
+    include_config: bool,
+) -> Result<(), String> {
+    // PBKDF2 派生较耗时，放到阻塞线程执行
+    let content = tokio::task::spawn_blocking(move || {
+        modules::account_bundle::export_bundle(&passphrase, include_config)
+    })
+    .await
+    .map_err(|e| format!("导出任务失败: {}", e))??;
+
+    std::fs::write(&path, content).map_err(|e| format!("写入账号包失败: {}", e))?;
+    modules::logger::log_info(&format!("账号包已导出: {}", path));
+    Ok(())
+}
+
+/// 预览加密账号包中的账号 (用于选择性导入)
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn preview_account_bundle(
+    path: String,
+    passphrase: String,
+) -> Result<modules::account_bundle::BundlePreview, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取账号包失败: {}", e))?;
+    tokio::task::spawn_blocking(move || modules::account_bundle::preview_bundle(&content, &passphrase))
+        .await
+        .map_err(|e| format!("预览任务失败: {}", e))?
+}
+
+/// 导入加密账号包
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn import_account_bundle(
+    app: tauri::AppHandle,
+    path: String,
+    passphrase: String,
+    options: Option<modules::account_bundle::BundleImportOptions>,
+) -> Result<modules::account_bundle::BundleImportReport, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取账号包失败: {}", e))?;
+    let options = options.unwrap_or_default();
+    let report = tokio::task::spawn_blocking(move || {
+        modules::account_bundle::import_bundle(&content, &passphrase, &options)
+    })
+    .await
+    .map_err(|e| format!("导入任务失败: {}", e))??;
+
+    let proxy_state = app.state::<crate::commands::proxy::ProxyServiceState>();
+    if report.config_imported {
+        let config = modules::load_app_config()?;
+        proxy_state.apply_config(&config.proxy).await;
+        let _ = app.emit("config://updated", ());
+    }
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+    crate::modules::tray::update_tray_menus(&app);
+
+    Ok(report)
+}
+
+// --- 令牌加密命令 ---
+
+/// 查看账号令牌的加密状态
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_encryption_status() -> Result<modules::crypto::rotation::EncryptionStatus, String> {
+    tokio::task::spawn_blocking(modules::crypto::rotation::encryption_status)
+        .await
+        .map_err(|e| format!("查询任务失败: {}", e))?
+}
+
+/// 轮换主密钥, 或批量加密/解密所有账号令牌
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn migrate_account_encryption(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    mode: modules::crypto::rotation::MigrationMode,
+) -> Result<modules::crypto::rotation::MigrationReport, String> {
+    let report = tokio::task::spawn_blocking(move || modules::crypto::rotation::migrate_accounts(mode))
+        .await
+        .map_err(|e| format!("迁移任务失败: {}", e))??;
+
+    modules::logger::log_info(&format!(
+        "令牌加密迁移完成 ({:?}): 失败 {} 个",
+        mode,
+        report.failed.len()
+    ));
+    // 运行中的账号池持有迁移前读取的令牌
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    Ok(report)
+}
+
 /// 保存文本文件 (绕过前端 Scope 限制)
 #[cfg(feature = "desktop")]
 #[tauri::command]
@@ -793,50 +783,38 @@ pub async fn toggle_proxy_status(
         if enable { "启用" } else { "禁用" }
     ));
 
-    // 1. 读取账号文件
-    let data_dir = modules::account::get_data_dir()?;
-    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
-
-    if !account_path.exists() {
-        return Err(format!("账号文件不存在: {}", account_id));
-    }
-
-    let content = std::fs::read_to_string(&account_path)
-        .map_err(|e| format!("读取账号文件失败: {}", e))?;
-
-    let mut account_json: serde_json::Value = serde_json::from_str(&content)
-        .map_err(|e| format!("解析账号文件失败: {}", e))?;
-
-    // 2. 更新 proxy_disabled 字段
-    if enable {
-        // 启用反代
-        account_json["proxy_disabled"] = serde_json::Value::Bool(false);
-        account_json["proxy_disabled_reason"] = serde_json::Value::Null;
-        account_json["proxy_disabled_at"] = serde_json::Value::Null;
-    } else {
-        // 禁用反代
-        let now = chrono::Utc::now().timestamp();
-        account_json["proxy_disabled"] = serde_json::Value::Bool(true);
-        account_json["proxy_disabled_at"] = serde_json::Value::Number(now.into());
-        account_json["proxy_disabled_reason"] = serde_json::Value::String(
-            reason.unwrap_or_else(|| "用户手动禁用".to_string())
-        );
-    }
-
-    // 3. 保存到磁盘
-    std::fs::write(&account_path, serde_json::to_string_pretty(&account_json).unwrap())
-        .map_err(|e| format!("写入账号文件失败: {}", e))?;
+    // 1. 更新 proxy_disabled 字段
+    let account = modules::account::update_account(&account_id, |account| {
+        if enable {
+            // 启用反代
+            account.proxy_disabled = false;
+            account.proxy_disabled_reason = None;
+            account.proxy_disabled_at = None;
+        } else {
+            // 禁用反代
+            account.proxy_disabled = true;
+            account.proxy_disabled_at = Some(chrono::Utc::now().timestamp());
+            account.proxy_disabled_reason = Some(reason.unwrap_or_else(|| "用户手动禁用".to_string()));
+        }
+        Ok(())
+    })?;
 
     modules::logger::log_info(&format!(
         "账号反代状态已更新: {} ({})",
         account_id,
         if enable { "已启用" } else { "已禁用" }
     ));
-
-    // 4. 如果反代服务正在运行,重新加载账号池
+    crate::proxy::events::publish(crate::proxy::events::ProxyEvent::AccountStateChanged {
+        account_id: account_id.clone(),
+        field: "proxy_disabled",
+        enabled: enable,
+        reason: account.proxy_disabled_reason,
+    });
+
+    // 2. 如果反代服务正在运行,重新加载账号池
     let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
 
-    // 5. 更新托盘菜单
+    // 3. 更新托盘菜单
     crate::modules::tray::update_tray_menus(&app);
 
     Ok(())
@@ -29,6 +29,10 @@ pub struct ProxyServiceInstance {
     pub token_manager: Arc<TokenManager>,
     pub axum_server: crate::proxy::AxumServer,
     pub server_handle: tokio::task::JoinHandle<()>,
+    /// 账号热重载任务 (随实例 drop 停止)
+    pub account_watcher: Option<crate::proxy::account_watcher::AccountWatcher>,
+    /// 隔离账号探测任务 (随实例 drop 停止)
+    pub health_prober: crate::proxy::account_health::HealthProber,
 }
 
 impl ProxyServiceState {
@@ -38,6 +42,35 @@ impl ProxyServiceState {
             monitor: Arc::new(RwLock::new(None)),
         }
     }
+
+    /// 将配置热更新到正在运行的服务 (未运行时为空操作)
+    pub async fn apply_config(&self, config: &ProxyConfig) {
+        let instance_lock = self.instance.read().await;
+        if let Some(instance) = instance_lock.as_ref() {
+            // 更新完整配置 (包括 log_stream_content 等新增字段)
+            instance.axum_server.update_config(config).await;
+            // 更新模型映射
+            instance.axum_server.update_mapping(config).await;
+            // 更新上游代理
+            instance
+                .axum_server
+                .update_proxy(config.upstream_proxy.clone())
+                .await;
+            // 更新安全策略 (auth)
+            instance.axum_server.update_security(config).await;
+            // 更新 z.ai 配置
+            instance.axum_server.update_zai(config).await;
+            // 更新熔断配置 (端点熔断在 update_config 中同步)
+            instance
+                .token_manager
+                .update_circuit_breaker_config(config.circuit_breaker.clone());
+            // 更新账号健康度配置
+            instance
+                .token_manager
+                .update_account_health_config(config.account_health.clone());
+            tracing::debug!("已同步热更新反代服务配置");
+        }
+    }
 }
 
 /// 启动反代服务
@@ -78,6 +111,10 @@ pub async fn start_proxy_service(
     let token_manager = Arc::new(TokenManager::new(accounts_dir));
     // 同步 UI 传递的调度配置
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    monitor.attach_queue_stats(token_manager.queue_stats()).await;
+    token_manager.update_circuit_breaker_config(config.circuit_breaker.clone());
+    token_manager.circuit_breakers().set_listener(monitor.circuit_listener());
+    token_manager.update_account_health_config(config.account_health.clone());
 
     // 3. 加载账号
     let active_accounts = token_manager.load_accounts().await
@@ -99,28 +136,28 @@ pub async fn start_proxy_service(
     // 启动 Axum 服务器
     let (axum_server, server_handle) =
         match crate::proxy::AxumServer::start(
-            config.get_bind_address().to_string(),
-            config.port,
             token_manager.clone(),
-            config.custom_mapping.clone(),
-            config.request_timeout,
-            config.upstream_proxy.clone(),
-            crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
-            config.zai.clone(),
             monitor.clone(),
-            config.experimental.clone(),
             app_config.clone(),
         ).await {
             Ok((server, handle)) => (server, handle),
             Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
         };
 
+    let health_prober =
+        crate::proxy::account_health::HealthProber::spawn(token_manager.clone(), axum_server.upstream());
+
     // 创建服务实例
     let instance = ProxyServiceInstance {
         config: config.clone(),
         token_manager: token_manager.clone(), // Clone for ProxyServiceInstance
         axum_server,
         server_handle,
+        account_watcher: crate::proxy::account_watcher::AccountWatcher::spawn(
+            token_manager.clone(),
+            &config.account_watch,
+        ),
+        health_prober,
     };
 
     *instance_lock = Some(instance);
@@ -430,6 +467,65 @@ pub async fn update_proxy_scheduling_config(
     }
 }
 
+/// 调度模拟结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulingSimulation {
+    pub model: String,
+    pub mode: crate::proxy::sticky_config::SchedulingMode,
+    /// 模拟使用的会话 ID (会话粘性)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// 实际会被选中的账号 email
+    pub selected: Option<String>,
+    /// 没有账号可选时的原因 (与真实请求返回的错误一致)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 按预测优先级排序的候选账号
+    pub candidates: Vec<crate::proxy::quota_predictor::QuotaForecast>,
+}
+
+/// 模拟账号调度 (Dry-run)，使用真实的选择逻辑但不会修改任何调度状态
+pub async fn simulate_scheduling(
+    token_manager: &TokenManager,
+    model: &str,
+    session_id: Option<&str>,
+) -> Result<SchedulingSimulation, String> {
+    let (quota_threshold, quota_priority) = crate::modules::config::load_app_config()
+        .map(|c| (c.model_quota_threshold, c.proxy.quota_priority_enabled))
+        .unwrap_or((0.01, false));
+    let (candidates, selected) = token_manager
+        .simulate_selection(model, quota_threshold, session_id, quota_priority)
+        .await;
+    let (selected, error) = match selected {
+        Ok(email) => (Some(email), None),
+        Err(e) => (None, Some(e)),
+    };
+    Ok(SchedulingSimulation {
+        model: model.to_string(),
+        mode: token_manager.get_sticky_config().await.mode,
+        session_id: session_id.map(str::to_string),
+        selected,
+        error,
+        candidates,
+    })
+}
+
+/// 模拟指定模型 (及会话) 的账号选择
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn simulate_proxy_scheduling(
+    state: State<'_, ProxyServiceState>,
+    model: String,
+    session_id: Option<String>,
+) -> Result<SchedulingSimulation, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        simulate_scheduling(&instance.token_manager, &model, session_id.as_deref()).await
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
 /// 清除所有会话粘性绑定
 #[cfg(feature = "desktop")]
 #[tauri::command]
@@ -445,3 +541,70 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+
+/// 熔断器状态
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerStatus {
+    /// 上游端点熔断器
+    pub endpoints: Vec<crate::proxy::circuit_breaker::CircuitSnapshot>,
+    /// 账号熔断器
+    pub accounts: Vec<crate::proxy::circuit_breaker::CircuitSnapshot>,
+    /// 最近的状态变更事件 (最新在前)
+    pub recent_transitions: Vec<crate::proxy::circuit_breaker::CircuitTransition>,
+}
+
+/// 汇总端点与账号的熔断状态
+pub fn circuit_breaker_status(
+    instance: &ProxyServiceInstance,
+    monitor: Option<&ProxyMonitor>,
+) -> CircuitBreakerStatus {
+    CircuitBreakerStatus {
+        endpoints: instance.axum_server.endpoint_circuits(),
+        accounts: instance.token_manager.circuit_breakers().snapshot(),
+        recent_transitions: monitor.map(|m| m.get_circuit_events(50)).unwrap_or_default(),
+    }
+}
+
+/// 获取熔断器状态
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_proxy_circuit_breakers(
+    state: State<'_, ProxyServiceState>,
+) -> Result<CircuitBreakerStatus, String> {
+    let monitor = state.monitor.read().await.clone();
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(circuit_breaker_status(instance, monitor.as_deref()))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+/// 获取账号健康度 (健康分从低到高排列)
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_proxy_account_health(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::account_health::AccountHealthSnapshot>, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.account_health().snapshot())
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+/// 手动解除账号隔离 (返回账号此前是否处于隔离状态)
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn restore_proxy_account_health(
+    state: State<'_, ProxyServiceState>,
+    email: String,
+) -> Result<bool, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.account_health().restore(&email))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
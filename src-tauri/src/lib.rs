@@ -114,6 +114,11 @@ pub fn run() {
             commands::import_from_db,
             commands::import_custom_db,
             commands::sync_account_from_db,
+            commands::export_account_bundle,
+            commands::preview_account_bundle,
+            commands::import_account_bundle,
+            commands::get_encryption_status,
+            commands::migrate_account_encryption,
             commands::save_text_file,
             commands::clear_log_cache,
             commands::open_data_folder,
@@ -138,6 +143,10 @@ pub fn run() {
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::simulate_proxy_scheduling,
+            commands::proxy::get_proxy_circuit_breakers,
+            commands::proxy::get_proxy_account_health,
+            commands::proxy::restore_proxy_account_health,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
@@ -21,6 +21,8 @@ pub struct AppConfig {
     pub model_quota_threshold: f64, // 模型配额跳过阈值 (0.01 = 1%)
     #[serde(default)]
     pub web_admin_lan_access: bool, // 是否允许局域网访问 Web Admin
+    #[serde(default)]
+    pub web_admin_public_url: Option<String>, // Web Admin 的公网访问地址 (远程 OAuth 回调)
 }
 
 fn default_quota_threshold() -> f64 {
@@ -43,6 +45,7 @@ impl AppConfig {
             auto_launch: false,
             model_quota_threshold: default_quota_threshold(),
             web_admin_lan_access: false, // 默认禁止 LAN 访问
+            web_admin_public_url: None,
         }
     }
 }
@@ -48,9 +48,7 @@ impl TokenData {
     /// If encrypted flag is true, decrypts the token; otherwise returns as-is
     pub fn get_access_token(&self) -> Result<String, String> {
         if self.encrypted {
-            use crate::modules::crypto::{get_master_key, decrypt_string};
-            let key = get_master_key()?;
-            decrypt_string(&self.access_token, &key)
+            crate::modules::crypto::decrypt_token(&self.access_token)
         } else {
             Ok(self.access_token.clone())
         }
@@ -60,9 +58,7 @@ impl TokenData {
     /// If encrypted flag is true, decrypts the token; otherwise returns as-is
     pub fn get_refresh_token(&self) -> Result<String, String> {
         if self.encrypted {
-            use crate::modules::crypto::{get_master_key, decrypt_string};
-            let key = get_master_key()?;
-            decrypt_string(&self.refresh_token, &key)
+            crate::modules::crypto::decrypt_token(&self.refresh_token)
         } else {
             Ok(self.refresh_token.clone())
         }
@@ -72,10 +68,9 @@ impl TokenData {
     /// Encrypts both access_token and refresh_token if encryption is enabled
     pub fn update_tokens(&mut self, access_token: String, refresh_token: String) -> Result<(), String> {
         if self.encrypted {
-            use crate::modules::crypto::{get_master_key, encrypt_string};
-            let key = get_master_key()?;
-            self.access_token = encrypt_string(&access_token, &key)?;
-            self.refresh_token = encrypt_string(&refresh_token, &key)?;
+            let keys = crate::modules::crypto::load_keyset()?;
+            self.access_token = keys.encrypt(&access_token)?;
+            self.refresh_token = keys.encrypt(&refresh_token)?;
         } else {
             self.access_token = access_token;
             self.refresh_token = refresh_token;
@@ -91,11 +86,10 @@ impl TokenData {
             return Ok(()); // Already encrypted
         }
 
-        use crate::modules::crypto::{get_master_key, encrypt_string};
-        let key = get_master_key()?;
+        let keys = crate::modules::crypto::load_keyset()?;
 
-        self.access_token = encrypt_string(&self.access_token, &key)?;
-        self.refresh_token = encrypt_string(&self.refresh_token, &key)?;
+        self.access_token = keys.encrypt(&self.access_token)?;
+        self.refresh_token = keys.encrypt(&self.refresh_token)?;
         self.encrypted = true;
 
         Ok(())
@@ -108,11 +102,10 @@ impl TokenData {
             return Ok(()); // Already plain text
         }
 
-        use crate::modules::crypto::{get_master_key, decrypt_string};
-        let key = get_master_key()?;
+        let keys = crate::modules::crypto::load_keyset()?;
 
-        self.access_token = decrypt_string(&self.access_token, &key)?;
-        self.refresh_token = decrypt_string(&self.refresh_token, &key)?;
+        self.access_token = keys.decrypt(&self.access_token)?;
+        self.refresh_token = keys.decrypt(&self.refresh_token)?;
         self.encrypted = false;
 
         Ok(())
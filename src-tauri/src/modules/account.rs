@@ -1,22 +1,17 @@
 use std::fs;
 use std::path::PathBuf;
-use serde_json;
 use uuid::Uuid;
 
 use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData};
 use crate::modules;
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
-
-/// 全局账号写入锁，防止并发操作导致索引文件损坏
-static ACCOUNT_INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+use crate::modules::account_store::{self, AccountTxn};
+use crate::proxy::events::{self, ProxyEvent};
 
 // ... existing constants ...
 const DATA_DIR: &str = ".antigravity_tools";
-const ACCOUNTS_INDEX: &str = "accounts.json";
 const ACCOUNTS_DIR: &str = "accounts";
 
-// ... existing functions get_data_dir, get_accounts_dir, load_account_index, save_account_index ...
+// ... existing functions get_data_dir, get_accounts_dir ...
 /// 获取数据目录路径
 ///
 /// 优先级：
@@ -54,66 +49,28 @@ pub fn get_accounts_dir() -> Result<PathBuf, String> {
 
 /// 加载账号索引
 pub fn load_account_index() -> Result<AccountIndex, String> {
-    let data_dir = get_data_dir()?;
-    let index_path = data_dir.join(ACCOUNTS_INDEX);
-    // modules::logger::log_info(&format!("正在加载账号索引: {:?}", index_path)); // Optional: reduce noise
-    
-    if !index_path.exists() {
-        crate::modules::logger::log_warn("账号索引文件不存在");
-        return Ok(AccountIndex::new());
-    }
-    
-    let content = fs::read_to_string(&index_path)
-        .map_err(|e| format!("读取账号索引失败: {}", e))?;
-    
-    let index: AccountIndex = serde_json::from_str(&content)
-        .map_err(|e| format!("解析账号索引失败: {}", e))?;
-        
-    crate::modules::logger::log_info(&format!("成功加载索引，包含 {} 个账号", index.accounts.len()));
-    Ok(index)
+    account_store::current()?.load_index()
 }
 
-/// 保存账号索引 (原子化写入)
+/// 保存账号索引
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
-    let index_path = data_dir.join(ACCOUNTS_INDEX);
-    let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
-    
-    let content = serde_json::to_string_pretty(index)
-        .map_err(|e| format!("序列化账号索引失败: {}", e))?;
-    
-    // 写入临时文件
-    fs::write(&temp_path, content)
-        .map_err(|e| format!("写入临时索引文件失败: {}", e))?;
-        
-    // 原子重命名
-    fs::rename(temp_path, index_path)
-        .map_err(|e| format!("替换索引文件失败: {}", e))
+    account_store::current()?.transact(|txn| {
+        *txn.index_mut() = index.clone();
+        Ok(())
+    })
 }
 
 /// 加载账号数据
 pub fn load_account(account_id: &str) -> Result<Account, String> {
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
-    
-    if !account_path.exists() {
-        return Err(format!("账号不存在: {}", account_id));
-    }
-    
-    let content = fs::read_to_string(&account_path)
-        .map_err(|e| format!("读取账号数据失败: {}", e))?;
-    
-    serde_json::from_str(&content)
-        .map_err(|e| format!("解析账号数据失败: {}", e))
+    account_store::current()?
+        .load(account_id)?
+        .ok_or_else(|| format!("账号不存在: {}", account_id))
 }
 
-/// 保存账号数据
+/// 写入前处理 Token 加密
 ///
 /// 在服务器模式下，自动加密 tokens 后再保存
-pub fn save_account(account: &Account) -> Result<(), String> {
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account.id));
-
+fn prepare_for_storage(account: &Account) -> Result<Account, String> {
     // Clone account to avoid modifying the original
     let mut account_to_save = account.clone();
 
@@ -122,111 +79,133 @@ pub fn save_account(account: &Account) -> Result<(), String> {
         account_to_save.token.encrypt_tokens()
             .map_err(|e| format!("加密 token 失败: {}", e))?;
     }
+    Ok(account_to_save)
+}
 
-    let content = serde_json::to_string_pretty(&account_to_save)
-        .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+/// 保存账号数据
+pub fn save_account(account: &Account) -> Result<(), String> {
+    account_store::current()?.save(&prepare_for_storage(account)?)
+}
 
-    fs::write(&account_path, content)
-        .map_err(|e| format!("保存账号数据失败: {}", e))
+/// 在事务中读取-修改-写回单个账号
+pub fn update_account(account_id: &str, f: impl FnOnce(&mut Account) -> Result<(), String>) -> Result<Account, String> {
+    account_store::current()?.transact(|txn| {
+        let mut account = txn
+            .load(account_id)?
+            .ok_or_else(|| format!("账号不存在: {}", account_id))?;
+        f(&mut account)?;
+        txn.save(&prepare_for_storage(&account)?)?;
+        Ok(account)
+    })
+}
+
+/// 修改账号名称 (同步更新索引)
+pub fn rename_account(account_id: &str, name: Option<String>) -> Result<Account, String> {
+    account_store::current()?.transact(|txn| {
+        let mut account = txn
+            .load(account_id)?
+            .ok_or_else(|| format!("账号不存在: {}", account_id))?;
+        account.name = name.clone();
+        txn.save(&prepare_for_storage(&account)?)?;
+
+        if let Some(summary) = txn.index_mut().accounts.iter_mut().find(|s| s.id == account_id) {
+            summary.name = name;
+        }
+        Ok(account)
+    })
 }
 
-/// 列出所有账号
 /// 列出所有账号
 pub fn list_accounts() -> Result<Vec<Account>, String> {
     crate::modules::logger::log_info("已开始列出账号...");
-    let mut index = load_account_index()?;
-    let mut accounts = Vec::new();
-    let mut invalid_ids = Vec::new();
-    
-    for summary in &index.accounts {
-        match load_account(&summary.id) {
-            Ok(account) => accounts.push(account),
-            Err(e) => {
-                crate::modules::logger::log_error(&format!("加载账号 {} 失败: {}", summary.id, e));
-                // 如果是文件不存在导致的错误，标记为无效 ID
-                // load_account 返回 "账号不存在: id" 或者底层 io error
-                if e.contains("账号不存在") || e.contains("Os { code: 2,") || e.contains("No such file") {
-                    invalid_ids.push(summary.id.clone());
-                }
-            },
-        }
-    }
-    
-    // 自动修复索引：移除无效的账号 ID
-    if !invalid_ids.is_empty() {
+    let store = account_store::current()?;
+    let listing = store.list()?;
+
+    // 自动修复索引：移除没有账号数据的 ID
+    if !listing.missing.is_empty() {
+        let invalid_ids = listing.missing;
         crate::modules::logger::log_warn(&format!("发现 {} 个无效的账号索引，正在自动清理...", invalid_ids.len()));
-        
-        index.accounts.retain(|s| !invalid_ids.contains(&s.id));
-        
-        // 如果当前选中的账号也是无效的，重置为第一个可用账号
-        if let Some(current_id) = &index.current_account_id {
-            if invalid_ids.contains(current_id) {
+
+        let result = store.transact(|txn| {
+            let index = txn.index_mut();
+            index.accounts.retain(|s| !invalid_ids.contains(&s.id));
+
+            // 如果当前选中的账号也是无效的，重置为第一个可用账号
+            if index.current_account_id.as_ref().is_some_and(|id| invalid_ids.contains(id)) {
                 index.current_account_id = index.accounts.first().map(|s| s.id.clone());
             }
-        }
-        
-        if let Err(e) = save_account_index(&index) {
+            Ok(())
+        });
+
+        if let Err(e) = result {
             crate::modules::logger::log_error(&format!("自动清理索引失败: {}", e));
         } else {
             crate::modules::logger::log_info("索引自动清理完成");
         }
     }
-    
-    // modules::logger::log_info(&format!("共找到 {} 个有效账号", accounts.len()));
-    Ok(accounts)
+
+    Ok(listing.accounts)
 }
 
-/// 添加账号
-pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    
+fn summary_of(account: &Account) -> AccountSummary {
+    AccountSummary {
+        id: account.id.clone(),
+        email: account.email.clone(),
+        name: account.name.clone(),
+        created_at: account.created_at,
+        last_used: account.last_used,
+    }
+}
+
+/// 在事务中新增账号
+fn insert_account(txn: &mut dyn AccountTxn, email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
     // 检查是否已存在
-    if index.accounts.iter().any(|s| s.email == email) {
+    if txn.index().accounts.iter().any(|s| s.email == email) {
         return Err(format!("账号已存在: {}", email));
     }
-    
+
     // 创建新账号
     let account_id = Uuid::new_v4().to_string();
-    let mut account = Account::new(account_id.clone(), email.clone(), token);
-    account.name = name.clone();
-    
+    let mut account = Account::new(account_id.clone(), email, token);
+    account.name = name;
+
     // 保存账号数据
-    save_account(&account)?;
-    
+    txn.save(&prepare_for_storage(&account)?)?;
+
     // 更新索引
-    index.accounts.push(AccountSummary {
-        id: account_id.clone(),
-        email: email.clone(),
-        name: name.clone(),
-        created_at: account.created_at,
-        last_used: account.last_used,
-    });
-    
+    let index = txn.index_mut();
+    index.accounts.push(summary_of(&account));
+
     // 如果是第一个账号，设为当前账号
     if index.current_account_id.is_none() {
         index.current_account_id = Some(account_id);
     }
-    
-    save_account_index(&index)?;
-    
+
     Ok(account)
 }
 
+/// 添加账号
+pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
+    account_store::current()?.transact(|txn| insert_account(txn, email, name, token))
+}
+
 /// 添加或更新账号
 pub fn upsert_account(email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    
-    // 先找到账号 ID（如果存在）
-    let existing_account_id = index.accounts.iter()
-        .find(|s| s.email == email)
-        .map(|s| s.id.clone());
-    
-    if let Some(account_id) = existing_account_id {
+    let (account, reenabled) = account_store::current()?.transact(|txn| {
+        // 先找到账号 ID（如果存在）
+        let existing_account_id = txn.index().accounts.iter()
+            .find(|s| s.email == email)
+            .map(|s| s.id.clone());
+
+        let Some(account_id) = existing_account_id else {
+            // 不存在则添加
+            return insert_account(txn, email, name, token).map(|account| (account, false));
+        };
+
         // 更新现有账号
-        match load_account(&account_id) {
-            Ok(mut account) => {
+        let mut reenabled = false;
+        let account = match txn.load(&account_id)? {
+            Some(mut account) => {
                 let old_access_token = account.token.access_token.clone();
                 let old_refresh_token = account.token.refresh_token.clone();
                 account.token = token;
@@ -240,150 +219,156 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
                     account.disabled = false;
                     account.disabled_reason = None;
                     account.disabled_at = None;
+                    reenabled = true;
                 }
                 account.update_last_used();
-                save_account(&account)?;
-                
-                // 同步更新索引中的 name
-                if let Some(idx_summary) = index.accounts.iter_mut().find(|s| s.id == account_id) {
-                    idx_summary.name = name;
-                    save_account_index(&index)?;
-                }
-                
-                return Ok(account);
+                account
             },
-            Err(e) => {
-                crate::modules::logger::log_warn(&format!("Account {} file missing ({}), recreating...", account_id, e));
-                // 索引存在但文件丢失，重新创建
-                let mut account = Account::new(account_id.clone(), email.clone(), token);
+            None => {
+                crate::modules::logger::log_warn(&format!("Account {} data missing, recreating...", account_id));
+                // 索引存在但数据丢失，重新创建
+                let mut account = Account::new(account_id.clone(), email, token);
                 account.name = name.clone();
-                save_account(&account)?;
-                
-                // 同步更新索引中的 name
-                if let Some(idx_summary) = index.accounts.iter_mut().find(|s| s.id == account_id) {
-                    idx_summary.name = name;
-                    save_account_index(&index)?;
-                }
-                
-                return Ok(account);
+                account
             }
+        };
+        txn.save(&prepare_for_storage(&account)?)?;
+
+        // 同步更新索引中的 name
+        if let Some(idx_summary) = txn.index_mut().accounts.iter_mut().find(|s| s.id == account_id) {
+            idx_summary.name = name;
         }
+
+        Ok((account, reenabled))
+    })?;
+
+    // 事务提交成功后再推送状态变更，避免监听方看到被回滚的状态
+    if reenabled {
+        publish_disabled_state(&account);
     }
-    
-    // 不存在则添加
-    // 注意：这里手动调用 add_account，它也会尝试获取锁，但因为 Mutex 库限制会死锁
-    // 所以我们需要一个不带锁的内部版本，或者重构。简单起见，这里直接展开添加逻辑或不重复加锁
-    
-    // 释放锁，让 add_account 处理
-    drop(_lock);
-    add_account(email, name, token)
+    Ok(account)
+}
+
+/// 写入完整的账号数据 (账号包导入)
+///
+/// 按邮箱匹配：已存在时沿用本地 ID 与创建时间并覆盖其余字段，否则追加到索引末尾。
+/// 返回保存后的账号及是否为更新。
+pub fn store_imported_account(mut account: Account) -> Result<(Account, bool), String> {
+    account_store::current()?.transact(|txn| {
+        let index = txn.index_mut();
+        let existing = index.accounts.iter_mut().find(|s| s.email == account.email);
+        let updated = if let Some(summary) = existing {
+            account.id = summary.id.clone();
+            account.created_at = summary.created_at;
+            summary.name = account.name.clone();
+            summary.last_used = account.last_used;
+            true
+        } else {
+            if account.id.is_empty() || index.accounts.iter().any(|s| s.id == account.id) {
+                account.id = Uuid::new_v4().to_string();
+            }
+            index.accounts.push(summary_of(&account));
+            if index.current_account_id.is_none() {
+                index.current_account_id = Some(account.id.clone());
+            }
+            false
+        };
+
+        txn.save(&prepare_for_storage(&account)?)?;
+        Ok((account, updated))
+    })
 }
 
 /// 删除账号
 pub fn delete_account(account_id: &str) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    
-    // 从索引中移除
-    let original_len = index.accounts.len();
-    index.accounts.retain(|s| s.id != account_id);
-    
-    if index.accounts.len() == original_len {
-        return Err(format!("找不到账号 ID: {}", account_id));
-    }
-    
-    // 如果是当前账号，清除当前账号
-    if index.current_account_id.as_deref() == Some(account_id) {
-        index.current_account_id = index.accounts.first().map(|s| s.id.clone());
-    }
-    
-    save_account_index(&index)?;
-    
-    // 删除账号文件
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
-    
-    if account_path.exists() {
-        fs::remove_file(&account_path)
-            .map_err(|e| format!("删除账号文件失败: {}", e))?;
-    }
-    
-    Ok(())
-}
+    account_store::current()?.transact(|txn| {
+        let index = txn.index_mut();
 
-/// 批量删除账号 (原子性操作索引)
-pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    
-    let accounts_dir = get_accounts_dir()?;
-    
-    for account_id in account_ids {
         // 从索引中移除
-        index.accounts.retain(|s| &s.id != account_id);
-        
+        let original_len = index.accounts.len();
+        index.accounts.retain(|s| s.id != account_id);
+
+        if index.accounts.len() == original_len {
+            return Err(format!("找不到账号 ID: {}", account_id));
+        }
+
         // 如果是当前账号，清除当前账号
         if index.current_account_id.as_deref() == Some(account_id) {
-            index.current_account_id = None;
+            index.current_account_id = index.accounts.first().map(|s| s.id.clone());
         }
-        
-        // 删除账号文件
-        let account_path = accounts_dir.join(format!("{}.json", account_id));
-        if account_path.exists() {
-            let _ = fs::remove_file(&account_path);
+
+        // 删除账号数据
+        txn.remove(account_id)
+    })
+}
+
+/// 批量删除账号 (原子性操作索引)
+pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
+    account_store::current()?.transact(|txn| {
+        for account_id in account_ids {
+            let index = txn.index_mut();
+            // 从索引中移除
+            index.accounts.retain(|s| &s.id != account_id);
+
+            // 如果是当前账号，清除当前账号
+            if index.current_account_id.as_deref() == Some(account_id) {
+                index.current_account_id = None;
+            }
+
+            // 删除账号数据
+            if let Err(e) = txn.remove(account_id) {
+                crate::modules::logger::log_warn(&format!("删除账号 {} 数据失败: {}", account_id, e));
+            }
         }
-    }
-    
-    // 如果当前账号为空，尝试选取第一个作为默认
-    if index.current_account_id.is_none() {
-        index.current_account_id = index.accounts.first().map(|s| s.id.clone());
-    }
-    
-    save_account_index(&index)
+
+        // 如果当前账号为空，尝试选取第一个作为默认
+        let index = txn.index_mut();
+        if index.current_account_id.is_none() {
+            index.current_account_id = index.accounts.first().map(|s| s.id.clone());
+        }
+        Ok(())
+    })
 }
 
 /// 重新排序账号列表
-/// 根据传入的账号ID顺序更新索引文件中的账号排列顺序
+/// 根据传入的账号ID顺序更新索引中的账号排列顺序
 pub fn reorder_accounts(account_ids: &[String]) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    
-    // 创建一个映射，记录每个账号ID对应的摘要信息
-    let id_to_summary: std::collections::HashMap<_, _> = index.accounts
-        .iter()
-        .map(|s| (s.id.clone(), s.clone()))
-        .collect();
-    
-    // 按照新顺序重建账号列表
-    let mut new_accounts = Vec::new();
-    for id in account_ids {
-        if let Some(summary) = id_to_summary.get(id) {
-            new_accounts.push(summary.clone());
+    account_store::current()?.transact(|txn| {
+        let index = txn.index_mut();
+
+        // 创建一个映射，记录每个账号ID对应的摘要信息
+        let id_to_summary: std::collections::HashMap<_, _> = index.accounts
+            .iter()
+            .map(|s| (s.id.clone(), s.clone()))
+            .collect();
+
+        // 按照新顺序重建账号列表
+        let mut new_accounts = Vec::new();
+        for id in account_ids {
+            if let Some(summary) = id_to_summary.get(id) {
+                new_accounts.push(summary.clone());
+            }
         }
-    }
-    
-    // 添加未在新顺序中出现的账号（保持原有顺序追加到末尾）
-    for summary in &index.accounts {
-        if !account_ids.contains(&summary.id) {
-            new_accounts.push(summary.clone());
+
+        // 添加未在新顺序中出现的账号（保持原有顺序追加到末尾）
+        for summary in &index.accounts {
+            if !account_ids.contains(&summary.id) {
+                new_accounts.push(summary.clone());
+            }
         }
-    }
-    
-    index.accounts = new_accounts;
-    
-    crate::modules::logger::log_info(&format!("账号顺序已更新，共 {} 个账号", index.accounts.len()));
-    
-    save_account_index(&index)
+
+        index.accounts = new_accounts;
+
+        crate::modules::logger::log_info(&format!("账号顺序已更新，共 {} 个账号", index.accounts.len()));
+        Ok(())
+    })
 }
 
 /// 切换当前账号
 pub async fn switch_account(account_id: &str) -> Result<(), String> {
     use crate::modules::{oauth, process, db};
     
-    let index = {
-        let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-        load_account_index()?
-    };
+    let index = load_account_index()?;
     
     // 1. 验证账号存在
     if !index.accounts.iter().any(|s| s.id == account_id) {
@@ -428,12 +413,7 @@ pub async fn switch_account(account_id: &str) -> Result<(), String> {
     )?;
     
     // 6. 更新工具内部状态
-    {
-        let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-        let mut index = load_account_index()?;
-        index.current_account_id = Some(account_id.to_string());
-        save_account_index(&index)?;
-    }
+    set_current_account_id(account_id)?;
     
     account.update_last_used();
     save_account(&account)?;
@@ -462,17 +442,33 @@ pub fn get_current_account() -> Result<Option<Account>, String> {
 
 /// 设置当前激活账号 ID
 pub fn set_current_account_id(account_id: &str) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
-    index.current_account_id = Some(account_id.to_string());
-    save_account_index(&index)
+    account_store::current()?.transact(|txn| {
+        txn.index_mut().current_account_id = Some(account_id.to_string());
+        Ok(())
+    })
 }
 
 /// 更新账号配额
 pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), String> {
-    let mut account = load_account(account_id)?;
-    account.update_quota(quota);
-    save_account(&account)
+    update_account(account_id, |account| {
+        account.update_quota(quota.clone());
+        Ok(())
+    })?;
+    events::publish(ProxyEvent::QuotaRefreshed {
+        account_id: account_id.to_string(),
+        quota,
+    });
+    Ok(())
+}
+
+/// 推送账号禁用状态变更到 Web Admin
+fn publish_disabled_state(account: &Account) {
+    events::publish(ProxyEvent::AccountStateChanged {
+        account_id: account.id.clone(),
+        field: "disabled",
+        enabled: !account.disabled,
+        reason: account.disabled_reason.clone(),
+    });
 }
 
 /// 导出所有账号的 refresh_token
@@ -488,6 +484,111 @@ pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
     Ok(exports)
 }
 
+#[derive(serde::Serialize)]
+pub struct RefreshStats {
+    pub total: usize,
+    pub success: usize,
+    pub failed: usize,
+    pub details: Vec<String>,
+    /// 刷新成功的账号配额 (account_id, quota)，供调用方同步到运行中的反代服务
+    #[serde(skip)]
+    pub updated: Vec<(String, QuotaData)>,
+}
+
+/// 刷新所有账号配额 (跳过已禁用/403 账号，并发执行)
+pub async fn refresh_all_quotas() -> Result<RefreshStats, String> {
+    use futures::future::join_all;
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+
+    const MAX_CONCURRENT: usize = 5;
+    let start = std::time::Instant::now();
+
+    modules::logger::log_info(&format!(
+        "开始批量刷新所有账号配额 (并发模式, 最大并发: {})",
+        MAX_CONCURRENT
+    ));
+    let accounts = list_accounts()?;
+
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT));
+
+    let tasks: Vec<_> = accounts
+        .into_iter()
+        .filter(|account| {
+            if account.disabled {
+                modules::logger::log_info(&format!("  - Skipping {} (Disabled)", account.email));
+                return false;
+            }
+            if let Some(ref q) = account.quota {
+                if q.is_forbidden {
+                    modules::logger::log_info(&format!("  - Skipping {} (Forbidden)", account.email));
+                    return false;
+                }
+            }
+            true
+        })
+        .map(|mut account| {
+            let email = account.email.clone();
+            let account_id = account.id.clone();
+            let permit = semaphore.clone();
+            async move {
+                let _guard = permit.acquire().await.unwrap();
+                modules::logger::log_info(&format!("  - Processing {}", email));
+                match fetch_quota_with_retry(&mut account).await {
+                    Ok(quota) => {
+                        if let Err(e) = update_account_quota(&account_id, quota.clone()) {
+                            let msg = format!("Account {}: Save quota failed - {}", email, e);
+                            modules::logger::log_error(&msg);
+                            Err(msg)
+                        } else {
+                            modules::logger::log_info(&format!("    ✅ {} Success", email));
+                            Ok((account_id, quota))
+                        }
+                    }
+                    Err(e) => {
+                        let msg = format!("Account {}: Fetch quota failed - {}", email, e);
+                        modules::logger::log_error(&msg);
+                        Err(msg)
+                    }
+                }
+            }
+        })
+        .collect();
+
+    let total = tasks.len();
+    let results = join_all(tasks).await;
+
+    let mut stats = RefreshStats {
+        total,
+        success: 0,
+        failed: 0,
+        details: Vec::new(),
+        updated: Vec::new(),
+    };
+
+    for result in results {
+        match result {
+            Ok(updated) => {
+                stats.success += 1;
+                stats.updated.push(updated);
+            }
+            Err(msg) => {
+                stats.failed += 1;
+                stats.details.push(msg);
+            }
+        }
+    }
+
+    modules::logger::log_info(&format!(
+        "批量刷新完成: {} 成功, {} 失败, 耗时: {}ms",
+        stats.success,
+        stats.failed,
+        start.elapsed().as_millis()
+    ));
+
+    Ok(stats)
+}
+
 /// 带有重试机制的配额查询 (从 commands 移动到 modules 以便共享)
 pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppResult<QuotaData> {
     use crate::modules::oauth;
@@ -507,6 +608,7 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 account.disabled_at = Some(chrono::Utc::now().timestamp());
                 account.disabled_reason = Some(format!("invalid_grant: {}", e));
                 let _ = save_account(account);
+                publish_disabled_state(account);
             }
             return Err(AppError::OAuth(e));
         }
@@ -583,6 +685,7 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                             account.disabled_at = Some(chrono::Utc::now().timestamp());
                             account.disabled_reason = Some(format!("invalid_grant: {}", e));
                             let _ = save_account(account);
+                            publish_disabled_state(account);
                         }
                         return Err(AppError::OAuth(e));
                     }
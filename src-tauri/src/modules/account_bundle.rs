@@ -0,0 +1,358 @@
+// 加密账号包 (Account bundle) 导出/导入
+//
+// 账号包是一个版本化的 JSON 文件，外层记录 KDF 参数，内层载荷 (账号、应用配置) 使用
+// 口令经 PBKDF2-HMAC-SHA256 派生的密钥做 AES-256-GCM 加密，可在桌面端与服务器之间迁移账号。
+// 载荷中的 Token 始终为明文，导入时由 save_account 按本机主密钥重新加密。
+use std::collections::HashSet;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Account, AppConfig};
+use crate::modules::crypto::{cipher, kdf};
+use crate::modules::{account, config};
+
+const BUNDLE_FORMAT: &str = "antigravity-account-bundle";
+const BUNDLE_VERSION: u32 = 1;
+const KDF_NAME: &str = "pbkdf2-hmac-sha256";
+const CIPHER_NAME: &str = "aes-256-gcm";
+
+/// 口令最短长度
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// 账号包文件 (外层，明文)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptedBundle {
+    pub format: String,
+    pub version: u32,
+    pub created_at: i64,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    /// base64(nonce || ciphertext || tag)
+    pub payload: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub name: String,
+    pub iterations: u32,
+    /// base64 编码的盐
+    pub salt: String,
+}
+
+/// 账号包载荷 (内层，加密)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundlePayload {
+    /// 按本地账号列表顺序排列
+    pub accounts: Vec<Account>,
+    pub current_account_email: Option<String>,
+    #[serde(default)]
+    pub config: Option<AppConfig>,
+}
+
+/// 同邮箱账号冲突处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// 保留本地账号
+    #[default]
+    Skip,
+    /// 使用账号包中的数据覆盖
+    Overwrite,
+    /// 保留 Token 更新 (过期时间更晚) 的一方
+    KeepNewer,
+}
+
+/// 配置导入范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigImportMode {
+    #[default]
+    None,
+    /// 仅导入模型映射 (custom_mapping 与 z.ai model_mapping)
+    Mappings,
+    /// 导入完整应用配置
+    Full,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BundleImportOptions {
+    /// 只导入这些邮箱的账号 (为空则全部导入)
+    #[serde(default)]
+    pub emails: Option<Vec<String>>,
+    #[serde(default)]
+    pub conflict: ConflictStrategy,
+    #[serde(default)]
+    pub config: ConfigImportMode,
+}
+
+/// 账号包预览条目 (用于选择性导入)
+#[derive(Debug, Serialize)]
+pub struct BundleAccountPreview {
+    pub email: String,
+    pub name: Option<String>,
+    pub disabled: bool,
+    pub proxy_disabled: bool,
+    /// 本地已存在同邮箱账号
+    pub exists: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundlePreview {
+    pub created_at: i64,
+    pub accounts: Vec<BundleAccountPreview>,
+    pub has_config: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BundleImportReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, String)>,
+    pub config_imported: bool,
+    /// 恢复为当前账号的邮箱 (导出时的当前账号本次被导入时)
+    pub current_account: Option<String>,
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("口令至少需要 {} 个字符", MIN_PASSPHRASE_LEN));
+    }
+    Ok(())
+}
+
+/// 导出当前所有账号 (以及可选的应用配置) 为加密账号包
+pub fn export_bundle(passphrase: &str, include_config: bool) -> Result<String, String> {
+    validate_passphrase(passphrase)?;
+
+    let mut accounts = account::list_accounts()?;
+    for acc in &mut accounts {
+        acc.token
+            .decrypt_tokens()
+            .map_err(|e| format!("解密账号 {} 的 Token 失败: {}", acc.email, e))?;
+    }
+    let current_account_email = account::get_current_account()?.map(|a| a.email);
+    let config = if include_config {
+        Some(config::load_app_config()?)
+    } else {
+        None
+    };
+
+    let payload = BundlePayload {
+        accounts,
+        current_account_email,
+        config,
+    };
+    seal(&payload, passphrase, kdf::PBKDF2_DEFAULT_ITERATIONS)
+}
+
+fn seal(payload: &BundlePayload, passphrase: &str, iterations: u32) -> Result<String, String> {
+    let plaintext = serde_json::to_string(payload).map_err(|e| format!("序列化账号包失败: {}", e))?;
+    let salt = kdf::generate_salt();
+    let key = kdf::derive_key_pbkdf2(passphrase, &salt, iterations)?;
+
+    let bundle = EncryptedBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now().timestamp(),
+        kdf: KdfParams {
+            name: KDF_NAME.to_string(),
+            iterations,
+            salt: base64::engine::general_purpose::STANDARD.encode(&salt),
+        },
+        cipher: CIPHER_NAME.to_string(),
+        payload: cipher::encrypt_string(&plaintext, &key)?,
+    };
+    serde_json::to_string_pretty(&bundle).map_err(|e| format!("序列化账号包失败: {}", e))
+}
+
+/// 解密账号包
+pub fn open_bundle(content: &str, passphrase: &str) -> Result<(EncryptedBundle, BundlePayload), String> {
+    let bundle: EncryptedBundle =
+        serde_json::from_str(content).map_err(|e| format!("账号包格式无效: {}", e))?;
+    if bundle.format != BUNDLE_FORMAT {
+        return Err("不是有效的账号包文件".to_string());
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!("账号包版本 {} 过新，请升级应用后再导入", bundle.version));
+    }
+    if bundle.kdf.name != KDF_NAME || bundle.cipher != CIPHER_NAME {
+        return Err(format!("不支持的加密方式: {} / {}", bundle.kdf.name, bundle.cipher));
+    }
+
+    // 迭代次数来自不可信的文件头，超出范围时拒绝，避免构造的文件让导入长时间卡住
+    if bundle.kdf.iterations == 0 || bundle.kdf.iterations > kdf::PBKDF2_MAX_ITERATIONS {
+        return Err(format!("账号包的密钥派生迭代次数无效: {}", bundle.kdf.iterations));
+    }
+
+    let salt = base64::engine::general_purpose::STANDARD.decode(&bundle.kdf.salt).map_err(|e| format!("账号包盐值无效: {}", e))?;
+    let key = kdf::derive_key_pbkdf2(passphrase, &salt, bundle.kdf.iterations)?;
+    let plaintext = cipher::decrypt_string(&bundle.payload, &key).map_err(|_| "口令错误或账号包已损坏".to_string())?;
+    let payload: BundlePayload =
+        serde_json::from_str(&plaintext).map_err(|e| format!("账号包内容无效: {}", e))?;
+
+    Ok((bundle, payload))
+}
+
+/// 预览账号包内容 (不写入任何数据)
+pub fn preview_bundle(content: &str, passphrase: &str) -> Result<BundlePreview, String> {
+    let (bundle, payload) = open_bundle(content, passphrase)?;
+    let local: HashSet<String> = account::load_account_index()?
+        .accounts
+        .into_iter()
+        .map(|s| s.email)
+        .collect();
+
+    Ok(BundlePreview {
+        created_at: bundle.created_at,
+        accounts: payload
+            .accounts
+            .iter()
+            .map(|a| BundleAccountPreview {
+                email: a.email.clone(),
+                name: a.name.clone(),
+                disabled: a.disabled,
+                proxy_disabled: a.proxy_disabled,
+                exists: local.contains(&a.email),
+            })
+            .collect(),
+        has_config: payload.config.is_some(),
+    })
+}
+
+/// 导入账号包
+pub fn import_bundle(content: &str, passphrase: &str, options: &BundleImportOptions) -> Result<BundleImportReport, String> {
+    let (_, payload) = open_bundle(content, passphrase)?;
+    let mut report = BundleImportReport::default();
+
+    let local_accounts = account::list_accounts()?;
+    let selected: Option<HashSet<&str>> = options
+        .emails
+        .as_ref()
+        .filter(|e| !e.is_empty())
+        .map(|e| e.iter().map(String::as_str).collect());
+
+    for mut incoming in payload.accounts {
+        if selected.as_ref().is_some_and(|s| !s.contains(incoming.email.as_str())) {
+            continue;
+        }
+        let email = incoming.email.clone();
+
+        if let Some(local) = local_accounts.iter().find(|a| a.email == email) {
+            if !should_replace(options.conflict, local, &incoming) {
+                report.skipped.push(email);
+                continue;
+            }
+        }
+
+        // 载荷中的 Token 为明文
+        incoming.token.encrypted = false;
+        match account::store_imported_account(incoming) {
+            Ok((_, true)) => report.updated.push(email),
+            Ok((_, false)) => report.added.push(email),
+            Err(e) => report.failed.push((email, e)),
+        }
+    }
+
+    // 恢复导出时的当前账号 (仅当该账号本次被导入)
+    if let Some(email) = payload
+        .current_account_email
+        .filter(|e| report.added.contains(e) || report.updated.contains(e))
+    {
+        if let Some(summary) = account::load_account_index()?.accounts.into_iter().find(|s| s.email == email) {
+            account::set_current_account_id(&summary.id)?;
+            report.current_account = Some(email);
+        }
+    }
+
+    if let Some(bundle_config) = payload.config {
+        report.config_imported = apply_config(options.config, bundle_config)?;
+    }
+
+    crate::modules::logger::log_info(&format!(
+        "账号包导入完成: 新增 {}, 更新 {}, 跳过 {}, 失败 {}",
+        report.added.len(),
+        report.updated.len(),
+        report.skipped.len(),
+        report.failed.len()
+    ));
+    Ok(report)
+}
+
+fn should_replace(strategy: ConflictStrategy, local: &Account, incoming: &Account) -> bool {
+    match strategy {
+        ConflictStrategy::Skip => false,
+        ConflictStrategy::Overwrite => true,
+        ConflictStrategy::KeepNewer => incoming.token.expiry_timestamp > local.token.expiry_timestamp,
+    }
+}
+
+fn apply_config(mode: ConfigImportMode, bundle_config: AppConfig) -> Result<bool, String> {
+    let config = match mode {
+        ConfigImportMode::None => return Ok(false),
+        ConfigImportMode::Full => bundle_config,
+        ConfigImportMode::Mappings => {
+            let mut current = config::load_app_config()?;
+            current.proxy.custom_mapping = bundle_config.proxy.custom_mapping;
+            current.proxy.zai.model_mapping = bundle_config.proxy.zai.model_mapping;
+            current
+        }
+    };
+    config::save_app_config(&config)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn sample_account(email: &str, expiry: i64) -> Account {
+        let mut token = TokenData::new("at".into(), "1//refresh".into(), 3600, Some(email.into()), None, None);
+        token.expiry_timestamp = expiry;
+        let mut account = Account::new(format!("id-{}", email), email.to_string(), token);
+        account.proxy_disabled = true;
+        account
+    }
+
+    #[test]
+    fn test_bundle_roundtrip_and_wrong_passphrase() {
+        let payload = BundlePayload {
+            accounts: vec![sample_account("a@example.com", 100)],
+            current_account_email: Some("a@example.com".into()),
+            config: None,
+        };
+        let sealed = seal(&payload, "correct horse", 10).unwrap();
+        assert!(!sealed.contains("1//refresh"));
+
+        let (bundle, opened) = open_bundle(&sealed, "correct horse").unwrap();
+        assert_eq!(bundle.kdf.iterations, 10);
+        assert_eq!(opened.accounts[0].token.refresh_token, "1//refresh");
+        assert!(opened.accounts[0].proxy_disabled);
+
+        let err = open_bundle(&sealed, "wrong horse").unwrap_err();
+        assert!(err.contains("口令错误"));
+    }
+
+    #[test]
+    fn test_rejects_excessive_kdf_iterations() {
+        let payload = BundlePayload { accounts: vec![], current_account_email: None, config: None };
+        let sealed = seal(&payload, "correct horse", 10).unwrap();
+        let mut bundle: serde_json::Value = serde_json::from_str(&sealed).unwrap();
+        bundle["kdf"]["iterations"] = serde_json::json!(u32::MAX);
+
+        let err = open_bundle(&bundle.to_string(), "correct horse").unwrap_err();
+        assert!(err.contains("迭代次数"));
+    }
+
+    #[test]
+    fn test_conflict_strategies() {
+        let local = sample_account("a@example.com", 100);
+        let newer = sample_account("a@example.com", 200);
+        assert!(!should_replace(ConflictStrategy::Skip, &local, &newer));
+        assert!(should_replace(ConflictStrategy::Overwrite, &newer, &local));
+        assert!(should_replace(ConflictStrategy::KeepNewer, &local, &newer));
+        assert!(!should_replace(ConflictStrategy::KeepNewer, &newer, &local));
+    }
+}
@@ -0,0 +1,801 @@
+// 账号存储后端 (AccountStore)
+//
+// 账号数据的持久化统一经由 AccountStore，目前有两种实现:
+// - FileAccountStore: 原有布局，accounts.json 索引 + accounts/<id>.json，进程内由全局锁串行化写入
+// - SqliteAccountStore: accounts.db 单文件，索引与账号数据在同一个 SQLite 事务中更新，可跨进程安全并发
+//
+// 后端选择: 环境变量 ANTIGRAVITY_ACCOUNT_STORE=file|sqlite 优先，否则数据目录中存在 accounts.db 即使用 SQLite。
+// 选择 SQLite 但数据库尚不存在时，会自动从文件布局做一次性迁移 (见 migrate_to_sqlite)。
+// 存储层只负责原样读写，Token 加密由 modules::account 在写入前处理。
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+
+use crate::models::{Account, AccountIndex, AccountSummary};
+
+const ACCOUNTS_INDEX: &str = "accounts.json";
+const ACCOUNTS_DIR: &str = "accounts";
+const SQLITE_FILE: &str = "accounts.db";
+/// 迁移完成后旧索引文件的新名称 (账号文件原样保留作为备份)
+const MIGRATED_INDEX: &str = "accounts.json.migrated";
+const STORE_ENV: &str = "ANTIGRAVITY_ACCOUNT_STORE";
+
+/// 文件后端的全局写入锁，防止并发操作导致索引文件损坏
+static FILE_STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 已打开的存储 (按数据目录缓存，避免重复打开数据库连接)
+static STORES: Lazy<RwLock<HashMap<PathBuf, Arc<dyn AccountStore>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreBackend {
+    File,
+    Sqlite,
+}
+
+/// 按索引顺序加载的账号列表
+#[derive(Debug, Default)]
+pub struct AccountListing {
+    pub accounts: Vec<Account>,
+    /// 索引中存在但没有账号数据的 ID
+    pub missing: Vec<String>,
+}
+
+/// 事务内的读写视图，闭包返回 Err 时事务内的修改不会提交
+pub trait AccountTxn {
+    fn index(&self) -> &AccountIndex;
+    fn index_mut(&mut self) -> &mut AccountIndex;
+    fn load(&mut self, account_id: &str) -> Result<Option<Account>, String>;
+    fn save(&mut self, account: &Account) -> Result<(), String>;
+    fn remove(&mut self, account_id: &str) -> Result<(), String>;
+}
+
+pub trait AccountStore: Send + Sync {
+    fn backend(&self) -> StoreBackend;
+
+    fn load_index(&self) -> Result<AccountIndex, String>;
+
+    fn load(&self, account_id: &str) -> Result<Option<Account>, String>;
+
+    /// 写入单个账号 (不修改索引)
+    fn save(&self, account: &Account) -> Result<(), String>;
+
+    /// 在事务中执行一组读写 (索引 + 账号)
+    fn transaction(&self, f: &mut dyn FnMut(&mut dyn AccountTxn) -> Result<(), String>) -> Result<(), String>;
+
+    /// 将当前存储内容完整复制到备份目录
+    fn backup_to(&self, dir: &Path) -> Result<(), String>;
+
+    /// 存储文件的元数据指纹 (大小 + 修改时间)，用于廉价地检测其他进程的写入
+    fn fingerprint(&self) -> Result<u64, String>;
+
+    fn list(&self) -> Result<AccountListing, String> {
+        let index = self.load_index()?;
+        let mut listing = AccountListing::default();
+        for summary in &index.accounts {
+            match self.load(&summary.id) {
+                Ok(Some(account)) => listing.accounts.push(account),
+                Ok(None) => listing.missing.push(summary.id.clone()),
+                Err(e) => crate::modules::logger::log_error(&format!("加载账号 {} 失败: {}", summary.id, e)),
+            }
+        }
+        Ok(listing)
+    }
+}
+
+impl dyn AccountStore + '_ {
+    /// 带返回值的事务
+    pub fn transact<T>(&self, f: impl FnOnce(&mut dyn AccountTxn) -> Result<T, String>) -> Result<T, String> {
+        let mut f = Some(f);
+        let mut output = None;
+        self.transaction(&mut |txn| {
+            let f = f.take().ok_or("事务闭包被重复调用")?;
+            output = Some(f(txn)?);
+            Ok(())
+        })?;
+        output.ok_or_else(|| "事务未执行".to_string())
+    }
+
+    /// 读取-修改-写回单个账号
+    pub fn update(&self, account_id: &str, f: impl FnOnce(&mut Account) -> Result<(), String>) -> Result<Account, String> {
+        self.transact(|txn| {
+            let mut account = txn
+                .load(account_id)?
+                .ok_or_else(|| format!("账号不存在: {}", account_id))?;
+            f(&mut account)?;
+            txn.save(&account)?;
+            Ok(account)
+        })
+    }
+}
+
+// ===== 后端选择 =====
+
+/// 当前数据目录对应的账号存储
+pub fn current() -> Result<Arc<dyn AccountStore>, String> {
+    for_dir(&crate::modules::account::get_data_dir()?)
+}
+
+/// 指定数据目录对应的账号存储
+pub fn for_dir(data_dir: &Path) -> Result<Arc<dyn AccountStore>, String> {
+    if let Some(store) = STORES.read().map_err(|e| format!("获取锁失败: {}", e))?.get(data_dir) {
+        return Ok(store.clone());
+    }
+
+    let mut stores = STORES.write().map_err(|e| format!("获取锁失败: {}", e))?;
+    if let Some(store) = stores.get(data_dir) {
+        return Ok(store.clone());
+    }
+    let store = open(data_dir)?;
+    stores.insert(data_dir.to_path_buf(), store.clone());
+    Ok(store)
+}
+
+fn open(data_dir: &Path) -> Result<Arc<dyn AccountStore>, String> {
+    let db_path = data_dir.join(SQLITE_FILE);
+    let backend = match std::env::var(STORE_ENV).ok().as_deref().map(str::trim) {
+        Some("sqlite") => StoreBackend::Sqlite,
+        Some("file") => {
+            if db_path.exists() {
+                crate::modules::logger::log_warn(&format!(
+                    "{}=file，忽略已存在的账号数据库 {:?}",
+                    STORE_ENV, db_path
+                ));
+            }
+            StoreBackend::File
+        }
+        Some(other) if !other.is_empty() => return Err(format!("未知的账号存储后端: {}", other)),
+        _ if db_path.exists() => StoreBackend::Sqlite,
+        _ => StoreBackend::File,
+    };
+
+    match backend {
+        StoreBackend::File => Ok(Arc::new(FileAccountStore::new(data_dir))),
+        StoreBackend::Sqlite => {
+            if !db_path.exists() && data_dir.join(ACCOUNTS_INDEX).exists() {
+                // 调用方 (for_dir) 持有 STORES 写锁，新后端写入 STORES 前不会有其他线程拿到文件后端
+                let _lock = FILE_STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+                let report = migrate_files(data_dir)?;
+                crate::modules::logger::log_info(&format!(
+                    "账号存储已自动迁移到 SQLite: {} 个账号，{} 个无法读取的账号文件已跳过",
+                    report.migrated,
+                    report.unreadable.len()
+                ));
+            }
+            Ok(Arc::new(SqliteAccountStore::open(&db_path)?))
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountStoreStatus {
+    pub backend: StoreBackend,
+    pub accounts: usize,
+}
+
+/// 当前账号存储后端及账号数量
+pub fn status() -> Result<AccountStoreStatus, String> {
+    let store = current()?;
+    Ok(AccountStoreStatus {
+        backend: store.backend(),
+        accounts: store.load_index()?.accounts.len(),
+    })
+}
+
+// ===== 一次性迁移 =====
+
+#[derive(Debug, Serialize)]
+pub struct StoreMigrationReport {
+    pub migrated: usize,
+    /// 索引中缺少账号文件而被丢弃的 ID
+    pub dropped: Vec<String>,
+    /// 账号文件无法读取而跳过的账号 (`ID: 错误`)，原文件保留在 accounts/ 目录中
+    pub unreadable: Vec<String>,
+    pub database: String,
+}
+
+/// 将文件布局的账号迁移到 SQLite，并切换当前进程使用新后端
+///
+/// 旧索引重命名为 accounts.json.migrated，accounts/ 目录保留作为备份。
+/// 迁移期间请停止其他使用同一数据目录的实例。
+pub fn migrate_to_sqlite(data_dir: &Path) -> Result<StoreMigrationReport, String> {
+    // 加锁顺序与 for_dir 一致 (STORES → FILE_STORE_LOCK)。两把锁都持有到 STORES 中的后端
+    // 被替换为 SQLite 之后，期间既没有文件写入，也没有线程能取到旧的文件后端
+    let mut stores = STORES.write().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = FILE_STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let report = migrate_files(data_dir)?;
+    let store: Arc<dyn AccountStore> = Arc::new(SqliteAccountStore::open(&data_dir.join(SQLITE_FILE))?);
+    stores.insert(data_dir.to_path_buf(), store);
+    drop(_lock);
+    drop(stores);
+
+    crate::modules::logger::log_info(&format!(
+        "账号存储迁移完成: {} 个账号 -> {}",
+        report.migrated, report.database
+    ));
+    Ok(report)
+}
+
+/// 调用方需持有 FILE_STORE_LOCK，保证迁移期间本进程内不会再有文件写入
+fn migrate_files(data_dir: &Path) -> Result<StoreMigrationReport, String> {
+    let db_path = data_dir.join(SQLITE_FILE);
+    if db_path.exists() {
+        return Err(format!("账号数据库已存在: {:?}", db_path));
+    }
+
+    let files = FileAccountStore::new(data_dir);
+    let mut index = files.load_index()?;
+
+    let temp_path = data_dir.join(format!("{}.tmp", SQLITE_FILE));
+    let _ = fs::remove_file(&temp_path);
+
+    let mut dropped = Vec::new();
+    let mut unreadable = Vec::new();
+    {
+        let mut conn = open_connection(&temp_path)?;
+        let tx = conn.transaction().map_err(db_err)?;
+        let mut migrated = Vec::with_capacity(index.accounts.len());
+        for summary in index.accounts.drain(..) {
+            // 单个损坏的账号文件不应阻止整个迁移 (自动迁移失败会导致启动时无法读取账号)
+            match files.load(&summary.id) {
+                Ok(Some(account)) => {
+                    write_account(&tx, &account)?;
+                    migrated.push(summary);
+                }
+                Ok(None) => dropped.push(summary.id),
+                Err(e) => {
+                    crate::modules::logger::log_warn(&format!("跳过无法读取的账号文件 {}: {}", summary.id, e));
+                    unreadable.push(format!("{}: {}", summary.id, e));
+                }
+            }
+        }
+        index.accounts = migrated;
+        if index
+            .current_account_id
+            .as_ref()
+            .is_some_and(|id| !index.accounts.iter().any(|s| &s.id == id))
+        {
+            index.current_account_id = index.accounts.first().map(|s| s.id.clone());
+        }
+        write_index(&tx, &index)?;
+        tx.commit().map_err(db_err)?;
+    }
+
+    fs::rename(&temp_path, &db_path).map_err(|e| format!("替换账号数据库失败: {}", e))?;
+    let index_path = data_dir.join(ACCOUNTS_INDEX);
+    if index_path.exists() {
+        fs::rename(&index_path, data_dir.join(MIGRATED_INDEX))
+            .map_err(|e| format!("重命名旧账号索引失败: {}", e))?;
+    }
+
+    Ok(StoreMigrationReport {
+        migrated: index.accounts.len(),
+        dropped,
+        unreadable,
+        database: db_path.to_string_lossy().to_string(),
+    })
+}
+
+// ===== 文件后端 =====
+
+pub struct FileAccountStore {
+    data_dir: PathBuf,
+}
+
+impl FileAccountStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+        }
+    }
+
+    fn accounts_dir(&self) -> Result<PathBuf, String> {
+        let dir = self.data_dir.join(ACCOUNTS_DIR);
+        if !dir.exists() {
+            fs::create_dir_all(&dir).map_err(|e| format!("创建账号目录失败: {}", e))?;
+        }
+        Ok(dir)
+    }
+
+    fn account_path(&self, account_id: &str) -> Result<PathBuf, String> {
+        Ok(self.accounts_dir()?.join(format!("{}.json", account_id)))
+    }
+
+    /// 保存账号索引 (原子化写入)
+    fn save_index(&self, index: &AccountIndex) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(index).map_err(|e| format!("序列化账号索引失败: {}", e))?;
+        write_atomic(&self.data_dir.join(ACCOUNTS_INDEX), &content)
+    }
+
+    fn remove(&self, account_id: &str) -> Result<(), String> {
+        let path = self.account_path(account_id)?;
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| format!("删除账号文件失败: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+impl AccountStore for FileAccountStore {
+    fn backend(&self) -> StoreBackend {
+        StoreBackend::File
+    }
+
+    fn load_index(&self) -> Result<AccountIndex, String> {
+        let index_path = self.data_dir.join(ACCOUNTS_INDEX);
+        if !index_path.exists() {
+            crate::modules::logger::log_warn("账号索引文件不存在");
+            return Ok(AccountIndex::new());
+        }
+
+        let content = fs::read_to_string(&index_path).map_err(|e| format!("读取账号索引失败: {}", e))?;
+        serde_json::from_str(&content).map_err(|e| format!("解析账号索引失败: {}", e))
+    }
+
+    fn load(&self, account_id: &str) -> Result<Option<Account>, String> {
+        let path = self.account_path(account_id)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path).map_err(|e| format!("读取账号数据失败: {}", e))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("解析账号数据失败: {}", e))
+    }
+
+    fn save(&self, account: &Account) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(account).map_err(|e| format!("序列化账号数据失败: {}", e))?;
+        write_atomic(&self.account_path(&account.id)?, &content)
+    }
+
+    fn transaction(&self, f: &mut dyn FnMut(&mut dyn AccountTxn) -> Result<(), String>) -> Result<(), String> {
+        let _lock = FILE_STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+        let mut txn = FileTxn {
+            store: self,
+            index: self.load_index()?,
+            index_dirty: false,
+        };
+        // 文件后端无法回滚已写入的账号文件，但索引只在闭包成功后才写回
+        f(&mut txn)?;
+        if txn.index_dirty {
+            self.save_index(&txn.index)?;
+        }
+        Ok(())
+    }
+
+    fn backup_to(&self, dir: &Path) -> Result<(), String> {
+        let index_path = self.data_dir.join(ACCOUNTS_INDEX);
+        if index_path.exists() {
+            fs::copy(&index_path, dir.join(ACCOUNTS_INDEX)).map_err(|e| format!("备份账号索引失败: {}", e))?;
+        }
+
+        let backup_accounts = dir.join(ACCOUNTS_DIR);
+        fs::create_dir_all(&backup_accounts).map_err(|e| format!("创建备份目录失败: {}", e))?;
+        let entries = fs::read_dir(self.accounts_dir()?).map_err(|e| format!("读取账号目录失败: {}", e))?;
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(name) = path.file_name() {
+                    fs::copy(&path, backup_accounts.join(name)).map_err(|e| format!("备份 {:?} 失败: {}", name, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fingerprint(&self) -> Result<u64, String> {
+        let entries = fs::read_dir(self.accounts_dir()?).map_err(|e| format!("读取账号目录失败: {}", e))?;
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        paths.push(self.data_dir.join(ACCOUNTS_INDEX));
+        Ok(metadata_fingerprint(&paths))
+    }
+}
+
+struct FileTxn<'a> {
+    store: &'a FileAccountStore,
+    index: AccountIndex,
+    index_dirty: bool,
+}
+
+impl AccountTxn for FileTxn<'_> {
+    fn index(&self) -> &AccountIndex {
+        &self.index
+    }
+
+    fn index_mut(&mut self) -> &mut AccountIndex {
+        self.index_dirty = true;
+        &mut self.index
+    }
+
+    fn load(&mut self, account_id: &str) -> Result<Option<Account>, String> {
+        self.store.load(account_id)
+    }
+
+    fn save(&mut self, account: &Account) -> Result<(), String> {
+        self.store.save(account)
+    }
+
+    fn remove(&mut self, account_id: &str) -> Result<(), String> {
+        self.store.remove(account_id)
+    }
+}
+
+/// 先写临时文件再重命名，避免写入中断留下半个文件
+fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!("{}.tmp", file_name));
+    fs::write(&temp_path, content).map_err(|e| format!("写入临时文件失败: {}", e))?;
+    fs::rename(&temp_path, path).map_err(|e| format!("替换文件失败: {}", e))
+}
+
+/// 文件不存在时也计入 (删除同样是变更)
+fn metadata_fingerprint(paths: &[PathBuf]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for path in paths {
+        path.hash(&mut hasher);
+        if let Ok(meta) = fs::metadata(path) {
+            meta.len().hash(&mut hasher);
+            meta.modified().ok().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+// ===== SQLite 后端 =====
+
+pub struct SqliteAccountStore {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteAccountStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            conn: Mutex::new(open_connection(path)?),
+        })
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, String> {
+        self.conn.lock().map_err(|e| format!("获取锁失败: {}", e))
+    }
+}
+
+impl AccountStore for SqliteAccountStore {
+    fn backend(&self) -> StoreBackend {
+        StoreBackend::Sqlite
+    }
+
+    fn load_index(&self) -> Result<AccountIndex, String> {
+        read_index(&*self.conn()?)
+    }
+
+    fn load(&self, account_id: &str) -> Result<Option<Account>, String> {
+        read_account(&*self.conn()?, account_id)
+    }
+
+    fn save(&self, account: &Account) -> Result<(), String> {
+        write_account(&*self.conn()?, account)
+    }
+
+    fn list(&self) -> Result<AccountListing, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT i.id, a.data FROM account_index i
+                 LEFT JOIN accounts a ON a.id = i.id
+                 ORDER BY i.position",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))
+            .map_err(db_err)?;
+
+        let mut listing = AccountListing::default();
+        for row in rows {
+            match row.map_err(db_err)? {
+                (_, Some(data)) => match serde_json::from_str(&data) {
+                    Ok(account) => listing.accounts.push(account),
+                    Err(e) => crate::modules::logger::log_error(&format!("解析账号数据失败: {}", e)),
+                },
+                (id, None) => listing.missing.push(id),
+            }
+        }
+        Ok(listing)
+    }
+
+    fn transaction(&self, f: &mut dyn FnMut(&mut dyn AccountTxn) -> Result<(), String>) -> Result<(), String> {
+        let mut conn = self.conn()?;
+        // IMMEDIATE: 事务开始即获取写锁，避免与其他进程的读后写冲突
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+        let index = read_index(&tx)?;
+        let mut txn = SqliteTxn {
+            tx,
+            index,
+            index_dirty: false,
+        };
+        // 出错时 Transaction 被 drop，自动回滚
+        f(&mut txn)?;
+        if txn.index_dirty {
+            write_index(&txn.tx, &txn.index)?;
+        }
+        txn.tx.commit().map_err(db_err)
+    }
+
+    fn backup_to(&self, dir: &Path) -> Result<(), String> {
+        let target = dir.join(SQLITE_FILE);
+        self.conn()?
+            .execute("VACUUM INTO ?1", params![target.to_string_lossy()])
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    fn fingerprint(&self) -> Result<u64, String> {
+        // WAL 模式下每次提交都会写入 -wal 文件
+        let mut wal = self.path.clone().into_os_string();
+        wal.push("-wal");
+        Ok(metadata_fingerprint(&[self.path.clone(), PathBuf::from(wal)]))
+    }
+}
+
+struct SqliteTxn<'a> {
+    tx: rusqlite::Transaction<'a>,
+    index: AccountIndex,
+    index_dirty: bool,
+}
+
+impl AccountTxn for SqliteTxn<'_> {
+    fn index(&self) -> &AccountIndex {
+        &self.index
+    }
+
+    fn index_mut(&mut self) -> &mut AccountIndex {
+        self.index_dirty = true;
+        &mut self.index
+    }
+
+    fn load(&mut self, account_id: &str) -> Result<Option<Account>, String> {
+        read_account(&self.tx, account_id)
+    }
+
+    fn save(&mut self, account: &Account) -> Result<(), String> {
+        write_account(&self.tx, account)
+    }
+
+    fn remove(&mut self, account_id: &str) -> Result<(), String> {
+        self.tx
+            .execute("DELETE FROM accounts WHERE id = ?1", params![account_id])
+            .map_err(db_err)?;
+        Ok(())
+    }
+}
+
+fn db_err(e: rusqlite::Error) -> String {
+    format!("账号数据库错误: {}", e)
+}
+
+fn open_connection(path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(db_err)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5)).map_err(db_err)?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         CREATE TABLE IF NOT EXISTS accounts (
+             id TEXT PRIMARY KEY,
+             email TEXT NOT NULL,
+             data TEXT NOT NULL,
+             updated_at INTEGER NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS account_index (
+             id TEXT PRIMARY KEY,
+             position INTEGER NOT NULL,
+             email TEXT NOT NULL,
+             name TEXT,
+             created_at INTEGER NOT NULL,
+             last_used INTEGER NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS meta (
+             key TEXT PRIMARY KEY,
+             value TEXT NOT NULL
+         );",
+    )
+    .map_err(db_err)?;
+    Ok(conn)
+}
+
+fn read_index(conn: &Connection) -> Result<AccountIndex, String> {
+    let mut index = AccountIndex::new();
+    let mut stmt = conn
+        .prepare("SELECT id, email, name, created_at, last_used FROM account_index ORDER BY position")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(AccountSummary {
+                id: row.get(0)?,
+                email: row.get(1)?,
+                name: row.get(2)?,
+                created_at: row.get(3)?,
+                last_used: row.get(4)?,
+            })
+        })
+        .map_err(db_err)?;
+    for row in rows {
+        index.accounts.push(row.map_err(db_err)?);
+    }
+
+    let meta = |key: &str| -> Result<Option<String>, String> {
+        conn.query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(db_err)
+    };
+    if let Some(version) = meta("version")? {
+        index.version = version;
+    }
+    index.current_account_id = meta("current_account_id")?;
+    Ok(index)
+}
+
+fn write_index(conn: &Connection, index: &AccountIndex) -> Result<(), String> {
+    conn.execute("DELETE FROM account_index", []).map_err(db_err)?;
+    let mut stmt = conn
+        .prepare(
+            "INSERT OR REPLACE INTO account_index (id, position, email, name, created_at, last_used)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .map_err(db_err)?;
+    for (position, s) in index.accounts.iter().enumerate() {
+        stmt.execute(params![s.id, position as i64, s.email, s.name, s.created_at, s.last_used])
+            .map_err(db_err)?;
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('version', ?1)",
+        params![index.version],
+    )
+    .map_err(db_err)?;
+    match &index.current_account_id {
+        Some(id) => conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('current_account_id', ?1)",
+            params![id],
+        ),
+        None => conn.execute("DELETE FROM meta WHERE key = 'current_account_id'", []),
+    }
+    .map_err(db_err)?;
+    Ok(())
+}
+
+fn read_account(conn: &Connection, account_id: &str) -> Result<Option<Account>, String> {
+    let data: Option<String> = conn
+        .query_row("SELECT data FROM accounts WHERE id = ?1", params![account_id], |row| row.get(0))
+        .optional()
+        .map_err(db_err)?;
+    data.map(|d| serde_json::from_str(&d).map_err(|e| format!("解析账号数据失败: {}", e)))
+        .transpose()
+}
+
+fn write_account(conn: &Connection, account: &Account) -> Result<(), String> {
+    let data = serde_json::to_string(account).map_err(|e| format!("序列化账号数据失败: {}", e))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO accounts (id, email, data, updated_at) VALUES (?1, ?2, ?3, ?4)",
+        params![account.id, account.email, data, chrono::Utc::now().timestamp()],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn sample_account(id: &str, email: &str) -> Account {
+        let token = TokenData::new("at".into(), "1//refresh".into(), 3600, Some(email.into()), None, None);
+        Account::new(id.to_string(), email.to_string(), token)
+    }
+
+    fn add(store: &dyn AccountStore, account: &Account) {
+        let account = account.clone();
+        store
+            .transact(|txn| {
+                txn.save(&account)?;
+                txn.index_mut().accounts.push(AccountSummary {
+                    id: account.id.clone(),
+                    email: account.email.clone(),
+                    name: None,
+                    created_at: account.created_at,
+                    last_used: account.last_used,
+                });
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_transaction_rollback_and_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteAccountStore::open(&dir.path().join(SQLITE_FILE)).unwrap();
+        let store: &dyn AccountStore = &store;
+
+        add(store, &sample_account("a", "a@example.com"));
+        add(store, &sample_account("b", "b@example.com"));
+
+        // 闭包出错: 账号与索引修改都不应生效
+        let result: Result<(), String> = store.transact(|txn| {
+            txn.save(&sample_account("c", "c@example.com"))?;
+            txn.index_mut().accounts.clear();
+            Err("boom".into())
+        });
+        assert!(result.is_err());
+        assert!(store.load("c").unwrap().is_none());
+        assert_eq!(store.load_index().unwrap().accounts.len(), 2);
+
+        store
+            .transact(|txn| {
+                let index = txn.index_mut();
+                index.accounts.reverse();
+                index.current_account_id = Some("a".into());
+                Ok(())
+            })
+            .unwrap();
+        let updated = store.update("a", |acc| {
+            acc.proxy_disabled = true;
+            Ok(())
+        });
+        assert!(updated.unwrap().proxy_disabled);
+
+        let listing = store.list().unwrap();
+        let emails: Vec<_> = listing.accounts.iter().map(|a| a.email.as_str()).collect();
+        assert_eq!(emails, ["b@example.com", "a@example.com"]);
+        assert!(listing.accounts[1].proxy_disabled);
+        assert_eq!(store.load_index().unwrap().current_account_id.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_migrate_file_layout_to_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = FileAccountStore::new(dir.path());
+        let files: &dyn AccountStore = &files;
+        add(files, &sample_account("a", "a@example.com"));
+        add(files, &sample_account("ghost", "ghost@example.com"));
+        add(files, &sample_account("broken", "broken@example.com"));
+        fs::write(dir.path().join(ACCOUNTS_DIR).join("broken.json"), "{not json").unwrap();
+        files
+            .transact(|txn| {
+                txn.remove("ghost")?;
+                txn.index_mut().current_account_id = Some("ghost".into());
+                Ok(())
+            })
+            .unwrap();
+
+        let report = migrate_to_sqlite(dir.path()).unwrap();
+        assert_eq!(report.migrated, 1);
+        assert_eq!(report.dropped, vec!["ghost".to_string()]);
+        assert_eq!(report.unreadable.len(), 1);
+        assert!(report.unreadable[0].starts_with("broken: "));
+        assert!(dir.path().join(ACCOUNTS_DIR).join("broken.json").exists());
+        assert!(!dir.path().join(ACCOUNTS_INDEX).exists());
+        assert!(dir.path().join(MIGRATED_INDEX).exists());
+
+        let store = for_dir(dir.path()).unwrap();
+        assert_eq!(store.backend(), StoreBackend::Sqlite);
+        let index = store.load_index().unwrap();
+        assert_eq!(index.current_account_id.as_deref(), Some("a"));
+        assert_eq!(store.load("a").unwrap().unwrap().email, "a@example.com");
+
+        // 只能迁移一次
+        assert!(migrate_to_sqlite(dir.path()).is_err());
+    }
+}
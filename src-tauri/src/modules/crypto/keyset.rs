@@ -0,0 +1,109 @@
+//! Key-ID tagged token encryption
+//!
+//! Token ciphertexts are stored as `k1:<key_id>:<base64>` so that the current and
+//! retired master keys can coexist while a rotation is rolled out. Untagged (legacy)
+//! ciphertexts are tried against every known key.
+
+use sha2::{Digest, Sha256};
+
+use super::cipher::{decrypt_string, encrypt_string};
+
+const TAG_PREFIX: &str = "k1:";
+
+/// Short, non-secret identifier of a key (first 4 bytes of a domain-separated SHA-256)
+pub fn key_id(key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"antigravity-key-id:");
+    hasher.update(key);
+    hasher.finalize()[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Extract the key ID from a tagged ciphertext (`None` for legacy ciphertexts)
+pub fn ciphertext_key_id(ciphertext: &str) -> Option<&str> {
+    ciphertext.strip_prefix(TAG_PREFIX)?.split_once(':').map(|(kid, _)| kid)
+}
+
+/// The current master key plus any retired keys still needed for decryption
+#[derive(Clone)]
+pub struct KeySet {
+    pub current: Vec<u8>,
+    pub previous: Vec<Vec<u8>>,
+}
+
+impl KeySet {
+    pub fn new(current: Vec<u8>, previous: Vec<Vec<u8>>) -> Self {
+        Self { current, previous }
+    }
+
+    pub fn current_id(&self) -> String {
+        key_id(&self.current)
+    }
+
+    /// Encrypt with the current key and tag the result with its key ID
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        Ok(format!(
+            "{}{}:{}",
+            TAG_PREFIX,
+            self.current_id(),
+            encrypt_string(plaintext, &self.current)?
+        ))
+    }
+
+    pub fn decrypt(&self, ciphertext: &str) -> Result<String, String> {
+        let keys = std::iter::once(&self.current).chain(self.previous.iter());
+
+        if let Some(rest) = ciphertext.strip_prefix(TAG_PREFIX) {
+            let (kid, body) = rest
+                .split_once(':')
+                .ok_or("Malformed tagged ciphertext")?;
+            let key = keys
+                .into_iter()
+                .find(|k| key_id(k) == kid)
+                .ok_or_else(|| format!("No master key available for key ID {}", kid))?;
+            return decrypt_string(body, key);
+        }
+
+        // Legacy ciphertext: GCM authentication rejects wrong keys, so try each in turn
+        let mut last_err = "No master key available".to_string();
+        for key in keys {
+            match decrypt_string(ciphertext, key) {
+                Ok(plaintext) => return Ok(plaintext),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Whether the ciphertext is already tagged with the current key
+    pub fn is_current(&self, ciphertext: &str) -> bool {
+        ciphertext_key_id(ciphertext) == Some(self.current_id().as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_ciphertext_with_retired_key() {
+        let old = KeySet::new(vec![1u8; 32], Vec::new());
+        let rotated = KeySet::new(vec![2u8; 32], vec![vec![1u8; 32]]);
+
+        let tagged = old.encrypt("1//refresh").unwrap();
+        assert_eq!(ciphertext_key_id(&tagged), Some(key_id(&[1u8; 32]).as_str()));
+        assert!(!rotated.is_current(&tagged));
+        assert_eq!(rotated.decrypt(&tagged).unwrap(), "1//refresh");
+
+        let reencrypted = rotated.encrypt("1//refresh").unwrap();
+        assert!(rotated.is_current(&reencrypted));
+        assert!(old.decrypt(&reencrypted).unwrap_err().contains("No master key"));
+    }
+
+    #[test]
+    fn test_legacy_ciphertext_falls_back_to_previous_keys() {
+        let legacy = encrypt_string("token", &[1u8; 32]).unwrap();
+        let rotated = KeySet::new(vec![2u8; 32], vec![vec![1u8; 32]]);
+        assert_eq!(ciphertext_key_id(&legacy), None);
+        assert_eq!(rotated.decrypt(&legacy).unwrap(), "token");
+    }
+}
@@ -6,25 +6,56 @@
 pub mod store;
 pub mod cipher;
 pub mod config;
+pub mod kdf;
+pub mod keyset;
+pub mod rotation;
 
-pub use store::{SecretStore, SystemKeyring, EnvKeyring};
+pub use store::{SecretStore, SystemKeyring, EnvKeyring, FileKeyring, PassphraseKeyring};
 pub use cipher::{encrypt_string, decrypt_string};
 pub use config::is_encryption_enabled;
+pub use keyset::KeySet;
 
 use once_cell::sync::Lazy;
 use std::sync::RwLock;
 
 /// Global secret store instance
-static SECRET_STORE: Lazy<RwLock<Box<dyn SecretStore>>> = Lazy::new(|| {
+static SECRET_STORE: Lazy<RwLock<Box<dyn SecretStore>>> = Lazy::new(|| RwLock::new(default_secret_store()));
+
+/// Keys loaded from the secret store, so token decryption does not hit the keyring every
+/// time. Invalidated whenever keys are written through this module; keys changed outside
+/// the process (env vars, key file) take effect on restart.
+static KEYSET_CACHE: Lazy<RwLock<Option<KeySet>>> = Lazy::new(|| RwLock::new(None));
+
+fn invalidate_keyset_cache() {
+    if let Ok(mut cache) = KEYSET_CACHE.write() {
+        *cache = None;
+    }
+}
+
+/// Pick the secret store from the environment:
+/// 1. ANTIGRAVITY_MASTER_KEY_FILE: key read from a mounted file (Docker secrets)
+/// 2. ANTIGRAVITY_MASTER_PASSPHRASE: key derived from a passphrase (PBKDF2)
+/// 3. Server mode: ANTIGRAVITY_MASTER_KEY, desktop mode: OS keyring
+fn default_secret_store() -> Box<dyn SecretStore> {
+    if let Ok(path) = std::env::var("ANTIGRAVITY_MASTER_KEY_FILE") {
+        return Box::new(FileKeyring::new(path));
+    }
+    if let Ok(passphrase) = std::env::var("ANTIGRAVITY_MASTER_PASSPHRASE") {
+        match crate::modules::account::get_data_dir() {
+            Ok(dir) => return Box::new(PassphraseKeyring::new(passphrase, dir.join("master_key.salt"))),
+            Err(e) => tracing::error!("Passphrase key store unavailable: {}", e),
+        }
+    }
+
     #[cfg(feature = "server")]
     {
-        RwLock::new(Box::new(EnvKeyring::new()))
+        Box::new(EnvKeyring::new())
     }
     #[cfg(not(feature = "server"))]
     {
-        RwLock::new(Box::new(SystemKeyring::new()))
+        Box::new(SystemKeyring::new())
     }
-});
+}
 
 /// Get the master key from the configured secret store
 pub fn get_master_key() -> Result<Vec<u8>, String> {
@@ -34,6 +65,53 @@ pub fn get_master_key() -> Result<Vec<u8>, String> {
     store.get_master_key()
 }
 
+/// Load the current master key together with the retired keys kept during a rotation
+pub fn load_keyset() -> Result<KeySet, String> {
+    if let Some(keys) = KEYSET_CACHE.read().ok().and_then(|cache| cache.clone()) {
+        return Ok(keys);
+    }
+
+    let store = SECRET_STORE.read()
+        .map_err(|e| format!("Failed to acquire read lock on secret store: {}", e))?;
+
+    let keys = KeySet::new(store.get_master_key()?, store.get_previous_keys()?);
+    if let Ok(mut cache) = KEYSET_CACHE.write() {
+        *cache = Some(keys.clone());
+    }
+    Ok(keys)
+}
+
+/// Encrypt a token with the current master key (tagged with its key ID)
+pub fn encrypt_token(plaintext: &str) -> Result<String, String> {
+    load_keyset()?.encrypt(plaintext)
+}
+
+/// Decrypt a token encrypted with the current or a retired master key
+pub fn decrypt_token(ciphertext: &str) -> Result<String, String> {
+    load_keyset()?.decrypt(ciphertext)
+}
+
+/// Install a new master key, keeping `previous` available for decryption
+pub fn store_rotated_keys(new_key: &[u8], previous: &[Vec<u8>]) -> Result<(), String> {
+    let store = SECRET_STORE.read()
+        .map_err(|e| format!("Failed to acquire read lock on secret store: {}", e))?;
+
+    // Retired keys first: a crash in between must never leave a key unrecoverable
+    let result = store.set_previous_keys(previous).and_then(|_| store.set_master_key(new_key));
+    invalidate_keyset_cache();
+    result
+}
+
+/// Forget retired keys once every ciphertext has been re-encrypted
+pub fn clear_previous_keys() -> Result<(), String> {
+    let store = SECRET_STORE.read()
+        .map_err(|e| format!("Failed to acquire read lock on secret store: {}", e))?;
+
+    let result = store.set_previous_keys(&[]);
+    invalidate_keyset_cache();
+    result
+}
+
 /// Initialize the secret store with a custom implementation
 /// This is useful for testing or when runtime feature selection is needed
 pub fn init_secret_store(store: Box<dyn SecretStore>) -> Result<(), String> {
@@ -41,5 +119,6 @@ pub fn init_secret_store(store: Box<dyn SecretStore>) -> Result<(), String> {
         .map_err(|e| format!("Failed to acquire write lock on secret store: {}", e))?;
 
     *global_store = store;
+    invalidate_keyset_cache();
     Ok(())
 }
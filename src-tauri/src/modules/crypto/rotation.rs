@@ -0,0 +1,282 @@
+//! Master key rotation and migration of at-rest token encryption
+//!
+//! Walks every stored account and re-encrypts (or decrypts) its tokens, then rewrites the
+//! secret-bearing app config fields with the resulting key:
+//! 1. The account store and the app config are copied to `backups/key-rotation-<timestamp>/` first.
+//! 2. When rotating, the new key is installed with the old one kept as a "previous"
+//!    key, so accounts written with either key stay readable if the process dies mid-way.
+//! 3. All accounts are rewritten in a single account store transaction.
+//! 4. Previous keys are dropped only after every account migrated successfully.
+//! 5. The backup is deleted once the migration succeeded. It holds tokens in their
+//!    pre-migration form (plain text before a first `Encrypt`, or under a key that is
+//!    about to be dropped), so it is only kept while a failed migration may need it.
+//!
+//! Re-running after a crash is safe: accounts already on the target key are skipped.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::KeySet;
+use crate::models::Account;
+use crate::modules::account;
+use crate::modules::account_store::{self, AccountStore};
+
+/// Report entry name used for the app config file
+const CONFIG_ENTRY: &str = "gui_config.json";
+
+/// Prevents concurrent migrations
+static MIGRATION_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationMode {
+    /// Generate a new master key (where the store supports it) and re-encrypt with it
+    Rotate,
+    /// Encrypt every account with the current master key
+    Encrypt,
+    /// Decrypt every account back to plain text (desktop only)
+    Decrypt,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    /// Pre-migration backup, kept only when some entry failed to migrate
+    pub backup_dir: Option<String>,
+    /// Key ID that tokens are now encrypted with (`None` after decrypting)
+    pub key_id: Option<String>,
+    /// Whether a new master key was generated and stored
+    pub key_generated: bool,
+    pub migrated: usize,
+    pub unchanged: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Per-key-ID breakdown of the stored accounts
+#[derive(Debug, Default, Serialize)]
+pub struct EncryptionStatus {
+    pub encryption_enabled: bool,
+    pub current_key_id: Option<String>,
+    pub plain: usize,
+    /// Encrypted without a key ID tag (written before key IDs were introduced)
+    pub legacy: usize,
+    pub by_key_id: std::collections::BTreeMap<String, usize>,
+    pub unreadable: usize,
+}
+
+/// Count stored accounts by encryption state
+pub fn encryption_status() -> Result<EncryptionStatus, String> {
+    let mut status = EncryptionStatus {
+        encryption_enabled: super::is_encryption_enabled(),
+        current_key_id: super::load_keyset().ok().map(|k| k.current_id()),
+        ..Default::default()
+    };
+
+    let store = account_store::current()?;
+    for id in account_ids(store.as_ref())? {
+        match read_account(store.as_ref(), &id) {
+            Ok(acc) if !acc.token.encrypted => status.plain += 1,
+            Ok(acc) => match super::keyset::ciphertext_key_id(&acc.token.refresh_token) {
+                Some(kid) => *status.by_key_id.entry(kid.to_string()).or_default() += 1,
+                None => status.legacy += 1,
+            },
+            Err(_) => status.unreadable += 1,
+        }
+    }
+    Ok(status)
+}
+
+/// Run a rotation/migration over every stored account
+pub fn migrate_accounts(mode: MigrationMode) -> Result<MigrationReport, String> {
+    let _guard = MIGRATION_LOCK
+        .try_lock()
+        .map_err(|_| "A key migration is already running".to_string())?;
+
+    if mode == MigrationMode::Decrypt && super::is_encryption_enabled() {
+        return Err("Token encryption is mandatory in this mode; refusing to decrypt".to_string());
+    }
+
+    let store = account_store::current()?;
+    let backup_dir = backup_accounts(store.as_ref())?;
+    let mut report = MigrationReport::default();
+
+    let mut keys = super::load_keyset()?;
+    if mode == MigrationMode::Rotate {
+        let new_key = generate_key();
+        let mut previous = vec![keys.current.clone()];
+        previous.extend(keys.previous.iter().cloned());
+
+        match super::store_rotated_keys(&new_key, &previous) {
+            Ok(()) => {
+                keys = KeySet::new(new_key, previous);
+                report.key_generated = true;
+            }
+            // Env-based stores: the operator rotates ANTIGRAVITY_MASTER_KEY and lists the
+            // old one in ANTIGRAVITY_PREVIOUS_MASTER_KEYS; we only re-encrypt.
+            Err(e) => crate::modules::logger::log_warn(&format!(
+                "Secret store cannot generate keys ({}), re-encrypting with the current key",
+                e
+            )),
+        }
+    }
+
+    let encrypt_plain = mode == MigrationMode::Encrypt || super::is_encryption_enabled();
+    store.transact(|txn| {
+        let ids: Vec<String> = txn.index().accounts.iter().map(|s| s.id.clone()).collect();
+        for id in ids {
+            let result = txn
+                .load(&id)
+                .and_then(|acc| acc.ok_or_else(|| format!("Account {} has no stored data", id)))
+                .and_then(|acc| match migrate_account(acc, &keys, mode, encrypt_plain)? {
+                    Some(updated) => txn.save(&updated).map(|_| true),
+                    None => Ok(false),
+                });
+            match result {
+                Ok(true) => report.migrated += 1,
+                Ok(false) => report.unchanged += 1,
+                Err(e) => report.failed.push((id, e)),
+            }
+        }
+        Ok(())
+    })?;
+
+    // Secret-bearing config fields follow the same keys
+    if let Err(e) = crate::modules::config::reseal_app_config() {
+        report.failed.push((CONFIG_ENTRY.to_string(), e));
+    }
+
+    if report.failed.is_empty() {
+        if let Err(e) = super::clear_previous_keys() {
+            tracing::debug!("Previous keys not cleared: {}", e);
+        }
+        if let Err(e) = fs::remove_dir_all(&backup_dir) {
+            crate::modules::logger::log_warn(&format!(
+                "Failed to remove key rotation backup {}: {}",
+                backup_dir.display(),
+                e
+            ));
+            report.backup_dir = Some(backup_dir.to_string_lossy().to_string());
+        }
+    } else {
+        report.backup_dir = Some(backup_dir.to_string_lossy().to_string());
+    }
+    report.key_id = (mode != MigrationMode::Decrypt).then(|| keys.current_id());
+
+    crate::modules::logger::log_info(&format!(
+        "Key migration ({:?}) finished: {} migrated, {} unchanged, {} failed, backup {}",
+        mode,
+        report.migrated,
+        report.unchanged,
+        report.failed.len(),
+        report.backup_dir.as_deref().unwrap_or("removed")
+    ));
+    Ok(report)
+}
+
+/// Re-encrypt one account; returns `None` when it is already in the target state
+fn migrate_account(
+    mut acc: Account,
+    keys: &KeySet,
+    mode: MigrationMode,
+    encrypt_plain: bool,
+) -> Result<Option<Account>, String> {
+    let token = &mut acc.token;
+    let target_encrypted = match mode {
+        MigrationMode::Decrypt => false,
+        MigrationMode::Encrypt => true,
+        MigrationMode::Rotate => token.encrypted || encrypt_plain,
+    };
+
+    if !token.encrypted && !target_encrypted {
+        return Ok(None);
+    }
+    if token.encrypted
+        && target_encrypted
+        && keys.is_current(&token.access_token)
+        && keys.is_current(&token.refresh_token)
+    {
+        return Ok(None);
+    }
+
+    let (access, refresh) = if token.encrypted {
+        (keys.decrypt(&token.access_token)?, keys.decrypt(&token.refresh_token)?)
+    } else {
+        (token.access_token.clone(), token.refresh_token.clone())
+    };
+
+    if target_encrypted {
+        token.access_token = keys.encrypt(&access)?;
+        token.refresh_token = keys.encrypt(&refresh)?;
+    } else {
+        token.access_token = access;
+        token.refresh_token = refresh;
+    }
+    token.encrypted = target_encrypted;
+    Ok(Some(acc))
+}
+
+fn generate_key() -> Vec<u8> {
+    use rand::RngCore;
+    let mut key = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
+
+fn account_ids(store: &dyn AccountStore) -> Result<Vec<String>, String> {
+    Ok(store.load_index()?.accounts.into_iter().map(|s| s.id).collect())
+}
+
+fn read_account(store: &dyn AccountStore, id: &str) -> Result<Account, String> {
+    store.load(id)?.ok_or_else(|| format!("Account {} has no stored data", id))
+}
+
+fn backup_accounts(store: &dyn AccountStore) -> Result<PathBuf, String> {
+    let data_dir = account::get_data_dir()?;
+    let backup_dir = data_dir
+        .join("backups")
+        .join(format!("key-rotation-{}", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    fs::create_dir_all(&backup_dir).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+    store.backup_to(&backup_dir)?;
+
+    let config_path = data_dir.join(CONFIG_ENTRY);
+    if config_path.exists() {
+        fs::copy(&config_path, backup_dir.join(CONFIG_ENTRY))
+            .map_err(|e| format!("Failed to back up {}: {}", CONFIG_ENTRY, e))?;
+    }
+    Ok(backup_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn plain_account() -> Account {
+        let token = TokenData::new("ya29.access".into(), "1//refresh".into(), 3600, None, None, None);
+        Account::new("id".into(), "a@example.com".into(), token)
+    }
+
+    #[test]
+    fn test_migrate_account_rotate_and_decrypt() {
+        let old = KeySet::new(vec![1u8; 32], Vec::new());
+        let new = KeySet::new(vec![2u8; 32], vec![vec![1u8; 32]]);
+
+        let encrypted = migrate_account(plain_account(), &old, MigrationMode::Encrypt, true).unwrap().unwrap();
+        assert!(encrypted.token.encrypted);
+        assert!(old.is_current(&encrypted.token.refresh_token));
+        // Already on the target key
+        assert!(migrate_account(encrypted.clone(), &old, MigrationMode::Encrypt, true).unwrap().is_none());
+
+        let rotated = migrate_account(encrypted, &new, MigrationMode::Rotate, false).unwrap().unwrap();
+        assert!(new.is_current(&rotated.token.access_token));
+
+        let decrypted = migrate_account(rotated, &new, MigrationMode::Decrypt, false).unwrap().unwrap();
+        assert!(!decrypted.token.encrypted);
+        assert_eq!(decrypted.token.refresh_token, "1//refresh");
+
+        // Plain accounts stay plain on rotation unless encryption is enabled
+        assert!(migrate_account(plain_account(), &new, MigrationMode::Rotate, false).unwrap().is_none());
+    }
+}
@@ -1,10 +1,15 @@
 /// Secret storage abstraction for master key management
 ///
-/// Provides two implementations:
+/// Provides four implementations:
 /// - SystemKeyring: Uses OS keyring (macOS Keychain, Windows Credential Manager, Linux Secret Service)
 /// - EnvKeyring: Uses ANTIGRAVITY_MASTER_KEY environment variable (for Docker/server deployments)
+/// - FileKeyring: Reads the key from a mounted file such as a Docker secret (ANTIGRAVITY_MASTER_KEY_FILE)
+/// - PassphraseKeyring: Derives the key from a passphrase with PBKDF2 and a salt kept in the data dir
 
+use base64::Engine;
 use sha2::{Sha256, Digest};
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
 /// Trait for secret storage backends
 pub trait SecretStore: Send + Sync {
@@ -22,6 +27,17 @@ pub trait SecretStore: Send + Sync {
     fn delete_master_key(&self) -> Result<(), String> {
         Err("Deleting master key is not supported for this store".to_string())
     }
+
+    /// Retired keys that must still decrypt existing ciphertexts during a key rotation
+    fn get_previous_keys(&self) -> Result<Vec<Vec<u8>>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Replace the list of retired keys (optional, mainly for desktop keyring)
+    fn set_previous_keys(&self, keys: &[Vec<u8>]) -> Result<(), String> {
+        let _ = keys;
+        Err("Storing previous keys is not supported for this store".to_string())
+    }
 }
 
 /// Environment variable-based secret store (for server/Docker mode)
@@ -61,8 +77,128 @@ impl SecretStore for EnvKeyring {
 
         Ok(Self::derive_key(&key_str))
     }
+
+    fn get_previous_keys(&self) -> Result<Vec<Vec<u8>>, String> {
+        Ok(previous_keys_from_env())
+    }
+}
+
+/// Reads ANTIGRAVITY_PREVIOUS_MASTER_KEYS (comma-separated passphrases) so tokens
+/// encrypted with a retired key stay readable until they are re-encrypted.
+fn previous_keys_from_env() -> Vec<Vec<u8>> {
+    std::env::var("ANTIGRAVITY_PREVIOUS_MASTER_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(EnvKeyring::derive_key)
+        .collect()
+}
+
+/// File-based secret store (for Docker/Kubernetes secrets)
+///
+/// Reads the key material from a file (e.g. `/run/secrets/antigravity_master_key`) on
+/// every access, so a remounted secret takes effect without a restart. The content is
+/// trimmed and derived to 32 bytes the same way as EnvKeyring, so moving a key between
+/// the two stores keeps existing ciphertexts readable.
+pub struct FileKeyring {
+    path: PathBuf,
+}
+
+impl FileKeyring {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SecretStore for FileKeyring {
+    fn get_master_key(&self) -> Result<Vec<u8>, String> {
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read master key file {:?}: {}", self.path, e))?;
+        let key_str = content.trim();
+
+        if key_str.len() < 16 {
+            return Err(format!(
+                "Master key in {:?} is too short. Please use at least 16 characters for security.",
+                self.path
+            ));
+        }
+
+        Ok(EnvKeyring::derive_key(key_str))
+    }
+
+    fn get_previous_keys(&self) -> Result<Vec<Vec<u8>>, String> {
+        Ok(previous_keys_from_env())
+    }
+}
+
+/// Passphrase-derived secret store
+///
+/// Derives the key with PBKDF2-HMAC-SHA256 from a passphrase and a random salt stored
+/// next to the data (created on first use). The derivation is slow by design, so the
+/// result is cached for the lifetime of the store.
+pub struct PassphraseKeyring {
+    passphrase: String,
+    salt_path: PathBuf,
+    iterations: u32,
+    key: OnceLock<Vec<u8>>,
+}
+
+impl PassphraseKeyring {
+    pub fn new(passphrase: String, salt_path: impl Into<PathBuf>) -> Self {
+        Self {
+            passphrase,
+            salt_path: salt_path.into(),
+            iterations: super::kdf::PBKDF2_DEFAULT_ITERATIONS,
+            key: OnceLock::new(),
+        }
+    }
+
+    /// Override the PBKDF2 iteration count (must stay the same for existing data)
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    fn load_or_create_salt(&self) -> Result<Vec<u8>, String> {
+        if self.salt_path.exists() {
+            let encoded = std::fs::read_to_string(&self.salt_path)
+                .map_err(|e| format!("Failed to read key salt: {}", e))?;
+            return base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| format!("Failed to decode key salt: {}", e));
+        }
+
+        let salt = super::kdf::generate_salt();
+        std::fs::write(&self.salt_path, base64::engine::general_purpose::STANDARD.encode(&salt))
+            .map_err(|e| format!("Failed to store key salt: {}", e))?;
+        Ok(salt)
+    }
+}
+
+impl SecretStore for PassphraseKeyring {
+    fn get_master_key(&self) -> Result<Vec<u8>, String> {
+        if let Some(key) = self.key.get() {
+            return Ok(key.clone());
+        }
+        if self.passphrase.chars().count() < 16 {
+            return Err("Master passphrase is too short. Please use at least 16 characters for security.".to_string());
+        }
+
+        let salt = self.load_or_create_salt()?;
+        let key = super::kdf::derive_key_pbkdf2(&self.passphrase, &salt, self.iterations)?.to_vec();
+        Ok(self.key.get_or_init(|| key).clone())
+    }
+
+    fn get_previous_keys(&self) -> Result<Vec<Vec<u8>>, String> {
+        Ok(previous_keys_from_env())
+    }
 }
 
+/// Keyring entry holding retired keys during a rotation
+#[cfg(feature = "desktop")]
+const PREVIOUS_KEYS_USERNAME: &str = "previous_master_keys";
+
 /// System keyring-based secret store (for desktop mode)
 ///
 /// Uses the `keyring` crate to store the master key in the OS keyring.
@@ -137,6 +273,37 @@ impl SecretStore for SystemKeyring {
         entry.delete_password()
             .map_err(|e| format!("Failed to delete master key from keyring: {}", e))
     }
+
+    fn get_previous_keys(&self) -> Result<Vec<Vec<u8>>, String> {
+        let entry = keyring::Entry::new(&self.service, PREVIOUS_KEYS_USERNAME)
+            .map_err(|e| format!("Failed to access system keyring: {}", e))?;
+
+        match entry.get_password() {
+            Ok(list) => list
+                .split(',')
+                .filter(|k| !k.is_empty())
+                .map(|k| base64::decode(k).map_err(|e| format!("Failed to decode stored key: {}", e)))
+                .collect(),
+            Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+            Err(e) => Err(format!("Failed to retrieve previous keys from keyring: {}", e)),
+        }
+    }
+
+    fn set_previous_keys(&self, keys: &[Vec<u8>]) -> Result<(), String> {
+        let entry = keyring::Entry::new(&self.service, PREVIOUS_KEYS_USERNAME)
+            .map_err(|e| format!("Failed to access system keyring: {}", e))?;
+
+        if keys.is_empty() {
+            return match entry.delete_password() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(format!("Failed to delete previous keys from keyring: {}", e)),
+            };
+        }
+
+        let encoded: Vec<String> = keys.iter().map(base64::encode).collect();
+        entry.set_password(&encoded.join(","))
+            .map_err(|e| format!("Failed to store previous keys in keyring: {}", e))
+    }
 }
 
 /// Stub implementation for server mode (SystemKeyring not available without keyring feature)
@@ -185,6 +352,35 @@ mod tests {
         assert_eq!(key.len(), 32); // SHA-256 produces 32 bytes
     }
 
+    #[test]
+    fn test_file_keyring_matches_env_derivation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("master_key");
+        std::fs::write(&path, "file-based-master-key-1234\n").unwrap();
+
+        let key = FileKeyring::new(&path).get_master_key().unwrap();
+        assert_eq!(key, EnvKeyring::derive_key("file-based-master-key-1234"));
+
+        std::fs::write(&path, "short").unwrap();
+        assert!(FileKeyring::new(&path).get_master_key().is_err());
+        assert!(FileKeyring::new(dir.path().join("missing")).get_master_key().is_err());
+    }
+
+    #[test]
+    fn test_passphrase_keyring_reuses_salt() {
+        let dir = tempfile::tempdir().unwrap();
+        let salt_path = dir.path().join("master_key.salt");
+
+        let store = |p: &str| PassphraseKeyring::new(p.into(), &salt_path).with_iterations(10);
+
+        let key1 = store("correct horse battery staple").get_master_key().unwrap();
+        assert!(salt_path.exists());
+        let key2 = store("correct horse battery staple").get_master_key().unwrap();
+        assert_ne!(key1, store("another long passphrase!").get_master_key().unwrap());
+        assert_eq!(key1, key2);
+        assert_eq!(key1.len(), 32);
+    }
+
     #[test]
     fn test_env_keyring_deterministic() {
         std::env::set_var("ANTIGRAVITY_MASTER_KEY", "deterministic-test-key");
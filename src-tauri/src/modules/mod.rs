@@ -1,4 +1,6 @@
 pub mod account;
+pub mod account_bundle;
+pub mod account_store;
 pub mod quota;
 pub mod config;
 pub mod logger;
@@ -7,6 +9,7 @@ pub mod process;
 pub mod oauth;
 #[cfg(feature = "desktop")]
 pub mod oauth_server;
+pub mod oauth_remote;
 pub mod migration;
 #[cfg(feature = "desktop")]
 pub mod tray;
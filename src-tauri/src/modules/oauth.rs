@@ -50,6 +50,11 @@ impl UserInfo {
 
 /// 生成 OAuth 授权 URL
 pub fn get_auth_url(redirect_uri: &str) -> String {
+    build_auth_url(redirect_uri, &[])
+}
+
+/// 生成带附加参数 (如 state) 的 OAuth 授权 URL
+pub fn build_auth_url(redirect_uri: &str, extra_params: &[(&str, &str)]) -> String {
     let scopes = vec![
         "https://www.googleapis.com/auth/cloud-platform",
         "https://www.googleapis.com/auth/userinfo.email",
@@ -58,7 +63,7 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
         "https://www.googleapis.com/auth/experimentsandconfigs"
     ].join(" ");
 
-    let params = vec![
+    let mut params = vec![
         ("client_id", CLIENT_ID),
         ("redirect_uri", redirect_uri),
         ("response_type", "code"),
@@ -67,6 +72,7 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
         ("prompt", "consent"),
         ("include_granted_scopes", "true"),
     ];
+    params.extend_from_slice(extra_params);
     
     let url = url::Url::parse_with_params(AUTH_URL, &params).expect("无效的 Auth URL");
     url.to_string()
@@ -74,15 +80,27 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
 
 /// 使用 Authorization Code 交换 Token
 pub async fn exchange_code(code: &str, redirect_uri: &str) -> Result<TokenResponse, String> {
+    exchange_code_with_verifier(code, redirect_uri, None).await
+}
+
+/// 使用 Authorization Code 交换 Token (PKCE 流程需附带 code_verifier)
+pub async fn exchange_code_with_verifier(
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: Option<&str>,
+) -> Result<TokenResponse, String> {
     let client = crate::utils::http::create_client(15);
     
-    let params = [
+    let mut params = vec![
         ("client_id", CLIENT_ID),
         ("client_secret", CLIENT_SECRET),
         ("code", code),
         ("redirect_uri", redirect_uri),
         ("grant_type", "authorization_code"),
     ];
+    if let Some(verifier) = code_verifier {
+        params.push(("code_verifier", verifier));
+    }
 
     let response = client
         .post(TOKEN_URL)
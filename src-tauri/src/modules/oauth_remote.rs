@@ -0,0 +1,342 @@
+// 远程 OAuth 授权流程 (Web Admin / 服务器部署)
+//
+// 桌面端的 oauth_server 会在本机 loopback 上监听回调，服务器远程部署时浏览器无法访问。
+// 提供两种模式，均使用 state 校验与 PKCE (S256)：
+// - paste: 回调地址为不被监听的 localhost 端口，用户授权后被重定向到失败页面，
+//   将地址栏中的完整 URL (或其中的 code) 粘贴回 Web Admin，由服务端完成 Token 交换
+// - callback: 以 Web Admin 的公网地址作为回调，由 /api/v1/oauth/callback 直接完成授权。
+//   该地址必须是 OAuth 客户端允许的重定向地址 (例如通过反向代理映射到 localhost)
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use base64::Engine;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::models::{Account, TokenData};
+use crate::modules::{self, oauth};
+
+/// 待完成授权的有效期 (秒)
+const FLOW_TTL_SECS: i64 = 600;
+
+/// 公网回调路径 (挂载在 Web Admin 的公共路由上)
+pub const CALLBACK_PATH: &str = "/api/v1/oauth/callback";
+
+/// 公网地址的环境变量 (优先于配置文件中的 web_admin_public_url)
+const PUBLIC_URL_ENV: &str = "ANTIGRAVITY_PUBLIC_URL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuthMode {
+    /// 用户手动粘贴回调 URL
+    #[default]
+    Paste,
+    /// 浏览器直接回调到 Web Admin 公网地址
+    Callback,
+}
+
+struct PendingFlow {
+    redirect_uri: String,
+    code_verifier: String,
+    created_at: i64,
+}
+
+/// 授权流程状态 (供 Web Admin 轮询 callback 模式的结果)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FlowStatus {
+    Pending,
+    Completed { account_id: String, email: String },
+    Failed { error: String },
+}
+
+#[derive(Default)]
+struct FlowRegistry {
+    pending: HashMap<String, PendingFlow>,
+    /// 已结束的流程结果 (state -> (结束时间, 状态))
+    finished: HashMap<String, (i64, FlowStatus)>,
+}
+
+impl FlowRegistry {
+    fn prune(&mut self, now: i64) {
+        self.pending.retain(|_, f| now - f.created_at < FLOW_TTL_SECS);
+        self.finished.retain(|_, (at, _)| now - *at < FLOW_TTL_SECS);
+    }
+}
+
+static FLOWS: OnceLock<Mutex<FlowRegistry>> = OnceLock::new();
+
+fn flows() -> &'static Mutex<FlowRegistry> {
+    FLOWS.get_or_init(|| Mutex::new(FlowRegistry::default()))
+}
+
+/// 授权流程启动结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteOAuthStart {
+    pub auth_url: String,
+    pub state: String,
+    pub redirect_uri: String,
+    pub mode: OAuthMode,
+    pub expires_at: i64,
+}
+
+/// 解析 Web Admin 的公网地址：环境变量优先，其次为配置文件
+pub fn resolve_public_url(configured: Option<&str>) -> Option<String> {
+    std::env::var(PUBLIC_URL_ENV)
+        .ok()
+        .as_deref()
+        .or(configured)
+        .map(|u| u.trim().trim_end_matches('/').to_string())
+        .filter(|u| !u.is_empty())
+}
+
+/// 生成 PKCE 的 (code_verifier, code_challenge)
+fn generate_pkce() -> (String, String) {
+    let verifier: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect();
+    let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    (verifier, challenge)
+}
+
+/// 启动授权流程：生成带 state 与 PKCE challenge 的授权链接
+///
+/// callback 模式需要传入 Web Admin 的公网地址。
+pub fn start_flow(mode: OAuthMode, public_url: Option<&str>) -> Result<RemoteOAuthStart, String> {
+    let redirect_uri = match mode {
+        OAuthMode::Paste => {
+            // Google 桌面客户端允许任意 loopback 端口，此端口不会被监听
+            let port = 20000 + (rand::random::<u16>() % 40000);
+            format!("http://localhost:{}/oauth-callback", port)
+        }
+        OAuthMode::Callback => {
+            let base = public_url.ok_or_else(|| {
+                format!("callback 模式需要配置 Web Admin 公网地址 (web_admin_public_url 或 {})", PUBLIC_URL_ENV)
+            })?;
+            format!("{}{}", base.trim_end_matches('/'), CALLBACK_PATH)
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let state = uuid::Uuid::new_v4().simple().to_string();
+    let (code_verifier, code_challenge) = generate_pkce();
+    let auth_url = oauth::build_auth_url(
+        &redirect_uri,
+        &[
+            ("state", &state),
+            ("code_challenge", &code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    );
+
+    let mut registry = flows().lock().unwrap();
+    registry.prune(now);
+    registry.pending.insert(
+        state.clone(),
+        PendingFlow {
+            redirect_uri: redirect_uri.clone(),
+            code_verifier,
+            created_at: now,
+        },
+    );
+
+    Ok(RemoteOAuthStart {
+        auth_url,
+        state,
+        redirect_uri,
+        mode,
+        expires_at: now + FLOW_TTL_SECS,
+    })
+}
+
+/// 查询授权流程状态 (未知或已过期的 state 返回 None)
+pub fn flow_status(state: &str) -> Option<FlowStatus> {
+    let mut registry = flows().lock().unwrap();
+    registry.prune(chrono::Utc::now().timestamp());
+    if registry.pending.contains_key(state) {
+        return Some(FlowStatus::Pending);
+    }
+    registry.finished.get(state).map(|(_, status)| status.clone())
+}
+
+fn record_result(state: &str, result: &Result<Account, String>) {
+    let status = match result {
+        Ok(account) => FlowStatus::Completed {
+            account_id: account.id.clone(),
+            email: account.email.clone(),
+        },
+        Err(e) => FlowStatus::Failed { error: e.clone() },
+    };
+    flows()
+        .lock()
+        .unwrap()
+        .finished
+        .insert(state.to_string(), (chrono::Utc::now().timestamp(), status));
+}
+
+/// 从粘贴内容中解析出的授权回调参数
+#[derive(Debug, PartialEq)]
+pub struct CallbackParams {
+    pub code: String,
+    pub state: Option<String>,
+}
+
+/// 解析用户粘贴的内容：完整回调 URL、查询串或单独的 code
+pub fn parse_callback_input(input: &str) -> Result<CallbackParams, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("授权回调内容为空".to_string());
+    }
+
+    let query = if let Ok(url) = url::Url::parse(input) {
+        url.query().unwrap_or_default().to_string()
+    } else if input.contains("code=") {
+        input.trim_start_matches('?').to_string()
+    } else {
+        return Ok(CallbackParams {
+            code: input.to_string(),
+            state: None,
+        });
+    };
+
+    let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    if let Some(error) = params.get("error") {
+        return Err(format!("授权被拒绝: {}", error));
+    }
+    let code = params
+        .get("code")
+        .filter(|c| !c.is_empty())
+        .ok_or("回调 URL 中缺少 code 参数")?;
+
+    Ok(CallbackParams {
+        code: code.clone(),
+        state: params.get("state").cloned(),
+    })
+}
+
+/// 完成授权流程：校验 state 并交换 Token
+///
+/// `state` 优先取回调 URL 中的值；仅粘贴 code 时需由调用方传入。
+pub async fn complete_flow(input: &str, state: Option<&str>) -> Result<oauth::TokenResponse, String> {
+    let params = parse_callback_input(input)?;
+    let state = params
+        .state
+        .as_deref()
+        .or(state)
+        .ok_or("缺少 state 参数，请粘贴完整的回调 URL")?;
+
+    exchange_for_state(&params.code, state).await
+}
+
+/// 完成授权流程并保存账号，结果记录到流程状态中供轮询
+pub async fn complete_and_save(code: &str, state: &str) -> Result<Account, String> {
+    let result = match exchange_for_state(code, state).await {
+        Ok(token_res) => save_authorized_account(token_res).await,
+        Err(e) => Err(e),
+    };
+    record_result(state, &result);
+    result
+}
+
+/// 记录授权被拒绝等回调错误
+pub fn fail_flow(state: &str, error: &str) {
+    let known = flows().lock().unwrap().pending.remove(state).is_some();
+    if known {
+        record_result(state, &Err(error.to_string()));
+    }
+}
+
+/// 取出 state 对应的待完成流程 (一次性)，并使用其 redirect_uri 与 code_verifier 交换 Token
+async fn exchange_for_state(code: &str, state: &str) -> Result<oauth::TokenResponse, String> {
+    let flow = flows()
+        .lock()
+        .unwrap()
+        .pending
+        .remove(state)
+        .ok_or("授权流程不存在或已使用，请重新生成授权链接")?;
+    if chrono::Utc::now().timestamp() - flow.created_at >= FLOW_TTL_SECS {
+        return Err("授权链接已过期，请重新生成".to_string());
+    }
+
+    oauth::exchange_code_with_verifier(code, &flow.redirect_uri, Some(&flow.code_verifier)).await
+}
+
+/// 使用授权得到的 Token 添加或更新账号
+pub async fn save_authorized_account(token_res: oauth::TokenResponse) -> Result<Account, String> {
+    let refresh_token = token_res.refresh_token.ok_or_else(|| {
+        "未获取到 Refresh Token。请访问 https://myaccount.google.com/permissions 撤销 \
+         'Antigravity Tools' 的访问权限后重新授权"
+            .to_string()
+    })?;
+
+    let user_info = oauth::get_user_info(&token_res.access_token).await?;
+    modules::logger::log_info(&format!("获取用户信息成功: {}", user_info.email));
+
+    let project_id = crate::proxy::project_resolver::fetch_project_id(&token_res.access_token)
+        .await
+        .ok();
+
+    let token_data = TokenData::new(
+        token_res.access_token,
+        refresh_token,
+        token_res.expires_in,
+        Some(user_info.email.clone()),
+        project_id,
+        None,
+    );
+
+    modules::upsert_account(user_info.email.clone(), user_info.get_display_name(), token_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_callback_input() {
+        let parsed = parse_callback_input("http://localhost:23456/oauth-callback?state=abc&code=4%2F0Ab&scope=email").unwrap();
+        assert_eq!(parsed.code, "4/0Ab");
+        assert_eq!(parsed.state.as_deref(), Some("abc"));
+
+        let parsed = parse_callback_input("  4/0Ab-raw  ").unwrap();
+        assert_eq!(parsed, CallbackParams { code: "4/0Ab-raw".into(), state: None });
+
+        assert!(parse_callback_input("http://localhost:1/oauth-callback?error=access_denied").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_complete_flow_rejects_unknown_state() {
+        let err = complete_flow("http://localhost:1/cb?code=x&state=unknown", None).await.unwrap_err();
+        assert!(err.contains("不存在"));
+        assert!(flow_status("unknown").is_none());
+    }
+
+    #[test]
+    fn test_start_flow_uses_pkce_and_public_callback() {
+        let start = start_flow(OAuthMode::Paste, None).unwrap();
+        assert!(start.auth_url.contains(&format!("state={}", start.state)));
+        assert!(start.auth_url.contains("code_challenge_method=S256"));
+        assert!(matches!(flow_status(&start.state), Some(FlowStatus::Pending)));
+
+        assert!(start_flow(OAuthMode::Callback, None).is_err());
+        let start = start_flow(OAuthMode::Callback, Some("https://admin.example.com/")).unwrap();
+        assert_eq!(start.redirect_uri, "https://admin.example.com/api/v1/oauth/callback");
+
+        fail_flow(&start.state, "access_denied");
+        assert!(matches!(flow_status(&start.state), Some(FlowStatus::Failed { .. })));
+    }
+
+    #[test]
+    fn test_pkce_challenge_matches_verifier() {
+        let (verifier, challenge) = generate_pkce();
+        assert_eq!(verifier.len(), 64);
+        let expected = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        assert_eq!(challenge, expected);
+    }
+}
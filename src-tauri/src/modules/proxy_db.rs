@@ -1,4 +1,5 @@
 use rusqlite::{params, Connection};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use crate::proxy::monitor::ProxyRequestLog;
 
@@ -32,6 +33,19 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN model_hops TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cache_hit INTEGER DEFAULT 0", []);
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS response_cache (
+            key TEXT PRIMARY KEY,
+            model TEXT,
+            body BLOB NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -46,8 +60,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, model_hops, cache_hit)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             log.id,
             log.timestamp,
@@ -63,6 +77,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.model_hops,
+            log.cache_hit,
         ],
     ).map_err(|e| e.to_string())?;
 
@@ -74,7 +90,7 @@ pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model
+        "SELECT id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, model_hops, cache_hit
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1"
@@ -96,6 +112,8 @@ pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            model_hops: row.get(14).unwrap_or(None),
+            cache_hit: row.get::<_, Option<bool>>(15).unwrap_or(None).unwrap_or(false),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -132,9 +150,90 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
         total_requests,
         success_count,
         error_count,
+        queue: Default::default(),
+        background_tasks: Default::default(),
     })
 }
 
+/// 读取未过期的缓存响应，返回 (响应体, 过期时间)
+pub fn get_cached_response(key: &str, now: i64) -> Result<Option<(Vec<u8>, i64)>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare("SELECT body, expires_at FROM response_cache WHERE key = ?1 AND expires_at > ?2")
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt.query(params![key, now]).map_err(|e| e.to_string())?;
+    match rows.next().map_err(|e| e.to_string())? {
+        Some(row) => Ok(Some((row.get(0).map_err(|e| e.to_string())?, row.get(1).map_err(|e| e.to_string())?))),
+        None => Ok(None),
+    }
+}
+
+/// 写入缓存响应，并清理过期与超出数量上限的条目
+pub fn save_cached_response(
+    key: &str,
+    model: &str,
+    body: &[u8],
+    created_at: i64,
+    expires_at: i64,
+    max_entries: usize,
+) -> Result<(), String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO response_cache (key, model, body, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![key, model, body, created_at, expires_at],
+    ).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM response_cache WHERE expires_at <= ?1", params![created_at])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM response_cache WHERE key NOT IN (SELECT key FROM response_cache ORDER BY created_at DESC LIMIT ?1)",
+        params![max_entries as i64],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// 累计 token 用量 (input + output)
+pub fn get_total_tokens() -> Result<u64, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let total: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(COALESCE(input_tokens, 0) + COALESCE(output_tokens, 0)), 0) FROM request_logs",
+        [],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+
+    Ok(total.max(0) as u64)
+}
+
+/// 统计指定时间点 (毫秒) 之后各账号针对某模型的请求数 (account_email -> count)
+pub fn get_account_request_counts(model: &str, since: i64) -> Result<HashMap<String, u64>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT account_email, COUNT(*) FROM request_logs
+         WHERE timestamp >= ?1 AND account_email IS NOT NULL
+           AND (mapped_model = ?2 OR (mapped_model IS NULL AND model = ?2))
+         GROUP BY account_email"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(params![since, model], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+    }).map_err(|e| e.to_string())?;
+
+    let mut counts = HashMap::new();
+    for row in rows {
+        let (email, count) = row.map_err(|e| e.to_string())?;
+        counts.insert(email, count);
+    }
+    Ok(counts)
+}
+
 pub fn clear_logs() -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
@@ -4,7 +4,13 @@
 /// to work in both desktop (Tauri) and server (standalone) modes.
 
 #[cfg(feature = "desktop")]
-use tauri::AppHandle;
+use tauri::{AppHandle, Manager};
+
+use std::sync::OnceLock;
+
+use crate::models::QuotaData;
+
+static CURRENT_CONTEXT: OnceLock<ServiceContext> = OnceLock::new();
 
 /// ServiceContext provides access to application services without direct Tauri dependency
 ///
@@ -49,6 +55,85 @@ impl ServiceContext {
     pub fn app_handle(&self) -> Option<&AppHandle> {
         self.app_handle.as_ref()
     }
+
+    /// Register this context as the process-wide one used by handlers
+    pub fn install(&self) {
+        let _ = CURRENT_CONTEXT.set(self.clone());
+    }
+
+    /// The context installed by the running Web Admin server, if any
+    pub fn current() -> Option<&'static ServiceContext> {
+        CURRENT_CONTEXT.get()
+    }
+
+    /// Propagate account changes (add/delete/enable/disable/reorder) to the running
+    /// proxy pool and the tray menu. No-op in server mode.
+    pub async fn accounts_changed(&self) {
+        #[cfg(feature = "desktop")]
+        if let Some(app) = &self.app_handle {
+            let _ = crate::commands::proxy::reload_proxy_accounts(
+                app.state::<crate::commands::proxy::ProxyServiceState>(),
+            )
+            .await;
+            crate::modules::tray::update_tray_menus(app);
+        }
+    }
+
+    /// Hot-reload the running proxy with the saved configuration. No-op in server mode.
+    pub async fn proxy_config_changed(&self) {
+        #[cfg(feature = "desktop")]
+        if let Some(app) = &self.app_handle {
+            match crate::modules::config::load_app_config() {
+                Ok(config) => {
+                    app.state::<crate::commands::proxy::ProxyServiceState>()
+                        .apply_config(&config.proxy)
+                        .await
+                }
+                Err(e) => tracing::warn!("Failed to reload proxy config: {}", e),
+            }
+        }
+    }
+
+    /// Push a refreshed quota for `account_id` into the running proxy pool, and refresh
+    /// the tray menu when it shows that account. No-op in server mode.
+    #[cfg_attr(not(feature = "desktop"), allow(unused_variables))]
+    pub async fn quota_updated(&self, account_id: &str, quota: &QuotaData) {
+        #[cfg(feature = "desktop")]
+        if let Some(app) = &self.app_handle {
+            let proxy_state = app.state::<crate::commands::proxy::ProxyServiceState>();
+            if let Some(instance) = proxy_state.instance.read().await.as_ref() {
+                instance.token_manager.update_token_quota(account_id, quota);
+            }
+            let is_current = crate::modules::account::get_current_account_id()
+                .ok()
+                .flatten()
+                .is_some_and(|id| id == account_id);
+            if is_current {
+                crate::modules::tray::update_tray_menus(app);
+            }
+        }
+    }
+}
+
+/// Notify the installed context that accounts changed
+pub async fn notify_accounts_changed() {
+    if let Some(ctx) = ServiceContext::current() {
+        ctx.accounts_changed().await;
+    }
+}
+
+/// Notify the installed context that the proxy configuration was replaced on disk
+pub async fn notify_proxy_config_changed() {
+    if let Some(ctx) = ServiceContext::current() {
+        ctx.proxy_config_changed().await;
+    }
+}
+
+/// Notify the installed context that an account's quota was refreshed
+pub async fn notify_quota_updated(account_id: &str, quota: &QuotaData) {
+    if let Some(ctx) = ServiceContext::current() {
+        ctx.quota_updated(account_id, quota).await;
+    }
 }
 
 // For backward compatibility with existing handlers that expect AppHandle in State
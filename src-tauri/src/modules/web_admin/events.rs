@@ -0,0 +1,26 @@
+//! Web Admin implementation of the proxy event sink.
+//!
+//! Events produced by the proxy and account modules (`crate::proxy::events`) are
+//! forwarded to `/api/v1/ws`. Every event belongs to a topic; clients pick topics via
+//! `?topics=a,b` on connect or with `{"action":"subscribe","topics":[...]}` messages,
+//! and the server only forwards matching events.
+use crate::modules::web_admin::websocket::{WebSocketEvent, WebSocketState};
+use crate::proxy::events::EventSink;
+
+/// Broadcasts proxy events to connected WebSocket clients
+pub struct WebSocketSink;
+
+impl EventSink for WebSocketSink {
+    fn has_subscribers(&self) -> bool {
+        WebSocketState::global().has_subscribers()
+    }
+
+    fn publish(&self, topic: &str, event_type: &str, data: serde_json::Value) {
+        WebSocketState::global().broadcast(WebSocketEvent::new(topic, event_type, data));
+    }
+}
+
+/// Register the WebSocket broadcaster as the process-wide event sink
+pub fn install() {
+    crate::proxy::events::set_sink(Box::new(WebSocketSink));
+}
@@ -1,11 +1,15 @@
 use axum::{
-    extract::Path,
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{Html, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::models::{Account, AccountSummary, TokenData};
-use crate::modules::account;
+use crate::modules::{account, account_bundle, migration, oauth_remote};
+use crate::modules::web_admin::context::{notify_accounts_changed, notify_proxy_config_changed, notify_quota_updated};
+use crate::proxy::events::{self, ProxyEvent};
 use crate::modules::web_admin::{Result, WebAdminError};
 
 #[derive(Debug, Serialize)]
@@ -46,11 +50,14 @@ pub async fn refresh_account(
     let mut account = account::load_account(&id)
         .map_err(|e| WebAdminError::ServerError(e))?;
 
-    // Use the fetch_quota_with_retry logic which handles token refresh and quota update
-    let _quota = account::fetch_quota_with_retry(&mut account).await
+    // Use the fetch_quota_with_retry logic which handles token refresh
+    let quota = account::fetch_quota_with_retry(&mut account).await
         .map_err(|e| WebAdminError::ServerError(e.to_string()))?;
+    account::update_account_quota(&id, quota.clone())
+        .map_err(WebAdminError::ServerError)?;
+    notify_quota_updated(&id, &quota).await;
 
-    // Reload account to get the latest state (saved by fetch_quota_with_retry)
+    // Reload account to get the latest state
     let updated_account = account::load_account(&id)
         .map_err(|e| WebAdminError::ServerError(e))?;
 
@@ -68,14 +75,10 @@ pub async fn update_account(
     Path(id): Path<String>,
     Json(payload): Json<UpdateAccountRequest>,
 ) -> Result<Json<Account>> {
-    let mut account = account::load_account(&id)
-        .map_err(|e| WebAdminError::ServerError(e))?;
-
     if let Some(name) = payload.name {
-        account.name = Some(name.clone());
-        // We need to use upsert to update both file and index
-        account::upsert_account(account.email.clone(), Some(name), account.token.clone())
-            .map_err(|e| WebAdminError::ServerError(e))?;
+        // Keep the stored account and its index summary in sync
+        account::rename_account(&id, Some(name))
+            .map_err(WebAdminError::ServerError)?;
     }
 
     let updated_account = account::load_account(&id)
@@ -126,7 +129,7 @@ pub async fn add_account(
     let account = account::upsert_account(email, payload.name, token)
         .map_err(|e| WebAdminError::ServerError(e))?;
 
-    Ok(Json(account))
+    Ok(Json(after_account_added(account).await))
 }
 
 /// DELETE /api/v1/accounts/:id
@@ -136,6 +139,351 @@ pub async fn delete_account(
 ) -> Result<Json<()>> {
     account::delete_account(&id)
         .map_err(|e| WebAdminError::ServerError(e))?;
+    notify_accounts_changed().await;
 
     Ok(Json(()))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct AccountIdsRequest {
+    pub account_ids: Vec<String>,
+}
+
+/// POST /api/v1/accounts/delete
+/// Delete several accounts at once
+pub async fn delete_accounts(
+    Json(payload): Json<AccountIdsRequest>,
+) -> Result<Json<()>> {
+    account::delete_accounts(&payload.account_ids)
+        .map_err(WebAdminError::ServerError)?;
+    notify_accounts_changed().await;
+
+    Ok(Json(()))
+}
+
+/// PUT /api/v1/accounts/order
+/// Reorder accounts; ids missing from the list keep their relative order at the end
+pub async fn reorder_accounts(
+    Json(payload): Json<AccountIdsRequest>,
+) -> Result<Json<()>> {
+    account::reorder_accounts(&payload.account_ids)
+        .map_err(WebAdminError::ServerError)?;
+    notify_accounts_changed().await;
+
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProxyToggleRequest {
+    pub enabled: bool,
+    pub reason: Option<String>,
+}
+
+/// POST /api/v1/accounts/:id/proxy
+/// Include or exclude an account from the proxy pool (`proxy_disabled`)
+pub async fn set_proxy_enabled(
+    Path(id): Path<String>,
+    Json(payload): Json<ProxyToggleRequest>,
+) -> Result<Json<Account>> {
+    let account = account::update_account(&id, |account| {
+        if payload.enabled {
+            account.proxy_disabled = false;
+            account.proxy_disabled_reason = None;
+            account.proxy_disabled_at = None;
+        } else {
+            account.proxy_disabled = true;
+            account.proxy_disabled_reason = Some(payload.reason.unwrap_or_else(|| "Disabled via Web Admin".to_string()));
+            account.proxy_disabled_at = Some(chrono::Utc::now().timestamp());
+        }
+        Ok(())
+    })
+    .map_err(WebAdminError::ServerError)?;
+
+    events::publish(ProxyEvent::AccountStateChanged {
+        account_id: account.id.clone(),
+        field: "proxy_disabled",
+        enabled: payload.enabled,
+        reason: account.proxy_disabled_reason.clone(),
+    });
+    notify_accounts_changed().await;
+
+    Ok(Json(account))
+}
+
+/// POST /api/v1/accounts/refresh
+/// Refresh quotas of all enabled accounts
+pub async fn refresh_all_accounts() -> Result<Json<account::RefreshStats>> {
+    let stats = account::refresh_all_quotas().await
+        .map_err(WebAdminError::ServerError)?;
+    for (account_id, quota) in &stats.updated {
+        notify_quota_updated(account_id, quota).await;
+    }
+
+    Ok(Json(stats))
+}
+
+/// GET /api/v1/accounts/current
+pub async fn get_current_account() -> Result<Json<Option<Account>>> {
+    let current = account::get_current_account()
+        .map_err(WebAdminError::ServerError)?;
+
+    Ok(Json(current))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwitchAccountRequest {
+    pub account_id: String,
+}
+
+/// PUT /api/v1/accounts/current
+/// Switch the current (IDE) account
+pub async fn switch_account(
+    Json(payload): Json<SwitchAccountRequest>,
+) -> Result<Json<Account>> {
+    account::switch_account(&payload.account_id).await
+        .map_err(WebAdminError::ServerError)?;
+    notify_accounts_changed().await;
+
+    let account = account::load_account(&payload.account_id)
+        .map_err(WebAdminError::ServerError)?;
+    Ok(Json(account))
+}
+
+/// POST /api/v1/accounts/import/v1
+/// Import accounts from the v1 data directory
+pub async fn import_v1_accounts() -> Result<Json<Vec<Account>>> {
+    let accounts = migration::import_from_v1().await
+        .map_err(WebAdminError::ServerError)?;
+
+    let mut imported = Vec::with_capacity(accounts.len());
+    for account in accounts {
+        imported.push(refresh_quota_quietly(account).await);
+    }
+    notify_accounts_changed().await;
+
+    Ok(Json(imported))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportDbRequest {
+    /// Custom path to the IDE state database; defaults to the standard location
+    pub path: Option<String>,
+}
+
+/// POST /api/v1/accounts/import/db
+/// Import the account currently logged into the IDE and make it current
+pub async fn import_from_db(
+    payload: Option<Json<ImportDbRequest>>,
+) -> Result<Json<Account>> {
+    let path = payload.and_then(|Json(p)| p.path);
+    let account = match path {
+        Some(path) => migration::import_from_custom_db_path(path).await,
+        None => migration::import_from_db().await,
+    }
+    .map_err(WebAdminError::ServerError)?;
+
+    account::set_current_account_id(&account.id)
+        .map_err(WebAdminError::ServerError)?;
+
+    Ok(Json(after_account_added(account).await))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StartOAuthRequest {
+    /// `paste` (default) or `callback`; the latter needs a public Web Admin URL
+    #[serde(default)]
+    pub mode: oauth_remote::OAuthMode,
+}
+
+/// POST /api/v1/accounts/oauth/start
+/// Begin a remote OAuth flow. In `paste` mode the admin opens `auth_url` and pastes back the
+/// final redirect URL; in `callback` mode Google redirects straight to `/api/v1/oauth/callback`.
+pub async fn start_oauth(
+    payload: Option<Json<StartOAuthRequest>>,
+) -> Result<Json<oauth_remote::RemoteOAuthStart>> {
+    let mode = payload.map(|Json(p)| p.mode).unwrap_or_default();
+    let configured = crate::modules::config::load_app_config()
+        .ok()
+        .and_then(|c| c.web_admin_public_url);
+    let public_url = oauth_remote::resolve_public_url(configured.as_deref());
+
+    oauth_remote::start_flow(mode, public_url.as_deref())
+        .map(Json)
+        .map_err(WebAdminError::BadRequest)
+}
+
+/// GET /api/v1/accounts/oauth/:state
+/// Poll the outcome of a `callback` mode flow
+pub async fn get_oauth_status(
+    Path(state): Path<String>,
+) -> Result<Json<oauth_remote::FlowStatus>> {
+    oauth_remote::flow_status(&state)
+        .map(Json)
+        .ok_or_else(|| WebAdminError::BadRequest("OAuth flow not found or expired".to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// GET /api/v1/oauth/callback (public)
+/// Redirect target for `callback` mode flows. The one-time `state` plus the PKCE verifier
+/// held server-side stand in for the admin session, so this route needs no token.
+pub async fn oauth_callback(Query(query): Query<OAuthCallbackQuery>) -> Html<String> {
+    let Some(state) = query.state else {
+        return callback_page(false, "Missing state parameter");
+    };
+    if let Some(error) = query.error {
+        oauth_remote::fail_flow(&state, &error);
+        return callback_page(false, &format!("Authorization denied: {}", error));
+    }
+    let Some(code) = query.code else {
+        oauth_remote::fail_flow(&state, "missing code");
+        return callback_page(false, "Missing code parameter");
+    };
+
+    match oauth_remote::complete_and_save(&code, &state).await {
+        Ok(account) => {
+            let email = account.email.clone();
+            after_account_added(account).await;
+            callback_page(true, &format!("Account {} added. You can close this window.", email))
+        }
+        Err(e) => {
+            tracing::warn!("Remote OAuth callback failed: {}", e);
+            callback_page(false, &e)
+        }
+    }
+}
+
+fn callback_page(success: bool, message: &str) -> Html<String> {
+    let (title, color) = if success {
+        ("Authorization successful", "green")
+    } else {
+        ("Authorization failed", "red")
+    };
+    let message = message.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    Html(format!(
+        "<html><body style='font-family: sans-serif; text-align: center; padding: 50px;'>\
+         <h1 style='color: {};'>{}</h1><p>{}</p></body></html>",
+        color, title, message
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteOAuthRequest {
+    /// Full redirect URL from the browser address bar, or just the `code` value
+    pub callback: String,
+    /// Required only when `callback` is a bare code
+    pub state: Option<String>,
+}
+
+/// POST /api/v1/accounts/oauth/complete
+pub async fn complete_oauth(
+    Json(payload): Json<CompleteOAuthRequest>,
+) -> Result<Json<Account>> {
+    let token_res = oauth_remote::complete_flow(&payload.callback, payload.state.as_deref()).await
+        .map_err(WebAdminError::BadRequest)?;
+    let account = oauth_remote::save_authorized_account(token_res).await
+        .map_err(WebAdminError::ServerError)?;
+
+    Ok(Json(after_account_added(account).await))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportBundleRequest {
+    pub passphrase: String,
+    #[serde(default)]
+    pub include_config: bool,
+}
+
+/// POST /api/v1/accounts/bundle/export
+/// Download all accounts (and optionally the app config) as a passphrase-encrypted bundle
+pub async fn export_bundle(
+    Json(payload): Json<ExportBundleRequest>,
+) -> Result<Response> {
+    let content = tokio::task::spawn_blocking(move || {
+        account_bundle::export_bundle(&payload.passphrase, payload.include_config)
+    })
+    .await
+    .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+    .map_err(WebAdminError::BadRequest)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"antigravity-accounts-{}.agbundle\"",
+                chrono::Local::now().format("%Y-%m-%d")
+            ),
+        )
+        .body(axum::body::Body::from(content))
+        .map_err(|e| WebAdminError::ServerError(e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BundleRequest {
+    /// Bundle file content
+    pub bundle: String,
+    pub passphrase: String,
+    #[serde(default)]
+    pub options: account_bundle::BundleImportOptions,
+}
+
+/// POST /api/v1/accounts/bundle/preview
+/// Decrypt a bundle and list its accounts without importing anything
+pub async fn preview_bundle(
+    Json(payload): Json<BundleRequest>,
+) -> Result<Json<account_bundle::BundlePreview>> {
+    tokio::task::spawn_blocking(move || account_bundle::preview_bundle(&payload.bundle, &payload.passphrase))
+        .await
+        .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+        .map(Json)
+        .map_err(WebAdminError::BadRequest)
+}
+
+/// POST /api/v1/accounts/bundle/import
+/// Import selected accounts from a bundle, resolving email conflicts per `options.conflict`
+pub async fn import_bundle(
+    Json(payload): Json<BundleRequest>,
+) -> Result<Json<account_bundle::BundleImportReport>> {
+    let report = tokio::task::spawn_blocking(move || {
+        account_bundle::import_bundle(&payload.bundle, &payload.passphrase, &payload.options)
+    })
+    .await
+    .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+    .map_err(WebAdminError::BadRequest)?;
+
+    if report.config_imported {
+        notify_proxy_config_changed().await;
+    }
+    notify_accounts_changed().await;
+    Ok(Json(report))
+}
+
+/// Refresh the quota of a newly added account and propagate it to the proxy pool
+async fn after_account_added(account: Account) -> Account {
+    let account = refresh_quota_quietly(account).await;
+    notify_accounts_changed().await;
+    account
+}
+
+/// Best-effort quota refresh; failures are logged and the account is returned unchanged
+async fn refresh_quota_quietly(mut account: Account) -> Account {
+    match account::fetch_quota_with_retry(&mut account).await {
+        Ok(quota) => {
+            if account::update_account_quota(&account.id, quota.clone()).is_ok() {
+                notify_quota_updated(&account.id, &quota).await;
+                account.quota = Some(quota);
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Quota refresh after adding {} failed: {}", account.email, e);
+        }
+    }
+    account
+}
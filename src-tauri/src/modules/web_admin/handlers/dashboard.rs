@@ -1,14 +1,28 @@
 use axum::{Extension, Json};
+#[cfg(feature = "desktop")]
+use axum::extract::State;
 use serde::Serialize;
+use std::collections::BTreeMap;
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, Manager};
 
-use crate::modules::web_admin::{auth::Claims, Result};
+use crate::models::Account;
+use crate::modules::{account, proxy_db};
+use crate::modules::web_admin::{auth::Claims, Result, WebAdminError};
+use crate::proxy::concurrency::model_family;
 
 #[derive(Debug, Serialize)]
 pub struct DashboardStats {
     /// Total number of accounts
     pub total_accounts: usize,
-    /// Number of active accounts
+    /// Number of active accounts (usable by the proxy pool)
     pub active_accounts: usize,
+    /// Accounts disabled due to auth failures (e.g. invalid_grant)
+    pub disabled_accounts: usize,
+    /// Accounts manually excluded from the proxy pool
+    pub proxy_disabled_accounts: usize,
+    /// Accounts whose quota check returned 403
+    pub forbidden_accounts: usize,
     /// Proxy service status
     pub proxy_status: String,
     /// Total requests handled
@@ -17,23 +31,146 @@ pub struct DashboardStats {
     pub total_tokens: u64,
     /// Current requests per minute
     pub requests_per_minute: f64,
+    /// Aggregate quota headroom per model family
+    pub quota_headroom: Vec<ModelFamilyHeadroom>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ModelFamilyHeadroom {
+    pub family: String,
+    /// Number of active accounts reporting quota for this family
+    pub accounts: usize,
+    /// Average remaining percentage across those accounts
+    pub avg_percentage: f64,
+    /// Lowest remaining percentage across those accounts
+    pub min_percentage: i32,
+    /// Remaining quota expressed in "full accounts" (sum of percentages / 100)
+    pub total_headroom: f64,
 }
 
 /// GET /api/v1/dashboard/stats
 /// Returns dashboard overview statistics
+#[cfg(feature = "desktop")]
 pub async fn get_stats(
+    State(app): State<AppHandle>,
     Extension(_claims): Extension<Claims>,
 ) -> Result<Json<DashboardStats>> {
-    // TODO: Implement actual stats collection from modules
-    // For now, return placeholder data
-    let stats = DashboardStats {
-        total_accounts: 0,
-        active_accounts: 0,
-        proxy_status: "stopped".to_string(),
-        total_requests: 0,
-        total_tokens: 0,
-        requests_per_minute: 0.0,
+    let state = app.state::<crate::commands::proxy::ProxyServiceState>();
+    let running = state.instance.read().await.is_some();
+    let requests_per_minute = match state.monitor.read().await.as_ref() {
+        Some(monitor) if running => monitor.requests_per_minute(),
+        _ => 0.0,
     };
 
-    Ok(Json(stats))
+    Ok(Json(collect_stats(running, requests_per_minute)?))
+}
+
+/// GET /api/v1/dashboard/stats
+/// Returns dashboard overview statistics
+#[cfg(not(feature = "desktop"))]
+pub async fn get_stats(
+    Extension(_claims): Extension<Claims>,
+) -> Result<Json<DashboardStats>> {
+    // Server mode does not run the proxy service yet
+    Ok(Json(collect_stats(false, 0.0)?))
+}
+
+fn collect_stats(proxy_running: bool, requests_per_minute: f64) -> Result<DashboardStats> {
+    let accounts = account::list_accounts().map_err(WebAdminError::ServerError)?;
+
+    let is_forbidden = |a: &Account| a.quota.as_ref().map(|q| q.is_forbidden).unwrap_or(false);
+    let active: Vec<&Account> = accounts
+        .iter()
+        .filter(|a| !a.disabled && !a.proxy_disabled && !is_forbidden(a))
+        .collect();
+
+    let total_requests = proxy_db::get_stats()
+        .map(|s| s.total_requests)
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to read proxy stats: {}", e);
+            0
+        });
+    let total_tokens = proxy_db::get_total_tokens().unwrap_or_else(|e| {
+        tracing::warn!("Failed to read token totals: {}", e);
+        0
+    });
+
+    Ok(DashboardStats {
+        total_accounts: accounts.len(),
+        active_accounts: active.len(),
+        disabled_accounts: accounts.iter().filter(|a| a.disabled).count(),
+        proxy_disabled_accounts: accounts.iter().filter(|a| a.proxy_disabled).count(),
+        forbidden_accounts: accounts.iter().filter(|a| is_forbidden(a)).count(),
+        proxy_status: if proxy_running { "running" } else { "stopped" }.to_string(),
+        total_requests,
+        total_tokens,
+        requests_per_minute,
+        quota_headroom: quota_headroom(&active),
+    })
+}
+
+/// Aggregates remaining quota per model family. An account with several models in the
+/// same family contributes its lowest percentage, since that model runs out first.
+fn quota_headroom(accounts: &[&Account]) -> Vec<ModelFamilyHeadroom> {
+    let mut per_family: BTreeMap<&'static str, Vec<i32>> = BTreeMap::new();
+
+    for account in accounts {
+        let Some(quota) = &account.quota else { continue };
+        let mut account_min: BTreeMap<&'static str, i32> = BTreeMap::new();
+        for model in &quota.models {
+            let entry = account_min.entry(model_family(&model.name)).or_insert(model.percentage);
+            *entry = (*entry).min(model.percentage);
+        }
+        for (family, percentage) in account_min {
+            per_family.entry(family).or_default().push(percentage.clamp(0, 100));
+        }
+    }
+
+    per_family
+        .into_iter()
+        .map(|(family, values)| {
+            let sum: i32 = values.iter().sum();
+            ModelFamilyHeadroom {
+                family: family.to_string(),
+                accounts: values.len(),
+                avg_percentage: sum as f64 / values.len() as f64,
+                min_percentage: values.iter().copied().min().unwrap_or(0),
+                total_headroom: sum as f64 / 100.0,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{QuotaData, TokenData};
+
+    fn account_with_quota(email: &str, models: &[(&str, i32)]) -> Account {
+        let token = TokenData::new("at".into(), "rt".into(), 3600, None, None, None);
+        let mut account = Account::new(email.to_string(), email.to_string(), token);
+        let mut quota = QuotaData::new();
+        for (name, pct) in models {
+            quota.add_model(name.to_string(), *pct, String::new());
+        }
+        account.quota = Some(quota);
+        account
+    }
+
+    #[test]
+    fn test_quota_headroom_per_family() {
+        let a = account_with_quota("a", &[("gemini-3-pro-high", 80), ("gemini-3-pro-low", 40), ("claude-sonnet-4-5", 100)]);
+        let b = account_with_quota("b", &[("gemini-3-pro-high", 20), ("gemini-2.5-flash", 50)]);
+        let headroom = quota_headroom(&[&a, &b]);
+
+        let pro = headroom.iter().find(|h| h.family == "gemini-pro").unwrap();
+        assert_eq!(pro.accounts, 2);
+        assert_eq!(pro.min_percentage, 20);
+        assert_eq!(pro.avg_percentage, 30.0);
+        assert_eq!(pro.total_headroom, 0.6);
+
+        let claude = headroom.iter().find(|h| h.family == "claude").unwrap();
+        assert_eq!(claude.accounts, 1);
+        assert_eq!(claude.total_headroom, 1.0);
+    }
 }
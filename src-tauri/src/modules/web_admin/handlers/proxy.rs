@@ -1,7 +1,7 @@
 #![cfg(feature = "desktop")]
 
 use axum::{
-    extract::State,
+    extract::{Query, State},
     http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
@@ -9,17 +9,20 @@ use axum::{
 use axum::extract::Multipart;
 use tauri::{AppHandle, Manager};
 
-use crate::commands::proxy::{self, ProxyServiceState, ProxyStatus};
+use crate::commands::proxy::{self, CircuitBreakerStatus, ProxyServiceState, ProxyStatus, SchedulingSimulation};
 use crate::modules::web_admin::{websocket::{WebSocketEvent, WebSocketState}, Result, WebAdminError};
+use crate::proxy::events::topics;
+use crate::proxy::account_health::AccountHealthSnapshot;
 use crate::proxy::ProxyConfig;
 
 // Helper to broadcast status updates via WebSocket
 fn broadcast_status(app: &AppHandle, status: &ProxyStatus) {
     if let Some(ws_state) = app.try_state::<WebSocketState>() {
-        let event = WebSocketEvent {
-            event_type: "proxy_status_update".to_string(),
-            data: serde_json::to_value(status).unwrap_or(serde_json::Value::Null),
-        };
+        let event = WebSocketEvent::new(
+            topics::PROXY,
+            "proxy_status_update",
+            serde_json::to_value(status).unwrap_or(serde_json::Value::Null),
+        );
         ws_state.broadcast(event);
     }
 }
@@ -138,6 +141,7 @@ pub async fn update_config(
         .map_err(|e| WebAdminError::ServerError(e))?;
 
     // Replace proxy config
+    config.validate().map_err(WebAdminError::ConfigError)?;
     app_config.proxy = config;
 
     // Save to file
@@ -173,6 +177,7 @@ pub async fn patch_config(
         .map_err(|e| WebAdminError::ServerError(e.to_string()))?;
 
     // Update app config
+    updated_config.validate().map_err(WebAdminError::ConfigError)?;
     app_config.proxy = updated_config;
 
     // Save to file
@@ -252,3 +257,84 @@ pub async fn import_config(
 
     Ok(Json(()))
 }
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SimulateQuery {
+    pub model: String,
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// GET /api/v1/proxy/scheduling/simulate?model=...&session_id=...
+/// Dry-run: shows which account would be picked for the given model
+pub async fn simulate_scheduling(
+    State(app): State<AppHandle>,
+    Query(query): Query<SimulateQuery>,
+) -> Result<Json<SchedulingSimulation>> {
+    let state = app.state::<ProxyServiceState>();
+    let instance_lock = state.instance.read().await;
+    let instance = instance_lock
+        .as_ref()
+        .ok_or_else(|| WebAdminError::BadRequest("Proxy service is not running".to_string()))?;
+
+    let simulation = proxy::simulate_scheduling(&instance.token_manager, &query.model, query.session_id.as_deref())
+        .await
+        .map_err(WebAdminError::ServerError)?;
+
+    Ok(Json(simulation))
+}
+
+/// GET /api/v1/proxy/circuits
+pub async fn get_circuit_breakers(
+    State(app): State<AppHandle>,
+) -> Result<Json<CircuitBreakerStatus>> {
+    let state = app.state::<ProxyServiceState>();
+    let monitor = state.monitor.read().await.clone();
+    let instance_lock = state.instance.read().await;
+    let instance = instance_lock
+        .as_ref()
+        .ok_or_else(|| WebAdminError::BadRequest("Proxy service is not running".to_string()))?;
+
+    Ok(Json(proxy::circuit_breaker_status(instance, monitor.as_deref())))
+}
+
+/// GET /api/v1/proxy/health
+/// Per-account health scores, lowest first, including quarantine details
+pub async fn get_account_health(
+    State(app): State<AppHandle>,
+) -> Result<Json<Vec<AccountHealthSnapshot>>> {
+    let state = app.state::<ProxyServiceState>();
+    let instance_lock = state.instance.read().await;
+    let instance = instance_lock
+        .as_ref()
+        .ok_or_else(|| WebAdminError::BadRequest("Proxy service is not running".to_string()))?;
+
+    Ok(Json(instance.token_manager.account_health().snapshot()))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RestoreHealthRequest {
+    pub email: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RestoreHealthResponse {
+    /// Whether the account was quarantined before the reset
+    pub was_quarantined: bool,
+}
+
+/// POST /api/v1/proxy/health/restore
+/// Lift an account's quarantine and clear its health history
+pub async fn restore_account_health(
+    State(app): State<AppHandle>,
+    Json(request): Json<RestoreHealthRequest>,
+) -> Result<Json<RestoreHealthResponse>> {
+    let state = app.state::<ProxyServiceState>();
+    let instance_lock = state.instance.read().await;
+    let instance = instance_lock
+        .as_ref()
+        .ok_or_else(|| WebAdminError::BadRequest("Proxy service is not running".to_string()))?;
+
+    let was_quarantined = instance.token_manager.account_health().restore(&request.email);
+    Ok(Json(RestoreHealthResponse { was_quarantined }))
+}
@@ -3,7 +3,10 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::time::SystemTime;
 
+use crate::modules::account_store;
+use crate::modules::crypto::rotation;
 use crate::modules::logger;
+use crate::modules::web_admin::context::notify_accounts_changed;
 use crate::modules::web_admin::{Result, WebAdminError};
 
 #[derive(Debug, Serialize)]
@@ -115,3 +118,58 @@ pub async fn get_logs(
 
     Ok(Json(LogContent { lines }))
 }
+
+/// GET /api/v1/system/encryption
+/// Breakdown of account token encryption by key ID
+pub async fn get_encryption_status() -> Result<Json<rotation::EncryptionStatus>> {
+    tokio::task::spawn_blocking(rotation::encryption_status)
+        .await
+        .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+        .map(Json)
+        .map_err(WebAdminError::ServerError)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyMigrationRequest {
+    pub mode: rotation::MigrationMode,
+}
+
+/// POST /api/v1/system/encryption/migrate
+/// Rotate the master key or switch at-rest token encryption on/off
+pub async fn migrate_encryption(
+    Json(payload): Json<KeyMigrationRequest>,
+) -> Result<Json<rotation::MigrationReport>> {
+    let report = tokio::task::spawn_blocking(move || rotation::migrate_accounts(payload.mode))
+        .await
+        .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+        .map_err(WebAdminError::BadRequest)?;
+
+    // Running token pools hold tokens read before the migration
+    notify_accounts_changed().await;
+    Ok(Json(report))
+}
+
+/// GET /api/v1/system/account-store
+/// Active account storage backend
+pub async fn get_account_store() -> Result<Json<account_store::AccountStoreStatus>> {
+    tokio::task::spawn_blocking(account_store::status)
+        .await
+        .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+        .map(Json)
+        .map_err(WebAdminError::ServerError)
+}
+
+/// POST /api/v1/system/account-store/migrate
+/// One-shot migration of the file-based account layout into SQLite
+pub async fn migrate_account_store() -> Result<Json<account_store::StoreMigrationReport>> {
+    let report = tokio::task::spawn_blocking(|| {
+        let data_dir = crate::modules::account::get_data_dir()?;
+        account_store::migrate_to_sqlite(&data_dir)
+    })
+    .await
+    .map_err(|e| WebAdminError::ServerError(e.to_string()))?
+    .map_err(WebAdminError::BadRequest)?;
+
+    notify_accounts_changed().await;
+    Ok(Json(report))
+}
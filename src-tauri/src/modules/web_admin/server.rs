@@ -14,7 +14,7 @@ use tower_http::cors::{CorsLayer, Any};
 use tracing::info;
 use rust_embed::Embed;
 
-use crate::modules::web_admin::{assets::Assets, handlers, middleware, websocket, Result, WebAdminError, context::ServiceContext};
+use crate::modules::web_admin::{assets::Assets, events, handlers, middleware, websocket, Result, WebAdminError, context::ServiceContext};
 use crate::modules::config::load_app_config;
 
 #[cfg(feature = "desktop")]
@@ -29,6 +29,7 @@ pub async fn start_server_standalone() -> Result<()> {
 
 async fn start_server_with_context(context: ServiceContext) -> Result<()> {
     let port = 8046;
+    context.install();
 
     // Load configuration to check for LAN access setting
     let config = load_app_config().unwrap_or_default();
@@ -43,7 +44,8 @@ async fn start_server_with_context(context: ServiceContext) -> Result<()> {
     info!("Starting Web Admin server on {}", addr);
 
     // Initialize WebSocket state
-    let ws_state = websocket::WebSocketState::new();
+    let ws_state = websocket::WebSocketState::global();
+    events::install();
 
     #[cfg(feature = "desktop")]
     if let Some(app) = &context.app_handle {
@@ -59,8 +61,25 @@ async fn start_server_with_context(context: ServiceContext) -> Result<()> {
             .route("/api/v1/accounts", get(handlers::account::list_accounts).post(handlers::account::add_account))
             .route("/api/v1/accounts/:id", get(handlers::account::get_account).patch(handlers::account::update_account).delete(handlers::account::delete_account))
             .route("/api/v1/accounts/:id/refresh", post(handlers::account::refresh_account))
+            .route("/api/v1/accounts/:id/proxy", post(handlers::account::set_proxy_enabled))
+            .route("/api/v1/accounts/delete", post(handlers::account::delete_accounts))
+            .route("/api/v1/accounts/order", put(handlers::account::reorder_accounts))
+            .route("/api/v1/accounts/refresh", post(handlers::account::refresh_all_accounts))
+            .route("/api/v1/accounts/current", get(handlers::account::get_current_account).put(handlers::account::switch_account))
+            .route("/api/v1/accounts/import/v1", post(handlers::account::import_v1_accounts))
+            .route("/api/v1/accounts/import/db", post(handlers::account::import_from_db))
+            .route("/api/v1/accounts/bundle/export", post(handlers::account::export_bundle))
+            .route("/api/v1/accounts/bundle/preview", post(handlers::account::preview_bundle))
+            .route("/api/v1/accounts/bundle/import", post(handlers::account::import_bundle))
+            .route("/api/v1/accounts/oauth/start", post(handlers::account::start_oauth))
+            .route("/api/v1/accounts/oauth/complete", post(handlers::account::complete_oauth))
+            .route("/api/v1/accounts/oauth/:state", get(handlers::account::get_oauth_status))
             .route("/api/v1/system/logs/files", get(handlers::system::list_log_files))
             .route("/api/v1/system/logs", get(handlers::system::get_logs))
+            .route("/api/v1/system/encryption", get(handlers::system::get_encryption_status))
+            .route("/api/v1/system/encryption/migrate", post(handlers::system::migrate_encryption))
+            .route("/api/v1/system/account-store", get(handlers::system::get_account_store))
+            .route("/api/v1/system/account-store/migrate", post(handlers::system::migrate_account_store))
             .route("/api/v1/proxy/status", get(handlers::proxy::get_status))
             .route("/api/v1/proxy/start", post(handlers::proxy::start_proxy))
             .route("/api/v1/proxy/stop", post(handlers::proxy::stop_proxy))
@@ -68,6 +87,10 @@ async fn start_server_with_context(context: ServiceContext) -> Result<()> {
             .route("/api/v1/proxy/config", get(handlers::proxy::get_config).put(handlers::proxy::update_config).patch(handlers::proxy::patch_config))
             .route("/api/v1/proxy/config/export", post(handlers::proxy::export_config))
             .route("/api/v1/proxy/config/import", post(handlers::proxy::import_config))
+            .route("/api/v1/proxy/scheduling/simulate", get(handlers::proxy::simulate_scheduling))
+            .route("/api/v1/proxy/circuits", get(handlers::proxy::get_circuit_breakers))
+            .route("/api/v1/proxy/health", get(handlers::proxy::get_account_health))
+            .route("/api/v1/proxy/health/restore", post(handlers::proxy::restore_account_health))
             .layer(axum_middleware::from_fn(middleware::auth_middleware))
             .with_state(context.app_handle.clone().unwrap())
     };
@@ -78,8 +101,25 @@ async fn start_server_with_context(context: ServiceContext) -> Result<()> {
         .route("/api/v1/accounts", get(handlers::account::list_accounts).post(handlers::account::add_account))
         .route("/api/v1/accounts/:id", get(handlers::account::get_account).patch(handlers::account::update_account).delete(handlers::account::delete_account))
         .route("/api/v1/accounts/:id/refresh", post(handlers::account::refresh_account))
+        .route("/api/v1/accounts/:id/proxy", post(handlers::account::set_proxy_enabled))
+        .route("/api/v1/accounts/delete", post(handlers::account::delete_accounts))
+        .route("/api/v1/accounts/order", put(handlers::account::reorder_accounts))
+        .route("/api/v1/accounts/refresh", post(handlers::account::refresh_all_accounts))
+        .route("/api/v1/accounts/current", get(handlers::account::get_current_account).put(handlers::account::switch_account))
+        .route("/api/v1/accounts/import/v1", post(handlers::account::import_v1_accounts))
+        .route("/api/v1/accounts/import/db", post(handlers::account::import_from_db))
+        .route("/api/v1/accounts/oauth/start", post(handlers::account::start_oauth))
+        .route("/api/v1/accounts/oauth/complete", post(handlers::account::complete_oauth))
+        .route("/api/v1/accounts/oauth/:state", get(handlers::account::get_oauth_status))
+        .route("/api/v1/accounts/bundle/export", post(handlers::account::export_bundle))
+        .route("/api/v1/accounts/bundle/preview", post(handlers::account::preview_bundle))
+        .route("/api/v1/accounts/bundle/import", post(handlers::account::import_bundle))
         .route("/api/v1/system/logs/files", get(handlers::system::list_log_files))
         .route("/api/v1/system/logs", get(handlers::system::get_logs))
+        .route("/api/v1/system/encryption", get(handlers::system::get_encryption_status))
+        .route("/api/v1/system/encryption/migrate", post(handlers::system::migrate_encryption))
+        .route("/api/v1/system/account-store", get(handlers::system::get_account_store))
+        .route("/api/v1/system/account-store/migrate", post(handlers::system::migrate_account_store))
         .layer(axum_middleware::from_fn(middleware::auth_middleware));
 
     // Build public routes (no auth required)
@@ -87,6 +127,7 @@ async fn start_server_with_context(context: ServiceContext) -> Result<()> {
         .route("/health", get(health_check))
         .route("/api/v1/auth/login", post(handlers::auth::login))
         .route("/api/v1/ws", get(websocket::ws_handler))
+        .route("/api/v1/oauth/callback", get(handlers::account::oauth_callback))
         .route("/", get(redirect_to_admin))
         .route("/admin", get(serve_admin_html))
         .route("/assets/*path", get(serve_asset))
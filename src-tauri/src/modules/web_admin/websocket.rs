@@ -7,39 +7,115 @@ use axum::{
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use serde::Deserialize;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock, RwLock};
 use tokio::sync::broadcast;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::modules::web_admin::{auth, WebAdminError};
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct WebSocketEvent {
+    /// Subscription topic (see `proxy::events::topics`)
+    pub topic: String,
     pub event_type: String,
     pub data: serde_json::Value,
 }
 
+impl WebSocketEvent {
+    pub fn new(topic: &str, event_type: &str, data: serde_json::Value) -> Self {
+        Self {
+            topic: topic.to_string(),
+            event_type: event_type.to_string(),
+            data,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct WebSocketState {
     pub broadcaster: Arc<broadcast::Sender<WebSocketEvent>>,
 }
 
+static GLOBAL_WS_STATE: OnceLock<WebSocketState> = OnceLock::new();
+
 impl WebSocketState {
     pub fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(100);
+        let (tx, _rx) = broadcast::channel(1024);
         Self {
             broadcaster: Arc::new(tx),
         }
     }
 
+    /// Process-wide broadcaster, so modules without an AppHandle (proxy, account storage)
+    /// can publish events to connected admins.
+    pub fn global() -> WebSocketState {
+        GLOBAL_WS_STATE.get_or_init(WebSocketState::new).clone()
+    }
+
     pub fn broadcast(&self, event: WebSocketEvent) {
         let _ = self.broadcaster.send(event);
     }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.broadcaster.receiver_count() > 0
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct WsQuery {
     token: String,
+    /// Comma-separated topic list; omitted means all topics
+    topics: Option<String>,
+}
+
+/// Client -> server control message: `{"action":"subscribe","topics":["requests"]}`
+#[derive(Debug, Deserialize)]
+struct ClientMessage {
+    action: String,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+/// Per-connection topic filter. `None` subscribes to every topic.
+#[derive(Debug, Default)]
+struct Subscription {
+    topics: Option<HashSet<String>>,
+}
+
+impl Subscription {
+    fn from_query(topics: Option<&str>) -> Self {
+        let topics = topics
+            .map(|t| {
+                t.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect::<HashSet<_>>()
+            })
+            .filter(|set| !set.is_empty());
+        Self { topics }
+    }
+
+    fn accepts(&self, topic: &str) -> bool {
+        self.topics.as_ref().is_none_or(|set| set.contains(topic))
+    }
+
+    fn apply(&mut self, msg: ClientMessage) {
+        match msg.action.as_str() {
+            "subscribe" => {
+                self.topics.get_or_insert_with(HashSet::new).extend(msg.topics);
+            }
+            "unsubscribe" => {
+                if let Some(set) = self.topics.as_mut() {
+                    for topic in &msg.topics {
+                        set.remove(topic);
+                    }
+                }
+            }
+            "subscribe_all" => self.topics = None,
+            other => warn!("Unknown WebSocket action: {}", other),
+        }
+    }
 }
 
 /// WebSocket handler with JWT authentication
@@ -53,20 +129,35 @@ pub async fn ws_handler(
 
     info!("WebSocket connection authenticated for user: {}", claims.sub);
 
-    Ok(ws.on_upgrade(move |socket| handle_socket(socket, ws_state, claims.sub)))
+    let subscription = Subscription::from_query(query.topics.as_deref());
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, ws_state, claims.sub, subscription)))
 }
 
-async fn handle_socket(socket: WebSocket, ws_state: WebSocketState, user_id: String) {
+async fn handle_socket(socket: WebSocket, ws_state: WebSocketState, user_id: String, subscription: Subscription) {
     info!("WebSocket connection established for user: {}", user_id);
 
     let (mut sender, mut receiver) = socket.split();
 
     // Subscribe to broadcasts
     let mut rx = ws_state.broadcaster.subscribe();
+    let subscription = Arc::new(RwLock::new(subscription));
+    let send_filter = subscription.clone();
 
     // Spawn a task to forward broadcast events to this client
     let mut send_task = tokio::spawn(async move {
-        while let Ok(event) = rx.recv().await {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client lagging, skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if !send_filter.read().unwrap().accepts(&event.topic) {
+                continue;
+            }
             match serde_json::to_string(&event) {
                 Ok(json) => {
                     if sender.send(Message::Text(json)).await.is_err() {
@@ -84,10 +175,10 @@ async fn handle_socket(socket: WebSocket, ws_state: WebSocketState, user_id: Str
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
             match msg {
-                Message::Text(text) => {
-                    info!("Received WebSocket message: {}", text);
-                    // Handle client messages if needed
-                }
+                Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(msg) => subscription.write().unwrap().apply(msg),
+                    Err(e) => warn!("Invalid WebSocket message: {}", e),
+                },
                 Message::Close(_) => {
                     info!("WebSocket close message received");
                     break;
@@ -116,3 +207,25 @@ async fn handle_socket(socket: WebSocket, ws_state: WebSocketState, user_id: Str
 
     info!("WebSocket connection closed for user: {}", user_id);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_filtering() {
+        let mut sub = Subscription::from_query(None);
+        assert!(sub.accepts("requests"));
+
+        let mut sub_q = Subscription::from_query(Some("requests, rate_limits"));
+        assert!(sub_q.accepts("rate_limits"));
+        assert!(!sub_q.accepts("config"));
+
+        sub_q.apply(ClientMessage { action: "unsubscribe".into(), topics: vec!["requests".into()] });
+        assert!(!sub_q.accepts("requests"));
+
+        sub.apply(ClientMessage { action: "subscribe".into(), topics: vec!["accounts".into()] });
+        assert!(sub.accepts("accounts"));
+        assert!(!sub.accepts("requests"));
+    }
+}
@@ -0,0 +1,517 @@
+// 账号健康度 (Account health)
+//
+// 按账号 email 统计最近一段时间内的请求结果，计算 0-100 的健康分:
+// 成功不扣分，403/invalid_grant/异常 400/空流按严重程度扣分 (5xx 属于上游故障，交由熔断器处理)。
+// 健康分低于阈值的账号自动隔离 (不参与调度)，由后台任务定期发送探测请求，
+// 连续探测成功后恢复。与熔断器不同，隔离只看账号自身问题，不受上游端点抖动影响。
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::AccountHealthConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+
+/// 一次请求的结果分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthOutcome {
+    Success,
+    /// 403 Forbidden (账号无权限/被封禁)
+    Forbidden,
+    /// 401 Unauthorized (token 失效)
+    Unauthorized,
+    /// refresh_token 被撤销
+    InvalidGrant,
+    /// 非签名类的异常 400
+    BadRequest,
+    /// 上游返回了空的流式响应
+    EmptyStream,
+}
+
+impl HealthOutcome {
+    /// 扣分权重 (0.0-1.0)
+    fn penalty(self) -> f64 {
+        match self {
+            HealthOutcome::Success => 0.0,
+            HealthOutcome::Forbidden | HealthOutcome::InvalidGrant => 1.0,
+            HealthOutcome::Unauthorized => 0.8,
+            HealthOutcome::EmptyStream => 0.6,
+            HealthOutcome::BadRequest => 0.5,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            HealthOutcome::Success => "success",
+            HealthOutcome::Forbidden => "403 forbidden",
+            HealthOutcome::Unauthorized => "401 unauthorized",
+            HealthOutcome::InvalidGrant => "invalid_grant",
+            HealthOutcome::BadRequest => "unexpected 400",
+            HealthOutcome::EmptyStream => "empty stream",
+        }
+    }
+
+    /// 根据上游错误状态码与错误内容分类 (429 等限流与 5xx 上游故障不计入健康度)
+    pub fn from_upstream_error(status: u16, error_text: &str) -> Option<Self> {
+        if error_text.contains("invalid_grant") {
+            return Some(HealthOutcome::InvalidGrant);
+        }
+        match status {
+            401 => Some(HealthOutcome::Unauthorized),
+            403 => Some(HealthOutcome::Forbidden),
+            // 签名失效、上下文超长等请求内容问题与账号无关
+            400 if !is_request_error(error_text) => Some(HealthOutcome::BadRequest),
+            _ => None,
+        }
+    }
+}
+
+/// 隔离信息
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineInfo {
+    pub reason: String,
+    /// 隔离开始时间 (毫秒)
+    pub since: i64,
+    /// 已完成的探测次数
+    pub probes: u32,
+    /// 连续探测成功次数
+    pub probe_successes: u32,
+    pub last_probe_error: Option<String>,
+    /// 距离下次探测的秒数
+    pub next_probe_secs: u64,
+}
+
+/// 账号健康状态快照 (用于管理接口展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountHealthSnapshot {
+    pub email: String,
+    /// 健康分 (0-100)，窗口内无样本时为 100
+    pub score: f64,
+    /// 窗口内请求数
+    pub samples: usize,
+    /// 窗口内各类失败次数
+    pub failures: HashMap<HealthOutcome, usize>,
+    pub quarantine: Option<QuarantineInfo>,
+    /// 累计隔离次数
+    pub quarantine_count: u64,
+}
+
+struct Quarantine {
+    reason: String,
+    since: i64,
+    next_probe: Instant,
+    probes: u32,
+    probe_successes: u32,
+    last_probe_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Health {
+    /// 滑动窗口: (时间, 结果)
+    window: VecDeque<(Instant, HealthOutcome)>,
+    quarantine: Option<Quarantine>,
+    quarantine_count: u64,
+}
+
+impl Health {
+    fn prune(&mut self, config: &AccountHealthConfig, now: Instant) {
+        let window = Duration::from_secs(config.window_seconds);
+        while let Some(&(t, _)) = self.window.front() {
+            if now.duration_since(t) > window || self.window.len() > config.window_size.max(1) {
+                self.window.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn score(&self) -> f64 {
+        if self.window.is_empty() {
+            return 100.0;
+        }
+        let penalty: f64 = self.window.iter().map(|(_, o)| o.penalty()).sum();
+        100.0 * (1.0 - penalty / self.window.len() as f64)
+    }
+
+    /// 窗口内最常见的失败类型
+    fn dominant_failure(&self) -> Option<HealthOutcome> {
+        let mut counts: HashMap<HealthOutcome, usize> = HashMap::new();
+        for (_, outcome) in &self.window {
+            if *outcome != HealthOutcome::Success {
+                *counts.entry(*outcome).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(a.0.penalty().total_cmp(&b.0.penalty())))
+            .map(|(o, _)| o)
+    }
+}
+
+/// 账号健康度注册表 (按账号 email)
+pub struct AccountHealthRegistry {
+    config: RwLock<AccountHealthConfig>,
+    accounts: DashMap<String, Health>,
+}
+
+impl AccountHealthRegistry {
+    pub fn new(config: AccountHealthConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            accounts: DashMap::new(),
+        }
+    }
+
+    pub fn update_config(&self, config: AccountHealthConfig) {
+        if !config.enabled {
+            self.accounts.clear();
+        }
+        *self.config.write().unwrap() = config;
+    }
+
+    /// 记录一次请求结果，健康分跌破阈值时隔离账号
+    pub fn record(&self, email: &str, outcome: HealthOutcome, detail: &str) {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return;
+        }
+        // 从未失败过的账号无需记录成功
+        if outcome == HealthOutcome::Success && !self.accounts.contains_key(email) {
+            return;
+        }
+        let now = Instant::now();
+
+        let mut health = self.accounts.entry(email.to_string()).or_default();
+        // 隔离期间由探测结果决定恢复，隔离前发出的请求结果忽略
+        if health.quarantine.is_some() {
+            return;
+        }
+        health.window.push_back((now, outcome));
+        health.prune(&config, now);
+
+        let score = health.score();
+        if health.window.len() < config.min_samples.max(1) || score >= config.quarantine_threshold {
+            return;
+        }
+
+        let cause = health.dominant_failure().unwrap_or(outcome);
+        let reason = format!(
+            "health score {:.0} < {:.0} over {} requests (mostly {}; last: {})",
+            score,
+            config.quarantine_threshold,
+            health.window.len(),
+            cause.label(),
+            truncate(detail, 200)
+        );
+        tracing::warn!("[AccountHealth] {} quarantined: {}", email, reason);
+        health.quarantine = Some(Quarantine {
+            reason,
+            since: chrono::Utc::now().timestamp_millis(),
+            next_probe: now + Duration::from_secs(config.probe_interval_seconds),
+            probes: 0,
+            probe_successes: 0,
+            last_probe_error: None,
+        });
+        health.quarantine_count += 1;
+    }
+
+    pub fn is_quarantined(&self, email: &str) -> bool {
+        if !self.config.read().unwrap().enabled {
+            return false;
+        }
+        self.accounts
+            .get(email)
+            .map(|h| h.quarantine.is_some())
+            .unwrap_or(false)
+    }
+
+    /// 取出到期需要探测的账号，并预约下一次探测时间
+    pub fn due_probes(&self) -> Vec<String> {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let interval = Duration::from_secs(config.probe_interval_seconds);
+
+        let mut due = Vec::new();
+        for mut entry in self.accounts.iter_mut() {
+            if let Some(q) = entry.quarantine.as_mut() {
+                if q.next_probe <= now {
+                    q.next_probe = now + interval;
+                    due.push(entry.key().clone());
+                }
+            }
+        }
+        due
+    }
+
+    /// 记录探测结果，连续成功达到要求后恢复账号
+    pub fn record_probe(&self, email: &str, result: Result<(), String>) {
+        let required = self.config.read().unwrap().restore_after_probes.max(1);
+        let Some(mut health) = self.accounts.get_mut(email) else {
+            return;
+        };
+        let Some(q) = health.quarantine.as_mut() else {
+            return;
+        };
+        q.probes += 1;
+        match result {
+            Ok(()) => {
+                q.probe_successes += 1;
+                if q.probe_successes >= required {
+                    tracing::info!(
+                        "[AccountHealth] {} restored after {} probe(s) (was: {})",
+                        email, q.probes, q.reason
+                    );
+                    health.quarantine = None;
+                    health.window.clear();
+                }
+            }
+            Err(e) => {
+                tracing::debug!("[AccountHealth] probe for {} failed: {}", email, e);
+                q.probe_successes = 0;
+                q.last_probe_error = Some(truncate(&e, 200));
+            }
+        }
+    }
+
+    /// 手动恢复账号 (清空统计)，返回账号此前是否处于隔离状态
+    pub fn restore(&self, email: &str) -> bool {
+        match self.accounts.remove(email) {
+            Some((_, health)) => {
+                let was_quarantined = health.quarantine.is_some();
+                if was_quarantined {
+                    tracing::info!("[AccountHealth] {} manually restored", email);
+                }
+                was_quarantined
+            }
+            None => false,
+        }
+    }
+
+    /// 当前健康分 (无记录时为 100)
+    pub fn score(&self, email: &str) -> f64 {
+        self.accounts.get(email).map(|h| h.score()).unwrap_or(100.0)
+    }
+
+    pub fn snapshot(&self) -> Vec<AccountHealthSnapshot> {
+        let config = self.config.read().unwrap().clone();
+        let now = Instant::now();
+
+        let mut out: Vec<AccountHealthSnapshot> = self
+            .accounts
+            .iter_mut()
+            .map(|mut entry| {
+                entry.prune(&config, now);
+                let h = entry.value();
+                let mut failures: HashMap<HealthOutcome, usize> = HashMap::new();
+                for (_, outcome) in &h.window {
+                    if *outcome != HealthOutcome::Success {
+                        *failures.entry(*outcome).or_default() += 1;
+                    }
+                }
+                AccountHealthSnapshot {
+                    email: entry.key().clone(),
+                    score: h.score(),
+                    samples: h.window.len(),
+                    failures,
+                    quarantine: h.quarantine.as_ref().map(|q| QuarantineInfo {
+                        reason: q.reason.clone(),
+                        since: q.since,
+                        probes: q.probes,
+                        probe_successes: q.probe_successes,
+                        last_probe_error: q.last_probe_error.clone(),
+                        next_probe_secs: q.next_probe.saturating_duration_since(now).as_secs(),
+                    }),
+                    quarantine_count: h.quarantine_count,
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.score.total_cmp(&b.score).then_with(|| a.email.cmp(&b.email)));
+        out
+    }
+}
+
+/// 由请求内容引起的 400 (签名失效由处理器重试修复)
+fn is_request_error(error_text: &str) -> bool {
+    const PATTERNS: [&str; 5] = [
+        "signature",
+        "thinking.",
+        "failed to deserialise",
+        "too long",
+        "exceeds the maximum",
+    ];
+    PATTERNS.iter().any(|p| error_text.contains(p))
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_string(),
+    }
+}
+
+/// 包装上游流式响应并记录该请求的结果: 返回过候选结果 (candidates) 记为成功，
+/// 在此之前结束或出错记为空流。客户端中途断开时流被直接 drop，不会记录。
+/// 经此包装的请求在 HTTP 200 时应调用 `mark_stream_opened` 而非 `mark_account_success`。
+pub fn watch_stream<S, E>(
+    stream: S,
+    token_manager: Arc<TokenManager>,
+    email: String,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    const MARKER: &[u8] = b"\"candidates\"";
+
+    async_stream::stream! {
+        let mut stream = Box::pin(stream);
+        let mut seen = false;
+        // 保留上一块的末尾，避免标记被拆分到两个 chunk 中
+        let mut tail: Vec<u8> = Vec::new();
+        while let Some(item) = stream.next().await {
+            if !seen {
+                if let Ok(bytes) = &item {
+                    tail.extend_from_slice(bytes);
+                    seen = tail.windows(MARKER.len()).any(|w| w == MARKER);
+                    let keep_from = tail.len().saturating_sub(MARKER.len());
+                    tail.drain(..keep_from);
+                }
+            }
+            yield item;
+        }
+        if !seen {
+            tracing::warn!("[AccountHealth] Empty upstream stream from {}", email);
+        }
+        token_manager.record_stream_outcome(&email, seen);
+    }
+}
+
+/// 探测任务检查间隔 (实际探测频率由 probe_interval_seconds 控制)
+const PROBE_TICK: Duration = Duration::from_secs(5);
+
+/// 后台探测任务，drop 时停止
+pub struct HealthProber {
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl HealthProber {
+    pub fn spawn(token_manager: Arc<TokenManager>, upstream: Arc<UpstreamClient>) -> Self {
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PROBE_TICK);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                for email in token_manager.account_health().due_probes() {
+                    let result = token_manager.probe_account(&email, &upstream).await;
+                    token_manager.account_health().record_probe(&email, result);
+                }
+            }
+        });
+        Self { handle }
+    }
+}
+
+impl Drop for HealthProber {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AccountHealthConfig {
+        AccountHealthConfig {
+            enabled: true,
+            window_seconds: 600,
+            window_size: 10,
+            min_samples: 4,
+            quarantine_threshold: 50.0,
+            probe_interval_seconds: 0,
+            restore_after_probes: 2,
+        }
+    }
+
+    #[test]
+    fn test_classify_upstream_errors() {
+        assert_eq!(HealthOutcome::from_upstream_error(403, ""), Some(HealthOutcome::Forbidden));
+        assert_eq!(
+            HealthOutcome::from_upstream_error(400, r#"{"error":"invalid_grant"}"#),
+            Some(HealthOutcome::InvalidGrant)
+        );
+        assert_eq!(HealthOutcome::from_upstream_error(400, "Invalid `signature` in thinking block"), None);
+        assert_eq!(HealthOutcome::from_upstream_error(429, "RESOURCE_EXHAUSTED"), None);
+        assert_eq!(HealthOutcome::from_upstream_error(503, ""), None);
+        assert_eq!(HealthOutcome::from_upstream_error(500, "Internal error"), None);
+    }
+
+    #[test]
+    fn test_quarantine_and_probe_restore() {
+        let registry = AccountHealthRegistry::new(config());
+        registry.record("a@test.com", HealthOutcome::Forbidden, "403");
+        registry.record("a@test.com", HealthOutcome::Success, "");
+        registry.record("a@test.com", HealthOutcome::Forbidden, "403");
+        // 样本不足，不隔离
+        assert!(!registry.is_quarantined("a@test.com"));
+        registry.record("a@test.com", HealthOutcome::Forbidden, "403");
+        assert!(registry.is_quarantined("a@test.com"));
+        assert!(!registry.is_quarantined("b@test.com"));
+
+        let snapshot = registry.snapshot();
+        let quarantine = snapshot[0].quarantine.as_ref().unwrap();
+        assert!(quarantine.reason.contains("403 forbidden"));
+        assert_eq!(snapshot[0].score, 25.0);
+
+        assert_eq!(registry.due_probes(), vec!["a@test.com".to_string()]);
+        registry.record_probe("a@test.com", Ok(()));
+        registry.record_probe("a@test.com", Err("403".to_string()));
+        registry.record_probe("a@test.com", Ok(()));
+        assert!(registry.is_quarantined("a@test.com"));
+        registry.record_probe("a@test.com", Ok(()));
+        assert!(!registry.is_quarantined("a@test.com"));
+        assert_eq!(registry.score("a@test.com"), 100.0);
+    }
+
+    #[test]
+    fn test_success_dilutes_failures() {
+        let registry = AccountHealthRegistry::new(config());
+        registry.record("a@test.com", HealthOutcome::BadRequest, "400");
+        for _ in 0..5 {
+            registry.record("a@test.com", HealthOutcome::Success, "");
+        }
+        registry.record("a@test.com", HealthOutcome::BadRequest, "400");
+        assert!(!registry.is_quarantined("a@test.com"));
+        assert!(registry.score("a@test.com") > 80.0);
+        // 成功请求不会为无失败记录的账号建档
+        registry.record("b@test.com", HealthOutcome::Success, "");
+        assert_eq!(registry.snapshot().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watch_stream_records_one_outcome_per_request() {
+        let token_manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        token_manager.update_account_health_config(config());
+        let registry = token_manager.account_health();
+        registry.record("a@test.com", HealthOutcome::BadRequest, "400");
+
+        let chunks = |items: Vec<&'static str>| {
+            futures::stream::iter(items.into_iter().map(|c| Ok::<_, std::io::Error>(Bytes::from(c))))
+        };
+        // 并发的两个请求: 一个正常返回，一个空流，结果互不覆盖
+        let ok = watch_stream(chunks(vec!["data: {\"cand", "idates\":[]}\n\n"]), token_manager.clone(), "a@test.com".to_string());
+        let empty = watch_stream(chunks(vec!["data: {}\n\n"]), token_manager.clone(), "a@test.com".to_string());
+        let _ = futures::join!(ok.collect::<Vec<_>>(), empty.collect::<Vec<_>>());
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot[0].samples, 3);
+        assert_eq!(snapshot[0].failures.get(&HealthOutcome::EmptyStream), Some(&1));
+    }
+}
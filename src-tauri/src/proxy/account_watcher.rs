@@ -0,0 +1,67 @@
+// 账号热重载 (Account watcher)
+//
+// 通过轮询实现，不使用文件系统事件: 每个周期读取账号存储的元数据指纹
+// (文件存储为 accounts/*.json 与索引文件，SQLite 存储为数据库与 -wal 文件的大小 + 修改时间)，
+// 发生变化时对账号池做增量同步。每次检查只有几次 stat 调用，且在所有平台与网络文件系统上行为一致。
+// 指纹与账号读取都在阻塞线程池中执行，不占用异步运行时。
+// 其他进程新增账号、GUI 切换 proxy_disabled 等写入最迟在一个检查周期 (interval_ms) 后生效，
+// 未变化账号的会话绑定与限流状态不受影响。
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::proxy::config::AccountWatchConfig;
+use crate::proxy::TokenManager;
+
+/// 最短检查间隔，避免配置过小时空转
+const MIN_INTERVAL_MS: u64 = 200;
+
+/// 后台监视任务，drop 时停止
+pub struct AccountWatcher {
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl AccountWatcher {
+    /// 启动监视任务 (配置关闭时返回 None)
+    pub fn spawn(token_manager: Arc<TokenManager>, config: &AccountWatchConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let interval = Duration::from_millis(config.interval_ms.max(MIN_INTERVAL_MS));
+
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            // 首次检查总是同步一次，覆盖启动加载与监视开始之间的写入
+            let mut last_fingerprint: Option<u64> = None;
+
+            loop {
+                ticker.tick().await;
+                let fingerprint = match token_manager.store_fingerprint().await {
+                    Ok(f) => f,
+                    Err(e) => {
+                        tracing::debug!("Account watcher: fingerprint failed: {}", e);
+                        continue;
+                    }
+                };
+                if last_fingerprint == Some(fingerprint) {
+                    continue;
+                }
+
+                match token_manager.sync_accounts().await {
+                    Ok(_) => last_fingerprint = Some(fingerprint),
+                    // 不记录指纹，下个周期重试
+                    Err(e) => tracing::warn!("Account watcher: sync failed: {}", e),
+                }
+            }
+        });
+
+        tracing::info!("Account watcher started (polling every {:?})", interval);
+        Some(Self { handle })
+    }
+}
+
+impl Drop for AccountWatcher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
@@ -0,0 +1,306 @@
+// 后台任务检测与降级路由
+//
+// 客户端的标题生成、摘要、提示建议等后台调用不需要高端模型。按配置的分类器识别这些请求，
+// 改用廉价模型 (链) 并可限定到专用账号组。Claude / OpenAI / Gemini 三种协议共用同一分类器。
+use serde_json::Value;
+
+use crate::proxy::common::model_router::cached_regex;
+use crate::proxy::config::{BackgroundClassifier, BackgroundTaskConfig, InboundProtocol};
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock, OpenAIRequest};
+
+/// 关键词/正则只匹配最后一条用户消息的前 500 个字符
+const PREVIEW_CHARS: usize = 500;
+
+/// 命中的后台任务
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundTask {
+    /// 分类器名称
+    pub name: String,
+    /// 目标模型链
+    pub chain: Vec<String>,
+    /// 专用账号组 (邮箱列表)
+    pub accounts: Option<Vec<String>>,
+}
+
+/// 按配置顺序匹配分类器，返回第一条命中的后台任务
+pub fn classify(
+    config: &BackgroundTaskConfig,
+    protocol: InboundProtocol,
+    last_user_text: Option<&str>,
+    has_tools: bool,
+) -> Option<BackgroundTask> {
+    if !config.enabled || !config.protocols.contains(&protocol) {
+        return None;
+    }
+    let text = last_user_text?;
+
+    let classifier = config.classifiers.iter().find(|c| classifier_matches(c, text, has_tools))?;
+    let chain = classifier.target.to_chain();
+    if chain.is_empty() {
+        tracing::warn!("[Background] Classifier '{}' has an empty target, ignoring", classifier.name);
+        return None;
+    }
+
+    let accounts = classifier.account_group.as_ref().and_then(|group| {
+        let accounts = config.account_groups.get(group);
+        if accounts.is_none() {
+            tracing::warn!("[Background] Unknown account group '{}' for classifier '{}'", group, classifier.name);
+        }
+        accounts.cloned()
+    });
+
+    Some(BackgroundTask {
+        name: classifier.name.clone(),
+        chain,
+        accounts,
+    })
+}
+
+fn classifier_matches(classifier: &BackgroundClassifier, text: &str, has_tools: bool) -> bool {
+    if !classifier.enabled {
+        return false;
+    }
+    if classifier.max_length.is_some_and(|max| text.len() > max) {
+        return false;
+    }
+    if classifier.has_tools.is_some_and(|required| required != has_tools) {
+        return false;
+    }
+
+    let preview: String = text.chars().take(PREVIEW_CHARS).collect();
+    if !classifier.required_keywords.iter().all(|kw| preview.contains(kw.as_str())) {
+        return false;
+    }
+    if classifier.keywords.is_empty() && classifier.patterns.is_empty() {
+        return true;
+    }
+
+    classifier.keywords.iter().any(|kw| preview.contains(kw.as_str()))
+        || classifier.patterns.iter().any(|pattern| match cached_regex(pattern) {
+            Ok(re) => re.is_match(&preview),
+            Err(e) => {
+                tracing::warn!("[Background] Skipping pattern of classifier '{}': {}", classifier.name, e);
+                false
+            }
+        })
+}
+
+/// 跳过空消息、Warmup 与 system-reminder 注入的消息
+fn detection_text(content: String) -> Option<String> {
+    if content.trim().is_empty() || content.starts_with("Warmup") || content.contains("<system-reminder>") {
+        None
+    } else {
+        Some(content)
+    }
+}
+
+/// Claude 请求中最后一条用户消息 (用于检测)
+pub fn last_user_text_claude(request: &ClaudeRequest) -> Option<String> {
+    request
+        .messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == "user")
+        .find_map(|m| {
+            let content = match &m.content {
+                MessageContent::String(s) => s.to_string(),
+                MessageContent::Array(arr) => arr
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            detection_text(content)
+        })
+}
+
+/// OpenAI 请求中最后一条用户消息 (用于检测)
+pub fn last_user_text_openai(request: &OpenAIRequest) -> Option<String> {
+    request
+        .messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == "user")
+        .find_map(|m| {
+            let content = match m.content.as_ref()? {
+                OpenAIContent::String(s) => s.to_string(),
+                OpenAIContent::Array(arr) => arr
+                    .iter()
+                    .filter_map(|block| match block {
+                        OpenAIContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            detection_text(content)
+        })
+}
+
+/// Gemini 请求体中最后一条用户消息 (用于检测)
+pub fn last_user_text_gemini(body: &Value) -> Option<String> {
+    body.get("contents")?
+        .as_array()?
+        .iter()
+        .rev()
+        .filter(|c| c.get("role").and_then(|r| r.as_str()).unwrap_or("user") == "user")
+        .find_map(|c| {
+            let content = c
+                .get("parts")?
+                .as_array()?
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            detection_text(content)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::ModelMappingTarget;
+    use serde_json::json;
+
+    fn classify_default(text: &str) -> Option<String> {
+        classify(&BackgroundTaskConfig::default(), InboundProtocol::Claude, Some(text), false).map(|t| t.name)
+    }
+
+    #[test]
+    fn builtin_classifiers_keep_previous_behavior() {
+        assert_eq!(classify_default("Please write a 5-10 word title for this").as_deref(), Some("title_generation"));
+        assert_eq!(
+            classify_default("Summarize the conversation in under 50 characters").as_deref(),
+            Some("simple_summary")
+        );
+        assert_eq!(classify_default("Summarize this coding conversation").as_deref(), Some("context_compression"));
+        assert_eq!(classify_default("Fix the bug in main.rs").as_deref(), None);
+        // 单独的长度要求不视为摘要任务
+        assert_eq!(classify_default("Explain ownership in under 50 characters").as_deref(), None);
+
+        let long = format!("Generate a title for {}", "x".repeat(800));
+        assert_eq!(classify_default(&long), None);
+
+        // 内置规则同样作用于 OpenAI / Gemini 协议
+        for protocol in [InboundProtocol::OpenAI, InboundProtocol::Gemini] {
+            let task = classify(&BackgroundTaskConfig::default(), protocol, Some("Summarize this coding conversation"), false);
+            assert_eq!(task.map(|t| t.name).as_deref(), Some("context_compression"));
+        }
+
+        let task = classify(
+            &BackgroundTaskConfig::default(),
+            InboundProtocol::Claude,
+            Some("Summarize this coding conversation"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(task.chain, vec!["gemini-2.5-flash".to_string()]);
+        assert!(task.accounts.is_none());
+    }
+
+    /// 原 Claude 处理器中的关键词表及其判定结果
+    #[test]
+    fn builtin_classifiers_match_legacy_keyword_tables() {
+        let legacy: &[(&str, &[&str])] = &[
+            ("system_message", &["Warmup", "<system-reminder>", "This is a system message"]),
+            (
+                "title_generation",
+                &[
+                    "write a 5-10 word title",
+                    "Please write a 5-10 word title",
+                    "Respond with the title",
+                    "Generate a title for",
+                    "Create a brief title",
+                    "title for the conversation",
+                    "conversation title",
+                    "生成标题",
+                    "为对话起个标题",
+                ],
+            ),
+            (
+                "context_compression",
+                &[
+                    "Summarize this coding conversation",
+                    "Summarize the conversation",
+                    "Concise summary",
+                    "compress the context",
+                    "Provide a concise summary",
+                    "condense the previous messages",
+                    "shorten the conversation history",
+                    "extract key points from",
+                ],
+            ),
+            (
+                "prompt_suggestion",
+                &[
+                    "prompt suggestion generator",
+                    "suggest next prompts",
+                    "what should I ask next",
+                    "generate follow-up questions",
+                    "recommend next steps",
+                    "possible next actions",
+                ],
+            ),
+            (
+                "environment_probe",
+                &["check current directory", "list available tools", "verify environment", "test connection"],
+            ),
+        ];
+
+        for (expected, keywords) in legacy {
+            for keyword in keywords.iter() {
+                let text = format!("Please handle this: {} now", keyword);
+                assert_eq!(classify_default(&text).as_deref(), Some(*expected), "{}", keyword);
+                if *expected == "context_compression" {
+                    let short = format!("{} in under 50 characters", keyword);
+                    assert_eq!(classify_default(&short).as_deref(), Some("simple_summary"), "{}", keyword);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn custom_classifier_with_regex_tools_and_account_group() {
+        let mut config = BackgroundTaskConfig {
+            protocols: vec![InboundProtocol::Gemini],
+            classifiers: vec![BackgroundClassifier {
+                name: "commit_message".to_string(),
+                enabled: true,
+                keywords: Vec::new(),
+                patterns: vec![r"(?i)^write a commit message".to_string()],
+                required_keywords: Vec::new(),
+                max_length: None,
+                has_tools: Some(false),
+                target: ModelMappingTarget::Chain(vec!["gemini-2.5-flash-lite".to_string(), "gemini-2.5-flash".to_string()]),
+                account_group: Some("cheap".to_string()),
+            }],
+            ..Default::default()
+        };
+        config.account_groups.insert("cheap".to_string(), vec!["free@example.com".to_string()]);
+
+        let task = classify(&config, InboundProtocol::Gemini, Some("Write a commit message for this diff"), false).unwrap();
+        assert_eq!(task.name, "commit_message");
+        assert_eq!(task.chain.len(), 2);
+        assert_eq!(task.accounts, Some(vec!["free@example.com".to_string()]));
+
+        // 工具条件不满足 / 协议未启用
+        assert!(classify(&config, InboundProtocol::Gemini, Some("Write a commit message"), true).is_none());
+        assert!(classify(&config, InboundProtocol::Claude, Some("Write a commit message"), false).is_none());
+    }
+
+    #[test]
+    fn extracts_last_user_text_for_gemini() {
+        let body = json!({
+            "contents": [
+                {"role": "user", "parts": [{"text": "Generate a title for"}, {"text": "this chat"}]},
+                {"role": "model", "parts": [{"text": "ok"}]},
+                {"role": "user", "parts": [{"text": "<system-reminder>ignored</system-reminder>"}]}
+            ]
+        });
+        assert_eq!(last_user_text_gemini(&body).as_deref(), Some("Generate a title for this chat"));
+    }
+}
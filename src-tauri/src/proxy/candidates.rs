@@ -0,0 +1,474 @@
+// 多候选结果 (OpenAI n > 1)
+//
+// OpenAI 的 n 映射为 Gemini candidateCount。经 v1internal 转发的部分模型 (如 Claude) 或账号不接受
+// candidateCount，此时把请求拆分为 n 个并行的单候选请求，再按请求序号改写 candidate 的 index 合并，
+// 下游的流式/非流式转换器按 index 输出各个 choice，无需感知拆分。
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::proxy::account_health::watch_stream;
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::concurrency::{guard_stream, ConcurrencySlot};
+use crate::proxy::config::MultiCandidateConfig;
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
+
+/// 上游 (v1internal) 原始字节流
+pub type GeminiStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// 学习到的拒绝记录的有效期，过期后重新尝试 candidateCount (上游可能已开始支持)
+const REJECTION_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// 运行中学习到的拒绝 candidateCount 的 `账号:模型` 组合及记录时间
+static REJECTED: Lazy<DashMap<String, Instant>> = Lazy::new(DashMap::new);
+
+fn rejection_key(model: &str, email: &str) -> String {
+    format!("{}:{}", email, model)
+}
+
+/// 请求体中的 candidateCount (未设置时为 1)
+pub fn candidate_count(body: &Value) -> u32 {
+    body.get("request")
+        .and_then(|r| r.get("generationConfig"))
+        .and_then(|g| g.get("candidateCount"))
+        .and_then(|c| c.as_u64())
+        .map(|c| c.max(1) as u32)
+        .unwrap_or(1)
+}
+
+/// 移除 candidateCount，改为单候选请求
+pub fn strip_candidate_count(body: &mut Value) {
+    if let Some(gen) = body
+        .get_mut("request")
+        .and_then(|r| r.get_mut("generationConfig"))
+        .and_then(|g| g.as_object_mut())
+    {
+        gen.remove("candidateCount");
+    }
+}
+
+/// 该模型/账号是否需要拆分为并行请求
+pub fn should_fan_out(config: &MultiCandidateConfig, model: &str, email: &str) -> bool {
+    config.fan_out_models.iter().any(|pattern| wildcard_match(pattern, model))
+        || is_rejected(&rejection_key(model, email))
+}
+
+fn is_rejected(key: &str) -> bool {
+    match REJECTED.get(key).map(|at| at.elapsed() < REJECTION_TTL) {
+        Some(true) => true,
+        Some(false) => {
+            REJECTED.remove(key);
+            false
+        }
+        None => false,
+    }
+}
+
+/// 上游是否因 candidateCount 拒绝了请求
+pub fn is_candidate_count_rejected(error_text: &str) -> bool {
+    let lower = error_text.to_lowercase();
+    lower.contains("candidatecount")
+        || lower.contains("candidate_count")
+        || lower.contains("multiple candidates")
+}
+
+/// 记录拒绝 candidateCount 的组合，后续请求直接拆分
+pub fn remember_rejection(model: &str, email: &str) {
+    tracing::warn!("[Candidates] {} rejected candidateCount on {}, falling back to parallel requests", model, email);
+    REJECTED.insert(rejection_key(model, email), Instant::now());
+}
+
+/// 同一账号上的并行单候选请求: 主请求之外再发送若干额外请求，每个额外请求各自占用并发槽位
+pub struct FanOut<'a> {
+    pub upstream: &'a Arc<UpstreamClient>,
+    pub token_manager: &'a Arc<TokenManager>,
+    pub email: &'a str,
+    pub model: &'a str,
+    pub access_token: &'a str,
+    pub method: &'a str,
+    pub query_string: Option<&'a str>,
+}
+
+/// 成功的额外请求 (槽位持有到响应读取完毕)
+struct Extra {
+    response: reqwest::Response,
+    slot: ConcurrencySlot,
+}
+
+enum ExtraError {
+    /// 上游返回了错误响应
+    Upstream(reqwest::Response),
+    /// 网络错误
+    Failed(String),
+}
+
+/// 任务句柄在 drop 时不会取消任务，提前返回时需显式中止尚未完成的额外请求
+struct AbortOnDrop(Vec<JoinHandle<Result<Extra, ExtraError>>>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// 并行请求的结果
+pub struct FanOutResponse {
+    /// 主请求的响应; 某个额外请求失败时为该请求的错误响应，调用方按普通上游错误处理
+    pub response: reqwest::Response,
+    extras: Vec<Extra>,
+}
+
+impl FanOut<'_> {
+    /// 发送主请求与 `extra` 个额外请求
+    ///
+    /// 先为所有额外请求占好槽位再发送; 主请求失败时取消额外请求; 任一额外请求失败时整个请求按失败处理，
+    /// 不向客户端返回缺少 choice 的结果。`model` 需与主请求占用槽位时使用的模型一致。
+    pub async fn send(&self, body: Value, extra: usize) -> Result<FanOutResponse, String> {
+        let mut slots = Vec::with_capacity(extra);
+        for _ in 0..extra {
+            slots.push(self.token_manager.acquire_slot(self.email, self.model).await?);
+        }
+
+        let mut tasks = AbortOnDrop(
+            slots
+                .into_iter()
+                .enumerate()
+                .map(|(i, slot)| {
+                    let mut body = body.clone();
+                    if let Some(id) = body.get("requestId").and_then(|v| v.as_str()).map(|s| format!("{}-{}", s, i + 1)) {
+                        body["requestId"] = Value::String(id);
+                    }
+                    let upstream = self.upstream.clone();
+                    let access_token = self.access_token.to_string();
+                    let method = self.method.to_string();
+                    let query_string = self.query_string.map(str::to_string);
+                    tokio::spawn(async move {
+                        let response = upstream
+                            .call_v1_internal(&method, &access_token, body, query_string.as_deref())
+                            .await
+                            .map_err(ExtraError::Failed)?;
+                        if response.status().is_success() {
+                            Ok(Extra { response, slot })
+                        } else {
+                            Err(ExtraError::Upstream(response))
+                        }
+                    })
+                })
+                .collect(),
+        );
+
+        let response = self
+            .upstream
+            .call_v1_internal(self.method, self.access_token, body, self.query_string)
+            .await?;
+        if !response.status().is_success() {
+            return Ok(FanOutResponse { response, extras: Vec::new() });
+        }
+
+        let mut extras = Vec::with_capacity(extra);
+        for task in tasks.0.iter_mut() {
+            match task.await {
+                Ok(Ok(extra)) => extras.push(extra),
+                Ok(Err(ExtraError::Upstream(failed))) => {
+                    tracing::warn!("[Candidates] Parallel candidate request failed: HTTP {}", failed.status());
+                    return Ok(FanOutResponse { response: failed, extras: Vec::new() });
+                }
+                Ok(Err(ExtraError::Failed(e))) => {
+                    return Err(format!("Parallel candidate request failed: {}", e));
+                }
+                Err(e) => return Err(format!("Parallel candidate request failed: {}", e)),
+            }
+        }
+        Ok(FanOutResponse { response, extras })
+    }
+}
+
+impl FanOutResponse {
+    /// 合并为单个上游字节流; 每路请求分别记录账号健康度，额外请求的槽位持有到各自的流结束
+    pub fn into_stream(self, token_manager: &Arc<TokenManager>, email: &str) -> GeminiStream {
+        let primary: GeminiStream = Box::pin(watch_stream(
+            self.response.bytes_stream(),
+            token_manager.clone(),
+            email.to_string(),
+        ));
+        if self.extras.is_empty() {
+            return primary;
+        }
+
+        let mut streams = vec![primary];
+        for extra in self.extras {
+            let stream = watch_stream(extra.response.bytes_stream(), token_manager.clone(), email.to_string());
+            streams.push(Box::pin(guard_stream(stream, extra.slot)));
+        }
+        merge_streams(streams)
+    }
+
+    /// 读取并合并非流式响应
+    pub async fn into_json(self) -> Result<Value, String> {
+        let primary = self.response.json::<Value>().await.map_err(|e| e.to_string())?;
+        if self.extras.is_empty() {
+            return Ok(primary);
+        }
+
+        let mut responses = vec![primary];
+        for extra in self.extras {
+            let value = extra
+                .response
+                .json::<Value>()
+                .await
+                .map_err(|e| format!("parallel candidate: {}", e))?;
+            responses.push(value);
+        }
+        Ok(merge_json(responses))
+    }
+}
+
+/// 将第 `index` 路请求的候选结果改写为对应的 candidate index
+fn reindex(chunk: &mut Value, index: usize) {
+    let raw = match chunk.get_mut("response") {
+        Some(inner) => inner,
+        None => chunk,
+    };
+    if let Some(candidates) = raw.get_mut("candidates").and_then(|c| c.as_array_mut()) {
+        for candidate in candidates {
+            candidate["index"] = Value::from(index);
+        }
+    }
+}
+
+/// 改写单路 SSE 流中每个事件的 candidate index
+fn reindex_stream(stream: GeminiStream, index: usize) -> GeminiStream {
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let mut buffer = BytesMut::new();
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(bytes) => {
+                    buffer.extend_from_slice(&bytes);
+                    let mut out = BytesMut::new();
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line = buffer.split_to(pos + 1);
+                        push_line(&mut out, &line, index);
+                    }
+                    if !out.is_empty() {
+                        yield Ok(out.freeze());
+                    }
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        }
+        if !buffer.is_empty() {
+            let mut out = BytesMut::new();
+            push_line(&mut out, &buffer, index);
+            if !out.is_empty() {
+                yield Ok(out.freeze());
+            }
+        }
+    })
+}
+
+fn push_line(out: &mut BytesMut, line: &[u8], index: usize) {
+    let Ok(text) = std::str::from_utf8(line) else {
+        return;
+    };
+    let Some(payload) = text.trim().strip_prefix("data:") else {
+        return;
+    };
+    let payload = payload.trim();
+    match serde_json::from_str::<Value>(payload) {
+        Ok(mut chunk) => {
+            reindex(&mut chunk, index);
+            out.extend_from_slice(format!("data: {}\n\n", chunk).as_bytes());
+        }
+        Err(_) => out.extend_from_slice(format!("data: {}\n\n", payload).as_bytes()),
+    }
+}
+
+/// 合并多路单候选 SSE 流: 第 i 路的候选结果以 index = i 输出，事件按到达顺序交错
+pub fn merge_streams(streams: Vec<GeminiStream>) -> GeminiStream {
+    let reindexed: Vec<GeminiStream> = streams
+        .into_iter()
+        .enumerate()
+        .map(|(i, stream)| reindex_stream(stream, i))
+        .collect();
+    Box::pin(futures::stream::select_all(reindexed))
+}
+
+/// 合并多路单候选的非流式响应 (候选结果依次编号，输出 token 数累加)
+pub fn merge_json(responses: Vec<Value>) -> Value {
+    let mut responses = responses.into_iter();
+    let Some(mut merged) = responses.next() else {
+        return Value::Null;
+    };
+    reindex(&mut merged, 0);
+
+    for (i, mut extra) in responses.enumerate() {
+        reindex(&mut extra, i + 1);
+        let extra = match extra.get_mut("response") {
+            Some(inner) => inner.take(),
+            None => extra,
+        };
+        let target = match merged.get_mut("response") {
+            Some(inner) => inner,
+            None => &mut merged,
+        };
+
+        if let Some(candidates) = extra.get("candidates").and_then(|c| c.as_array()) {
+            if !target.get("candidates").is_some_and(|c| c.is_array()) {
+                target["candidates"] = Value::Array(Vec::new());
+            }
+            if let Some(all) = target["candidates"].as_array_mut() {
+                all.extend(candidates.iter().cloned());
+            }
+        }
+
+        let extra_output = extra
+            .get("usageMetadata")
+            .and_then(|u| u.get("candidatesTokenCount"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0);
+        if let Some(usage) = target.get_mut("usageMetadata").and_then(|u| u.as_object_mut()) {
+            for field in ["candidatesTokenCount", "totalTokenCount"] {
+                let current = usage.get(field).and_then(|v| v.as_u64()).unwrap_or(0);
+                usage.insert(field.to_string(), Value::from(current + extra_output));
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sse(chunks: &[&str]) -> GeminiStream {
+        let items: Vec<Result<Bytes, reqwest::Error>> =
+            chunks.iter().map(|c| Ok(Bytes::from(c.to_string()))).collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    #[tokio::test]
+    async fn merged_streams_carry_per_request_index() {
+        let a = sse(&[
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"A\"}]}",
+            "}]}}\n\n",
+        ]);
+        let b = sse(&["data: {\"candidates\":[{\"index\":0,\"content\":{\"parts\":[{\"text\":\"B\"}]},\"finishReason\":\"STOP\"}]}"]);
+
+        let bytes: Vec<u8> = merge_streams(vec![a, b])
+            .map(|item| item.unwrap().to_vec())
+            .concat()
+            .await;
+        let events: Vec<Value> = String::from_utf8(bytes)
+            .unwrap()
+            .split("\n\n")
+            .filter_map(|e| e.strip_prefix("data: "))
+            .map(|e| serde_json::from_str(e).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        let index_of = |text: &str| {
+            events
+                .iter()
+                .map(|e| e.get("response").unwrap_or(e))
+                .find(|e| e["candidates"][0]["content"]["parts"][0]["text"] == text)
+                .map(|e| e["candidates"][0]["index"].as_u64().unwrap())
+        };
+        assert_eq!(index_of("A"), Some(0));
+        assert_eq!(index_of("B"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn openai_stream_emits_choice_per_merged_request() {
+        use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
+
+        let one = |text: &str| {
+            sse(&[&format!(
+                "data: {{\"response\":{{\"candidates\":[{{\"content\":{{\"parts\":[{{\"text\":\"{}\"}}]}},\"finishReason\":\"STOP\"}}]}}}}\n\n",
+                text
+            )])
+        };
+        let merged = merge_streams(vec![one("first"), one("second")]);
+        let bytes: Vec<u8> = create_openai_sse_stream(merged, "gpt-4o".to_string(), "session".to_string())
+            .map(|item| item.unwrap().to_vec())
+            .concat()
+            .await;
+
+        let mut content_by_index = std::collections::BTreeMap::new();
+        for event in String::from_utf8(bytes).unwrap().split("\n\n") {
+            let Some(chunk) = event.trim().strip_prefix("data: ").and_then(|e| serde_json::from_str::<Value>(e).ok()) else {
+                continue;
+            };
+            for choice in chunk["choices"].as_array().into_iter().flatten() {
+                if let Some(text) = choice["delta"]["content"].as_str() {
+                    content_by_index
+                        .entry(choice["index"].as_u64().unwrap())
+                        .or_insert_with(String::new)
+                        .push_str(text);
+                }
+            }
+        }
+        assert_eq!(content_by_index.get(&0).map(String::as_str), Some("first"));
+        assert_eq!(content_by_index.get(&1).map(String::as_str), Some("second"));
+    }
+
+    #[test]
+    fn expired_rejection_is_forgotten() {
+        let config = MultiCandidateConfig::default();
+        let key = rejection_key("gemini-test-expired", "a@example.com");
+        let Some(expired) = Instant::now().checked_sub(REJECTION_TTL + Duration::from_secs(1)) else {
+            return;
+        };
+        REJECTED.insert(key.clone(), expired);
+        assert!(!should_fan_out(&config, "gemini-test-expired", "a@example.com"));
+        assert!(!REJECTED.contains_key(&key));
+    }
+
+    #[test]
+    fn merge_json_appends_candidates_and_usage() {
+        let one = |text: &str| {
+            json!({
+                "response": {
+                    "candidates": [{"content": {"parts": [{"text": text}]}, "finishReason": "STOP"}],
+                    "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 3, "totalTokenCount": 13}
+                }
+            })
+        };
+        let merged = merge_json(vec![one("a"), one("b"), one("c")]);
+        let candidates = merged["response"]["candidates"].as_array().unwrap();
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[2]["index"], 2);
+        assert_eq!(candidates[2]["content"]["parts"][0]["text"], "c");
+        assert_eq!(merged["response"]["usageMetadata"]["candidatesTokenCount"], 9);
+        assert_eq!(merged["response"]["usageMetadata"]["totalTokenCount"], 19);
+    }
+
+    #[test]
+    fn fan_out_by_pattern_or_learned_rejection() {
+        let config = MultiCandidateConfig::default();
+        assert!(should_fan_out(&config, "claude-sonnet-4-5", "a@example.com"));
+        assert!(!should_fan_out(&config, "gemini-test-fanout", "a@example.com"));
+
+        assert!(is_candidate_count_rejected("Invalid value for candidateCount: must be 1"));
+        remember_rejection("gemini-test-fanout", "a@example.com");
+        assert!(should_fan_out(&config, "gemini-test-fanout", "a@example.com"));
+        assert!(!should_fan_out(&config, "gemini-test-fanout", "b@example.com"));
+
+        let mut body = json!({"request": {"generationConfig": {"candidateCount": 3}}});
+        assert_eq!(candidate_count(&body), 3);
+        strip_candidate_count(&mut body);
+        assert_eq!(candidate_count(&body), 1);
+    }
+}
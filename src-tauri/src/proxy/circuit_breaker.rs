@@ -0,0 +1,588 @@
+// 熔断器 (Circuit breaker)
+//
+// 为每个上游端点 (v1internal base URL) 与每个账号维护独立的熔断状态:
+// Closed (正常) → Open (熔断，直接跳过) → HalfOpen (放行少量探测请求) → Closed/Open。
+// 失败率基于滑动时间窗口统计，连续失败次数过多也会直接熔断。
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::CircuitBreakerConfig;
+
+/// 熔断状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// 熔断状态变更事件
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitTransition {
+    /// 熔断器作用域 ("endpoint" | "account")
+    pub scope: String,
+    /// 端点 URL 或账号 email
+    pub key: String,
+    pub from: CircuitState,
+    pub to: CircuitState,
+    pub reason: String,
+    /// 发生时间 (毫秒)
+    pub timestamp: i64,
+}
+
+/// 熔断器状态快照 (用于监控展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitSnapshot {
+    pub scope: String,
+    pub key: String,
+    pub state: CircuitState,
+    /// 窗口内请求数
+    pub window_requests: usize,
+    /// 窗口内失败率 (0.0-1.0)
+    pub failure_rate: f64,
+    pub consecutive_failures: u32,
+    /// 距离进入半开状态的剩余秒数 (仅 Open 状态)
+    pub open_remaining_secs: u64,
+    /// 累计熔断次数
+    pub open_count: u64,
+}
+
+/// 状态变更监听器 (由 ProxyMonitor 提供)
+pub type TransitionListener = Arc<dyn Fn(&CircuitTransition) + Send + Sync>;
+
+struct Breaker {
+    state: CircuitState,
+    /// 滑动窗口: (时间, 是否成功)
+    window: VecDeque<(Instant, bool)>,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probes_in_flight: u32,
+    probe_started_at: Option<Instant>,
+    open_count: u64,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            window: VecDeque::new(),
+            consecutive_failures: 0,
+            opened_at: None,
+            probes_in_flight: 0,
+            probe_started_at: None,
+            open_count: 0,
+        }
+    }
+
+    fn prune(&mut self, window: Duration, now: Instant) {
+        while let Some(&(t, _)) = self.window.front() {
+            if now.duration_since(t) > window {
+                self.window.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn failure_rate(&self) -> f64 {
+        if self.window.is_empty() {
+            return 0.0;
+        }
+        let failures = self.window.iter().filter(|(_, ok)| !ok).count();
+        failures as f64 / self.window.len() as f64
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(now);
+        self.probes_in_flight = 0;
+        self.probe_started_at = None;
+        self.open_count += 1;
+    }
+
+    fn close(&mut self) {
+        self.state = CircuitState::Closed;
+        self.window.clear();
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.probes_in_flight = 0;
+        self.probe_started_at = None;
+    }
+}
+
+/// 发送名额守卫: 请求未发出就提前放弃时归还名额，避免半开状态因名额耗尽而卡住
+pub struct ProbeGuard<'a> {
+    registry: &'a CircuitBreakerRegistry,
+    key: Option<String>,
+}
+
+impl ProbeGuard<'_> {
+    /// 请求将被发出，名额由之后的成功/失败记录结束
+    pub fn commit(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for ProbeGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.registry.release_probe(&key);
+        }
+    }
+}
+
+/// 熔断器注册表 (同一作用域下按 key 独立熔断)
+pub struct CircuitBreakerRegistry {
+    scope: &'static str,
+    config: RwLock<CircuitBreakerConfig>,
+    breakers: DashMap<String, Breaker>,
+    listener: RwLock<Option<TransitionListener>>,
+}
+
+impl CircuitBreakerRegistry {
+    pub fn new(scope: &'static str, config: CircuitBreakerConfig) -> Self {
+        Self {
+            scope,
+            config: RwLock::new(config),
+            breakers: DashMap::new(),
+            listener: RwLock::new(None),
+        }
+    }
+
+    pub fn update_config(&self, config: CircuitBreakerConfig) {
+        if !config.enabled {
+            self.breakers.clear();
+        }
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn set_listener(&self, listener: TransitionListener) {
+        *self.listener.write().unwrap() = Some(listener);
+    }
+
+    /// 该 key 当前是否处于熔断中 (不会改变状态，也不会占用探测名额，用于筛选候选)
+    ///
+    /// Open 状态已过冷却时间、或 HalfOpen 状态仍有探测名额时视为可尝试。
+    pub fn is_open(&self, key: &str) -> bool {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return false;
+        }
+        let open_duration = Duration::from_secs(config.open_seconds);
+        let now = Instant::now();
+        let Some(breaker) = self.breakers.get(key) else {
+            return false;
+        };
+        match breaker.state {
+            CircuitState::Closed => false,
+            CircuitState::Open => breaker
+                .opened_at
+                .map(|t| now.duration_since(t) < open_duration)
+                .unwrap_or(false),
+            CircuitState::HalfOpen => {
+                let stale = breaker
+                    .probe_started_at
+                    .map(|t| now.duration_since(t) >= open_duration)
+                    .unwrap_or(true);
+                !stale && breaker.probes_in_flight >= config.half_open_max_probes.max(1)
+            }
+        }
+    }
+
+    /// 为已选定的 key 占用发送名额。
+    /// Closed 状态直接放行；Open 状态超过冷却时间后转为 HalfOpen，并占用一个探测名额。
+    pub fn try_acquire_probe(&self, key: &str) -> bool {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return true;
+        }
+        let open_duration = Duration::from_secs(config.open_seconds);
+        let now = Instant::now();
+
+        let mut transition = None;
+        let allowed = {
+            let Some(mut breaker) = self.breakers.get_mut(key) else {
+                return true;
+            };
+            match breaker.state {
+                CircuitState::Closed => true,
+                CircuitState::Open => {
+                    let elapsed = breaker.opened_at.map(|t| now.duration_since(t)).unwrap_or_default();
+                    if elapsed >= open_duration {
+                        breaker.state = CircuitState::HalfOpen;
+                        breaker.probes_in_flight = 1;
+                        breaker.probe_started_at = Some(now);
+                        transition = Some((CircuitState::Open, CircuitState::HalfOpen, "cooldown elapsed, probing".to_string()));
+                        true
+                    } else {
+                        false
+                    }
+                }
+                CircuitState::HalfOpen => {
+                    // 探测请求长时间无结果 (例如被调度跳过) 时视为丢失，重新放行
+                    let stale = breaker
+                        .probe_started_at
+                        .map(|t| now.duration_since(t) >= open_duration)
+                        .unwrap_or(true);
+                    if stale {
+                        breaker.probes_in_flight = 0;
+                    }
+                    if breaker.probes_in_flight < config.half_open_max_probes.max(1) {
+                        breaker.probes_in_flight += 1;
+                        breaker.probe_started_at = Some(now);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+        };
+
+        if let Some((from, to, reason)) = transition {
+            self.emit(key, from, to, reason);
+        }
+        allowed
+    }
+
+    /// 占用发送名额并返回守卫; 守卫在 `commit` 之前被丢弃时自动归还名额
+    pub fn acquire_probe(&self, key: &str) -> Option<ProbeGuard<'_>> {
+        self.try_acquire_probe(key).then(|| ProbeGuard {
+            registry: self,
+            key: Some(key.to_string()),
+        })
+    }
+
+    /// 归还未实际发出请求的探测名额 (例如选定账号后刷新 token 失败)
+    pub fn release_probe(&self, key: &str) {
+        if let Some(mut breaker) = self.breakers.get_mut(key) {
+            if breaker.state == CircuitState::HalfOpen {
+                breaker.probes_in_flight = breaker.probes_in_flight.saturating_sub(1);
+            }
+        }
+    }
+
+    /// 记录一次成功请求
+    pub fn record_success(&self, key: &str) {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return;
+        }
+        let now = Instant::now();
+
+        let mut transition = None;
+        {
+            let Some(mut breaker) = self.breakers.get_mut(key) else {
+                // 未出现过失败的 key 无需记录
+                return;
+            };
+            match breaker.state {
+                CircuitState::HalfOpen => {
+                    breaker.close();
+                    transition = Some((CircuitState::HalfOpen, CircuitState::Closed, "probe succeeded".to_string()));
+                }
+                CircuitState::Open => {
+                    // 熔断期间仍有请求成功 (例如熔断前发出的请求)，不改变状态
+                }
+                CircuitState::Closed => {
+                    breaker.consecutive_failures = 0;
+                    breaker.prune(Duration::from_secs(config.window_seconds), now);
+                    breaker.window.push_back((now, true));
+                }
+            }
+        }
+
+        if let Some((from, to, reason)) = transition {
+            self.emit(key, from, to, reason);
+        }
+    }
+
+    /// 探测请求得到了非故障类响应 (如 4xx/429): 上游可达，结束半开状态
+    pub fn resolve_probe(&self, key: &str, detail: &str) {
+        if !self.config.read().unwrap().enabled {
+            return;
+        }
+        let resolved = match self.breakers.get_mut(key) {
+            Some(mut breaker) if breaker.state == CircuitState::HalfOpen => {
+                breaker.close();
+                true
+            }
+            _ => false,
+        };
+        if resolved {
+            self.emit(key, CircuitState::HalfOpen, CircuitState::Closed, format!("probe answered: {}", detail));
+        }
+    }
+
+    /// 记录一次失败请求
+    pub fn record_failure(&self, key: &str, detail: &str) {
+        let config = self.config.read().unwrap().clone();
+        if !config.enabled {
+            return;
+        }
+        let now = Instant::now();
+
+        let mut transition = None;
+        {
+            let mut breaker = self.breakers.entry(key.to_string()).or_insert_with(Breaker::new);
+            match breaker.state {
+                CircuitState::HalfOpen => {
+                    breaker.open(now);
+                    transition = Some((CircuitState::HalfOpen, CircuitState::Open, format!("probe failed: {}", detail)));
+                }
+                CircuitState::Open => {}
+                CircuitState::Closed => {
+                    breaker.consecutive_failures += 1;
+                    breaker.prune(Duration::from_secs(config.window_seconds), now);
+                    breaker.window.push_back((now, false));
+
+                    let rate = breaker.failure_rate();
+                    let reason = if breaker.consecutive_failures >= config.consecutive_failures.max(1) {
+                        Some(format!("{} consecutive failures ({})", breaker.consecutive_failures, detail))
+                    } else if breaker.window.len() >= config.min_requests as usize
+                        && rate >= config.failure_rate_threshold
+                    {
+                        Some(format!(
+                            "failure rate {:.0}% over {} requests ({})",
+                            rate * 100.0,
+                            breaker.window.len(),
+                            detail
+                        ))
+                    } else {
+                        None
+                    };
+
+                    if let Some(reason) = reason {
+                        breaker.open(now);
+                        transition = Some((CircuitState::Closed, CircuitState::Open, reason));
+                    }
+                }
+            }
+        }
+
+        if let Some((from, to, reason)) = transition {
+            self.emit(key, from, to, reason);
+        }
+    }
+
+    /// 当前状态 (不会触发状态变更)
+    pub fn state(&self, key: &str) -> CircuitState {
+        self.breakers
+            .get(key)
+            .map(|b| b.state)
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// 手动重置指定 key 的熔断状态
+    pub fn reset(&self, key: &str) {
+        if let Some((_, breaker)) = self.breakers.remove(key) {
+            if breaker.state != CircuitState::Closed {
+                self.emit(key, breaker.state, CircuitState::Closed, "manual reset".to_string());
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<CircuitSnapshot> {
+        let config = self.config.read().unwrap().clone();
+        let window = Duration::from_secs(config.window_seconds);
+        let open_duration = Duration::from_secs(config.open_seconds);
+        let now = Instant::now();
+
+        let mut out: Vec<CircuitSnapshot> = self
+            .breakers
+            .iter()
+            .map(|entry| {
+                let b = entry.value();
+                let recent: Vec<bool> = b
+                    .window
+                    .iter()
+                    .filter(|(t, _)| now.duration_since(*t) <= window)
+                    .map(|(_, ok)| *ok)
+                    .collect();
+                let failure_rate = if recent.is_empty() {
+                    0.0
+                } else {
+                    recent.iter().filter(|ok| !**ok).count() as f64 / recent.len() as f64
+                };
+                let open_remaining_secs = match (b.state, b.opened_at) {
+                    (CircuitState::Open, Some(t)) => open_duration.saturating_sub(now.duration_since(t)).as_secs(),
+                    _ => 0,
+                };
+                CircuitSnapshot {
+                    scope: self.scope.to_string(),
+                    key: entry.key().clone(),
+                    state: b.state,
+                    window_requests: recent.len(),
+                    failure_rate,
+                    consecutive_failures: b.consecutive_failures,
+                    open_remaining_secs,
+                    open_count: b.open_count,
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.key.cmp(&b.key));
+        out
+    }
+
+    fn emit(&self, key: &str, from: CircuitState, to: CircuitState, reason: String) {
+        let transition = CircuitTransition {
+            scope: self.scope.to_string(),
+            key: key.to_string(),
+            from,
+            to,
+            reason,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        };
+        match to {
+            CircuitState::Open => tracing::warn!(
+                "[CircuitBreaker] {} {} opened: {}",
+                transition.scope, transition.key, transition.reason
+            ),
+            _ => tracing::info!(
+                "[CircuitBreaker] {} {} {:?} -> {:?}: {}",
+                transition.scope, transition.key, from, to, transition.reason
+            ),
+        }
+        let listener = self.listener.read().unwrap().clone();
+        if let Some(listener) = listener {
+            listener(&transition);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn config(open_seconds: u64) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            window_seconds: 60,
+            min_requests: 4,
+            failure_rate_threshold: 0.5,
+            consecutive_failures: 3,
+            open_seconds,
+            half_open_max_probes: 1,
+        }
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let registry = CircuitBreakerRegistry::new("endpoint", config(30));
+        for _ in 0..2 {
+            registry.record_failure("prod", "503");
+        }
+        assert!(registry.try_acquire_probe("prod"));
+        registry.record_failure("prod", "503");
+        assert_eq!(registry.state("prod"), CircuitState::Open);
+        assert!(!registry.try_acquire_probe("prod"));
+        assert!(registry.try_acquire_probe("daily"));
+    }
+
+    #[test]
+    fn test_opens_on_failure_rate() {
+        let registry = CircuitBreakerRegistry::new("account", config(30));
+        // 交替成功/失败，不会触发连续失败阈值，但失败率达到 50%
+        registry.record_failure("a", "500");
+        registry.record_success("a");
+        registry.record_failure("a", "500");
+        assert_eq!(registry.state("a"), CircuitState::Closed);
+        registry.record_success("a");
+        registry.record_failure("a", "500");
+        assert_eq!(registry.state("a"), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_half_open_probe_closes_on_success() {
+        let registry = CircuitBreakerRegistry::new("endpoint", config(0));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        registry.set_listener(Arc::new(move |t: &CircuitTransition| {
+            sink.lock().unwrap().push((t.from, t.to));
+        }));
+
+        for _ in 0..3 {
+            registry.record_failure("prod", "timeout");
+        }
+        // 冷却时间为 0: 首个请求作为探测放行，其余请求在探测完成前被拒绝
+        assert!(registry.try_acquire_probe("prod"));
+        assert_eq!(registry.state("prod"), CircuitState::HalfOpen);
+        registry.record_success("prod");
+        assert_eq!(registry.state("prod"), CircuitState::Closed);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (CircuitState::Closed, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_half_open_probe_failure_reopens() {
+        let registry = CircuitBreakerRegistry::new("endpoint", config(30));
+        for _ in 0..3 {
+            registry.record_failure("prod", "503");
+        }
+        // 手动模拟冷却结束
+        registry.breakers.get_mut("prod").unwrap().opened_at =
+            Some(Instant::now() - Duration::from_secs(31));
+        assert!(registry.try_acquire_probe("prod"));
+        assert!(!registry.try_acquire_probe("prod"), "only one probe allowed while half-open");
+        registry.record_failure("prod", "503");
+        assert_eq!(registry.state("prod"), CircuitState::Open);
+        assert_eq!(registry.snapshot()[0].open_count, 2);
+    }
+
+    #[test]
+    fn test_dropped_probe_guard_returns_slot() {
+        let registry = CircuitBreakerRegistry::new("account", config(30));
+        for _ in 0..3 {
+            registry.record_failure("a", "500");
+        }
+        registry.breakers.get_mut("a").unwrap().opened_at = Some(Instant::now() - Duration::from_secs(31));
+        // 选定后未发出请求 (如刷新 token 失败): 名额归还，下一个请求仍可探测
+        drop(registry.acquire_probe("a").unwrap());
+        assert_eq!(registry.state("a"), CircuitState::HalfOpen);
+        assert!(!registry.is_open("a"));
+
+        registry.acquire_probe("a").unwrap().commit();
+        assert!(registry.acquire_probe("a").is_none());
+    }
+
+    #[test]
+    fn test_disabled_always_allows() {
+        let mut cfg = config(30);
+        cfg.enabled = false;
+        let registry = CircuitBreakerRegistry::new("endpoint", cfg);
+        for _ in 0..10 {
+            registry.record_failure("prod", "503");
+        }
+        assert!(registry.try_acquire_probe("prod"));
+        assert!(registry.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_is_open_does_not_consume_probe() {
+        let registry = CircuitBreakerRegistry::new("account", config(0));
+        for _ in 0..3 {
+            registry.record_failure("a", "500");
+        }
+        // 冷却结束: 筛选时反复检查不会占用探测名额
+        assert!(!registry.is_open("a"));
+        assert!(!registry.is_open("a"));
+        assert_eq!(registry.state("a"), CircuitState::Open);
+
+        assert!(registry.try_acquire_probe("a"));
+        assert_eq!(registry.state("a"), CircuitState::HalfOpen);
+
+        // 探测请求得到 429: 上游可达，半开状态结束
+        registry.resolve_probe("a", "HTTP 429");
+        assert_eq!(registry.state("a"), CircuitState::Closed);
+    }
+}
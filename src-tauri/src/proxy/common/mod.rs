@@ -3,5 +3,7 @@
 // pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
+pub mod model_capabilities;
+pub mod model_router;
 pub mod utils;
 pub mod json_schema;
@@ -0,0 +1,331 @@
+// 模型能力注册表
+//
+// 记录各上游模型支持的能力 (thinking / 工具 / 图片 / 联网) 与输出、上下文上限，
+// 供模型链回退时挑选下一个兼容的模型，并按目标模型调整请求参数。
+use serde::Serialize;
+
+use crate::proxy::common::model_router::RequestFeatures;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+
+/// 单个模型的能力描述
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ModelCapabilities {
+    pub thinking: bool,
+    pub tools: bool,
+    pub vision: bool,
+    pub search: bool,
+    pub max_output_tokens: u32,
+    pub context_window: u32,
+}
+
+impl ModelCapabilities {
+    const fn new(thinking: bool, tools: bool, vision: bool, search: bool, max_output_tokens: u32, context_window: u32) -> Self {
+        Self { thinking, tools, vision, search, max_output_tokens, context_window }
+    }
+}
+
+/// 内置能力表，按顺序匹配模型名前缀 (更具体的条目在前)
+const CAPABILITY_TABLE: &[(&str, ModelCapabilities)] = &[
+    ("claude-opus-4-5-thinking", ModelCapabilities::new(true, true, true, false, 64000, 200_000)),
+    ("claude-sonnet-4-5-thinking", ModelCapabilities::new(true, true, true, false, 64000, 200_000)),
+    ("claude-", ModelCapabilities::new(true, true, true, false, 64000, 200_000)),
+    ("gemini-3-pro-image", ModelCapabilities::new(false, false, true, false, 32768, 65_536)),
+    ("gemini-2.5-flash-thinking", ModelCapabilities::new(true, true, true, true, 65536, 1_048_576)),
+    ("gemini-2.5-flash-lite", ModelCapabilities::new(false, true, true, true, 65536, 1_048_576)),
+    ("gemini-", ModelCapabilities::new(false, true, true, true, 65536, 1_048_576)),
+];
+
+/// 未登记模型的保守默认值
+const DEFAULT_CAPABILITIES: ModelCapabilities = ModelCapabilities::new(false, true, true, false, 64000, 200_000);
+
+/// 查询模型能力 (带 "-thinking" 后缀的模型总是视为支持 thinking)
+pub fn lookup(model: &str) -> ModelCapabilities {
+    let model = model.to_lowercase();
+    let mut caps = CAPABILITY_TABLE
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, caps)| *caps)
+        .unwrap_or(DEFAULT_CAPABILITIES);
+    if model.contains("-thinking") {
+        caps.thinking = true;
+    }
+    caps
+}
+
+/// 请求对模型能力的需求
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestNeeds {
+    pub thinking: bool,
+    pub tools: bool,
+    pub images: bool,
+    pub prompt_tokens: u64,
+}
+
+impl From<&RequestFeatures> for RequestNeeds {
+    fn from(features: &RequestFeatures) -> Self {
+        Self {
+            thinking: features.has_thinking,
+            tools: features.has_tools,
+            images: features.has_images,
+            prompt_tokens: features.prompt_tokens,
+        }
+    }
+}
+
+/// 检查模型能否承接请求，返回不兼容原因 (thinking 可降级，不算硬性要求)
+pub fn incompatibility(caps: &ModelCapabilities, needs: &RequestNeeds) -> Option<String> {
+    if needs.images && !caps.vision {
+        return Some("no-vision".to_string());
+    }
+    if needs.tools && !caps.tools {
+        return Some("no-tools".to_string());
+    }
+    if needs.prompt_tokens > caps.context_window as u64 {
+        return Some(format!("context>{}", caps.context_window));
+    }
+    None
+}
+
+/// 从模型链中筛选兼容的模型下标；全部不兼容时保留整条链，交由上游报错
+pub fn compatible_hops(chain: &[String], needs: &RequestNeeds, trace: &mut HopTrace) -> Vec<usize> {
+    let mut indices = Vec::new();
+    for (i, model) in chain.iter().enumerate() {
+        match incompatibility(&lookup(model), needs) {
+            Some(reason) => trace.skip(model, reason),
+            None => indices.push(i),
+        }
+    }
+    if indices.is_empty() {
+        tracing::warn!("[Capabilities] No model in chain {:?} satisfies the request, trying the full chain", chain);
+        return (0..chain.len()).collect();
+    }
+    indices
+}
+
+/// 按目标模型能力调整 Claude 请求，返回所做调整的简短标记
+pub fn adapt_claude_request(req: &mut ClaudeRequest, caps: &ModelCapabilities) -> Vec<&'static str> {
+    let mut adaptations = Vec::new();
+
+    if !caps.thinking {
+        let had_thinking = req.thinking.take().is_some_and(|t| t.type_ == "enabled");
+        let mut stripped = false;
+        for msg in req.messages.iter_mut() {
+            if let MessageContent::Array(blocks) = &mut msg.content {
+                let before = blocks.len();
+                blocks.retain(|b| !matches!(b, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }));
+                stripped |= blocks.len() != before;
+            }
+        }
+        if had_thinking || stripped {
+            adaptations.push("thinking-off");
+        }
+    }
+
+    if let Some(max_tokens) = req.max_tokens {
+        if max_tokens > caps.max_output_tokens {
+            req.max_tokens = Some(caps.max_output_tokens);
+            adaptations.push("max-tokens");
+        }
+    }
+
+    // thinking 预算必须小于输出上限
+    if let Some(thinking) = req.thinking.as_mut() {
+        let limit = req.max_tokens.unwrap_or(caps.max_output_tokens).min(caps.max_output_tokens);
+        if let Some(budget) = thinking.budget_tokens {
+            if budget >= limit {
+                thinking.budget_tokens = Some(limit / 2);
+                adaptations.push("thinking-budget");
+            }
+        }
+    }
+
+    adaptations
+}
+
+/// 模型链中单个模型的处理结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum HopStatus {
+    Skipped(String),
+    Exhausted,
+    Failed(String),
+    Served,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelHop {
+    pub model: String,
+    pub status: HopStatus,
+    pub attempts: u32,
+    pub adaptations: Vec<&'static str>,
+}
+
+/// 一次请求走过的模型链记录 (写入 X-Model-Hops 响应头与监控日志)
+#[derive(Debug, Clone, Default)]
+pub struct HopTrace {
+    hops: Vec<ModelHop>,
+}
+
+impl HopTrace {
+    pub fn skip(&mut self, model: &str, reason: String) {
+        self.hops.push(ModelHop {
+            model: model.to_string(),
+            status: HopStatus::Skipped(reason),
+            attempts: 0,
+            adaptations: Vec::new(),
+        });
+    }
+
+    /// 开始一次尝试 (同一模型的连续尝试合并为一条记录)
+    pub fn attempt(&mut self, model: &str, adaptations: Vec<&'static str>) {
+        if let Some(last) = self.hops.last_mut() {
+            if last.model == model && last.status != HopStatus::Served && !matches!(last.status, HopStatus::Skipped(_)) {
+                last.attempts += 1;
+                last.adaptations = adaptations;
+                return;
+            }
+        }
+        self.hops.push(ModelHop {
+            model: model.to_string(),
+            status: HopStatus::Failed(String::new()),
+            attempts: 1,
+            adaptations,
+        });
+    }
+
+    pub fn exhausted(&mut self, model: &str) {
+        match self.hops.last_mut() {
+            Some(last) if last.model == model && !matches!(last.status, HopStatus::Skipped(_)) => {
+                last.status = HopStatus::Exhausted;
+            }
+            _ => self.hops.push(ModelHop {
+                model: model.to_string(),
+                status: HopStatus::Exhausted,
+                attempts: 0,
+                adaptations: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn fail(&mut self, reason: impl Into<String>) {
+        if let Some(last) = self.hops.last_mut() {
+            last.status = HopStatus::Failed(reason.into());
+        }
+    }
+
+    pub fn serve(&mut self) {
+        if let Some(last) = self.hops.last_mut() {
+            last.status = HopStatus::Served;
+        }
+    }
+
+    /// 序列化为响应头值，例如
+    /// `claude-opus-4-5-thinking=failed(429)*2, gemini-3-pro-image=skipped(no-tools), gemini-3-pro-high=served[thinking-off]`
+    pub fn header_value(&self) -> String {
+        self.hops
+            .iter()
+            .map(|hop| {
+                let mut entry = format!("{}=", hop.model);
+                match &hop.status {
+                    HopStatus::Skipped(reason) => entry.push_str(&format!("skipped({})", reason)),
+                    HopStatus::Exhausted => entry.push_str("exhausted"),
+                    HopStatus::Failed(reason) if reason.is_empty() => entry.push_str("failed"),
+                    HopStatus::Failed(reason) => entry.push_str(&format!("failed({})", reason)),
+                    HopStatus::Served => entry.push_str("served"),
+                }
+                if hop.attempts > 1 {
+                    entry.push_str(&format!("*{}", hop.attempts));
+                }
+                if !hop.adaptations.is_empty() {
+                    entry.push_str(&format!("[{}]", hop.adaptations.join(";")));
+                }
+                entry
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+            .replace(|c: char| c.is_control(), "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::claude::models::{Message, ThinkingConfig};
+
+    fn request(thinking: bool, max_tokens: u32) -> ClaudeRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-opus-4-5-thinking",
+            "max_tokens": max_tokens,
+            "messages": [{"role": "user", "content": "hi"}],
+        }))
+        .map(|mut req: ClaudeRequest| {
+            if thinking {
+                req.thinking = Some(ThinkingConfig { type_: "enabled".to_string(), budget_tokens: Some(max_tokens) });
+                req.messages.push(Message {
+                    role: "assistant".to_string(),
+                    content: MessageContent::Array(vec![
+                        ContentBlock::Thinking { thinking: "hmm".to_string(), signature: None, cache_control: None },
+                        ContentBlock::Text { text: "hello".to_string() },
+                    ]),
+                });
+            }
+            req
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn lookup_prefers_specific_entries() {
+        assert!(lookup("claude-opus-4-5-thinking").thinking);
+        assert!(!lookup("gemini-3-pro-high").thinking);
+        assert!(lookup("gemini-3-pro-high-thinking").thinking);
+        assert!(!lookup("gemini-3-pro-image").tools);
+        assert_eq!(lookup("unknown-model"), DEFAULT_CAPABILITIES);
+    }
+
+    #[test]
+    fn skips_incompatible_hops_but_never_empties_the_chain() {
+        let chain = vec!["gemini-3-pro-image".to_string(), "gemini-3-pro-high".to_string()];
+        let needs = RequestNeeds { tools: true, ..Default::default() };
+        let mut trace = HopTrace::default();
+        assert_eq!(compatible_hops(&chain, &needs, &mut trace), vec![1]);
+        assert_eq!(trace.header_value(), "gemini-3-pro-image=skipped(no-tools)");
+
+        let needs = RequestNeeds { prompt_tokens: 2_000_000, ..Default::default() };
+        let mut trace = HopTrace::default();
+        assert_eq!(compatible_hops(&chain, &needs, &mut trace), vec![0, 1]);
+    }
+
+    #[test]
+    fn adapts_thinking_and_output_limits_per_hop() {
+        let mut req = request(true, 100_000);
+        let adaptations = adapt_claude_request(&mut req, &lookup("gemini-3-pro-high"));
+        assert_eq!(adaptations, vec!["thinking-off", "max-tokens"]);
+        assert!(req.thinking.is_none());
+        assert_eq!(req.max_tokens, Some(65536));
+        assert!(matches!(&req.messages[1].content, MessageContent::Array(b) if b.len() == 1));
+
+        let mut req = request(true, 100_000);
+        let adaptations = adapt_claude_request(&mut req, &lookup("claude-opus-4-5-thinking"));
+        assert_eq!(adaptations, vec!["max-tokens", "thinking-budget"]);
+        assert_eq!(req.thinking.unwrap().budget_tokens, Some(32000));
+
+        let mut req = request(false, 1024);
+        assert!(adapt_claude_request(&mut req, &lookup("gemini-2.5-flash")).is_empty());
+    }
+
+    #[test]
+    fn hop_trace_merges_retries_on_the_same_model() {
+        let mut trace = HopTrace::default();
+        trace.attempt("claude-opus-4-5-thinking", vec![]);
+        trace.fail("429");
+        trace.attempt("claude-opus-4-5-thinking", vec![]);
+        trace.fail("429");
+        trace.exhausted("claude-sonnet-4-5");
+        trace.attempt("gemini-3-pro-high", vec!["thinking-off"]);
+        trace.serve();
+        assert_eq!(
+            trace.header_value(),
+            "claude-opus-4-5-thinking=failed(429)*2, claude-sonnet-4-5=exhausted, gemini-3-pro-high=served[thinking-off]"
+        );
+    }
+}
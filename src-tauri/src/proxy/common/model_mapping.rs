@@ -125,21 +125,58 @@ pub async fn get_all_dynamic_models(
     sorted_ids
 }
 
-/// 通配符匹配辅助函数
-/// 支持简单的 * 通配符匹配
+/// 通配符匹配辅助函数 (模型映射、路由规则及各类模型列表共用)
+/// `*` 匹配任意长度字符，`?` 匹配单个字符
 ///
 /// # 示例
 /// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
 /// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
 /// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
-    if let Some(star_pos) = pattern.find('*') {
-        let prefix = &pattern[..star_pos];
-        let suffix = &pattern[star_pos + 1..];
-        text.starts_with(prefix) && text.ends_with(suffix)
-    } else {
-        pattern == text
+/// - `claude-*-4-*` 匹配 `claude-opus-4-5`
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = backtrack {
+            // 让上一个 * 多吞一个字符
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            backtrack = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
     }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// 在自定义映射表中查找匹配项
+/// 优先级：精确匹配 > 通配符匹配 (多个通配符同时命中时，非通配字符最多的模式优先，
+/// 长度相同时按字典序，保证结果与 HashMap 遍历顺序无关)
+pub fn match_custom_mapping<'a>(
+    original_model: &str,
+    custom_mapping: &'a std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>,
+) -> Option<(&'a str, &'a crate::proxy::config::ModelMappingTarget)> {
+    if let Some((pattern, target)) = custom_mapping.get_key_value(original_model) {
+        return Some((pattern.as_str(), target));
+    }
+
+    custom_mapping
+        .iter()
+        .filter(|(pattern, _)| pattern.contains(['*', '?']) && wildcard_match(pattern, original_model))
+        .min_by(|(a, _), (b, _)| {
+            let specificity = |p: &str| p.chars().filter(|c| *c != '*').count();
+            specificity(b).cmp(&specificity(a)).then_with(|| a.cmp(b))
+        })
+        .map(|(pattern, target)| (pattern.as_str(), target))
 }
 
 /// 核心模型路由解析引擎 - 链式解析
@@ -155,23 +192,18 @@ pub fn resolve_model_chain(
     original_model: &str,
     custom_mapping: &std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>,
 ) -> Vec<String> {
-    // 1. 精确匹配 (最高优先级)
-    if let Some(target) = custom_mapping.get(original_model) {
+    // 1. 自定义映射 (精确 > 通配符)
+    if let Some((pattern, target)) = match_custom_mapping(original_model, custom_mapping) {
         let chain = target.to_chain();
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {:?}", original_model, chain));
-        return chain;
-    }
-
-    // 2. 通配符匹配
-    for (pattern, target) in custom_mapping.iter() {
-        if pattern.contains('*') && wildcard_match(pattern, original_model) {
-            let chain = target.to_chain();
+        if pattern == original_model {
+            crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {:?}", original_model, chain));
+        } else {
             crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {:?} (规则: {})", original_model, chain, pattern));
-            return chain;
         }
+        return chain;
     }
 
-    // 3. 系统默认映射
+    // 2. 系统默认映射
     let result = map_claude_model_to_gemini(original_model);
     if result != original_model {
         crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
@@ -213,4 +245,17 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("claude-*", "claude-opus-4"));
+        assert!(wildcard_match("*-thinking", "gemini-2.5-flash-thinking"));
+        assert!(wildcard_match("gpt-4?", "gpt-4o"));
+        assert!(!wildcard_match("gpt-4?", "gpt-4"));
+        assert!(wildcard_match("claude-*-4-*", "claude-opus-4-5"));
+        assert!(!wildcard_match("claude-*-4-*", "claude-opus-3-5"));
+        // 多个 * 时不再只看第一个
+        assert!(!wildcard_match("a*b*c", "a-c-b"));
+        assert!(wildcard_match("exact", "exact"));
+    }
 }
@@ -0,0 +1,552 @@
+// 有序模型路由规则
+//
+// 按顺序评估 routing_rules，第一条 "模型名 + 全部条件" 都满足的规则决定模型链，
+// 规则可按百分比 A/B 分流。没有规则命中时回退到 custom_mapping 与系统默认映射。
+use axum::http::HeaderMap;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::proxy::common::model_mapping;
+use crate::proxy::config::{InboundProtocol, ModelMappingTarget, ModelMatchType, RoutingRule};
+
+/// 已编译的正则 (无效正则缓存错误信息，避免每次请求重复编译)
+static REGEX_CACHE: Lazy<DashMap<String, Result<Regex, String>>> = Lazy::new(DashMap::new);
+
+/// 路由条件使用的请求特征
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RequestFeatures {
+    /// 估算的 prompt token 数 (按字符数 / 4 粗略估计)
+    pub prompt_tokens: u64,
+    pub has_tools: bool,
+    pub has_images: bool,
+    pub has_thinking: bool,
+}
+
+impl RequestFeatures {
+    /// 从原始请求体提取特征 (兼容 Claude / OpenAI / Gemini 格式)
+    pub fn from_body(body: &Value) -> Self {
+        let non_empty = |key: &str| {
+            body.get(key)
+                .and_then(|v| v.as_array())
+                .is_some_and(|a| !a.is_empty())
+        };
+        let has_tools = non_empty("tools") || non_empty("functions");
+
+        let has_thinking = body
+            .get("thinking")
+            .and_then(|t| t.get("type"))
+            .and_then(|t| t.as_str())
+            == Some("enabled")
+            || body.get("reasoning_effort").is_some_and(|v| !v.is_null())
+            || body.get("reasoning").is_some_and(|v| v.is_object())
+            || body
+                .get("generationConfig")
+                .and_then(|g| g.get("thinkingConfig"))
+                .is_some();
+
+        let mut chars = 0usize;
+        let mut has_images = false;
+        for key in ["system", "messages", "contents", "systemInstruction", "input", "instructions", "prompt"] {
+            if let Some(v) = body.get(key) {
+                scan_content(v, &mut chars, &mut has_images);
+            }
+        }
+
+        Self {
+            prompt_tokens: chars.div_ceil(4) as u64,
+            has_tools,
+            has_images,
+            has_thinking,
+        }
+    }
+}
+
+/// 估算任意请求内容的 token 数 (与 `RequestFeatures::prompt_tokens` 口径一致: 字符数 / 4)
+pub fn estimate_tokens(value: &Value) -> u64 {
+    let mut chars = 0usize;
+    let mut has_images = false;
+    scan_content(value, &mut chars, &mut has_images);
+    chars.div_ceil(4) as u64
+}
+
+/// 统计文本字符数并检测图片 (跳过 base64 数据与签名等非文本字段)
+fn scan_content(value: &Value, chars: &mut usize, has_images: &mut bool) {
+    match value {
+        Value::String(s) => *chars += s.chars().count(),
+        Value::Array(items) => {
+            for item in items {
+                scan_content(item, chars, has_images);
+            }
+        }
+        Value::Object(map) => {
+            let block_type = map.get("type").and_then(|t| t.as_str());
+            if matches!(block_type, Some("image" | "image_url" | "input_image")) {
+                *has_images = true;
+                return;
+            }
+            for (key, v) in map {
+                match key.as_str() {
+                    "inlineData" | "inline_data" | "fileData" | "file_data" => {
+                        let mime = v
+                            .get("mimeType")
+                            .or_else(|| v.get("mime_type"))
+                            .and_then(|m| m.as_str())
+                            .unwrap_or_default();
+                        if mime.starts_with("image/") {
+                            *has_images = true;
+                        }
+                    }
+                    "data" | "signature" | "thoughtSignature" | "id" | "tool_use_id" | "type" | "role" => {}
+                    _ => scan_content(v, chars, has_images),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 一次路由解析的输入
+pub struct RouteRequest<'a> {
+    pub model: &'a str,
+    pub protocol: InboundProtocol,
+    pub headers: &'a HeaderMap,
+    pub features: RequestFeatures,
+    /// A/B 分流的稳定键 (通常为会话 ID)，为空时随机分流
+    pub split_key: Option<&'a str>,
+}
+
+/// 路由结果来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteSource {
+    Rule,
+    ExactMapping,
+    WildcardMapping,
+    Builtin,
+}
+
+/// 路由结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteDecision {
+    /// 目标模型链 (至少包含一个元素)
+    pub chain: Vec<String>,
+    pub source: RouteSource,
+    /// 命中的规则序号 (从 0 开始)
+    pub rule_index: Option<usize>,
+    pub rule_name: Option<String>,
+    /// 命中的 custom_mapping 模式
+    pub pattern: Option<String>,
+    /// A/B 分流: 命中的分流序号 (None 表示规则默认目标)
+    pub split: Option<usize>,
+    /// A/B 分流: 本次请求的分桶值 (0-99)
+    pub bucket: Option<u8>,
+}
+
+/// 单条规则的评估结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleEvaluation {
+    pub index: usize,
+    pub name: String,
+    pub matched: bool,
+    /// 命中或未命中的原因
+    pub reason: String,
+}
+
+/// 路由解释 (用于 /v1/models/route)
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteExplanation {
+    pub model: String,
+    pub protocol: InboundProtocol,
+    pub features: RequestFeatures,
+    pub rules: Vec<RuleEvaluation>,
+    pub decision: RouteDecision,
+}
+
+/// 解析请求的模型链
+pub fn resolve_route(
+    request: &RouteRequest,
+    rules: &[RoutingRule],
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
+) -> RouteDecision {
+    for (index, rule) in rules.iter().enumerate() {
+        if evaluate_rule(rule, request).is_ok() {
+            let decision = rule_decision(index, rule, request);
+            crate::modules::logger::log_info(&format!(
+                "[Router] 规则映射: {} -> {:?} (规则: {})",
+                request.model,
+                decision.chain,
+                rule.label(index)
+            ));
+            return decision;
+        }
+    }
+    mapping_decision(request.model, custom_mapping)
+}
+
+/// 解析模型链并说明每条规则命中/未命中的原因 (不写日志)
+pub fn explain_route(
+    request: &RouteRequest,
+    rules: &[RoutingRule],
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
+) -> RouteExplanation {
+    let mut evaluations = Vec::with_capacity(rules.len());
+    let mut decision = None;
+
+    for (index, rule) in rules.iter().enumerate() {
+        let name = rule.label(index);
+        if decision.is_some() {
+            evaluations.push(RuleEvaluation {
+                index,
+                name,
+                matched: false,
+                reason: "not evaluated: an earlier rule matched".to_string(),
+            });
+            continue;
+        }
+        match evaluate_rule(rule, request) {
+            Ok(()) => {
+                let d = rule_decision(index, rule, request);
+                let reason = match (d.split, d.bucket) {
+                    (Some(split), Some(bucket)) => format!("all conditions met; bucket {} -> split #{}", bucket, split),
+                    (None, Some(bucket)) => format!("all conditions met; bucket {} -> default target", bucket),
+                    _ => "all conditions met".to_string(),
+                };
+                evaluations.push(RuleEvaluation { index, name, matched: true, reason });
+                decision = Some(d);
+            }
+            Err(reason) => evaluations.push(RuleEvaluation { index, name, matched: false, reason }),
+        }
+    }
+
+    RouteExplanation {
+        model: request.model.to_string(),
+        protocol: request.protocol,
+        features: request.features.clone(),
+        rules: evaluations,
+        decision: decision.unwrap_or_else(|| mapping_decision(request.model, custom_mapping)),
+    }
+}
+
+/// 未命中任何规则: 使用 custom_mapping 或系统默认映射
+fn mapping_decision(model: &str, custom_mapping: &HashMap<String, ModelMappingTarget>) -> RouteDecision {
+    let (chain, source, pattern) = match model_mapping::match_custom_mapping(model, custom_mapping) {
+        Some((pattern, target)) => {
+            let source = if pattern == model {
+                RouteSource::ExactMapping
+            } else {
+                RouteSource::WildcardMapping
+            };
+            (target.to_chain(), source, Some(pattern.to_string()))
+        }
+        None => (vec![model_mapping::map_claude_model_to_gemini(model)], RouteSource::Builtin, None),
+    };
+    RouteDecision {
+        chain,
+        source,
+        rule_index: None,
+        rule_name: None,
+        pattern,
+        split: None,
+        bucket: None,
+    }
+}
+
+fn rule_decision(index: usize, rule: &RoutingRule, request: &RouteRequest) -> RouteDecision {
+    let mut target = &rule.target;
+    let mut split = None;
+    let mut bucket = None;
+
+    if !rule.splits.is_empty() {
+        let b = split_bucket(request.split_key, index, &rule.name);
+        let mut cumulative = 0u32;
+        for (i, s) in rule.splits.iter().enumerate() {
+            cumulative += s.percent as u32;
+            if (b as u32) < cumulative {
+                target = &s.target;
+                split = Some(i);
+                break;
+            }
+        }
+        bucket = Some(b);
+    }
+
+    let mut chain = target.to_chain();
+    if chain.is_empty() {
+        chain.push(model_mapping::map_claude_model_to_gemini(request.model));
+    }
+    RouteDecision {
+        chain,
+        source: RouteSource::Rule,
+        rule_index: Some(index),
+        rule_name: Some(rule.label(index)),
+        pattern: None,
+        split,
+        bucket,
+    }
+}
+
+/// 分桶 (0-99): 有稳定键时同一会话总是落在同一分桶，不同规则之间相互独立
+fn split_bucket(split_key: Option<&str>, index: usize, name: &str) -> u8 {
+    match split_key {
+        Some(key) => {
+            // FNV-1a，保证跨进程稳定
+            let mut hash: u64 = 0xcbf29ce484222325;
+            for byte in format!("{}|{}|{}", index, name, key).bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+            (hash % 100) as u8
+        }
+        None => rand::Rng::gen_range(&mut rand::thread_rng(), 0..100),
+    }
+}
+
+/// 评估单条规则，未命中时返回原因
+fn evaluate_rule(rule: &RoutingRule, request: &RouteRequest) -> Result<(), String> {
+    if !rule.enabled {
+        return Err("rule disabled".to_string());
+    }
+
+    let model_ok = match rule.match_type {
+        ModelMatchType::Exact => rule.model == request.model,
+        ModelMatchType::Glob => model_mapping::wildcard_match(&rule.model, request.model),
+        ModelMatchType::Regex => regex_match(&rule.model, request.model)?,
+    };
+    if !model_ok {
+        return Err(format!("model '{}' does not match '{}'", request.model, rule.model));
+    }
+
+    let when = &rule.when;
+    if !when.protocols.is_empty() && !when.protocols.contains(&request.protocol) {
+        return Err(format!("protocol {:?} not in {:?}", request.protocol, when.protocols));
+    }
+
+    if !when.api_keys.is_empty() {
+        let key = request_api_key(request.headers);
+        if !key.is_some_and(|k| when.api_keys.iter().any(|allowed| allowed == k)) {
+            return Err("api key not in the rule's key list".to_string());
+        }
+    }
+
+    // 按 header 名排序，保证解释结果稳定
+    let mut headers: Vec<(&String, &String)> = when.headers.iter().collect();
+    headers.sort();
+    for (name, pattern) in headers {
+        let value = request.headers.get(name.as_str()).and_then(|v| v.to_str().ok());
+        match value {
+            None => return Err(format!("header '{}' missing", name)),
+            Some(v) if pattern != "*" && !model_mapping::wildcard_match(pattern, v) => {
+                return Err(format!("header '{}' does not match '{}'", name, pattern));
+            }
+            _ => {}
+        }
+    }
+
+    let tokens = request.features.prompt_tokens;
+    if let Some(min) = when.min_prompt_tokens {
+        if tokens < min {
+            return Err(format!("prompt ~{} tokens < min {}", tokens, min));
+        }
+    }
+    if let Some(max) = when.max_prompt_tokens {
+        if tokens > max {
+            return Err(format!("prompt ~{} tokens > max {}", tokens, max));
+        }
+    }
+
+    let flags = [
+        ("tools", when.has_tools, request.features.has_tools),
+        ("images", when.has_images, request.features.has_images),
+        ("thinking", when.has_thinking, request.features.has_thinking),
+    ];
+    for (label, expected, actual) in flags {
+        if let Some(expected) = expected {
+            if expected != actual {
+                return Err(format!("has_{} is {}, rule requires {}", label, actual, expected));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 与鉴权中间件一致的 API Key 提取顺序
+pub fn request_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+        .or_else(|| headers.get("x-goog-api-key").and_then(|h| h.to_str().ok()))
+}
+
+fn regex_match(pattern: &str, text: &str) -> Result<bool, String> {
+    cached_regex(pattern).map(|re| re.is_match(text))
+}
+
+/// 获取缓存的已编译正则 (Regex 克隆开销很小)
+pub fn cached_regex(pattern: &str) -> Result<Regex, String> {
+    REGEX_CACHE
+        .entry(pattern.to_string())
+        .or_insert_with(|| Regex::new(pattern).map_err(|e| format!("invalid regex '{}': {}", pattern, e)))
+        .value()
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::{RouteConditions, RouteSplit};
+    use serde_json::json;
+
+    fn rule(name: &str, model: &str, target: &str) -> RoutingRule {
+        RoutingRule {
+            name: name.to_string(),
+            enabled: true,
+            model: model.to_string(),
+            match_type: ModelMatchType::Glob,
+            when: RouteConditions::default(),
+            target: ModelMappingTarget::Single(target.to_string()),
+            splits: Vec::new(),
+        }
+    }
+
+    fn request<'a>(model: &'a str, headers: &'a HeaderMap, features: RequestFeatures) -> RouteRequest<'a> {
+        RouteRequest {
+            model,
+            protocol: InboundProtocol::Claude,
+            headers,
+            features,
+            split_key: Some("session-1"),
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let headers = HeaderMap::new();
+        let rules = vec![
+            rule("opus", "claude-opus-*", "claude-opus-4-5-thinking"),
+            rule("claude", "claude-*", "claude-sonnet-4-5"),
+        ];
+        let mapping = HashMap::new();
+
+        let d = resolve_route(&request("claude-opus-4", &headers, RequestFeatures::default()), &rules, &mapping);
+        assert_eq!(d.chain, vec!["claude-opus-4-5-thinking"]);
+        assert_eq!(d.rule_index, Some(0));
+
+        let d = resolve_route(&request("claude-haiku-4", &headers, RequestFeatures::default()), &rules, &mapping);
+        assert_eq!(d.rule_name.as_deref(), Some("claude"));
+
+        let d = resolve_route(&request("gpt-4", &headers, RequestFeatures::default()), &rules, &mapping);
+        assert_eq!(d.source, RouteSource::Builtin);
+    }
+
+    #[test]
+    fn test_conditions_and_explanation() {
+        let mut long_context = rule("long", "*", "gemini-2.5-pro");
+        long_context.when = RouteConditions {
+            protocols: vec![InboundProtocol::Claude],
+            min_prompt_tokens: Some(1000),
+            headers: HashMap::from([("x-team".to_string(), "research-*".to_string())]),
+            ..Default::default()
+        };
+        let mut regex_rule = rule("regex", r"^claude-(sonnet|haiku)", "gemini-2.5-flash");
+        regex_rule.match_type = ModelMatchType::Regex;
+        regex_rule.when.has_tools = Some(true);
+        let rules = vec![long_context, regex_rule];
+        let mapping = HashMap::new();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-team", "research-ml".parse().unwrap());
+        let big = RequestFeatures { prompt_tokens: 5000, ..Default::default() };
+        let d = resolve_route(&request("claude-sonnet-4-5", &headers, big), &rules, &mapping);
+        assert_eq!(d.rule_name.as_deref(), Some("long"));
+
+        let tools = RequestFeatures { prompt_tokens: 10, has_tools: true, ..Default::default() };
+        let explanation = explain_route(&request("claude-sonnet-4-5", &headers, tools), &rules, &mapping);
+        assert!(!explanation.rules[0].matched);
+        assert!(explanation.rules[0].reason.contains("< min 1000"));
+        assert!(explanation.rules[1].matched);
+        assert_eq!(explanation.decision.chain, vec!["gemini-2.5-flash"]);
+
+        let mut openai = request("claude-sonnet-4-5", &headers, RequestFeatures::default());
+        openai.protocol = InboundProtocol::OpenAI;
+        let explanation = explain_route(&openai, &rules, &mapping);
+        assert!(explanation.rules[1].reason.contains("has_tools"));
+    }
+
+    #[test]
+    fn test_percentage_split_is_stable() {
+        let mut ab = rule("ab", "claude-*", "claude-sonnet-4-5");
+        ab.splits = vec![RouteSplit {
+            percent: 50,
+            target: ModelMappingTarget::Single("gemini-3-pro-high".to_string()),
+        }];
+        let rules = vec![ab];
+        let mapping = HashMap::new();
+        let headers = HeaderMap::new();
+
+        let mut split_hits = 0;
+        for i in 0..200 {
+            let key = format!("session-{}", i);
+            let mut req = request("claude-opus-4", &headers, RequestFeatures::default());
+            req.split_key = Some(&key);
+            let first = resolve_route(&req, &rules, &mapping);
+            let second = resolve_route(&req, &rules, &mapping);
+            assert_eq!(first.chain, second.chain);
+            if first.split == Some(0) {
+                assert_eq!(first.chain, vec!["gemini-3-pro-high"]);
+                split_hits += 1;
+            }
+        }
+        assert!((60..140).contains(&split_hits), "split hits: {}", split_hits);
+
+        // 分流比例之和超过 100 的配置无法保存
+        let mut config = crate::proxy::config::ProxyConfig::default();
+        config.routing_rules = rules;
+        assert!(config.validate().is_ok());
+        config.routing_rules[0].splits.push(RouteSplit {
+            percent: 60,
+            target: ModelMappingTarget::Single("gemini-2.5-flash".to_string()),
+        });
+        assert!(config.validate().unwrap_err().contains("110%"));
+
+        // 无效正则在保存时即被拒绝，未命名规则按 1 起始编号
+        config.routing_rules[0].splits.pop();
+        let mut bad = rule("", "claude-(", "claude-sonnet-4-5");
+        bad.match_type = ModelMatchType::Regex;
+        config.routing_rules.push(bad);
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("#2") && err.contains("正则"), "{}", err);
+    }
+
+    #[test]
+    fn test_request_features() {
+        let claude = json!({
+            "model": "claude-sonnet-4-5",
+            "system": "You are helpful",
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "tools": [{"name": "search"}],
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "a".repeat(400)},
+                {"type": "image", "source": {"type": "base64", "data": "x".repeat(10000)}}
+            ]}]
+        });
+        let f = RequestFeatures::from_body(&claude);
+        assert!(f.has_tools && f.has_images && f.has_thinking);
+        assert!(f.prompt_tokens >= 100 && f.prompt_tokens < 200);
+
+        let gemini = json!({
+            "contents": [{"role": "user", "parts": [
+                {"text": "hi"},
+                {"inlineData": {"mimeType": "image/png", "data": "AAAA"}}
+            ]}]
+        });
+        let f = RequestFeatures::from_body(&gemini);
+        assert!(f.has_images && !f.has_tools && !f.has_thinking);
+    }
+}
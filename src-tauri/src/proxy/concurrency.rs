@@ -0,0 +1,414 @@
+// 并发限制与排队 (Concurrency limits & FIFO queueing)
+//
+// 为每个账号 (以及账号下的每个模型族) 维护一个信号量。tokio 的 Semaphore 按 FIFO 顺序
+// 唤醒等待者，因此槽位已满时请求会公平排队，直到超时。
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::proxy::sticky_config::ConcurrencyConfig;
+
+/// 将模型名归类为模型族 (用于按模型族限制并发)
+pub fn model_family(model: &str) -> &'static str {
+    let lower = model.to_lowercase();
+    if lower.contains("claude") {
+        "claude"
+    } else if lower.contains("image") {
+        "gemini-image"
+    } else if lower.contains("flash") {
+        "gemini-flash"
+    } else if lower.contains("gemini") {
+        "gemini-pro"
+    } else {
+        "other"
+    }
+}
+
+/// 排队统计 (原子计数器，跨请求共享)
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    waiting: AtomicU64,
+    in_flight: AtomicU64,
+    total_queued: AtomicU64,
+    total_wait_ms: AtomicU64,
+    max_wait_ms: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+/// 排队统计快照 (用于监控展示)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct QueueStatsSnapshot {
+    /// 当前排队中的请求数
+    pub queue_depth: u64,
+    /// 当前占用槽位的请求数
+    pub in_flight: u64,
+    /// 累计排队过的请求数
+    pub total_queued: u64,
+    /// 平均排队等待时间 (毫秒)
+    pub avg_wait_ms: u64,
+    /// 最长排队等待时间 (毫秒)
+    pub max_wait_ms: u64,
+    /// 排队超时次数
+    pub timeouts: u64,
+}
+
+impl QueueStats {
+    pub fn snapshot(&self) -> QueueStatsSnapshot {
+        let total_queued = self.total_queued.load(Ordering::Relaxed);
+        let total_wait_ms = self.total_wait_ms.load(Ordering::Relaxed);
+        QueueStatsSnapshot {
+            queue_depth: self.waiting.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            total_queued,
+            avg_wait_ms: total_wait_ms.checked_div(total_queued).unwrap_or(0),
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_wait(&self, waited: Duration) {
+        let ms = waited.as_millis() as u64;
+        self.total_wait_ms.fetch_add(ms, Ordering::Relaxed);
+        self.max_wait_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+}
+
+/// 并发槽位 (RAII): 持有期间占用账号与模型族的并发额度，Drop 时自动释放
+pub struct ConcurrencySlot {
+    _permits: Vec<OwnedSemaphorePermit>,
+    stats: Arc<QueueStats>,
+}
+
+impl Drop for ConcurrencySlot {
+    fn drop(&mut self) {
+        self.stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 将槽位绑定到流的生命周期上，流结束 (或被客户端断开) 时释放槽位
+pub fn guard_stream<S>(stream: S, slot: ConcurrencySlot) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    stream.map(move |item| {
+        let _ = &slot;
+        item
+    })
+}
+
+/// 带额度记录的信号量 (配置变更时原地调整额度，已占用的槽位继续计入)
+struct LimitedSemaphore {
+    sem: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl LimitedSemaphore {
+    fn new(limit: usize) -> Self {
+        Self {
+            sem: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// 调整额度: 扩大时补发许可; 缩小时先回收空闲许可，不足部分等占用中的槽位释放后回收
+    fn resize(&mut self, limit: usize) {
+        if limit > self.limit {
+            self.sem.add_permits(limit - self.limit);
+        } else if limit < self.limit {
+            let shrink = self.limit - limit;
+            let forgotten = self.sem.forget_permits(shrink);
+            let remaining = shrink - forgotten;
+            if remaining > 0 {
+                match tokio::runtime::Handle::try_current() {
+                    Ok(handle) => {
+                        let sem = self.sem.clone();
+                        handle.spawn(async move {
+                            if let Ok(permits) = sem.acquire_many_owned(remaining as u32).await {
+                                permits.forget();
+                            }
+                        });
+                    }
+                    Err(_) => tracing::warn!("Concurrency limit shrink deferred: no async runtime"),
+                }
+            }
+        }
+        self.limit = limit;
+    }
+}
+
+/// 并发限制器
+pub struct ConcurrencyLimiter {
+    config: RwLock<ConcurrencyConfig>,
+    accounts: DashMap<String, LimitedSemaphore>,
+    families: DashMap<(String, &'static str), LimitedSemaphore>,
+    stats: Arc<QueueStats>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(config: ConcurrencyConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            accounts: DashMap::new(),
+            families: DashMap::new(),
+            stats: Arc::new(QueueStats::default()),
+        }
+    }
+
+    /// 更新限制配置: 保留现有信号量并按新额度调整，占用中的槽位仍计入新的限制
+    pub fn update_config(&self, config: ConcurrencyConfig) {
+        if config.max_per_account == 0 {
+            self.accounts.clear();
+        } else {
+            for mut entry in self.accounts.iter_mut() {
+                entry.value_mut().resize(config.max_per_account);
+            }
+        }
+        self.families.retain(|(_, family), _| config.max_per_model_family.get(*family).is_some_and(|&l| l > 0));
+        for mut entry in self.families.iter_mut() {
+            let family = entry.key().1;
+            if let Some(&limit) = config.max_per_model_family.get(family) {
+                entry.value_mut().resize(limit);
+            }
+        }
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn stats(&self) -> Arc<QueueStats> {
+        self.stats.clone()
+    }
+
+    fn semaphores(&self, account: &str, model: &str) -> Vec<Arc<Semaphore>> {
+        let config = self.config.read().unwrap();
+        let mut out = Vec::new();
+
+        if config.max_per_account > 0 {
+            let sem = self
+                .accounts
+                .entry(account.to_string())
+                .or_insert_with(|| LimitedSemaphore::new(config.max_per_account))
+                .sem
+                .clone();
+            out.push(sem);
+        }
+
+        let family = model_family(model);
+        if let Some(&limit) = config.max_per_model_family.get(family) {
+            if limit > 0 {
+                let sem = self
+                    .families
+                    .entry((account.to_string(), family))
+                    .or_insert_with(|| LimitedSemaphore::new(limit))
+                    .sem
+                    .clone();
+                out.push(sem);
+            }
+        }
+        out
+    }
+
+    /// 账号当前是否还有空闲槽位 (不会占用槽位)
+    pub fn has_capacity(&self, account: &str, model: &str) -> bool {
+        self.semaphores(account, model)
+            .iter()
+            .all(|s| s.available_permits() > 0)
+    }
+
+    /// 单个账号在该模型上最多可同时占用的槽位数 (不限制时为 None)
+    pub fn slot_limit(&self, model: &str) -> Option<usize> {
+        let config = self.config.read().unwrap();
+        let account = Some(config.max_per_account).filter(|&l| l > 0);
+        let family = config
+            .max_per_model_family
+            .get(model_family(model))
+            .copied()
+            .filter(|&l| l > 0);
+        match (account, family) {
+            (Some(a), Some(f)) => Some(a.min(f)),
+            (a, f) => a.or(f),
+        }
+    }
+
+    /// 默认排队超时时间
+    pub fn queue_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.read().unwrap().queue_timeout_seconds)
+    }
+
+    /// 获取并发槽位；槽位已满时按 FIFO 排队，超过 `timeout` 返回错误
+    pub async fn acquire(
+        &self,
+        account: &str,
+        model: &str,
+        timeout: Duration,
+    ) -> Result<ConcurrencySlot, String> {
+        let semaphores = self.semaphores(account, model);
+        let deadline = Instant::now() + timeout;
+        let mut permits = Vec::with_capacity(semaphores.len());
+        let mut queued_at: Option<Instant> = None;
+
+        for sem in semaphores {
+            if let Ok(p) = sem.clone().try_acquire_owned() {
+                permits.push(p);
+                continue;
+            }
+
+            if queued_at.is_none() {
+                queued_at = Some(Instant::now());
+                self.stats.total_queued.fetch_add(1, Ordering::Relaxed);
+            }
+            self.stats.waiting.fetch_add(1, Ordering::Relaxed);
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let result = tokio::time::timeout(remaining, sem.acquire_owned()).await;
+            self.stats.waiting.fetch_sub(1, Ordering::Relaxed);
+
+            match result {
+                Ok(Ok(p)) => permits.push(p),
+                _ => {
+                    self.stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                    if let Some(t) = queued_at {
+                        self.stats.record_wait(t.elapsed());
+                    }
+                    return Err(format!(
+                        "Concurrency queue timeout ({}s) for account {} ({})",
+                        timeout.as_secs(),
+                        account,
+                        model_family(model)
+                    ));
+                }
+            }
+        }
+
+        if let Some(t) = queued_at {
+            let waited = t.elapsed();
+            self.stats.record_wait(waited);
+            tracing::debug!("Concurrency slot for {} acquired after {}ms in queue", account, waited.as_millis());
+        }
+
+        self.stats.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(ConcurrencySlot {
+            _permits: permits,
+            stats: self.stats.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn limiter(per_account: usize) -> ConcurrencyLimiter {
+        ConcurrencyLimiter::new(ConcurrencyConfig {
+            max_per_account: per_account,
+            max_per_model_family: HashMap::new(),
+            queue_timeout_seconds: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_by_default() {
+        let limiter = ConcurrencyLimiter::new(ConcurrencyConfig::default());
+        let _a = limiter.acquire("a", "claude-sonnet-4-5", Duration::from_millis(10)).await.unwrap();
+        let _b = limiter.acquire("a", "claude-sonnet-4-5", Duration::from_millis(10)).await.unwrap();
+        assert!(limiter.has_capacity("a", "claude-sonnet-4-5"));
+        assert_eq!(limiter.stats().snapshot().in_flight, 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout_when_full() {
+        let limiter = limiter(1);
+        let slot = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+        assert!(!limiter.has_capacity("a", "gemini-3-flash"));
+        assert!(limiter.has_capacity("b", "gemini-3-flash"));
+
+        let err = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(20)).await;
+        assert!(err.is_err());
+        assert_eq!(limiter.stats().snapshot().timeouts, 1);
+
+        drop(slot);
+        assert!(limiter.has_capacity("a", "gemini-3-flash"));
+        assert_eq!(limiter.stats().snapshot().in_flight, 0);
+    }
+
+    #[test]
+    fn test_slot_limit_takes_tightest_limit() {
+        assert_eq!(ConcurrencyLimiter::new(ConcurrencyConfig::default()).slot_limit("gemini-3-flash"), None);
+
+        let mut families = HashMap::new();
+        families.insert("gemini-flash".to_string(), 2);
+        let limiter = ConcurrencyLimiter::new(ConcurrencyConfig {
+            max_per_account: 4,
+            max_per_model_family: families,
+            queue_timeout_seconds: 1,
+        });
+        assert_eq!(limiter.slot_limit("gemini-3-flash"), Some(2));
+        assert_eq!(limiter.slot_limit("claude-sonnet-4-5"), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_waiter_acquires_after_release() {
+        let limiter = Arc::new(limiter(1));
+        let slot = limiter.acquire("a", "gemini-3-pro-high", Duration::from_millis(10)).await.unwrap();
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                limiter.acquire("a", "gemini-3-pro-high", Duration::from_secs(2)).await.is_ok()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limiter.stats().snapshot().queue_depth, 1);
+
+        drop(slot);
+        assert!(waiter.await.unwrap());
+        let stats = limiter.stats().snapshot();
+        assert_eq!(stats.total_queued, 1);
+        assert_eq!(stats.queue_depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_model_family_limit() {
+        let limiter = ConcurrencyLimiter::new(ConcurrencyConfig {
+            max_per_account: 0,
+            max_per_model_family: HashMap::from([("claude".to_string(), 1)]),
+            queue_timeout_seconds: 1,
+        });
+        let _slot = limiter.acquire("a", "claude-opus-4-5-thinking", Duration::from_millis(10)).await.unwrap();
+        assert!(!limiter.has_capacity("a", "claude-sonnet-4-5"));
+        assert!(limiter.has_capacity("a", "gemini-3-flash"));
+    }
+
+    #[tokio::test]
+    async fn test_update_config_keeps_in_flight_slots() {
+        let limiter = limiter(2);
+        let a = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+        let b = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+
+        // 缩小到 1: 两个占用中的槽位仍计入，释放一个后依然没有空闲
+        limiter.update_config(ConcurrencyConfig {
+            max_per_account: 1,
+            max_per_model_family: HashMap::new(),
+            queue_timeout_seconds: 1,
+        });
+        assert!(!limiter.has_capacity("a", "gemini-3-flash"));
+        drop(a);
+        tokio::task::yield_now().await;
+        assert!(!limiter.has_capacity("a", "gemini-3-flash"));
+        drop(b);
+        assert!(limiter.has_capacity("a", "gemini-3-flash"));
+
+        // 扩大到 3: 补发许可
+        let _c = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+        limiter.update_config(ConcurrencyConfig {
+            max_per_account: 3,
+            max_per_model_family: HashMap::new(),
+            queue_timeout_seconds: 1,
+        });
+        let _d = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+        let _e = limiter.acquire("a", "gemini-3-flash", Duration::from_millis(10)).await.unwrap();
+        assert!(!limiter.has_capacity("a", "gemini-3-flash"));
+    }
+}
@@ -144,6 +144,212 @@ impl Default for ExperimentalConfig {
 
 fn default_true() -> bool { true }
 
+/// 熔断器配置 (上游端点与账号共用)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// 是否启用熔断
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 失败率统计窗口 (秒)
+    #[serde(default = "default_cb_window_seconds")]
+    pub window_seconds: u64,
+
+    /// 窗口内至少需要多少次请求才会按失败率判定
+    #[serde(default = "default_cb_min_requests")]
+    pub min_requests: u32,
+
+    /// 触发熔断的失败率 (0.0-1.0)
+    #[serde(default = "default_cb_failure_rate")]
+    pub failure_rate_threshold: f64,
+
+    /// 连续失败多少次直接熔断
+    #[serde(default = "default_cb_consecutive_failures")]
+    pub consecutive_failures: u32,
+
+    /// 熔断后多久进入半开状态 (秒)
+    #[serde(default = "default_cb_open_seconds")]
+    pub open_seconds: u64,
+
+    /// 半开状态下允许同时进行的探测请求数
+    #[serde(default = "default_cb_half_open_probes")]
+    pub half_open_max_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: default_cb_window_seconds(),
+            min_requests: default_cb_min_requests(),
+            failure_rate_threshold: default_cb_failure_rate(),
+            consecutive_failures: default_cb_consecutive_failures(),
+            open_seconds: default_cb_open_seconds(),
+            half_open_max_probes: default_cb_half_open_probes(),
+        }
+    }
+}
+
+/// 请求对冲配置 (非流式短请求)
+///
+/// 仅作用于 Anthropic 协议 (`/v1/messages`)；OpenAI 与 Gemini 协议的请求不会被对冲。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgingConfig {
+    /// 是否启用请求对冲 (仅 Claude 协议)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 仅对后台任务 (标题生成/摘要等) 启用，关闭后对所有非流式 Claude 请求生效
+    #[serde(default = "default_true")]
+    pub background_only: bool,
+
+    /// 触发对冲的耗时分位数 (默认 p95)
+    #[serde(default = "default_hedge_percentile")]
+    pub percentile: f64,
+
+    /// 样本不足时使用的默认延迟 (毫秒)
+    #[serde(default = "default_hedge_delay_ms")]
+    pub default_delay_ms: u64,
+
+    /// 延迟下限 (毫秒)
+    #[serde(default = "default_hedge_min_delay_ms")]
+    pub min_delay_ms: u64,
+
+    /// 延迟上限 (毫秒)
+    #[serde(default = "default_hedge_max_delay_ms")]
+    pub max_delay_ms: u64,
+
+    /// 使用分位数前至少需要的样本数
+    #[serde(default = "default_hedge_min_samples")]
+    pub min_samples: usize,
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            background_only: true,
+            percentile: default_hedge_percentile(),
+            default_delay_ms: default_hedge_delay_ms(),
+            min_delay_ms: default_hedge_min_delay_ms(),
+            max_delay_ms: default_hedge_max_delay_ms(),
+            min_samples: default_hedge_min_samples(),
+        }
+    }
+}
+
+/// 流式断线续传配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamFailoverConfig {
+    /// 是否启用: 上游 SSE 中途断开时，换号并以已输出内容为 prefill 续写 (OpenAI 协议仅对 n = 1 的请求生效)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个请求最多续传次数
+    #[serde(default = "default_stream_max_resumes")]
+    pub max_resumes: usize,
+}
+
+impl Default for StreamFailoverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_resumes: default_stream_max_resumes(),
+        }
+    }
+}
+
+fn default_stream_max_resumes() -> usize { 2 }
+
+/// 账号热重载配置 (轮询账号存储的元数据指纹，而非文件系统事件)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountWatchConfig {
+    /// 是否启用: 定期检查账号存储，增量同步外部新增/修改/删除的账号
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 轮询间隔 (毫秒)，外部写入最迟在一个间隔后生效
+    #[serde(default = "default_account_watch_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for AccountWatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: default_account_watch_interval_ms(),
+        }
+    }
+}
+
+fn default_account_watch_interval_ms() -> u64 { 2000 }
+
+/// 账号健康度与自动隔离配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountHealthConfig {
+    /// 是否启用: 健康分过低的账号自动隔离，探测成功后恢复
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 统计窗口 (秒)
+    #[serde(default = "default_health_window_seconds")]
+    pub window_seconds: u64,
+
+    /// 窗口内最多保留的请求结果数
+    #[serde(default = "default_health_window_size")]
+    pub window_size: usize,
+
+    /// 窗口内至少需要多少次请求才会判定隔离
+    #[serde(default = "default_health_min_samples")]
+    pub min_samples: usize,
+
+    /// 健康分低于该值时隔离 (0-100)
+    #[serde(default = "default_health_quarantine_threshold")]
+    pub quarantine_threshold: f64,
+
+    /// 隔离期间的探测间隔 (秒)
+    #[serde(default = "default_health_probe_interval_seconds")]
+    pub probe_interval_seconds: u64,
+
+    /// 连续探测成功多少次后恢复
+    #[serde(default = "default_health_restore_after_probes")]
+    pub restore_after_probes: u32,
+}
+
+impl Default for AccountHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: default_health_window_seconds(),
+            window_size: default_health_window_size(),
+            min_samples: default_health_min_samples(),
+            quarantine_threshold: default_health_quarantine_threshold(),
+            probe_interval_seconds: default_health_probe_interval_seconds(),
+            restore_after_probes: default_health_restore_after_probes(),
+        }
+    }
+}
+
+fn default_health_window_seconds() -> u64 { 900 }
+fn default_health_window_size() -> usize { 20 }
+fn default_health_min_samples() -> usize { 5 }
+fn default_health_quarantine_threshold() -> f64 { 40.0 }
+fn default_health_probe_interval_seconds() -> u64 { 120 }
+fn default_health_restore_after_probes() -> u32 { 1 }
+
+fn default_hedge_percentile() -> f64 { 0.95 }
+fn default_hedge_delay_ms() -> u64 { 3000 }
+fn default_hedge_min_delay_ms() -> u64 { 300 }
+fn default_hedge_max_delay_ms() -> u64 { 15000 }
+fn default_hedge_min_samples() -> usize { 20 }
+
+fn default_cb_window_seconds() -> u64 { 60 }
+fn default_cb_min_requests() -> u32 { 10 }
+fn default_cb_failure_rate() -> f64 { 0.5 }
+fn default_cb_consecutive_failures() -> u32 { 5 }
+fn default_cb_open_seconds() -> u64 { 30 }
+fn default_cb_half_open_probes() -> u32 { 1 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ModelMappingTarget {
@@ -160,6 +366,626 @@ impl ModelMappingTarget {
     }
 }
 
+/// 入站协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InboundProtocol {
+    Claude,
+    OpenAI,
+    Gemini,
+}
+
+/// 模型名匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelMatchType {
+    /// 支持 `*` (任意字符) 与 `?` (单个字符)，不含通配符时等同精确匹配
+    #[default]
+    Glob,
+    Regex,
+    Exact,
+}
+
+/// 路由规则的附加条件 (未设置的条件视为满足)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RouteConditions {
+    /// 入站协议 (为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<InboundProtocol>,
+
+    /// 请求携带的 API Key (为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+
+    /// 请求头条件 (header 名 -> glob 值，`*` 表示只要求存在)
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub headers: std::collections::HashMap<String, String>,
+
+    /// 估算的 prompt token 数下限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_prompt_tokens: Option<u64>,
+
+    /// 估算的 prompt token 数上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prompt_tokens: Option<u64>,
+
+    /// 是否携带工具定义
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_tools: Option<bool>,
+
+    /// 是否包含图片
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_images: Option<bool>,
+
+    /// 是否开启思考
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_thinking: Option<bool>,
+}
+
+/// A/B 分流: 按百分比将命中规则的请求路由到另一条模型链
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSplit {
+    /// 分流比例 (0-100)，各分流之和不足 100 的部分使用规则的默认目标
+    pub percent: u8,
+    pub target: ModelMappingTarget,
+}
+
+/// 有序路由规则 (按顺序匹配，第一条命中的规则生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// 规则名称 (用于日志与路由解释)
+    #[serde(default)]
+    pub name: String,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 模型名匹配模式
+    pub model: String,
+
+    #[serde(default)]
+    pub match_type: ModelMatchType,
+
+    #[serde(default)]
+    pub when: RouteConditions,
+
+    /// 目标模型链
+    pub target: ModelMappingTarget,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<RouteSplit>,
+}
+
+impl RoutingRule {
+    /// 日志、路由解释与校验错误中使用的规则名 (未命名时按 1 起始的序号显示)
+    pub fn label(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("#{}", index + 1)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Gemini 安全过滤阈值
+/// 未在策略中配置时可通过 GEMINI_SAFETY_THRESHOLD 环境变量设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SafetyThreshold {
+    /// Disable all safety filters (default for proxy compatibility)
+    #[serde(rename = "off")]
+    Off,
+    /// Block low probability and above
+    #[serde(rename = "low")]
+    BlockLowAndAbove,
+    /// Block medium probability and above
+    #[serde(rename = "medium")]
+    BlockMediumAndAbove,
+    /// Only block high probability content
+    #[serde(rename = "high")]
+    BlockOnlyHigh,
+    /// Don't block anything (BLOCK_NONE)
+    #[serde(rename = "none")]
+    BlockNone,
+}
+
+impl SafetyThreshold {
+    /// 解析阈值名称 (off / low / medium / high / none，不区分大小写)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(SafetyThreshold::Off),
+            "low" => Some(SafetyThreshold::BlockLowAndAbove),
+            "medium" => Some(SafetyThreshold::BlockMediumAndAbove),
+            "high" => Some(SafetyThreshold::BlockOnlyHigh),
+            "none" => Some(SafetyThreshold::BlockNone),
+            _ => None,
+        }
+    }
+
+    /// Get threshold from environment variable or default to Off
+    pub fn from_env() -> Self {
+        std::env::var("GEMINI_SAFETY_THRESHOLD")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(SafetyThreshold::Off) // Default: maintain current behavior
+    }
+
+    /// Convert to Gemini API threshold string
+    pub fn to_gemini_threshold(&self) -> &'static str {
+        match self {
+            SafetyThreshold::Off => "OFF",
+            SafetyThreshold::BlockLowAndAbove => "BLOCK_LOW_AND_ABOVE",
+            SafetyThreshold::BlockMediumAndAbove => "BLOCK_MEDIUM_AND_ABOVE",
+            SafetyThreshold::BlockOnlyHigh => "BLOCK_ONLY_HIGH",
+            SafetyThreshold::BlockNone => "BLOCK_NONE",
+        }
+    }
+}
+
+/// 按路由生效的提示词策略 (覆盖全局设置中对应的字段)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptRoutePolicy {
+    /// 策略名称 (可通过 X-Prompt-Policy 请求头指定)
+    #[serde(default)]
+    pub name: String,
+
+    /// 模型名 glob 匹配 (匹配客户端请求的模型名，为空时匹配全部)
+    #[serde(default)]
+    pub model: String,
+
+    /// 入站协议 (为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<InboundProtocol>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inject_identity: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prefix: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_suffix: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_threshold: Option<SafetyThreshold>,
+}
+
+/// 出站消息脱敏规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionRule {
+    #[serde(default)]
+    pub name: String,
+
+    /// 正则表达式
+    pub pattern: String,
+
+    #[serde(default = "default_redaction_replacement")]
+    pub replacement: String,
+}
+
+fn default_redaction_replacement() -> String { "[REDACTED]".to_string() }
+
+/// 系统提示词注入与请求改写策略 (对 Claude / OpenAI / Gemini 协议统一生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptPolicyConfig {
+    /// 是否注入 Antigravity 身份提示词与结束标记
+    #[serde(default = "default_true")]
+    pub inject_identity: bool,
+
+    /// 系统提示词前缀模板 (支持 {{model}} / {{mapped_model}} / {{protocol}} / {{date}})
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prefix: Option<String>,
+
+    /// 系统提示词后缀模板
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_suffix: Option<String>,
+
+    /// 安全阈值 (未设置时沿用各协议原有行为)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_threshold: Option<SafetyThreshold>,
+
+    /// 按路由覆盖 (按顺序匹配，第一条命中的生效)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<PromptRoutePolicy>,
+
+    /// 出站消息脱敏规则 (作用于文本、工具调用参数与工具结果)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redactions: Vec<RedactionRule>,
+
+    /// 是否允许通过请求头 (X-Prompt-Policy / X-Prompt-Identity / X-Safety-Threshold) 覆盖策略
+    #[serde(default)]
+    pub allow_header_overrides: bool,
+}
+
+impl Default for PromptPolicyConfig {
+    fn default() -> Self {
+        Self {
+            inject_identity: true,
+            system_prefix: None,
+            system_suffix: None,
+            safety_threshold: None,
+            routes: Vec::new(),
+            redactions: Vec::new(),
+            allow_header_overrides: false,
+        }
+    }
+}
+
+/// 后台任务分类器 (按顺序匹配，第一条命中的生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundClassifier {
+    /// 任务类型名称 (用于日志与监控计数)
+    pub name: String,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 最后一条用户消息前 500 字符中包含任一关键词即命中
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+
+    /// 正则匹配 (与关键词为“或”关系，两者都为空时不检查文本)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+
+    /// 必须同时包含的关键词 (全部出现才命中，与上面的关键词/正则为“且”关系)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_keywords: Vec<String>,
+
+    /// 最后一条用户消息的最大长度 (字节)，超过时不视为后台任务
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+
+    /// 是否要求请求携带工具定义 (未设置时不限)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_tools: Option<bool>,
+
+    /// 目标模型或模型链
+    pub target: ModelMappingTarget,
+
+    /// 专用账号组 (account_groups 中的名称，未设置时使用完整账号池)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_group: Option<String>,
+}
+
+impl BackgroundClassifier {
+    fn builtin(name: &str, keywords: &[&str], target: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: true,
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            patterns: Vec::new(),
+            required_keywords: Vec::new(),
+            max_length: Some(800),
+            has_tools: None,
+            target: ModelMappingTarget::Single(target.to_string()),
+            account_group: None,
+        }
+    }
+}
+
+/// 后台任务 (标题生成、摘要、建议等) 检测与降级路由
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 参与检测的入站协议 (默认 Claude / OpenAI / Gemini 全部启用)
+    #[serde(default = "default_background_protocols")]
+    pub protocols: Vec<InboundProtocol>,
+
+    /// 分类器列表 (未配置时使用内置的 Claude Code 后台任务规则)
+    #[serde(default = "default_background_classifiers")]
+    pub classifiers: Vec<BackgroundClassifier>,
+
+    /// 账号组 (组名 -> 账号邮箱列表)
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub account_groups: std::collections::HashMap<String, Vec<String>>,
+}
+
+impl Default for BackgroundTaskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            protocols: default_background_protocols(),
+            classifiers: default_background_classifiers(),
+            account_groups: std::collections::HashMap::new(),
+        }
+    }
+}
+
+fn default_background_protocols() -> Vec<InboundProtocol> {
+    vec![InboundProtocol::Claude, InboundProtocol::OpenAI, InboundProtocol::Gemini]
+}
+
+/// 内置规则 (沿用原先 Claude 处理器中的关键词表与匹配优先级)
+fn default_background_classifiers() -> Vec<BackgroundClassifier> {
+    const LITE: &str = "gemini-2.5-flash-lite";
+    const STANDARD: &str = "gemini-2.5-flash";
+    const SUMMARY_KEYWORDS: &[&str] = &[
+        "Summarize this coding conversation",
+        "Summarize the conversation",
+        "Concise summary",
+        "compress the context",
+        "Provide a concise summary",
+        "condense the previous messages",
+        "shorten the conversation history",
+        "extract key points from",
+    ];
+
+    // 仅包含 "in under 50 characters" 的普通提问不视为摘要任务
+    let mut simple_summary = BackgroundClassifier::builtin("simple_summary", SUMMARY_KEYWORDS, LITE);
+    simple_summary.required_keywords = vec!["in under 50 characters".to_string()];
+
+    vec![
+        BackgroundClassifier::builtin(
+            "system_message",
+            &["Warmup", "<system-reminder>", "This is a system message"],
+            LITE,
+        ),
+        BackgroundClassifier::builtin(
+            "title_generation",
+            &[
+                "write a 5-10 word title",
+                "Please write a 5-10 word title",
+                "Respond with the title",
+                "Generate a title for",
+                "Create a brief title",
+                "title for the conversation",
+                "conversation title",
+                "生成标题",
+                "为对话起个标题",
+            ],
+            LITE,
+        ),
+        simple_summary,
+        BackgroundClassifier::builtin("context_compression", SUMMARY_KEYWORDS, STANDARD),
+        BackgroundClassifier::builtin(
+            "prompt_suggestion",
+            &[
+                "prompt suggestion generator",
+                "suggest next prompts",
+                "what should I ask next",
+                "generate follow-up questions",
+                "recommend next steps",
+                "possible next actions",
+            ],
+            LITE,
+        ),
+        BackgroundClassifier::builtin(
+            "environment_probe",
+            &[
+                "check current directory",
+                "list available tools",
+                "verify environment",
+                "test connection",
+            ],
+            LITE,
+        ),
+    ]
+}
+
+/// Claude cache_control 提示词缓存 (基于上游隐式缓存与账号亲和)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptCacheConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 未指定 ttl 的断点的缓存时长 (秒)
+    #[serde(default = "default_prompt_cache_ttl")]
+    pub ttl_seconds: u64,
+
+    /// 可缓存前缀的最小估算 token 数
+    #[serde(default = "default_prompt_cache_min_tokens")]
+    pub min_prefix_tokens: u32,
+
+    /// 内存中最多记录的前缀数量
+    #[serde(default = "default_prompt_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for PromptCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_seconds: default_prompt_cache_ttl(),
+            min_prefix_tokens: default_prompt_cache_min_tokens(),
+            max_entries: default_prompt_cache_max_entries(),
+        }
+    }
+}
+
+fn default_prompt_cache_ttl() -> u64 { 300 }
+fn default_prompt_cache_min_tokens() -> u32 { 1024 }
+fn default_prompt_cache_max_entries() -> usize { 2000 }
+
+/// 确定性请求的响应缓存 (默认关闭)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 缓存有效期 (秒)
+    #[serde(default = "default_response_cache_ttl")]
+    pub ttl_seconds: u64,
+
+    /// 内存中最多保留的条目数
+    #[serde(default = "default_response_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// 是否持久化到 SQLite
+    #[serde(default = "default_true")]
+    pub persist: bool,
+
+    /// SQLite 中最多保留的条目数
+    #[serde(default = "default_response_cache_max_persisted")]
+    pub max_persisted_entries: usize,
+
+    /// 单条响应的最大字节数，超过时不缓存
+    #[serde(default = "default_response_cache_max_entry_bytes")]
+    pub max_entry_bytes: usize,
+
+    /// 仅缓存 temperature 为 0 的请求
+    #[serde(default = "default_true")]
+    pub deterministic_only: bool,
+
+    /// 启用缓存的模型 (客户端请求的模型名 glob，为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 启用缓存的 API Key (为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_seconds: default_response_cache_ttl(),
+            max_entries: default_response_cache_max_entries(),
+            persist: true,
+            max_persisted_entries: default_response_cache_max_persisted(),
+            max_entry_bytes: default_response_cache_max_entry_bytes(),
+            deterministic_only: true,
+            models: Vec::new(),
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+fn default_response_cache_ttl() -> u64 { 3600 }
+fn default_response_cache_max_entries() -> usize { 500 }
+fn default_response_cache_max_persisted() -> usize { 10_000 }
+fn default_response_cache_max_entry_bytes() -> usize { 2 * 1024 * 1024 }
+
+/// 超出上下文窗口时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextStrategy {
+    /// 丢弃最早的对话轮次
+    #[default]
+    Truncate,
+    /// 用廉价模型将最早的对话轮次压缩为摘要 (失败时退化为丢弃)
+    Summarize,
+}
+
+/// 上下文窗口管理 (默认关闭)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextWindowConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub strategy: ContextStrategy,
+
+    /// 参与管理的入站协议
+    #[serde(default = "default_context_protocols")]
+    pub protocols: Vec<InboundProtocol>,
+
+    /// 允许占用目标模型上下文窗口的比例 (为估算误差预留余量)
+    #[serde(default = "default_context_target_ratio")]
+    pub target_ratio: f64,
+
+    /// 始终保留的最近对话轮次数 (以用户提问划分轮次)
+    #[serde(default = "default_context_keep_turns")]
+    pub keep_recent_turns: usize,
+
+    /// 生成摘要使用的模型
+    #[serde(default = "default_context_summary_model")]
+    pub summary_model: String,
+
+    /// 摘要的最大输出 token 数
+    #[serde(default = "default_context_summary_tokens")]
+    pub summary_max_tokens: u32,
+}
+
+impl Default for ContextWindowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strategy: ContextStrategy::default(),
+            protocols: default_context_protocols(),
+            target_ratio: default_context_target_ratio(),
+            keep_recent_turns: default_context_keep_turns(),
+            summary_model: default_context_summary_model(),
+            summary_max_tokens: default_context_summary_tokens(),
+        }
+    }
+}
+
+fn default_context_protocols() -> Vec<InboundProtocol> {
+    vec![InboundProtocol::Claude, InboundProtocol::OpenAI]
+}
+fn default_context_target_ratio() -> f64 { 0.85 }
+fn default_context_keep_turns() -> usize { 2 }
+fn default_context_summary_model() -> String { "gemini-2.5-flash-lite".to_string() }
+fn default_context_summary_tokens() -> u32 { 2048 }
+
+/// 多候选结果 (OpenAI n > 1)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiCandidateConfig {
+    /// 不接受 candidateCount 的模型 (支持通配符)，这些模型改为并行发送 n 个单候选请求
+    /// 上游以 400 拒绝 candidateCount 的模型/账号组合也会被自动记录并改用并行请求
+    #[serde(default = "default_fan_out_models")]
+    pub fan_out_models: Vec<String>,
+
+    /// 并行拆分时允许的最大 n，超出时直接返回 400
+    #[serde(default = "default_max_fan_out")]
+    pub max_fan_out: u32,
+}
+
+impl Default for MultiCandidateConfig {
+    fn default() -> Self {
+        Self {
+            fan_out_models: default_fan_out_models(),
+            max_fan_out: default_max_fan_out(),
+        }
+    }
+}
+
+fn default_fan_out_models() -> Vec<String> {
+    vec!["claude-*".to_string()]
+}
+fn default_max_fan_out() -> u32 { 8 }
+
+/// 协议转换调试: 请求/响应抓取 (默认关闭)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceCaptureConfig {
+    /// 抓取所有匹配 `models` 的请求
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 允许客户端通过 `x-trace-capture: on` 请求头抓取单个请求 (即使 enabled 为 false)
+    #[serde(default)]
+    pub header_trigger: bool,
+
+    /// 抓取文件目录 (默认为数据目录下的 traces/)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+
+    /// 最多保留的抓取文件数，超出时删除最旧的文件
+    #[serde(default = "default_trace_max_files")]
+    pub max_files: usize,
+
+    /// 抓取的模型 (客户端请求的模型名 glob，为空时不限)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+}
+
+impl Default for TraceCaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header_trigger: false,
+            dir: None,
+            max_files: default_trace_max_files(),
+            models: Vec::new(),
+        }
+    }
+}
+
+fn default_trace_max_files() -> usize { 200 }
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -194,6 +1020,10 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub custom_mapping: std::collections::HashMap<String, ModelMappingTarget>,
 
+    /// 有序路由规则 (优先于 custom_mapping)
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+
     /// API 请求超时时间(秒)
     #[serde(default = "default_request_timeout")]
     pub request_timeout: u64,
@@ -225,6 +1055,54 @@ pub struct ProxyConfig {
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+
+    /// 熔断器配置 (上游端点/账号)
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    /// 请求对冲配置 (仅 Claude 协议)
+    #[serde(default)]
+    pub hedging: HedgingConfig,
+
+    /// 流式断线续传配置
+    #[serde(default)]
+    pub stream_failover: StreamFailoverConfig,
+
+    /// 账号热重载配置
+    #[serde(default)]
+    pub account_watch: AccountWatchConfig,
+
+    /// 账号健康度与自动隔离配置
+    #[serde(default)]
+    pub account_health: AccountHealthConfig,
+
+    /// 系统提示词注入与请求改写策略
+    #[serde(default)]
+    pub prompt_policy: PromptPolicyConfig,
+
+    /// 后台任务检测与降级路由
+    #[serde(default)]
+    pub background_tasks: BackgroundTaskConfig,
+
+    /// Claude cache_control 提示词缓存
+    #[serde(default)]
+    pub prompt_cache: PromptCacheConfig,
+
+    /// 确定性请求的响应缓存
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+
+    /// 协议转换调试抓取
+    #[serde(default)]
+    pub trace_capture: TraceCaptureConfig,
+
+    /// 上下文窗口管理 (超长对话的截断/摘要)
+    #[serde(default)]
+    pub context_window: ContextWindowConfig,
+
+    /// 多候选结果 (n > 1) 的并行拆分
+    #[serde(default)]
+    pub multi_candidate: MultiCandidateConfig,
 }
 
 /// 上游代理配置
@@ -246,6 +1124,7 @@ impl Default for ProxyConfig {
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
             auto_start: false,
             custom_mapping: std::collections::HashMap::new(),
+            routing_rules: Vec::new(),
             request_timeout: default_request_timeout(),
             enable_logging: false, // 默认关闭，节省性能
             log_stream_content: false, // 默认关闭
@@ -254,6 +1133,18 @@ impl Default for ProxyConfig {
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             quota_priority_enabled: false,
             experimental: ExperimentalConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            hedging: HedgingConfig::default(),
+            stream_failover: StreamFailoverConfig::default(),
+            account_watch: AccountWatchConfig::default(),
+            account_health: AccountHealthConfig::default(),
+            prompt_policy: PromptPolicyConfig::default(),
+            background_tasks: BackgroundTaskConfig::default(),
+            prompt_cache: PromptCacheConfig::default(),
+            response_cache: ResponseCacheConfig::default(),
+            trace_capture: TraceCaptureConfig::default(),
+            context_window: ContextWindowConfig::default(),
+            multi_candidate: MultiCandidateConfig::default(),
         }
     }
 }
@@ -279,6 +1170,34 @@ fn default_zai_haiku_model() -> String {
 }
 
 impl ProxyConfig {
+    /// 需要加密落盘的敏感字段 (API Key、上游代理地址等)
+    ///
+    /// 新增存放密钥或凭据的字段时必须加入此列表，保存配置时会据此加密。
+    pub fn secret_fields_mut(&mut self) -> Vec<&mut String> {
+        let mut fields = vec![&mut self.api_key, &mut self.zai.api_key, &mut self.upstream_proxy.url];
+        for rule in &mut self.routing_rules {
+            fields.extend(rule.when.api_keys.iter_mut());
+        }
+        fields.extend(self.response_cache.api_keys.iter_mut());
+        fields
+    }
+
+    /// 校验无法在反序列化时发现的配置错误 (保存配置前调用)
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, rule) in self.routing_rules.iter().enumerate() {
+            if rule.match_type == ModelMatchType::Regex {
+                if let Err(e) = regex::Regex::new(&rule.model) {
+                    return Err(format!("路由规则 {} 的正则表达式无效: {}", rule.label(i), e));
+                }
+            }
+            let total: u32 = rule.splits.iter().map(|s| s.percent as u32).sum();
+            if total > 100 {
+                return Err(format!("路由规则 {} 的分流比例之和为 {}%，不能超过 100%", rule.label(i), total));
+            }
+        }
+        Ok(())
+    }
+
     /// 获取实际的监听地址
     /// - allow_lan_access = false: 返回 "127.0.0.1"（默认，隐私优先）
     /// - allow_lan_access = true: 返回 "0.0.0.0"（允许局域网访问）
@@ -0,0 +1,426 @@
+// 上下文窗口管理
+//
+// 在发往上游之前估算 v1internal 请求体的 token 数，超过目标模型上下文窗口时按配置丢弃或摘要最早的对话轮次。
+// 轮次以"用户提问"划分 (functionResponse 属于发起调用的那一轮)，整轮丢弃可以保证 functionCall/functionResponse
+// 成对出现，当前工具循环与其 thoughtSignature 始终位于保留的最近轮次中。
+use serde_json::{json, Value};
+
+use crate::proxy::common::model_capabilities;
+use crate::proxy::common::model_router::{cached_regex, estimate_tokens};
+use crate::proxy::config::{ContextStrategy, ContextWindowConfig, InboundProtocol};
+use crate::proxy::upstream::client::UpstreamClient;
+
+/// 响应头: 本次请求被裁剪的内容
+pub const TRIM_HEADER: &str = "X-Context-Trimmed";
+
+/// 摘要输入的字符上限 (超出时保留较新的部分)
+const MAX_TRANSCRIPT_CHARS: usize = 400_000;
+
+/// 单个工具结果在摘要输入中的字符上限
+const MAX_TOOL_RESULT_CHARS: usize = 2_000;
+
+const SUMMARY_INSTRUCTION: &str = "Summarize the following earlier part of a conversation between a user and an AI assistant. \
+Preserve the user's goals, decisions made, important facts, file names, identifiers and the outcome of tool calls. \
+Write a concise summary in the language of the conversation, without any preamble.";
+
+/// 一次裁剪的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextTrim {
+    /// 实际采用的策略 (摘要失败时为 Truncate)
+    pub strategy: ContextStrategy,
+    /// 丢弃的对话轮次数
+    pub turns: usize,
+    /// 丢弃的 contents 条目数
+    pub contents: usize,
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+}
+
+impl ContextTrim {
+    pub fn header_value(&self) -> String {
+        let strategy = match self.strategy {
+            ContextStrategy::Truncate => "truncate",
+            ContextStrategy::Summarize => "summarize",
+        };
+        format!(
+            "{}; turns={}; contents={}; tokens={}->{}",
+            strategy, self.turns, self.contents, self.tokens_before, self.tokens_after
+        )
+    }
+}
+
+/// 在响应上标记裁剪信息
+pub fn annotate(mut response: axum::response::Response, trim: Option<&ContextTrim>) -> axum::response::Response {
+    if let Some(value) = trim.and_then(|t| axum::http::HeaderValue::from_str(&t.header_value()).ok()) {
+        response.headers_mut().insert(TRIM_HEADER, value);
+    }
+    response
+}
+
+/// 上游是否因上下文超限拒绝了请求
+pub fn is_context_overflow(error_text: &str) -> bool {
+    let text = error_text.to_lowercase();
+    text.contains("input token count")
+        || text.contains("exceeds the maximum number of tokens")
+        || text.contains("prompt is too long")
+        || text.contains("context length")
+}
+
+/// 从上游错误中解析 (实际 token 数, 上限)
+fn parse_overflow(error_text: &str) -> Option<(u64, u64)> {
+    let re = cached_regex(r"input token count \((\d+)\) exceeds the maximum number of tokens allowed \((\d+)\)").ok()?;
+    let caps = re.captures(error_text)?;
+    Some((caps[1].parse().ok()?, caps[2].parse().ok()?))
+}
+
+/// 裁剪计划: 丢弃 contents 的前 `drop` 条 (共 `turns` 轮)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TrimPlan {
+    drop: usize,
+    turns: usize,
+    tokens_before: u64,
+}
+
+/// 单个请求的上下文管理器 (跨重试保留预算修正与摘要结果)
+pub struct ContextManager {
+    config: ContextWindowConfig,
+    active: bool,
+    /// 上游报告超限后的预算修正系数
+    scale: f64,
+    tightened: bool,
+    /// (丢弃的 contents 条目数, 摘要)
+    summary: Option<(usize, String)>,
+}
+
+impl ContextManager {
+    pub fn new(config: &ContextWindowConfig, protocol: InboundProtocol) -> Self {
+        Self {
+            config: config.clone(),
+            active: config.enabled && config.protocols.contains(&protocol),
+            scale: 1.0,
+            tightened: false,
+            summary: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// 上游报告上下文超限时，按实际 token 数修正估算偏差；仅修正一次，返回是否值得重试
+    pub fn tighten(&mut self, error_text: &str) -> bool {
+        if !self.active || self.tightened {
+            return false;
+        }
+        self.tightened = true;
+        let factor = match parse_overflow(error_text) {
+            Some((actual, limit)) if actual > 0 => (limit as f64 / actual as f64).min(1.0) * 0.9,
+            _ => 0.75,
+        };
+        self.scale *= factor;
+        tracing::warn!("[Context] Upstream reported context overflow, tightening budget by {:.2}", factor);
+        true
+    }
+
+    fn budget(&self, model: &str) -> u64 {
+        let window = model_capabilities::lookup(model).context_window as f64;
+        (window * self.config.target_ratio * self.scale) as u64
+    }
+
+    /// 请求超出目标模型的上下文窗口时就地裁剪 v1internal 请求体
+    pub async fn fit(
+        &mut self,
+        body: &mut Value,
+        model: &str,
+        upstream: &UpstreamClient,
+        access_token: &str,
+        project_id: &str,
+    ) -> Option<ContextTrim> {
+        if !self.active {
+            return None;
+        }
+        let budget = self.budget(model);
+        let plan = plan_trim(body, budget, self.config.keep_recent_turns)?;
+
+        let mut strategy = self.config.strategy;
+        let note = match strategy {
+            ContextStrategy::Summarize => match self.summarize(body, plan.drop, upstream, access_token, project_id).await {
+                Some(summary) => format!(
+                    "[Summary of {} earlier conversation turn(s), condensed to fit the model's context window]\n{}",
+                    plan.turns, summary
+                ),
+                None => {
+                    strategy = ContextStrategy::Truncate;
+                    truncation_note(plan.turns)
+                }
+            },
+            ContextStrategy::Truncate => truncation_note(plan.turns),
+        };
+        apply_trim(body, plan.drop, &note);
+
+        let trim = ContextTrim {
+            strategy,
+            turns: plan.turns,
+            contents: plan.drop,
+            tokens_before: plan.tokens_before,
+            tokens_after: estimate_body(body),
+        };
+        tracing::info!("[Context] {} exceeded budget {} for {}: {}", plan.tokens_before, budget, model, trim.header_value());
+        Some(trim)
+    }
+
+    async fn summarize(
+        &mut self,
+        body: &Value,
+        drop: usize,
+        upstream: &UpstreamClient,
+        access_token: &str,
+        project_id: &str,
+    ) -> Option<String> {
+        if let Some((cached_drop, summary)) = &self.summary {
+            if *cached_drop == drop {
+                return Some(summary.clone());
+            }
+        }
+
+        let contents = body.pointer("/request/contents")?.as_array()?;
+        let transcript = transcript(&contents[..drop]);
+        let request = crate::proxy::mappers::gemini::wrap_request(
+            &json!({
+                "contents": [{"role": "user", "parts": [{"text": format!("{}\n\n{}", SUMMARY_INSTRUCTION, transcript)}]}],
+                "generationConfig": {"maxOutputTokens": self.config.summary_max_tokens, "temperature": 0.2}
+            }),
+            project_id,
+            &self.config.summary_model,
+        );
+
+        let response = match upstream.call_v1_internal("generateContent", access_token, request, None).await {
+            Ok(r) if r.status().is_success() => r,
+            Ok(r) => {
+                tracing::warn!("[Context] Summary request failed with HTTP {}", r.status());
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!("[Context] Summary request failed: {}", e);
+                return None;
+            }
+        };
+        let value: Value = response.json().await.ok()?;
+        let raw = value.get("response").unwrap_or(&value);
+        let summary = raw
+            .pointer("/candidates/0/content/parts")?
+            .as_array()?
+            .iter()
+            .filter(|p| !p.get("thought").and_then(|t| t.as_bool()).unwrap_or(false))
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<String>();
+        if summary.trim().is_empty() {
+            return None;
+        }
+        self.summary = Some((drop, summary.clone()));
+        Some(summary)
+    }
+}
+
+fn truncation_note(turns: usize) -> String {
+    format!(
+        "[{} earlier conversation turn(s) were omitted to fit the model's context window]",
+        turns
+    )
+}
+
+/// 整个请求 (系统提示词、工具声明与对话) 的估算 token 数
+fn estimate_body(body: &Value) -> u64 {
+    let Some(request) = body.get("request") else {
+        return 0;
+    };
+    let fixed = request.get("systemInstruction").map(estimate_tokens).unwrap_or(0)
+        + request.get("tools").map(|t| t.to_string().len().div_ceil(4) as u64).unwrap_or(0);
+    fixed
+        + request
+            .get("contents")
+            .and_then(|c| c.as_array())
+            .map(|contents| contents.iter().map(estimate_tokens).sum())
+            .unwrap_or(0)
+}
+
+/// 每一轮的起始下标: 包含非 functionResponse 内容的用户消息开启新的一轮
+fn turn_starts(contents: &[Value]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, content) in contents.iter().enumerate().skip(1) {
+        let is_user = content.get("role").and_then(|r| r.as_str()).unwrap_or("user") == "user";
+        let has_prompt = content
+            .get("parts")
+            .and_then(|p| p.as_array())
+            .is_some_and(|parts| parts.iter().any(|part| part.get("functionResponse").is_none()));
+        if is_user && has_prompt {
+            starts.push(i);
+        }
+    }
+    starts
+}
+
+/// 计算需要丢弃的最少轮次；未超出预算或没有可丢弃的轮次时返回 None
+fn plan_trim(body: &Value, budget: u64, keep_recent_turns: usize) -> Option<TrimPlan> {
+    let contents = body.pointer("/request/contents")?.as_array()?;
+    let tokens_before = estimate_body(body);
+    if tokens_before <= budget {
+        return None;
+    }
+
+    let starts = turn_starts(contents);
+    let max_turns = starts.len().saturating_sub(keep_recent_turns.max(1));
+    if max_turns == 0 {
+        tracing::warn!("[Context] Request exceeds budget {} but has no droppable turns", budget);
+        return None;
+    }
+
+    let per_content: Vec<u64> = contents.iter().map(estimate_tokens).collect();
+    let mut turns = max_turns;
+    for candidate in 1..=max_turns {
+        let dropped: u64 = per_content[..starts[candidate]].iter().sum();
+        if tokens_before - dropped <= budget {
+            turns = candidate;
+            break;
+        }
+    }
+    Some(TrimPlan {
+        drop: starts[turns],
+        turns,
+        tokens_before,
+    })
+}
+
+/// 丢弃前 `drop` 条 contents，并把说明插入第一条保留的用户消息 (保持 user/model 交替)
+fn apply_trim(body: &mut Value, drop: usize, note: &str) {
+    let Some(contents) = body.pointer_mut("/request/contents").and_then(|c| c.as_array_mut()) else {
+        return;
+    };
+    contents.drain(..drop.min(contents.len()));
+    if let Some(parts) = contents
+        .first_mut()
+        .and_then(|c| c.get_mut("parts"))
+        .and_then(|p| p.as_array_mut())
+    {
+        parts.insert(0, json!({"text": note}));
+    }
+}
+
+/// 将待摘要的对话转为纯文本 (跳过思考内容，工具结果截断)
+fn transcript(contents: &[Value]) -> String {
+    let mut out = String::new();
+    for content in contents {
+        let role = match content.get("role").and_then(|r| r.as_str()) {
+            Some("model") => "Assistant",
+            _ => "User",
+        };
+        for part in content.get("parts").and_then(|p| p.as_array()).into_iter().flatten() {
+            if part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false) {
+                continue;
+            }
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                out.push_str(&format!("{}: {}\n", role, text));
+            } else if let Some(call) = part.get("functionCall") {
+                out.push_str(&format!(
+                    "{} called tool {} with {}\n",
+                    role,
+                    call.get("name").and_then(|n| n.as_str()).unwrap_or("unknown"),
+                    call.get("args").cloned().unwrap_or(Value::Null)
+                ));
+            } else if let Some(result) = part.get("functionResponse") {
+                let text = result.get("response").cloned().unwrap_or(Value::Null).to_string();
+                let clipped: String = text.chars().take(MAX_TOOL_RESULT_CHARS).collect();
+                out.push_str(&format!(
+                    "Tool {} returned: {}\n",
+                    result.get("name").and_then(|n| n.as_str()).unwrap_or("unknown"),
+                    clipped
+                ));
+            }
+        }
+    }
+
+    let total = out.chars().count();
+    if total > MAX_TRANSCRIPT_CHARS {
+        out = out.chars().skip(total - MAX_TRANSCRIPT_CHARS).collect();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(role: &str, text: &str) -> Value {
+        json!({"role": role, "parts": [{"text": text}]})
+    }
+
+    /// 4 轮对话: 第 2 轮包含一次工具调用，每条消息约 250 token
+    fn conversation() -> Value {
+        let big = "x".repeat(1000);
+        json!({
+            "project": "p",
+            "model": "gemini-2.5-flash",
+            "request": {
+                "contents": [
+                    text("user", &big),
+                    text("model", &big),
+                    text("user", &big),
+                    {"role": "model", "parts": [{"functionCall": {"name": "read", "args": {"path": "a"}}, "thoughtSignature": "sig"}]},
+                    {"role": "user", "parts": [{"functionResponse": {"name": "read", "response": {"result": big}}}]},
+                    text("model", &big),
+                    text("user", &big),
+                    text("model", &big),
+                    text("user", "latest question")
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn turns_keep_tool_call_and_result_together() {
+        let body = conversation();
+        let contents = body.pointer("/request/contents").unwrap().as_array().unwrap();
+        assert_eq!(turn_starts(contents), vec![0, 2, 6, 8]);
+
+        // 预算只允许保留最后两轮: 第 2 轮 (含工具调用与结果) 整体丢弃
+        let plan = plan_trim(&body, 700, 1).unwrap();
+        assert_eq!(plan, TrimPlan { drop: 6, turns: 2, tokens_before: estimate_body(&body) });
+
+        let mut trimmed = body.clone();
+        apply_trim(&mut trimmed, plan.drop, &truncation_note(plan.turns));
+        let kept = trimmed.pointer("/request/contents").unwrap().as_array().unwrap();
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0]["role"], "user");
+        assert!(kept[0]["parts"][0]["text"].as_str().unwrap().contains("2 earlier conversation turn(s)"));
+        assert!(kept.iter().all(|c| c["parts"][0].get("functionResponse").is_none()));
+    }
+
+    #[test]
+    fn plan_respects_budget_and_recent_turns() {
+        let body = conversation();
+        assert!(plan_trim(&body, 1_000_000, 2).is_none());
+        // 只能丢弃到保留最近 2 轮为止，即使仍超出预算
+        let plan = plan_trim(&body, 10, 2).unwrap();
+        assert_eq!(plan.drop, 6);
+        // 只有一轮时无可丢弃内容
+        let single = json!({"request": {"contents": [text("user", &"y".repeat(4000))]}});
+        assert!(plan_trim(&single, 10, 1).is_none());
+    }
+
+    #[test]
+    fn overflow_errors_tighten_once() {
+        let error = r#"{"error":{"code":400,"message":"The input token count (1200000) exceeds the maximum number of tokens allowed (1048576).","status":"INVALID_ARGUMENT"}}"#;
+        assert!(is_context_overflow(error));
+        assert_eq!(parse_overflow(error), Some((1_200_000, 1_048_576)));
+        assert!(!is_context_overflow("Invalid `signature` in thinking block"));
+
+        let config = ContextWindowConfig { enabled: true, ..Default::default() };
+        let mut manager = ContextManager::new(&config, InboundProtocol::Claude);
+        let before = manager.budget("gemini-2.5-flash");
+        assert!(manager.tighten(error));
+        assert!(manager.budget("gemini-2.5-flash") < before);
+        assert!(!manager.tighten(error));
+
+        assert!(!ContextManager::new(&config, InboundProtocol::Gemini).is_active());
+    }
+}
@@ -0,0 +1,142 @@
+// 实时事件 (Event sink)
+//
+// 代理核心只负责产生事件 (请求日志、限流、会话绑定、熔断等)，不关心事件如何送达。
+// 推送通道实现 `EventSink` 并在启动时通过 `set_sink` 注册 (如 Web Admin 的 WebSocket 广播)；
+// 未注册时发布事件为空操作。
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use crate::models::QuotaData;
+use crate::proxy::monitor::ProxyRequestLog;
+
+pub mod topics {
+    pub const REQUESTS: &str = "requests";
+    pub const ACCOUNTS: &str = "accounts";
+    pub const RATE_LIMITS: &str = "rate_limits";
+    pub const QUOTA: &str = "quota";
+    pub const SESSIONS: &str = "sessions";
+    pub const CONFIG: &str = "config";
+    pub const PROXY: &str = "proxy";
+    pub const CIRCUITS: &str = "circuits";
+    pub const DASHBOARD: &str = "dashboard";
+}
+
+/// 事件推送通道
+pub trait EventSink: Send + Sync {
+    /// 是否有订阅者 (没有时跳过序列化)
+    fn has_subscribers(&self) -> bool;
+
+    /// 推送一个事件
+    fn publish(&self, topic: &str, event_type: &str, data: serde_json::Value);
+}
+
+static SINK: OnceLock<Box<dyn EventSink>> = OnceLock::new();
+
+/// 注册事件推送通道 (进程内只生效一次)
+pub fn set_sink(sink: Box<dyn EventSink>) {
+    if SINK.set(sink).is_err() {
+        tracing::debug!("[Events] Event sink already registered");
+    }
+}
+
+fn sink() -> Option<&'static dyn EventSink> {
+    SINK.get().map(|s| s.as_ref()).filter(|s| s.has_subscribers())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event_type", content = "data", rename_all = "snake_case")]
+pub enum ProxyEvent {
+    /// 代理请求完成 (不含请求/响应体)
+    RequestLog(ProxyRequestLog),
+    /// 账号被禁用/启用 (整体或仅代理池)
+    AccountStateChanged {
+        account_id: String,
+        /// "disabled" 或 "proxy_disabled"
+        field: &'static str,
+        enabled: bool,
+        reason: Option<String>,
+    },
+    RateLimitSet {
+        account: String,
+        reason: String,
+        model: Option<String>,
+        retry_after_sec: u64,
+    },
+    RateLimitCleared {
+        /// 一次性清除全部锁定时为 `None`
+        account: Option<String>,
+    },
+    QuotaRefreshed {
+        account_id: String,
+        quota: QuotaData,
+    },
+    SessionBound {
+        session_id: String,
+        account_id: String,
+    },
+    SessionUnbound {
+        /// 清除全部会话绑定时为 `None`
+        session_id: Option<String>,
+    },
+    ConfigChanged {
+        section: String,
+    },
+}
+
+impl ProxyEvent {
+    pub fn topic(&self) -> &'static str {
+        match self {
+            ProxyEvent::RequestLog(_) => topics::REQUESTS,
+            ProxyEvent::AccountStateChanged { .. } => topics::ACCOUNTS,
+            ProxyEvent::RateLimitSet { .. } | ProxyEvent::RateLimitCleared { .. } => topics::RATE_LIMITS,
+            ProxyEvent::QuotaRefreshed { .. } => topics::QUOTA,
+            ProxyEvent::SessionBound { .. } | ProxyEvent::SessionUnbound { .. } => topics::SESSIONS,
+            ProxyEvent::ConfigChanged { .. } => topics::CONFIG,
+        }
+    }
+
+    /// 拆分为 (event_type, data)
+    fn into_parts(self) -> Option<(String, serde_json::Value)> {
+        let mut value = serde_json::to_value(&self).ok()?;
+        let event_type = value.get("event_type")?.as_str()?.to_string();
+        let data = value.get_mut("data").map(serde_json::Value::take).unwrap_or_default();
+        Some((event_type, data))
+    }
+}
+
+/// 发布事件；没有订阅者时为空操作
+pub fn publish(event: ProxyEvent) {
+    let Some(sink) = sink() else {
+        return;
+    };
+    let topic = event.topic();
+    if let Some((event_type, data)) = event.into_parts() {
+        sink.publish(topic, &event_type, data);
+    }
+}
+
+/// 发布原始事件 (payload 已有独立的序列化类型)
+pub fn publish_raw(topic: &str, event_type: &str, data: serde_json::Value) {
+    if let Some(sink) = sink() {
+        sink.publish(topic, event_type, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_envelope() {
+        let event = ProxyEvent::RateLimitSet {
+            account: "a@example.com".into(),
+            reason: "QuotaExhausted".into(),
+            model: None,
+            retry_after_sec: 60,
+        };
+        assert_eq!(event.topic(), topics::RATE_LIMITS);
+        let (event_type, data) = event.into_parts().unwrap();
+        assert_eq!(event_type, "rate_limit_set");
+        assert_eq!(data["retry_after_sec"], 60);
+    }
+}
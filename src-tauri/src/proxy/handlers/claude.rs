@@ -13,9 +13,13 @@ use tokio::time::{sleep, Duration};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::claude::{
-    transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
+    transform_claude_request_in, transform_response, create_claude_sse_stream, create_claude_sse_stream_with_failover, ClaudeRequest,
     close_tool_loop_for_thinking,
 };
+use crate::proxy::background_tasks;
+use crate::proxy::context_window::{self, ContextManager};
+use crate::proxy::trace_capture::{self, TraceKind, TraceRecorder};
+use crate::proxy::common::model_capabilities;
 use crate::proxy::server::AppState;
 use axum::http::HeaderMap;
 use std::sync::atomic::Ordering;
@@ -23,11 +27,6 @@ use std::sync::atomic::Ordering;
 const MAX_RETRY_ATTEMPTS: usize = 3;
 const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
 
-// ===== Model Constants for Background Tasks =====
-// These can be adjusted for performance/cost optimization
-const BACKGROUND_MODEL_LITE: &str = "gemini-2.5-flash-lite";  // For simple/lightweight tasks
-const BACKGROUND_MODEL_STANDARD: &str = "gemini-2.5-flash";   // For complex background tasks
-
 // ===== Jitter Configuration (REMOVED) =====
 // Jitter was causing connection instability, reverted to fixed delays
 // const JITTER_FACTOR: f64 = 0.2;
@@ -190,6 +189,11 @@ fn determine_retry_strategy(
         return RetryStrategy::FixedDelay(Duration::from_millis(0));
     }
 
+    // 上下文超限: 换账号或去除 thinking 后重试同样会失败
+    if status_code == 400 && context_window::is_context_overflow(error_text) {
+        return RetryStrategy::NoRetry;
+    }
+
     match status_code {
         // 400 错误：Thinking 签名失败
         400 if !retried_without_thinking
@@ -346,6 +350,12 @@ pub async fn handle_messages(
     };
 
     // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
+    let route_features = crate::proxy::common::model_router::RequestFeatures::from_body(&body);
+    // [Prompt Cache] 在类型化解析丢弃 cache_control 之前提取缓存断点
+    let cache_config = state.config.read().await.proxy.prompt_cache.clone();
+    let cache_breakpoints = crate::proxy::prompt_cache::breakpoints(&body, &cache_config);
+    // [Trace] 协议转换调试抓取
+    let trace = TraceRecorder::start(&state.config.read().await.proxy.trace_capture, &headers, TraceKind::ClaudeMessages, &body);
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
         Ok(r) => r,
         Err(e) => {
@@ -495,25 +505,85 @@ pub async fn handle_messages(
 
     let pool_size = token_manager.len();
 
-    // Resolve model chain
-    let model_chain = crate::proxy::common::model_mapping::resolve_model_chain(
+    // Resolve model chain (有序路由规则 > 自定义映射 > 系统默认映射)
+    let route_session_id = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
+    // 系统提示词注入与请求改写策略
+    let prompt_policy = std::sync::Arc::new(crate::proxy::prompt_policy::ResolvedPromptPolicy::resolve(
+        &state.config.read().await.proxy.prompt_policy,
         &request_for_body.model,
-        &*state.custom_mapping.read().await,
+        crate::proxy::config::InboundProtocol::Claude,
+        &headers,
+    ));
+    // 后台任务检测 (标题生成/摘要等)，命中时改用分类器的目标模型链
+    let background_task = background_tasks::classify(
+        &state.config.read().await.proxy.background_tasks,
+        crate::proxy::config::InboundProtocol::Claude,
+        background_tasks::last_user_text_claude(&request_for_body).as_deref(),
+        request_for_body.tools.as_ref().is_some_and(|t| !t.is_empty()),
     );
+    let model_chain = match &background_task {
+        Some(task) => {
+            info!("[{}][AUTO] 检测到后台任务 (类型: {}),降级到: {:?}", trace_id, task.name, task.chain);
+            state.monitor.record_background_task(&task.name);
+            task.chain.clone()
+        }
+        None => {
+            super::common::resolve_model_chain(
+                &state.config,
+                &state.custom_mapping,
+                &crate::proxy::common::model_router::RouteRequest {
+                    model: &request_for_body.model,
+                    protocol: crate::proxy::config::InboundProtocol::Claude,
+                    headers: &headers,
+                    features: route_features.clone(),
+                    split_key: Some(&route_session_id),
+                },
+            )
+            .await
+        }
+    };
+
+    // 按模型能力过滤模型链 (跳过不支持图片/工具或上下文不足的模型)
+    let capability_needs = model_capabilities::RequestNeeds::from(&route_features);
+    let mut hop_trace = model_capabilities::HopTrace::default();
+    let hop_indices = model_capabilities::compatible_hops(&model_chain, &capability_needs, &mut hop_trace);
 
     let base_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
-    let max_attempts = base_attempts.max(model_chain.len());
+    let max_attempts = base_attempts.max(hop_indices.len());
 
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
+    // [Context Window] 超出目标模型上下文窗口时截断或摘要最早的对话轮次
+    let mut context_manager = ContextManager::new(
+        &state.config.read().await.proxy.context_window,
+        crate::proxy::config::InboundProtocol::Claude,
+    );
+
+    // 4. 上游调用 - 自动转换逻辑
+    let client_wants_stream = request.stream;
+    // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
+    let force_stream_internally = !client_wants_stream;
+    let actual_stream = client_wants_stream || force_stream_internally;
+    
+    if force_stream_internally {
+        info!("[{}] 🔄 Auto-converting non-stream request to stream for better quota", trace_id);
+    }
+    
+    let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
+    let query = if actual_stream { Some("alt=sse") } else { None };
 
-    for attempt in 0..max_attempts {
+    let response_cache_config = state.config.read().await.proxy.response_cache.clone();
+
+    // 上下文超限时修正预算后重放同一次尝试 (相同模型与账号选择)，不占用重试次数
+    let mut attempts = 0..max_attempts;
+    let mut repeat_attempt: Option<usize> = None;
+    while let Some(attempt) = repeat_attempt.take().or_else(|| attempts.next()) {
         // 2. Select model from chain
-        let chain_index = if attempt < model_chain.len() { attempt } else { model_chain.len() - 1 };
-        let mut mapped_model = model_chain[chain_index].clone();
+        let hop = attempt.min(hop_indices.len() - 1);
+        let mapped_model = model_chain[hop_indices[hop]].clone();
 
-        if attempt > 0 && chain_index > 0 && chain_index == attempt {
-             let prev_model = &model_chain[chain_index - 1];
+        if attempt > 0 && hop > 0 && hop == attempt {
+             let prev_model = &model_chain[hop_indices[hop - 1]];
              tracing::warn!("[Fallback] Switching model {} -> {} due to error", prev_model, mapped_model);
         }
 
@@ -530,74 +600,18 @@ pub async fn handle_messages(
         let session_id = Some(session_id_str.as_str());
 
         let force_rotate_token = attempt > 0;
-        let (quota_threshold, quota_priority) = {
-            let cfg = state.config.read().await;
-            (cfg.model_quota_threshold, cfg.proxy.quota_priority_enabled)
-        };
 
-        let (access_token, project_id, email) = match token_manager
-            .get_token(
-                &config.request_type,
-                Some(&config.final_model),
-                quota_threshold,
-                force_rotate_token,
-                session_id,
-                quota_priority,
-            )
-            .await
-        {
-            Ok(t) => t,
-            Err(e) => {
-                // [Fix] If current model pool is completely exhausted (Hard Floor),
-                // fallback to the next model in the chain immediately.
-                if e.contains("All accounts exhausted") {
-                    tracing::warn!("[Fallback] Current model {} pool exhausted (All accounts exhausted). Switching to next model.", mapped_model);
-                    continue;
-                }
-
-                let safe_message = if e.contains("invalid_grant") {
-                    "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
-                } else {
-                    e
-                };
-                 return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(json!({
-                        "type": "error",
-                        "error": {
-                            "type": "overloaded_error",
-                            "message": format!("No available accounts: {}", safe_message)
-                        }
-                    }))
-                ).into_response();
+        // [Prompt Cache] 首次尝试时将会话绑定到持有最长前缀缓存的账号，复用上游隐式缓存
+        if !force_rotate_token && background_task.is_none() {
+            if let Some(cached_email) = state.prompt_cache.preferred_account(&mapped_model, &cache_breakpoints) {
+                token_manager.prefer_account_for_session(&session_id_str, &cached_email);
             }
-        };
+        }
 
-        info!("✓ Using account: {} (type: {})", email, config.request_type);
-        
-        
-        // ===== 【优化】后台任务智能检测与降级 =====
-        // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略
-        let background_task_type = detect_background_task_type(&request_for_body);
-        
         // 传递映射后的模型名
         let mut request_with_mapped = request_for_body.clone();
 
-        if let Some(task_type) = background_task_type {
-            // 检测到后台任务,强制降级到 Flash 模型
-            let downgrade_model = select_background_model(task_type);
-            
-            info!(
-                "[{}][AUTO] 检测到后台任务 (类型: {:?}),强制降级: {} -> {}",
-                trace_id,
-                task_type,
-                mapped_model,
-                downgrade_model
-            );
-            
-            // 覆盖用户自定义映射
-            mapped_model = downgrade_model.to_string();
-            
+        if background_task.is_some() {
             // 后台任务净化：
             // 1. 移除工具定义（后台任务不需要工具）
             request_with_mapped.tools = None;
@@ -633,15 +647,112 @@ pub async fn handle_messages(
             }
         }
 
-        
-        request_with_mapped.model = mapped_model;
+        // 按目标模型能力调整请求 (关闭不支持的 thinking、收紧输出上限)
+        let hop_adaptations = model_capabilities::adapt_claude_request(
+            &mut request_with_mapped,
+            &model_capabilities::lookup(&mapped_model),
+        );
+        if !hop_adaptations.is_empty() {
+            debug!("[{}] Adapted request for {}: {:?}", trace_id, mapped_model, hop_adaptations);
+        }
+        hop_trace.attempt(&mapped_model, hop_adaptations);
+
+        request_with_mapped.model = mapped_model.clone();
+
+        // [Response Cache] 确定性请求命中缓存时直接回放，不占用账号与并发槽位 (缓存键与 project 无关)
+        let response_cache_key = if response_cache_config.enabled {
+            transform_claude_request_in(&request_with_mapped, "").ok().and_then(|mut probe| {
+                prompt_policy.apply(&mut probe);
+                crate::proxy::response_cache::ResponseCache::key_for(
+                    &response_cache_config,
+                    &request.model,
+                    &headers,
+                    &probe,
+                    actual_stream,
+                )
+            })
+        } else {
+            None
+        };
+        if let Some(key) = &response_cache_key {
+            if let Some(cached) = state.response_cache.get(key, &response_cache_config).await {
+                info!("[{}] ✓ Response cache hit ({} bytes)", trace_id, cached.len());
+                hop_trace.serve();
+                return replay_cached_response(cached, client_wants_stream, &trace_id, &request_with_mapped.model, &hop_trace.header_value()).await;
+            }
+        }
+
+        let (quota_threshold, quota_priority) = {
+            let cfg = state.config.read().await;
+            (cfg.model_quota_threshold, cfg.proxy.quota_priority_enabled)
+        };
+
+        let token = match background_task.as_ref().and_then(|t| t.accounts.as_deref()) {
+            Some(accounts) => {
+                token_manager
+                    .get_token_in_group(
+                        accounts,
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        force_rotate_token,
+                        quota_priority,
+                    )
+                    .await
+            }
+            None => {
+                token_manager
+                    .get_token(
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        force_rotate_token,
+                        session_id,
+                        quota_priority,
+                    )
+                    .await
+            }
+        };
+        let (access_token, project_id, email) = match token {
+            Ok(t) => t,
+            Err(e) => {
+                // [Fix] If current model pool is completely exhausted (Hard Floor),
+                // fallback to the next model in the chain immediately.
+                if e.contains("All accounts exhausted") {
+                    tracing::warn!("[Fallback] Current model {} pool exhausted (All accounts exhausted). Switching to next model.", mapped_model);
+                    hop_trace.exhausted(&mapped_model);
+                    continue;
+                }
+
+                let safe_message = if e.contains("invalid_grant") {
+                    "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
+                } else {
+                    e
+                };
+                 return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(json!({
+                        "type": "error",
+                        "error": {
+                            "type": "overloaded_error",
+                            "message": format!("No available accounts: {}", safe_message)
+                        }
+                    }))
+                ).into_response();
+            }
+        };
+
+        info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 生成 Trace ID (简单用时间戳后缀)
         // let _trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
 
-        let gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id) {
-            Ok(b) => {
-                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+        let mut gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id) {
+            Ok(mut b) => {
+                if let Some(trace) = &trace {
+                    trace.record_mapping(&request_with_mapped.model, &project_id, &request_with_mapped, &b);
+                }
+                prompt_policy.apply(&mut b);
                 b
             },
             Err(e) => {
@@ -657,46 +768,271 @@ pub async fn handle_messages(
                 ).into_response();
             }
         };
-        
-    // 4. 上游调用 - 自动转换逻辑
-    let client_wants_stream = request.stream;
-    // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
-    let force_stream_internally = !client_wants_stream;
-    let actual_stream = client_wants_stream || force_stream_internally;
-    
-    if force_stream_internally {
-        info!("[{}] 🔄 Auto-converting non-stream request to stream for better quota", trace_id);
-    }
-    
-    let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
-    let query = if actual_stream { Some("alt=sse") } else { None };
-
-    let response = match upstream.call_v1_internal(
-        method,
-        &access_token,
-        gemini_body,
-        query
-    ).await {
-            Ok(r) => r,
+        // 获取并发槽位 (按实际发送的模型计算模型族；槽位已满时排队，超时则尝试下一个账号)
+        let upstream_model = gemini_body
+            .get("model")
+            .and_then(|m| m.as_str())
+            .unwrap_or(&config.final_model)
+            .to_string();
+        let slot = match token_manager.acquire_slot(&email, &upstream_model).await {
+            Ok(s) => s,
             Err(e) => {
-                last_error = e.clone();
-                debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                tracing::warn!("[{}] {}", trace_id, e);
+                last_error = e;
                 continue;
             }
         };
+        let context_trim = context_manager
+            .fit(&mut gemini_body, &request_with_mapped.model, &upstream, &access_token, &project_id)
+            .await;
+        if let Some(trace) = &trace {
+            trace.record_upstream_request(&gemini_body);
+        }
+        debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&gemini_body).unwrap_or_default());
         
+        // [Hedging] 非流式短请求: 首个账号超过 p95 耗时仍未返回时，在另一个账号上发起对冲请求
+        let hedging = state.config.read().await.proxy.hedging.clone();
+        // 对冲请求会重新构建请求体，已裁剪的请求不参与对冲
+        let hedge_eligible = hedging.enabled
+            && context_trim.is_none()
+            && actual_stream
+            && !client_wants_stream
+            && (background_task.is_some() || !hedging.background_only);
+        let attempt_started = std::time::Instant::now();
+
+        let response = if hedge_eligible {
+            let delay = state
+                .latency
+                .hedge_delay(&request_with_mapped.model, &hedging);
+            let hedge_email: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+            let ctx = AttemptContext {
+                upstream: &upstream,
+                token_manager: &token_manager,
+                method,
+                query,
+                trace_id: &trace_id,
+                prompt_policy: &prompt_policy,
+                response_cache: response_cache_key
+                    .as_deref()
+                    .map(|key| ResponseCacheTarget {
+                        cache: &state.response_cache,
+                        key,
+                        model: &request.model,
+                        config: &response_cache_config,
+                    }),
+            };
+            let primary = execute_collected_attempt(&ctx, &access_token, gemini_body, &email);
+            let outcome = crate::proxy::hedging::race(primary, delay, || {
+                run_hedge_attempt(
+                    &ctx,
+                    &config.request_type,
+                    &config.final_model,
+                    &request_with_mapped,
+                    (quota_threshold, quota_priority),
+                    &email,
+                    &hedge_email,
+                )
+            })
+            .await;
+
+            // 对冲胜出时主请求的上游错误同样计入账号状态 (对冲请求的错误已在 run_hedge_attempt 中记录)
+            if let Some(HedgeAttemptError::Upstream(resp)) = outcome.loser_error {
+                let status_code = resp.status().as_u16();
+                let error_text = record_upstream_failure(
+                    &token_manager,
+                    &email,
+                    resp,
+                    &request_with_mapped.model,
+                )
+                .await;
+                tracing::warn!(
+                    "[{}] Primary request on {} failed with HTTP {} (served by hedge)",
+                    trace_id,
+                    email,
+                    status_code
+                );
+                debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
+            }
+
+            // 落败的请求已被取消，单独记录一条日志，使配额消耗归属到正确的账号
+            if outcome.loser_cancelled {
+                let loser = match outcome.winner {
+                    crate::proxy::hedging::HedgeWinner::Primary => hedge_email.get().cloned(),
+                    crate::proxy::hedging::HedgeWinner::Hedge => Some(email.clone()),
+                };
+                if let Some(loser) = loser {
+                    log_cancelled_hedge(
+                        &state.monitor,
+                        &request.model,
+                        &request_with_mapped.model,
+                        loser,
+                        attempt_started.elapsed(),
+                    )
+                    .await;
+                }
+            }
+
+            match outcome.result {
+                Ok(mut win) => {
+                    state
+                        .latency
+                        .record(&request_with_mapped.model, attempt_started.elapsed());
+                    hop_trace.serve();
+                    if !cache_breakpoints.is_empty() {
+                        state
+                            .prompt_cache
+                            .plan(&request_with_mapped.model, &cache_breakpoints, &win.email)
+                            .apply(&mut win.response.usage);
+                        state.prompt_cache.commit(
+                            &request_with_mapped.model,
+                            &cache_breakpoints,
+                            &win.email,
+                            cache_config.max_entries,
+                        );
+                    }
+                    info!(
+                        "[{}] ✓ Stream collected and converted to JSON (hedged: {}, winner: {}, account: {})",
+                        trace_id, outcome.hedged, outcome.winner.as_str(), win.email
+                    );
+                    return Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .header("X-Account-Email", &win.email)
+                        .header("X-Mapped-Model", &request_with_mapped.model)
+                        .header("X-Model-Hops", hop_trace.header_value())
+                        .header("X-Hedge-Winner", outcome.winner.as_str())
+                        .body(Body::from(serde_json::to_string(&win.response).unwrap()))
+                        .unwrap();
+                }
+                Err(HedgeAttemptError::Upstream(resp)) => resp,
+                Err(HedgeAttemptError::Failed(e)) => {
+                    hop_trace.fail("network");
+                    last_error = e.clone();
+                    debug!(
+                        "Request failed on attempt {}/{}: {}",
+                        attempt + 1,
+                        max_attempts,
+                        e
+                    );
+                    continue;
+                }
+                Err(HedgeAttemptError::Collect(e)) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Stream collection error: {}", e),
+                    )
+                        .into_response();
+                }
+            }
+        } else {
+            match upstream
+                .call_v1_internal(method, &access_token, gemini_body, query)
+                .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    hop_trace.fail("network");
+                    last_error = e.clone();
+                    debug!(
+                        "Request failed on attempt {}/{}: {}",
+                        attempt + 1,
+                        max_attempts,
+                        e
+                    );
+                    continue;
+                }
+            }
+        };
+
         let status = response.status();
         
         // 成功
         if status.is_success() {
-            // [智能限流] 请求成功，重置该账号的连续失败计数
-            token_manager.mark_account_success(&email);
+            // [智能限流] 请求成功，重置该账号的连续失败计数 (流式请求的健康度在流结束时记录)
+            if actual_stream {
+                token_manager.mark_stream_opened(&email);
+            } else {
+                token_manager.mark_account_success(&email);
+            }
+            hop_trace.serve();
+            let model_hops = hop_trace.header_value();
+
+            // [Prompt Cache] 计算本次缓存写入量并记录前缀所在账号
+            let cache_plan = (!cache_breakpoints.is_empty()).then(|| {
+                let plan = state.prompt_cache.plan(&request_with_mapped.model, &cache_breakpoints, &email);
+                state.prompt_cache.commit(&request_with_mapped.model, &cache_breakpoints, &email, cache_config.max_entries);
+                plan
+            });
             
             // 处理流式响应
             if actual_stream {
-                let stream = response.bytes_stream();
-                let gemini_stream = Box::pin(stream);
-                let claude_stream = create_claude_sse_stream(gemini_stream, trace_id.clone(), email.clone());
+                let stream = trace_capture::tee_upstream(
+                    trace.as_ref(),
+                    crate::proxy::account_health::watch_stream(response.bytes_stream(), token_manager.clone(), email.clone()),
+                );
+                let gemini_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+                    match response_cache_key {
+                        Some(key) => Box::pin(state.response_cache.record(
+                            stream,
+                            key,
+                            request.model.clone(),
+                            response_cache_config.clone(),
+                        )),
+                        None => Box::pin(stream),
+                    };
+
+                // [Stream Failover] 上游中途断流时换号续写
+                let failover = state.config.read().await.proxy.stream_failover.clone();
+                let resumer = if failover.enabled && context_trim.is_none() {
+                    let base_request = request_with_mapped.clone();
+                    let resume_policy = prompt_policy.clone();
+                    Some(crate::proxy::stream_failover::build_resumer(
+                        crate::proxy::stream_failover::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            request_type: config.request_type.clone(),
+                            model: request_with_mapped.model.clone(),
+                            quota_threshold,
+                            quota_priority,
+                            max_resumes: failover.max_resumes,
+                            trace_id: trace_id.clone(),
+                        },
+                        email.clone(),
+                        move |partial, project_id| {
+                            let mut req = base_request.clone();
+                            // 续写不再需要思考过程，已输出正文作为 assistant prefill
+                            req.thinking = None;
+                            if !partial.is_empty() {
+                                req.messages.push(crate::proxy::mappers::claude::models::Message {
+                                    role: "assistant".to_string(),
+                                    content: crate::proxy::mappers::claude::models::MessageContent::String(partial.to_string()),
+                                });
+                            }
+                            transform_claude_request_in(&req, project_id).map(|mut body| {
+                                resume_policy.apply(&mut body);
+                                body
+                            })
+                        },
+                    ))
+                } else {
+                    None
+                };
+                // 与 transform_claude_request_in 内部一致，以映射后的请求计算会话指纹
+                let signature_session = crate::proxy::session_manager::SessionManager::extract_session_id(&request_with_mapped);
+                if let Some(trace) = &trace {
+                    trace.record_stream_context(Some(signature_session.clone()), cache_plan);
+                }
+                let claude_stream = trace_capture::tee_client(
+                    trace.as_ref(),
+                    create_claude_sse_stream_with_failover(
+                        gemini_stream,
+                        trace_id.clone(),
+                        email.clone(),
+                        Some(signature_session),
+                        cache_plan,
+                        resumer,
+                    ),
+                );
 
                 // 转换为 Bytes stream
                 let sse_stream = claude_stream.map(|result| -> Result<Bytes, std::io::Error> {
@@ -709,15 +1045,19 @@ pub async fn handle_messages(
                 // 判断客户端期望的格式
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
-                    return Response::builder()
-                        .status(StatusCode::OK)
-                        .header(header::CONTENT_TYPE, "text/event-stream")
-                        .header(header::CACHE_CONTROL, "no-cache")
-                        .header(header::CONNECTION, "keep-alive")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &request_with_mapped.model)
-                        .body(Body::from_stream(sse_stream))
-                        .unwrap();
+                    return context_window::annotate(
+                        Response::builder()
+                            .status(StatusCode::OK)
+                            .header(header::CONTENT_TYPE, "text/event-stream")
+                            .header(header::CACHE_CONTROL, "no-cache")
+                            .header(header::CONNECTION, "keep-alive")
+                            .header("X-Account-Email", &email)
+                            .header("X-Mapped-Model", &request_with_mapped.model)
+                            .header("X-Model-Hops", &model_hops)
+                            .body(Body::from_stream(crate::proxy::concurrency::guard_stream(sse_stream, slot)))
+                            .unwrap(),
+                        context_trim.as_ref(),
+                    );
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::claude::collect_stream_to_json;
@@ -725,13 +1065,18 @@ pub async fn handle_messages(
                     match collect_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
-                            return Response::builder()
-                                .status(StatusCode::OK)
-                                .header(header::CONTENT_TYPE, "application/json")
-                                .header("X-Account-Email", &email)
-                                .header("X-Mapped-Model", &request_with_mapped.model)
-                                .body(Body::from(serde_json::to_string(&full_response).unwrap()))
-                                .unwrap();
+                            state.latency.record(&request_with_mapped.model, attempt_started.elapsed());
+                            return context_window::annotate(
+                                Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(header::CONTENT_TYPE, "application/json")
+                                    .header("X-Account-Email", &email)
+                                    .header("X-Mapped-Model", &request_with_mapped.model)
+                                    .header("X-Model-Hops", &model_hops)
+                                    .body(Body::from(serde_json::to_string(&full_response).unwrap()))
+                                    .unwrap(),
+                                context_trim.as_ref(),
+                            );
                         }
                         Err(e) => {
                             return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
@@ -765,10 +1110,13 @@ pub async fn handle_messages(
                 };
                 
                 // 转换
-                let claude_response = match transform_response(&gemini_response) {
+                let mut claude_response = match transform_response(&gemini_response) {
                     Ok(r) => r,
                     Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Transform error: {}", e)).into_response(),
                 };
+                if let Some(plan) = &cache_plan {
+                    plan.apply(&mut claude_response.usage);
+                }
 
                 // [Optimization] 记录闭环日志：消耗情况
                 let cache_info = if let Some(cached) = claude_response.usage.cache_read_input_tokens {
@@ -786,23 +1134,39 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                return context_window::annotate(
+                    (
+                        StatusCode::OK,
+                        [
+                            ("X-Account-Email", email.as_str()),
+                            ("X-Mapped-Model", request_with_mapped.model.as_str()),
+                            ("X-Model-Hops", model_hops.as_str()),
+                        ],
+                        Json(claude_response),
+                    )
+                        .into_response(),
+                    context_trim.as_ref(),
+                );
             }
         }
         
         // 1. 立即提取状态码和 headers（防止 response 被 move）
         let status_code = status.as_u16();
-        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
-        
-        // 2. 获取错误文本并转移 Response 所有权
-        let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
+
+        // 2. 获取错误文本并转移 Response 所有权，同时记录错误并标记限流状态 (模型级别限流)
+        let error_text = record_upstream_failure(&token_manager, &email, response, &request_with_mapped.model).await;
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        hop_trace.fail(status_code.to_string());
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
-        
-        // 3. 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
-        // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
-        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
+
+        // [Context Window] 上下文超限: 去除 thinking 或轮换账号都无济于事，修正预算后在本次尝试内重新裁剪发送
+        if status_code == 400 && context_window::is_context_overflow(&error_text) {
+            if context_manager.tighten(&error_text) {
+                repeat_attempt = Some(attempt);
+                continue;
+            }
+            error!("[{}] Context window exceeded: {}", trace_id, error_text);
+            return (status, [("X-Model-Hops", hop_trace.header_value())], error_text).into_response();
         }
 
         // 4. 处理 400 错误 (Thinking 签名失效)
@@ -878,11 +1242,11 @@ pub async fn handle_messages(
         } else {
             // 不可重试的错误，直接返回
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, error_text).into_response();
+            return (status, [("X-Model-Hops", hop_trace.header_value())], error_text).into_response();
         }
     }
     
-    (StatusCode::TOO_MANY_REQUESTS, Json(json!({
+    (StatusCode::TOO_MANY_REQUESTS, [("X-Model-Hops", hop_trace.header_value())], Json(json!({
         "type": "error",
         "error": {
             "type": "overloaded_error",
@@ -954,150 +1318,227 @@ mod tests {
 }
 */
 
-// ===== 后台任务检测辅助函数 =====
-
-/// 后台任务类型
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum BackgroundTaskType {
-    TitleGeneration,      // 标题生成
-    SimpleSummary,        // 简单摘要
-    ContextCompression,   // 上下文压缩
-    PromptSuggestion,     // 提示建议
-    SystemMessage,        // 系统消息
-    EnvironmentProbe,     // 环境探测
+// ===== 请求对冲辅助函数 =====
+
+/// 对冲竞速中单次尝试的成功结果
+struct HedgedSuccess {
+    response: crate::proxy::mappers::claude::models::ClaudeResponse,
+    email: String,
 }
 
-/// 标题生成关键词
-const TITLE_KEYWORDS: &[&str] = &[
-    "write a 5-10 word title",
-    "Please write a 5-10 word title",
-    "Respond with the title",
-    "Generate a title for",
-    "Create a brief title",
-    "title for the conversation",
-    "conversation title",
-    "生成标题",
-    "为对话起个标题",
-];
-
-/// 摘要生成关键词
-const SUMMARY_KEYWORDS: &[&str] = &[
-    "Summarize this coding conversation",
-    "Summarize the conversation",
-    "Concise summary",
-    "in under 50 characters",
-    "compress the context",
-    "Provide a concise summary",
-    "condense the previous messages",
-    "shorten the conversation history",
-    "extract key points from",
-];
-
-/// 建议生成关键词
-const SUGGESTION_KEYWORDS: &[&str] = &[
-    "prompt suggestion generator",
-    "suggest next prompts",
-    "what should I ask next",
-    "generate follow-up questions",
-    "recommend next steps",
-    "possible next actions",
-];
-
-/// 系统消息关键词
-const SYSTEM_KEYWORDS: &[&str] = &[
-    "Warmup",
-    "<system-reminder>",
-    // Removed: "Caveat: The messages below were generated" - this is a normal Claude Desktop system prompt
-    "This is a system message",
-];
-
-/// 环境探测关键词
-const PROBE_KEYWORDS: &[&str] = &[
-    "check current directory",
-    "list available tools",
-    "verify environment",
-    "test connection",
-];
-
-/// 检测后台任务并返回任务类型
-fn detect_background_task_type(request: &ClaudeRequest) -> Option<BackgroundTaskType> {
-    let last_user_msg = extract_last_user_message_for_detection(request)?;
-    let preview = last_user_msg.chars().take(500).collect::<String>();
-    
-    // 长度过滤：后台任务通常不超过 800 字符
-    if last_user_msg.len() > 800 {
-        return None;
+/// 对冲竞速中单次尝试的失败原因
+enum HedgeAttemptError {
+    /// 上游返回非 2xx (交由常规错误处理流程)
+    Upstream(reqwest::Response),
+    /// 网络错误、无可用账号等
+    Failed(String),
+    /// 流式响应收集失败
+    Collect(String),
+}
+
+/// 对冲请求共享的调用上下文
+struct AttemptContext<'a> {
+    upstream: &'a crate::proxy::upstream::client::UpstreamClient,
+    token_manager: &'a std::sync::Arc<crate::proxy::TokenManager>,
+    method: &'a str,
+    query: Option<&'a str>,
+    trace_id: &'a str,
+    prompt_policy: &'a crate::proxy::prompt_policy::ResolvedPromptPolicy,
+    /// 对冲请求共用同一缓存键 (与 project 无关)，只有完整结束的一方会写入缓存
+    response_cache: Option<ResponseCacheTarget<'a>>,
+}
+
+struct ResponseCacheTarget<'a> {
+    cache: &'a std::sync::Arc<crate::proxy::response_cache::ResponseCache>,
+    key: &'a str,
+    model: &'a str,
+    config: &'a crate::proxy::config::ResponseCacheConfig,
+}
+
+/// 回放缓存的上游响应 (复用 Claude 流式转换器，流式与非流式请求共用)
+async fn replay_cached_response(
+    body: Bytes,
+    client_wants_stream: bool,
+    trace_id: &str,
+    mapped_model: &str,
+    model_hops: &str,
+) -> Response {
+    let claude_stream = create_claude_sse_stream(
+        crate::proxy::response_cache::replay_stream(body),
+        trace_id.to_string(),
+        "response-cache".to_string(),
+    );
+    let sse_stream = claude_stream.map(|result| -> Result<Bytes, std::io::Error> {
+        match result {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+        }
+    });
+
+    if client_wants_stream {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header("X-Mapped-Model", mapped_model)
+            .header("X-Model-Hops", model_hops)
+            .header(crate::proxy::response_cache::HIT_HEADER, "HIT")
+            .body(Body::from_stream(sse_stream))
+            .unwrap();
     }
-    
-    // 按优先级匹配
-    if matches_keywords(&preview, SYSTEM_KEYWORDS) {
-        return Some(BackgroundTaskType::SystemMessage);
+
+    match crate::proxy::mappers::claude::collect_stream_to_json(sse_stream).await {
+        Ok(full_response) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Mapped-Model", mapped_model)
+            .header("X-Model-Hops", model_hops)
+            .header(crate::proxy::response_cache::HIT_HEADER, "HIT")
+            .body(Body::from(serde_json::to_string(&full_response).unwrap()))
+            .unwrap(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response(),
     }
-    
-    if matches_keywords(&preview, TITLE_KEYWORDS) {
-        return Some(BackgroundTaskType::TitleGeneration);
+}
+
+/// 执行一次完整的非流式尝试: 上游调用 + 收集流式响应为 JSON
+async fn execute_collected_attempt(
+    ctx: &AttemptContext<'_>,
+    access_token: &str,
+    body: Value,
+    email: &str,
+) -> Result<HedgedSuccess, HedgeAttemptError> {
+    let response = ctx
+        .upstream
+        .call_v1_internal(ctx.method, access_token, body, ctx.query)
+        .await
+        .map_err(HedgeAttemptError::Failed)?;
+    if !response.status().is_success() {
+        return Err(HedgeAttemptError::Upstream(response));
     }
-    
-    if matches_keywords(&preview, SUMMARY_KEYWORDS) {
-        if preview.contains("in under 50 characters") {
-            return Some(BackgroundTaskType::SimpleSummary);
+    // 健康度在流结束时按是否返回过候选结果记录
+    ctx.token_manager.mark_stream_opened(email);
+    let watched = crate::proxy::account_health::watch_stream(
+        response.bytes_stream(),
+        ctx.token_manager.clone(),
+        email.to_string(),
+    );
+
+    let upstream_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+        match &ctx.response_cache {
+            Some(target) => Box::pin(target.cache.record(
+                watched,
+                target.key.to_string(),
+                target.model.to_string(),
+                target.config.clone(),
+            )),
+            None => Box::pin(watched),
+        };
+    let claude_stream = create_claude_sse_stream(
+        upstream_stream,
+        ctx.trace_id.to_string(),
+        email.to_string(),
+    );
+    let sse_stream = claude_stream.map(|result| -> Result<Bytes, std::io::Error> {
+        match result {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
         }
-        return Some(BackgroundTaskType::ContextCompression);
-    }
-    
-    if matches_keywords(&preview, SUGGESTION_KEYWORDS) {
-        return Some(BackgroundTaskType::PromptSuggestion);
+    });
+    let response = crate::proxy::mappers::claude::collect_stream_to_json(sse_stream)
+        .await
+        .map_err(HedgeAttemptError::Collect)?;
+    Ok(HedgedSuccess {
+        response,
+        email: email.to_string(),
+    })
+}
+
+/// 在另一个账号上发起对冲请求
+async fn run_hedge_attempt(
+    ctx: &AttemptContext<'_>,
+    request_type: &str,
+    model: &str,
+    request: &ClaudeRequest,
+    (quota_threshold, quota_priority): (f64, bool),
+    primary_email: &str,
+    hedge_email: &std::sync::OnceLock<String>,
+) -> Result<HedgedSuccess, HedgeAttemptError> {
+    let (trace_id, token_manager) = (ctx.trace_id, ctx.token_manager);
+    let (access_token, project_id, email) = token_manager
+        .get_token(request_type, Some(model), quota_threshold, true, None, quota_priority)
+        .await
+        .map_err(HedgeAttemptError::Failed)?;
+    if email == primary_email {
+        return Err(HedgeAttemptError::Failed("No alternative account available for hedging".to_string()));
     }
-    
-    if matches_keywords(&preview, PROBE_KEYWORDS) {
-        return Some(BackgroundTaskType::EnvironmentProbe);
+    let _ = hedge_email.set(email.clone());
+    info!("[{}] ⏱ Account {} exceeded hedge delay, hedging on {}", trace_id, primary_email, email);
+
+    let mut body = transform_claude_request_in(request, &project_id)
+        .map_err(|e| HedgeAttemptError::Failed(format!("Transform error: {}", e)))?;
+    ctx.prompt_policy.apply(&mut body);
+    // 与主请求一致，按实际发送的模型计算模型族
+    let upstream_model = body.get("model").and_then(|m| m.as_str()).unwrap_or(&request.model).to_string();
+    let _slot = token_manager
+        .acquire_slot(&email, &upstream_model)
+        .await
+        .map_err(HedgeAttemptError::Failed)?;
+
+    match execute_collected_attempt(ctx, &access_token, body, &email).await {
+        Err(HedgeAttemptError::Upstream(resp)) => {
+            let status = resp.status().as_u16();
+            let error_text = record_upstream_failure(token_manager, &email, resp, &request.model).await;
+            tracing::warn!("[{}] Hedge request on {} failed with HTTP {}", trace_id, email, status);
+            Err(HedgeAttemptError::Failed(format!("HTTP {}: {}", status, error_text)))
+        }
+        other => other,
     }
-    
-    None
 }
 
-/// 辅助函数：关键词匹配
-fn matches_keywords(text: &str, keywords: &[&str]) -> bool {
-    keywords.iter().any(|kw| text.contains(kw))
+/// 读取上游错误响应，记录账号错误并在 429/529/503/500 时标记 (模型级别) 限流，返回错误文本
+async fn record_upstream_failure(
+    token_manager: &crate::proxy::TokenManager,
+    email: &str,
+    response: reqwest::Response,
+    model: &str,
+) -> String {
+    let status = response.status().as_u16();
+    let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+    let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
+    token_manager.record_upstream_error(email, status, &error_text);
+    if status == 429 || status == 529 || status == 503 || status == 500 {
+        token_manager.mark_rate_limited_async(email, status, retry_after.as_deref(), &error_text, Some(model)).await;
+    }
+    error_text
 }
 
-/// 辅助函数：提取最后一条用户消息（用于检测）
-fn extract_last_user_message_for_detection(request: &ClaudeRequest) -> Option<String> {
-    request.messages.iter().rev()
-        .filter(|m| m.role == "user")
-        .find_map(|m| {
-            let content = match &m.content {
-                crate::proxy::mappers::claude::models::MessageContent::String(s) => s.to_string(),
-                crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
-                    arr.iter()
-                        .filter_map(|block| match block {
-                            crate::proxy::mappers::claude::models::ContentBlock::Text { text } => Some(text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                }
-            };
-            
-            if content.trim().is_empty() 
-                || content.starts_with("Warmup") 
-                || content.contains("<system-reminder>") 
-            {
-                None 
-            } else {
-                Some(content)
-            }
+/// 记录被取消的对冲落败请求 (已消耗的配额归属到对应账号)
+async fn log_cancelled_hedge(
+    monitor: &crate::proxy::monitor::ProxyMonitor,
+    model: &str,
+    mapped_model: &str,
+    account_email: String,
+    duration: std::time::Duration,
+) {
+    monitor
+        .log_request(crate::proxy::monitor::ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 499,
+            duration: duration.as_millis() as u64,
+            model: Some(model.to_string()),
+            mapped_model: Some(mapped_model.to_string()),
+            account_email: Some(account_email),
+            error: Some("Hedged request cancelled (lost race)".to_string()),
+            request_body: None,
+            response_body: None,
+            input_tokens: None,
+            output_tokens: None,
+            model_hops: None,
+            cache_hit: false,
         })
+        .await;
 }
 
-/// 根据后台任务类型选择合适的模型
-fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
-    match task_type {
-        BackgroundTaskType::TitleGeneration => BACKGROUND_MODEL_LITE,     // 极简任务
-        BackgroundTaskType::SimpleSummary => BACKGROUND_MODEL_LITE,       // 简单摘要
-        BackgroundTaskType::SystemMessage => BACKGROUND_MODEL_LITE,       // 系统消息
-        BackgroundTaskType::PromptSuggestion => BACKGROUND_MODEL_LITE,    // 建议生成
-        BackgroundTaskType::EnvironmentProbe => BACKGROUND_MODEL_LITE,    // 环境探测
-        BackgroundTaskType::ContextCompression => BACKGROUND_MODEL_STANDARD, // 复杂压缩
-    }
-}
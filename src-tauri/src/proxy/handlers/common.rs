@@ -1,11 +1,28 @@
-use axum::{extract::State, extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::{Query, State}, extract::Json, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use crate::proxy::common::model_router::{self, RequestFeatures, RouteRequest};
+use crate::proxy::config::{InboundProtocol, ModelMappingTarget};
 use crate::proxy::server::AppState;
+use crate::proxy::session_manager::SessionManager;
+
+/// 按有序路由规则 > 自定义映射 > 系统默认映射解析模型链
+pub async fn resolve_model_chain(
+    config: &RwLock<crate::models::config::AppConfig>,
+    custom_mapping: &RwLock<HashMap<String, ModelMappingTarget>>,
+    request: &RouteRequest<'_>,
+) -> Vec<String> {
+    let config = config.read().await;
+    let mapping = custom_mapping.read().await;
+    model_router::resolve_route(request, &config.proxy.routing_rules, &mapping).chain
+}
 
 /// Detects model capabilities and configuration
 /// POST /v1/models/detect
 pub async fn handle_detect_model(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> impl IntoResponse {
     let model_name = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
@@ -14,11 +31,24 @@ pub async fn handle_detect_model(
         return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
     }
 
-    // 1. Resolve mapping
-    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-        model_name,
-        &*state.custom_mapping.read().await,
-    );
+    // 1. Resolve mapping (same routing rules as real requests)
+    let protocol = body
+        .get("protocol")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or(InboundProtocol::OpenAI);
+    let chain = resolve_model_chain(
+        &state.config,
+        &state.custom_mapping,
+        &RouteRequest {
+            model: model_name,
+            protocol,
+            headers: &headers,
+            features: RequestFeatures::from_body(&body),
+            split_key: None,
+        },
+    )
+    .await;
+    let mapped_model = chain[0].clone();
 
     // 2. Resolve capabilities
     let config = crate::proxy::mappers::common_utils::resolve_request_config(
@@ -46,3 +76,56 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ExplainRouteQuery {
+    /// Inbound protocol the request would arrive on (default: openai)
+    pub protocol: Option<InboundProtocol>,
+    /// Overrides the session-derived A/B split key
+    pub split_key: Option<String>,
+}
+
+/// Explains how a request would be routed: which rule matched, why the
+/// others did not, and the resulting model chain. The body is the request
+/// exactly as it would be sent to the protocol endpoint (for Gemini, add a
+/// top-level `model` field). Headers of this request are used for API key
+/// and header conditions.
+/// POST /v1/models/route
+pub async fn handle_explain_route(
+    State(state): State<AppState>,
+    Query(query): Query<ExplainRouteQuery>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> impl IntoResponse {
+    let Some(model) = body.get("model").and_then(|v| v.as_str()) else {
+        return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
+    };
+    let protocol = query.protocol.unwrap_or(InboundProtocol::OpenAI);
+
+    // A/B 分桶与真实请求一致: 按各协议的会话 ID 计算
+    let split_key = query.split_key.or_else(|| match protocol {
+        InboundProtocol::Claude => serde_json::from_value(body.clone())
+            .ok()
+            .map(|req| SessionManager::extract_session_id(&req)),
+        InboundProtocol::OpenAI => serde_json::from_value(body.clone())
+            .ok()
+            .map(|req| SessionManager::extract_openai_session_id(&req)),
+        InboundProtocol::Gemini => Some(SessionManager::extract_gemini_session_id(&body, model)),
+    });
+
+    let config = state.config.read().await;
+    let mapping = state.custom_mapping.read().await;
+    let explanation = model_router::explain_route(
+        &RouteRequest {
+            model,
+            protocol,
+            headers: &headers,
+            features: RequestFeatures::from_body(&body),
+            split_key: split_key.as_deref(),
+        },
+        &config.proxy.routing_rules,
+        &mapping,
+    );
+
+    Json(explanation).into_response()
+}
@@ -1,9 +1,11 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Json, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
+use crate::proxy::background_tasks;
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::response_cache::ResponseCache;
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
  
@@ -14,6 +16,7 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
@@ -35,16 +38,77 @@ pub async fn handle_generate(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     
+    // 系统提示词注入与请求改写策略
+    let prompt_policy = crate::proxy::prompt_policy::ResolvedPromptPolicy::resolve(
+        &state.config.read().await.proxy.prompt_policy,
+        &model_name,
+        crate::proxy::config::InboundProtocol::Gemini,
+        &headers,
+    );
+
+    // 3. 模型路由解析 (有序路由规则 > 自定义映射 > 系统默认映射)
+    let route_session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+    // 后台任务检测 (标题生成/摘要等)，命中时改用分类器的目标模型链
+    let background_task = background_tasks::classify(
+        &state.config.read().await.proxy.background_tasks,
+        crate::proxy::config::InboundProtocol::Gemini,
+        background_tasks::last_user_text_gemini(&body).as_deref(),
+        body.get("tools").and_then(|t| t.as_array()).is_some_and(|t| !t.is_empty()),
+    );
+    let model_chain = match &background_task {
+        Some(task) => {
+            info!("[Gemini][AUTO] 检测到后台任务 (类型: {}),降级到: {:?}", task.name, task.chain);
+            state.monitor.record_background_task(&task.name);
+            task.chain.clone()
+        }
+        None => {
+            super::common::resolve_model_chain(
+                &state.config,
+                &state.custom_mapping,
+                &crate::proxy::common::model_router::RouteRequest {
+                    model: &model_name,
+                    protocol: crate::proxy::config::InboundProtocol::Gemini,
+                    headers: &headers,
+                    features: crate::proxy::common::model_router::RequestFeatures::from_body(&body),
+                    split_key: Some(&route_session_id),
+                },
+            )
+            .await
+        }
+    };
+
+    let response_cache_config = state.config.read().await.proxy.response_cache.clone();
+
+    let base_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let max_attempts = base_attempts.max(model_chain.len());
+
     let mut last_error = String::new();
 
     for attempt in 0..max_attempts {
-        // 3. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &model_name,
-            &*state.custom_mapping.read().await,
-        );
+        // 按模型链顺序降级
+        let chain_index = if attempt < model_chain.len() { attempt } else { model_chain.len() - 1 };
+        let mapped_model = model_chain[chain_index].clone();
+
+        if attempt > 0 && chain_index > 0 && chain_index == attempt {
+             let prev_model = &model_chain[chain_index - 1];
+             tracing::warn!("[Fallback] Switching model {} -> {} due to error", prev_model, mapped_model);
+        }
+        // 响应缓存 (仅确定性请求; 按本次使用的模型计算，键与 project 无关，可在获取账号之前查询)
+        let response_cache_key = if response_cache_config.enabled {
+            let mut probe = wrap_request(&body, "", &mapped_model);
+            prompt_policy.apply(&mut probe);
+            ResponseCache::key_for(&response_cache_config, &model_name, &headers, &probe, is_stream)
+        } else {
+            None
+        };
+        if let Some(key) = &response_cache_key {
+            if let Some(cached) = state.response_cache.get(key, &response_cache_config).await {
+                info!("[Gemini] Response cache hit for {} ({})", model_name, mapped_model);
+                return replay_cached_response(cached, is_stream, &mapped_model);
+            }
+        }
+
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -70,27 +134,60 @@ pub async fn handle_generate(
             (cfg.model_quota_threshold, cfg.proxy.quota_priority_enabled)
         };
 
-        let (access_token, project_id, email) = match token_manager
-            .get_token(
-                &config.request_type,
-                Some(&config.final_model),
-                quota_threshold,
-                attempt > 0,
-                Some(&session_id),
-                quota_priority,
-            )
-            .await
-        {
+        let token = match background_task.as_ref().and_then(|t| t.accounts.as_deref()) {
+            Some(accounts) => {
+                token_manager
+                    .get_token_in_group(
+                        accounts,
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        attempt > 0,
+                        quota_priority,
+                    )
+                    .await
+            }
+            None => {
+                token_manager
+                    .get_token(
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        attempt > 0,
+                        Some(&session_id),
+                        quota_priority,
+                    )
+                    .await
+            }
+        };
+        let (access_token, project_id, email) = match token {
             Ok(t) => t,
             Err(e) => {
+                // 当前模型的账号池已耗尽时直接降级到模型链中的下一个模型
+                if e.contains("All accounts exhausted") {
+                    tracing::warn!("[Fallback] Current model {} pool exhausted (All accounts exhausted). Switching to next model.", mapped_model);
+                    last_error = e;
+                    continue;
+                }
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
             }
         };
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
+        // 获取并发槽位 (槽位已满时排队，超时则尝试下一个账号)
+        let slot = match token_manager.acquire_slot(&email, &config.final_model).await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("[Gemini] {}", e);
+                last_error = e;
+                continue;
+            }
+        };
+
         // 5. 包装请求 (project injection)
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        let mut wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        prompt_policy.apply(&mut wrapped_body);
 
         // 5. 上游调用
         let query_string = if is_stream { Some("alt=sse") } else { None };
@@ -109,70 +206,37 @@ pub async fn handle_generate(
 
         let status = response.status();
         if status.is_success() {
+            // [智能限流] 请求成功，重置该账号的连续失败计数 (流式请求的健康度在流结束时记录)
+            if is_stream {
+                token_manager.mark_stream_opened(&email);
+            } else {
+                token_manager.mark_account_success(&email);
+            }
+
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
                 use axum::response::Response;
-                use bytes::{Bytes, BytesMut};
-                use futures::StreamExt;
+                use bytes::Bytes;
                 
-                let mut response_stream = response.bytes_stream();
-                let mut buffer = BytesMut::new();
-
-                let stream = async_stream::stream! {
-                    while let Some(item) = response_stream.next().await {
-                        match item {
-                            Ok(bytes) => {
-                                debug!("[Gemini-SSE] Received chunk: {} bytes", bytes.len());
-                                buffer.extend_from_slice(&bytes);
-                                while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                                    let line_raw = buffer.split_to(pos + 1);
-                                    if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                                        let line = line_str.trim();
-                                        if line.is_empty() { continue; }
-                                        
-                                        if line.starts_with("data: ") {
-                                            let json_part = line.trim_start_matches("data: ").trim();
-                                            if json_part == "[DONE]" {
-                                                yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
-                                                continue;
-                                            }
-                                            
-                                            match serde_json::from_str::<Value>(json_part) {
-                                                Ok(mut json) => {
-                                                    // Unwrap v1internal response wrapper
-                                                    if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
-                                                        let new_line = format!("data: {}\n\n", serde_json::to_string(&inner).unwrap_or_default());
-                                                        yield Ok::<Bytes, String>(Bytes::from(new_line));
-                                                    } else {
-                                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&json).unwrap_or_default())));
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    debug!("[Gemini-SSE] JSON parse error: {}, passing raw line", e);
-                                                    yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                                }
-                                            }
-                                        } else {
-                                            // Non-data lines (comments, etc.)
-                                            yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                        }
-                                    } else {
-                                        // Non-UTF8 data? Just pass it through or skip
-                                        debug!("[Gemini-SSE] Non-UTF8 line encountered");
-                                        yield Ok::<Bytes, String>(line_raw.freeze());
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                error!("[Gemini-SSE] Connection error: {}", e);
-                                yield Err(format!("Stream error: {}", e));
-                            }
-                        }
-                    }
-                };
+                let response_stream = crate::proxy::account_health::watch_stream(
+                    response.bytes_stream(),
+                    token_manager.clone(),
+                    email.clone(),
+                );
+                let response_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+                    match response_cache_key.clone() {
+                        Some(key) => Box::pin(state.response_cache.record(
+                            response_stream,
+                            key,
+                            model_name.clone(),
+                            response_cache_config.clone(),
+                        )),
+                        None => Box::pin(response_stream),
+                    };
+                let stream = unwrap_sse_stream(response_stream);
                 
-                let body = Body::from_stream(stream);
+                let body = Body::from_stream(crate::proxy::concurrency::guard_stream(stream, slot));
                 return Ok(Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
@@ -184,10 +248,17 @@ pub async fn handle_generate(
                     .into_response());
             }
 
-            let gemini_resp: Value = response
-                .json()
+            let raw = response
+                .bytes()
                 .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Read error: {}", e)))?;
+            let gemini_resp: Value = serde_json::from_slice(&raw)
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            if let Some(key) = response_cache_key.clone() {
+                if gemini_resp.pointer("/response/candidates/0/finishReason").is_some() {
+                    state.response_cache.put(key, &model_name, raw, &response_cache_config);
+                }
+            }
 
             let unwrapped = unwrap_response(&gemini_resp);
             return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
@@ -198,6 +269,7 @@ pub async fn handle_generate(
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        token_manager.record_upstream_error(&email, status_code, &error_text);
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
@@ -222,6 +294,94 @@ pub async fn handle_generate(
     Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
 }
 
+/// 以缓存的上游原始响应构造客户端响应
+fn replay_cached_response(
+    cached: bytes::Bytes,
+    is_stream: bool,
+    mapped_model: &str,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    use crate::proxy::response_cache::{replay_stream, HIT_HEADER};
+
+    if is_stream {
+        let body = axum::body::Body::from_stream(unwrap_sse_stream(replay_stream(cached)));
+        return Ok(axum::response::Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("X-Mapped-Model", mapped_model)
+            .header(HIT_HEADER, "HIT")
+            .body(body)
+            .unwrap());
+    }
+
+    let gemini_resp: Value = serde_json::from_slice(&cached)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Corrupted cache entry: {}", e)))?;
+    let unwrapped = unwrap_response(&gemini_resp);
+    Ok((StatusCode::OK, [("X-Mapped-Model", mapped_model), (HIT_HEADER, "HIT")], Json(unwrapped)).into_response())
+}
+
+/// 解包 v1internal SSE 响应 (去除每个事件外层的 response 包装)
+fn unwrap_sse_stream(
+    mut response_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
+) -> impl futures::Stream<Item = Result<bytes::Bytes, String>> {
+    use bytes::{Bytes, BytesMut};
+    use futures::StreamExt;
+
+    let mut buffer = BytesMut::new();
+    async_stream::stream! {
+        while let Some(item) = response_stream.next().await {
+            match item {
+                Ok(bytes) => {
+                    debug!("[Gemini-SSE] Received chunk: {} bytes", bytes.len());
+                    buffer.extend_from_slice(&bytes);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line_raw = buffer.split_to(pos + 1);
+                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
+                            let line = line_str.trim();
+                            if line.is_empty() { continue; }
+                            
+                            if line.starts_with("data: ") {
+                                let json_part = line.trim_start_matches("data: ").trim();
+                                if json_part == "[DONE]" {
+                                    yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
+                                    continue;
+                                }
+                                
+                                match serde_json::from_str::<Value>(json_part) {
+                                    Ok(mut json) => {
+                                        // Unwrap v1internal response wrapper
+                                        if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
+                                            let new_line = format!("data: {}\n\n", serde_json::to_string(&inner).unwrap_or_default());
+                                            yield Ok::<Bytes, String>(Bytes::from(new_line));
+                                        } else {
+                                            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&json).unwrap_or_default())));
+                                        }
+                                    }
+                                    Err(e) => {
+                                        debug!("[Gemini-SSE] JSON parse error: {}, passing raw line", e);
+                                        yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
+                                    }
+                                }
+                            } else {
+                                // Non-data lines (comments, etc.)
+                                yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
+                            }
+                        } else {
+                            // Non-UTF8 data? Just pass it through or skip
+                            debug!("[Gemini-SSE] Non-UTF8 line encountered");
+                            yield Ok::<Bytes, String>(line_raw.freeze());
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("[Gemini-SSE] Connection error: {}", e);
+                    yield Err(format!("Stream error: {}", e));
+                }
+            }
+        }
+    }
+}
+
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
     use crate::proxy::common::model_mapping::get_all_dynamic_models;
 
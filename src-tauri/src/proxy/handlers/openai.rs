@@ -1,5 +1,5 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{extract::Json, extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use base64::Engine as _; 
 use bytes::Bytes;
 use serde_json::{json, Value};
@@ -9,6 +9,14 @@ use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::common::model_router::{RequestFeatures, RouteRequest};
+use crate::proxy::background_tasks;
+use crate::proxy::config::InboundProtocol;
+use crate::proxy::prompt_policy::ResolvedPromptPolicy;
+use crate::proxy::response_cache::ResponseCache;
+use crate::proxy::trace_capture::{self, TraceKind, TraceRecorder};
+use crate::proxy::context_window::{self, ContextManager};
+use crate::proxy::candidates;
 use crate::proxy::server::AppState;
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
@@ -16,8 +24,12 @@ use crate::proxy::session_manager::SessionManager;
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let route_features = RequestFeatures::from_body(&body);
+    // [Trace] 协议转换调试抓取
+    let trace = TraceRecorder::start(&state.config.read().await.proxy.trace_capture, &headers, TraceKind::OpenaiChat, &body);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -45,18 +57,64 @@ pub async fn handle_chat_completions(
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
 
-    // Resolve model chain
-    let model_chain = crate::proxy::common::model_mapping::resolve_model_chain(
+    // 系统提示词注入与请求改写策略
+    let prompt_policy = std::sync::Arc::new(ResolvedPromptPolicy::resolve(
+        &state.config.read().await.proxy.prompt_policy,
         &openai_req.model,
-        &*state.custom_mapping.read().await,
+        InboundProtocol::OpenAI,
+        &headers,
+    ));
+
+    // Resolve model chain (有序路由规则 > 自定义映射 > 系统默认映射)
+    let route_session_id = SessionManager::extract_openai_session_id(&openai_req);
+    // 后台任务检测 (标题生成/摘要等)，命中时改用分类器的目标模型链
+    let background_task = background_tasks::classify(
+        &state.config.read().await.proxy.background_tasks,
+        InboundProtocol::OpenAI,
+        background_tasks::last_user_text_openai(&openai_req).as_deref(),
+        openai_req.tools.as_ref().is_some_and(|t| !t.is_empty()),
     );
+    let model_chain = match &background_task {
+        Some(task) => {
+            info!("[OpenAI][AUTO] 检测到后台任务 (类型: {}),降级到: {:?}", task.name, task.chain);
+            state.monitor.record_background_task(&task.name);
+            task.chain.clone()
+        }
+        None => {
+            super::common::resolve_model_chain(
+                &state.config,
+                &state.custom_mapping,
+                &RouteRequest {
+                    model: &openai_req.model,
+                    protocol: InboundProtocol::OpenAI,
+                    headers: &headers,
+                    features: route_features,
+                    split_key: Some(&route_session_id),
+                },
+            )
+            .await
+        }
+    };
 
     let base_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     let max_attempts = base_attempts.max(model_chain.len());
 
     let mut last_error = String::new();
+    // [Context Window] 超出目标模型上下文窗口时截断或摘要最早的对话轮次
+    let mut context_manager = ContextManager::new(
+        &state.config.read().await.proxy.context_window,
+        InboundProtocol::OpenAI,
+    );
+    // [Multi-Candidate] n > 1 且上游不接受 candidateCount 时拆分为并行请求
+    let multi_candidate = state.config.read().await.proxy.multi_candidate.clone();
+    let mut force_fan_out = false;
 
-    for attempt in 0..max_attempts {
+    let response_cache_config = state.config.read().await.proxy.response_cache.clone();
+
+    // 上下文超限时修正预算后重放同一次尝试 (相同模型与账号选择)，不占用重试次数
+    let mut attempts = 0..max_attempts;
+    let mut repeat_attempt: Option<usize> = None;
+    while let Some(attempt) = repeat_attempt.take().or_else(|| attempts.next()) {
         // 2. Select model from chain
         let chain_index = if attempt < model_chain.len() { attempt } else { model_chain.len() - 1 };
         let mapped_model = model_chain[chain_index].clone();
@@ -79,6 +137,38 @@ pub async fn handle_chat_completions(
         // 3. 提取 SessionId (粘性指纹)
         let session_id = SessionManager::extract_openai_session_id(&openai_req);
 
+        // 5. 发送请求 - 自动转换逻辑
+        let client_wants_stream = openai_req.stream;
+        // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
+        let force_stream_internally = !client_wants_stream;
+        let actual_stream = client_wants_stream || force_stream_internally;
+        
+        if force_stream_internally {
+            info!("[OpenAI] 🔄 Auto-converting non-stream request to stream for better quota");
+        }
+        
+        let method = if actual_stream {
+            "streamGenerateContent"
+        } else {
+            "generateContent"
+        };
+        let query_string = if actual_stream { Some("alt=sse") } else { None };
+
+        // [Response Cache] 确定性请求命中缓存时直接回放，不占用账号与并发槽位 (缓存键与 project 无关)
+        let response_cache_key = if response_cache_config.enabled {
+            let mut probe = transform_openai_request(&openai_req, "", &mapped_model);
+            prompt_policy.apply(&mut probe);
+            ResponseCache::key_for(&response_cache_config, &openai_req.model, &headers, &probe, actual_stream)
+        } else {
+            None
+        };
+        if let Some(key) = &response_cache_key {
+            if let Some(cached) = state.response_cache.get(key, &response_cache_config).await {
+                info!("[OpenAI] ✓ Response cache hit ({} bytes)", cached.len());
+                return replay_cached_response(cached, client_wants_stream, &openai_req.model, &session_id, &mapped_model).await;
+            }
+        }
+
         // 4. 获取 Token (使用准确的 request_type)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
         let (quota_threshold, quota_priority) = {
@@ -86,17 +176,33 @@ pub async fn handle_chat_completions(
             (cfg.model_quota_threshold, cfg.proxy.quota_priority_enabled)
         };
 
-        let (access_token, project_id, email) = match token_manager
-            .get_token(
-                &config.request_type,
-                Some(&config.final_model),
-                quota_threshold,
-                attempt > 0,
-                Some(&session_id),
-                quota_priority,
-            )
-            .await
-        {
+        let token = match background_task.as_ref().and_then(|t| t.accounts.as_deref()) {
+            Some(accounts) => {
+                token_manager
+                    .get_token_in_group(
+                        accounts,
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        attempt > 0,
+                        quota_priority,
+                    )
+                    .await
+            }
+            None => {
+                token_manager
+                    .get_token(
+                        &config.request_type,
+                        Some(&config.final_model),
+                        quota_threshold,
+                        attempt > 0,
+                        Some(&session_id),
+                        quota_priority,
+                    )
+                    .await
+            }
+        };
+        let (access_token, project_id, email) = match token {
             Ok(t) => t,
             Err(e) => {
                 // [Fix] If current model pool is completely exhausted (Hard Floor),
@@ -115,35 +221,66 @@ pub async fn handle_chat_completions(
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
+        // 获取并发槽位 (槽位已满时排队，超时则尝试下一个账号)
+        let slot = match token_manager.acquire_slot(&email, &config.final_model).await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("[OpenAI] {}", e);
+                last_error = e;
+                continue;
+            }
+        };
+
         // 4. 转换请求
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        if let Some(trace) = &trace {
+            trace.record_mapping(&mapped_model, &project_id, &openai_req, &gemini_body);
+        }
+        prompt_policy.apply(&mut gemini_body);
+        let context_trim = context_manager
+            .fit(&mut gemini_body, &mapped_model, &upstream, &access_token, &project_id)
+            .await;
+        if let Some(trace) = &trace {
+            trace.record_upstream_request(&gemini_body);
+        }
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试
         if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
             debug!("[OpenAI-Request] Transformed Gemini Body:\n{}", body_json);
         }
 
-        // 5. 发送请求 - 自动转换逻辑
-        let client_wants_stream = openai_req.stream;
-        // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
-        let force_stream_internally = !client_wants_stream;
-        let actual_stream = client_wants_stream || force_stream_internally;
-        
-        if force_stream_internally {
-            info!("[OpenAI] 🔄 Auto-converting non-stream request to stream for better quota");
+
+        let candidate_count = candidates::candidate_count(&gemini_body);
+        let fan_out = candidate_count > 1
+            && (force_fan_out || candidates::should_fan_out(&multi_candidate, &mapped_model, &email));
+        if fan_out && candidate_count > multi_candidate.max_fan_out {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("n={} exceeds the parallel candidate limit ({}) for model {}", candidate_count, multi_candidate.max_fan_out, mapped_model),
+            ));
+        }
+        // 并行请求在同一账号上各占一个槽位，单账号并发上限不足 n 时无法完成
+        if let Some(limit) = token_manager.slot_limit(&config.final_model).filter(|&l| fan_out && candidate_count as usize > l) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("n={} exceeds the per-account concurrency limit ({}) for model {}", candidate_count, limit, mapped_model),
+            ));
+        }
+        if fan_out {
+            candidates::strip_candidate_count(&mut gemini_body);
         }
-        
-        let method = if actual_stream {
-            "streamGenerateContent"
-        } else {
-            "generateContent"
-        };
-        let query_string = if actual_stream { Some("alt=sse") } else { None };
 
-        let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
-            .await
-        {
+        let fan_out_request = candidates::FanOut {
+            upstream: &upstream,
+            token_manager: &token_manager,
+            email: &email,
+            model: &config.final_model,
+            access_token: &access_token,
+            method,
+            query_string,
+        };
+        let extra_count = if fan_out { candidate_count as usize - 1 } else { 0 };
+        let sent = match fan_out_request.send(gemini_body, extra_count).await {
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
@@ -157,31 +294,97 @@ pub async fn handle_chat_completions(
             }
         };
 
-        let status = response.status();
+        let status = sent.response.status();
         if status.is_success() {
+            // [智能限流] 请求成功，重置该账号的连续失败计数 (流式请求的健康度在流结束时记录)
+            if actual_stream {
+                token_manager.mark_stream_opened(&email);
+            } else {
+                token_manager.mark_account_success(&email);
+            }
+
             // 5. 处理流式 vs 非流式
             if actual_stream {
-                use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
+                use crate::proxy::mappers::openai::streaming::create_openai_sse_stream_with_failover;
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
-                let openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                // [Stream Failover] 上游中途断流时换号续写 (续传只跟踪单个候选结果，仅 n = 1 时启用)
+                let failover = state.config.read().await.proxy.stream_failover.clone();
+                let resumer = if failover.enabled && context_trim.is_none() && candidate_count <= 1 {
+                    let base_request = openai_req.clone();
+                    let resume_model = mapped_model.clone();
+                    let resume_policy = prompt_policy.clone();
+                    Some(crate::proxy::stream_failover::build_resumer(
+                        crate::proxy::stream_failover::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            request_type: config.request_type.clone(),
+                            model: config.final_model.clone(),
+                            quota_threshold,
+                            quota_priority,
+                            max_resumes: failover.max_resumes,
+                            trace_id: format!("OpenAI:{}", session_id),
+                        },
+                        email.clone(),
+                        move |partial, project_id| {
+                            let mut req = base_request.clone();
+                            // 已输出正文作为 assistant prefill
+                            if !partial.is_empty() {
+                                req.messages.push(crate::proxy::mappers::openai::OpenAIMessage {
+                                    role: "assistant".to_string(),
+                                    content: Some(crate::proxy::mappers::openai::OpenAIContent::String(partial.to_string())),
+                                    reasoning_content: None,
+                                    tool_calls: None,
+                                    tool_call_id: None,
+                                    name: None,
+                                });
+                            }
+                            let mut body = transform_openai_request(&req, project_id, &resume_model);
+                            resume_policy.apply(&mut body);
+                            Ok(body)
+                        },
+                    ))
+                } else {
+                    None
+                };
+
+                let upstream_stream = sent.into_stream(&token_manager, &email);
+                let gemini_stream = trace_capture::tee_upstream(trace.as_ref(), upstream_stream);
+                let gemini_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+                    match response_cache_key {
+                        Some(key) => Box::pin(state.response_cache.record(
+                            gemini_stream,
+                            key,
+                            openai_req.model.clone(),
+                            response_cache_config.clone(),
+                        )),
+                        None => Box::pin(gemini_stream),
+                    };
+                if let Some(trace) = &trace {
+                    trace.record_stream_context(Some(session_id.clone()), None);
+                }
+                let openai_stream = trace_capture::tee_client(
+                    trace.as_ref(),
+                    create_openai_sse_stream_with_failover(gemini_stream, openai_req.model.clone(), session_id.clone(), resumer),
+                );
                 
                 // 判断客户端期望的格式
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
-                    let body = Body::from_stream(openai_stream);
-                    return Ok(Response::builder()
-                        .header("Content-Type", "text/event-stream")
-                        .header("Cache-Control", "no-cache")
-                        .header("Connection", "keep-alive")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &mapped_model)
-                        .body(body)
-                        .unwrap()
-                        .into_response());
+                    let body = Body::from_stream(crate::proxy::concurrency::guard_stream(openai_stream, slot));
+                    return Ok(context_window::annotate(
+                        Response::builder()
+                            .header("Content-Type", "text/event-stream")
+                            .header("Cache-Control", "no-cache")
+                            .header("Connection", "keep-alive")
+                            .header("X-Account-Email", &email)
+                            .header("X-Mapped-Model", &mapped_model)
+                            .body(body)
+                            .unwrap()
+                            .into_response(),
+                        context_trim.as_ref(),
+                    ));
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::openai::collect_openai_stream_to_json;
@@ -198,7 +401,10 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            return Ok(context_window::annotate(
+                                (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response(),
+                                context_trim.as_ref(),
+                            ));
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -207,20 +413,25 @@ pub async fn handle_chat_completions(
                 }
             }
 
-            let gemini_resp: Value = response
-                .json()
+            let gemini_resp: Value = sent
+                .into_json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
-            let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            let openai_response = transform_openai_response(&gemini_resp, &session_id);
+            return Ok(context_window::annotate(
+                (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response(),
+                context_trim.as_ref(),
+            ));
         }
 
         // 处理特定错误并重试
+        let response = sent.response;
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        token_manager.record_upstream_error(&email, status_code, &error_text);
 
         // [New] 打印错误报文日志
         tracing::error!(
@@ -229,6 +440,23 @@ pub async fn handle_chat_completions(
             error_text
         );
 
+        // [Context Window] 上下文超限: 修正预算后在本次尝试内重新裁剪发送，否则直接返回
+        if status_code == 400 && context_window::is_context_overflow(&error_text) {
+            if context_manager.tighten(&error_text) {
+                repeat_attempt = Some(attempt);
+                continue;
+            }
+            return Err((status, error_text));
+        }
+
+        // [Multi-Candidate] 上游拒绝 candidateCount: 记录该模型/账号组合，在本次尝试内改用并行请求重发
+        if status_code == 400 && candidate_count > 1 && !fan_out && candidates::is_candidate_count_rejected(&error_text) {
+            candidates::remember_rejection(&mapped_model, &email);
+            force_fan_out = true;
+            repeat_attempt = Some(attempt);
+            continue;
+        }
+
         // 429/529/503 智能处理
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
             // 记录限流信息 (全局同步)
@@ -301,10 +529,51 @@ pub async fn handle_chat_completions(
     ))
 }
 
+/// 回放缓存的上游响应 (复用 OpenAI 流式转换器，流式与非流式请求共用)
+async fn replay_cached_response(
+    body: Bytes,
+    client_wants_stream: bool,
+    model: &str,
+    session_id: &str,
+    mapped_model: &str,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
+    use futures::StreamExt;
+
+    let openai_stream = create_openai_sse_stream(
+        crate::proxy::response_cache::replay_stream(body),
+        model.to_string(),
+        session_id.to_string(),
+    );
+
+    if client_wants_stream {
+        return Ok(axum::response::Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("X-Mapped-Model", mapped_model)
+            .header(crate::proxy::response_cache::HIT_HEADER, "HIT")
+            .body(axum::body::Body::from_stream(openai_stream))
+            .unwrap()
+            .into_response());
+    }
+
+    let sse_stream = openai_stream.map(|result| result.map_err(std::io::Error::other));
+    match crate::proxy::mappers::openai::collect_openai_stream_to_json(sse_stream).await {
+        Ok(full_response) => Ok((
+            StatusCode::OK,
+            [("X-Mapped-Model", mapped_model), (crate::proxy::response_cache::HIT_HEADER, "HIT")],
+            Json(full_response),
+        )
+            .into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e))),
+    }
+}
+
 /// 处理 Legacy Completions API (/v1/completions)
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     info!(
@@ -313,6 +582,13 @@ pub async fn handle_completions(
     );
 
     let is_codex_style = body.get("input").is_some() && body.get("instructions").is_some();
+    // [Trace] 协议转换调试抓取 (在改写为 Chat 格式之前保留原始请求)
+    let trace = TraceRecorder::start(
+        &state.config.read().await.proxy.trace_capture,
+        &headers,
+        if is_codex_style { TraceKind::OpenaiCodex } else { TraceKind::OpenaiLegacy },
+        &body,
+    );
 
     // 1. Convert Payload to Messages (Shared Chat Format)
     if is_codex_style {
@@ -561,6 +837,7 @@ pub async fn handle_completions(
     // Actually, due to SSE handling differences (Codex uses different event format), we replicate the loop here or abstract it.
     // For now, let's replicate the core loop but with Codex specific SSE mapping.
 
+    let route_features = RequestFeatures::from_body(&body);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body.clone())
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -584,18 +861,64 @@ pub async fn handle_completions(
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
 
-    // Resolve model chain
-    let model_chain = crate::proxy::common::model_mapping::resolve_model_chain(
+    // 系统提示词注入与请求改写策略
+    let prompt_policy = std::sync::Arc::new(ResolvedPromptPolicy::resolve(
+        &state.config.read().await.proxy.prompt_policy,
         &openai_req.model,
-        &*state.custom_mapping.read().await,
+        InboundProtocol::OpenAI,
+        &headers,
+    ));
+
+    // Resolve model chain (有序路由规则 > 自定义映射 > 系统默认映射)
+    let route_session_id = SessionManager::extract_openai_session_id(&openai_req);
+    // 后台任务检测 (标题生成/摘要等)，命中时改用分类器的目标模型链
+    let background_task = background_tasks::classify(
+        &state.config.read().await.proxy.background_tasks,
+        InboundProtocol::OpenAI,
+        background_tasks::last_user_text_openai(&openai_req).as_deref(),
+        openai_req.tools.as_ref().is_some_and(|t| !t.is_empty()),
     );
+    let model_chain = match &background_task {
+        Some(task) => {
+            info!("[OpenAI][AUTO] 检测到后台任务 (类型: {}),降级到: {:?}", task.name, task.chain);
+            state.monitor.record_background_task(&task.name);
+            task.chain.clone()
+        }
+        None => {
+            super::common::resolve_model_chain(
+                &state.config,
+                &state.custom_mapping,
+                &RouteRequest {
+                    model: &openai_req.model,
+                    protocol: InboundProtocol::OpenAI,
+                    headers: &headers,
+                    features: route_features,
+                    split_key: Some(&route_session_id),
+                },
+            )
+            .await
+        }
+    };
 
     let base_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     let max_attempts = base_attempts.max(model_chain.len());
 
     let mut last_error = String::new();
+    // [Context Window] 超出目标模型上下文窗口时截断或摘要最早的对话轮次
+    let mut context_manager = ContextManager::new(
+        &state.config.read().await.proxy.context_window,
+        InboundProtocol::OpenAI,
+    );
+    // [Multi-Candidate] n > 1 且上游不接受 candidateCount 时拆分为并行请求
+    let multi_candidate = state.config.read().await.proxy.multi_candidate.clone();
+    let mut force_fan_out = false;
 
-    for attempt in 0..max_attempts {
+    let response_cache_config = state.config.read().await.proxy.response_cache.clone();
+
+    // 上下文超限时修正预算后重放同一次尝试 (相同模型与账号选择)，不占用重试次数
+    let mut attempts = 0..max_attempts;
+    let mut repeat_attempt: Option<usize> = None;
+    while let Some(attempt) = repeat_attempt.take().or_else(|| attempts.next()) {
         // 1. Select model from chain
         let chain_index = if attempt < model_chain.len() { attempt } else { model_chain.len() - 1 };
         let mapped_model = model_chain[chain_index].clone();
@@ -615,12 +938,42 @@ pub async fn handle_completions(
             &tools_val,
         );
 
+        // [Response Cache] 确定性请求命中缓存时直接回放，不占用账号与并发槽位 (缓存键与 project 无关)
+        let response_cache_key = if response_cache_config.enabled {
+            let mut probe = transform_openai_request(&openai_req, "", &mapped_model);
+            prompt_policy.apply(&mut probe);
+            ResponseCache::key_for(&response_cache_config, &openai_req.model, &headers, &probe, openai_req.stream)
+        } else {
+            None
+        };
+        if let Some(key) = &response_cache_key {
+            if let Some(cached) = state.response_cache.get(key, &response_cache_config).await {
+                info!("[Codex] ✓ Response cache hit ({} bytes)", cached.len());
+                return replay_cached_completion(
+                    cached,
+                    openai_req.stream,
+                    is_codex_style,
+                    &openai_req.model,
+                    &route_session_id,
+                    &mapped_model,
+                );
+            }
+        }
+
         let (quota_threshold, quota_priority) = {
             let cfg = state.config.read().await;
             (cfg.model_quota_threshold, cfg.proxy.quota_priority_enabled)
         };
+        let token = match background_task.as_ref().and_then(|t| t.accounts.as_deref()) {
+            Some(accounts) => {
+                token_manager
+                    .get_token_in_group(accounts, &config.request_type, Some(&config.final_model), quota_threshold, false, quota_priority)
+                    .await
+            }
+            None => token_manager.get_token(&config.request_type, Some(&config.final_model), quota_threshold, false, None, quota_priority).await,
+        };
         let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, Some(&config.final_model), quota_threshold, false, None, quota_priority).await {
+            match token {
                 Ok(t) => t,
                 Err(e) => {
                     // [Fix] If current model pool is completely exhausted (Hard Floor),
@@ -638,7 +991,27 @@ pub async fn handle_completions(
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        // 获取并发槽位 (槽位已满时排队，超时则尝试下一个账号)
+        let slot = match token_manager.acquire_slot(&email, &config.final_model).await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("[Codex] {}", e);
+                last_error = e;
+                continue;
+            }
+        };
+
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        if let Some(trace) = &trace {
+            trace.record_mapping(&mapped_model, &project_id, &openai_req, &gemini_body);
+        }
+        prompt_policy.apply(&mut gemini_body);
+        let context_trim = context_manager
+            .fit(&mut gemini_body, &mapped_model, &upstream, &access_token, &project_id)
+            .await;
+        if let Some(trace) = &trace {
+            trace.record_upstream_request(&gemini_body);
+        }
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径)
         if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
@@ -653,10 +1026,39 @@ pub async fn handle_completions(
         };
         let query_string = if list_response { Some("alt=sse") } else { None };
 
-        let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
-            .await
-        {
+        // Codex (Responses API) 只有单个输出，不做拆分
+        let candidate_count = candidates::candidate_count(&gemini_body);
+        let fan_out = !is_codex_style
+            && candidate_count > 1
+            && (force_fan_out || candidates::should_fan_out(&multi_candidate, &mapped_model, &email));
+        if fan_out && candidate_count > multi_candidate.max_fan_out {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("n={} exceeds the parallel candidate limit ({}) for model {}", candidate_count, multi_candidate.max_fan_out, mapped_model),
+            ));
+        }
+        // 并行请求在同一账号上各占一个槽位，单账号并发上限不足 n 时无法完成
+        if let Some(limit) = token_manager.slot_limit(&config.final_model).filter(|&l| fan_out && candidate_count as usize > l) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("n={} exceeds the per-account concurrency limit ({}) for model {}", candidate_count, limit, mapped_model),
+            ));
+        }
+        if fan_out {
+            candidates::strip_candidate_count(&mut gemini_body);
+        }
+
+        let fan_out_request = candidates::FanOut {
+            upstream: &upstream,
+            token_manager: &token_manager,
+            email: &email,
+            model: &config.final_model,
+            access_token: &access_token,
+            method,
+            query_string,
+        };
+        let extra_count = if fan_out { candidate_count as usize - 1 } else { 0 };
+        let sent = match fan_out_request.send(gemini_body, extra_count).await {
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
@@ -664,72 +1066,91 @@ pub async fn handle_completions(
             }
         };
 
-        let status = response.status();
+        let status = sent.response.status();
         if status.is_success() {
+            // [智能限流] 请求成功，重置该账号的连续失败计数 (流式请求的健康度在流结束时记录)
+            if list_response {
+                token_manager.mark_stream_opened(&email);
+            } else {
+                token_manager.mark_account_success(&email);
+            }
+
             if list_response {
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
+                let upstream_stream = sent.into_stream(&token_manager, &email);
+                let gemini_stream = trace_capture::tee_upstream(trace.as_ref(), upstream_stream);
+                let gemini_stream: candidates::GeminiStream = match response_cache_key {
+                    Some(key) => Box::pin(state.response_cache.record(
+                        gemini_stream,
+                        key,
+                        openai_req.model.clone(),
+                        response_cache_config.clone(),
+                    )),
+                    None => Box::pin(gemini_stream),
+                };
+                if let Some(trace) = &trace {
+                    trace.record_stream_context(Some(route_session_id.clone()), None);
+                }
                 let body = if is_codex_style {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                     let s =
-                        create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_codex_sse_stream(gemini_stream, openai_req.model.clone(), route_session_id.clone());
+                    Body::from_stream(crate::proxy::concurrency::guard_stream(trace_capture::tee_client(trace.as_ref(), s), slot))
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
                     let s =
-                        create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_legacy_sse_stream(gemini_stream, openai_req.model.clone(), route_session_id.clone());
+                    Body::from_stream(crate::proxy::concurrency::guard_stream(trace_capture::tee_client(trace.as_ref(), s), slot))
                 };
 
-                return Ok(Response::builder()
-                    .header("Content-Type", "text/event-stream")
-                    .header("Cache-Control", "no-cache")
-                    .header("Connection", "keep-alive")
-                    .header("X-Account-Email", &email)
-                    .header("X-Mapped-Model", &mapped_model)
-                    .body(body)
-                    .unwrap()
-                    .into_response());
+                return Ok(context_window::annotate(
+                    Response::builder()
+                        .header("Content-Type", "text/event-stream")
+                        .header("Cache-Control", "no-cache")
+                        .header("Connection", "keep-alive")
+                        .header("X-Account-Email", &email)
+                        .header("X-Mapped-Model", &mapped_model)
+                        .body(body)
+                        .unwrap()
+                        .into_response(),
+                    context_trim.as_ref(),
+                ));
             }
 
-            let gemini_resp: Value = response
-                .json()
+            let gemini_resp: Value = sent
+                .into_json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            if let Some(key) = response_cache_key {
+                if gemini_resp.pointer("/response/candidates/0/finishReason").is_some() {
+                    if let Ok(raw) = serde_json::to_vec(&gemini_resp) {
+                        state.response_cache.put(key, &openai_req.model, Bytes::from(raw), &response_cache_config);
+                    }
+                }
+            }
 
-            let chat_resp = transform_openai_response(&gemini_resp);
-
-            // Map Chat Response -> Legacy Completions Response
-            let choices = chat_resp.choices.iter().map(|c| {
-                json!({
-                    "text": match &c.message.content {
-                        Some(crate::proxy::mappers::openai::OpenAIContent::String(s)) => s.clone(),
-                        _ => "".to_string()
-                    },
-                    "index": c.index,
-                    "logprobs": null,
-                    "finish_reason": c.finish_reason
-                })
-            }).collect::<Vec<_>>();
-
-            let legacy_resp = json!({
-                "id": chat_resp.id,
-                "object": "text_completion",
-                "created": chat_resp.created,
-                "model": chat_resp.model,
-                "choices": choices
-            });
-
-            return Ok(axum::Json(legacy_resp).into_response());
+            let legacy_resp = legacy_completion_response(&gemini_resp, &route_session_id);
+            return Ok(context_window::annotate(axum::Json(legacy_resp).into_response(), context_trim.as_ref()));
         }
 
         // Handle errors and retry
         let status_code = status.as_u16();
-        let error_text = response.text().await.unwrap_or_default();
+        let error_text = sent.response.text().await.unwrap_or_default();
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        token_manager.record_upstream_error(&email, status_code, &error_text);
 
+        if status_code == 400 && context_window::is_context_overflow(&error_text) && context_manager.tighten(&error_text) {
+            repeat_attempt = Some(attempt);
+            continue;
+        }
+        if status_code == 400 && candidate_count > 1 && !fan_out && candidates::is_candidate_count_rejected(&error_text) {
+            candidates::remember_rejection(&mapped_model, &email);
+            force_fan_out = true;
+            repeat_attempt = Some(attempt);
+            continue;
+        }
         if status_code == 429 || status_code == 403 || status_code == 401 {
             continue;
         }
@@ -742,6 +1163,70 @@ pub async fn handle_completions(
     ))
 }
 
+/// 将上游非流式响应转换为 Legacy Completions 响应
+fn legacy_completion_response(gemini_resp: &Value, session_id: &str) -> Value {
+    let chat_resp = transform_openai_response(gemini_resp, session_id);
+
+    // Map Chat Response -> Legacy Completions Response
+    let choices = chat_resp.choices.iter().map(|c| {
+        json!({
+            "text": match &c.message.content {
+                Some(crate::proxy::mappers::openai::OpenAIContent::String(s)) => s.clone(),
+                _ => "".to_string()
+            },
+            "index": c.index,
+            "logprobs": null,
+            "finish_reason": c.finish_reason
+        })
+    }).collect::<Vec<_>>();
+
+    json!({
+        "id": chat_resp.id,
+        "object": "text_completion",
+        "created": chat_resp.created,
+        "model": chat_resp.model,
+        "choices": choices
+    })
+}
+
+/// 回放缓存的上游响应 (Legacy Completions / Codex)
+fn replay_cached_completion(
+    body: Bytes,
+    stream: bool,
+    is_codex_style: bool,
+    model: &str,
+    session_id: &str,
+    mapped_model: &str,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    use crate::proxy::response_cache::{replay_stream, HIT_HEADER};
+
+    if stream {
+        let body = if is_codex_style {
+            use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
+            axum::body::Body::from_stream(create_codex_sse_stream(replay_stream(body), model.to_string(), session_id.to_string()))
+        } else {
+            use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
+            axum::body::Body::from_stream(create_legacy_sse_stream(replay_stream(body), model.to_string(), session_id.to_string()))
+        };
+        return Ok(axum::response::Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("X-Mapped-Model", mapped_model)
+            .header(HIT_HEADER, "HIT")
+            .body(body)
+            .unwrap());
+    }
+
+    let gemini_resp: Value = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Corrupted cache entry: {}", e)))?;
+    Ok((
+        StatusCode::OK,
+        [("X-Mapped-Model", mapped_model), (HIT_HEADER, "HIT")],
+        Json(legacy_completion_response(&gemini_resp, session_id)),
+    )
+        .into_response())
+}
+
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
     use crate::proxy::common::model_mapping::get_all_dynamic_models;
 
@@ -0,0 +1,191 @@
+// 请求对冲 (Request hedging)
+//
+// 针对耗时短的非流式请求 (如后台任务、标题生成)：若首个账号在延迟阈值 (默认取该模型
+// 历史耗时的 p95) 内未返回，则在另一个账号上发送同一请求，取最先成功的结果，
+// 落败的请求随 future 被 drop 而取消。
+//
+// 目前仅接入 Claude 协议处理器 (`handle_messages`)；OpenAI 与 Gemini 协议不做对冲。
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::proxy::config::HedgingConfig;
+
+/// 每个模型保留的耗时样本数
+const MAX_SAMPLES: usize = 200;
+
+/// 按模型统计的请求耗时 (毫秒)
+#[derive(Default)]
+pub struct LatencyTracker {
+    samples: DashMap<String, VecDeque<u64>>,
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, model: &str, duration: Duration) {
+        let mut entry = self.samples.entry(model.to_string()).or_default();
+        if entry.len() >= MAX_SAMPLES {
+            entry.pop_front();
+        }
+        entry.push_back(duration.as_millis() as u64);
+    }
+
+    /// 计算指定分位数 (0.0-1.0)，返回 (耗时, 样本数)
+    pub fn percentile(&self, model: &str, p: f64) -> Option<(u64, usize)> {
+        let entry = self.samples.get(model)?;
+        if entry.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u64> = entry.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = ((sorted.len() as f64) * p.clamp(0.0, 1.0)).ceil() as usize;
+        let idx = rank.saturating_sub(1).min(sorted.len() - 1);
+        Some((sorted[idx], sorted.len()))
+    }
+
+    /// 计算对冲延迟：样本足够时使用分位数耗时，否则使用默认延迟
+    pub fn hedge_delay(&self, model: &str, config: &HedgingConfig) -> Duration {
+        let ms = match self.percentile(model, config.percentile) {
+            Some((value, count)) if count >= config.min_samples => value,
+            _ => config.default_delay_ms,
+        };
+        Duration::from_millis(ms.clamp(config.min_delay_ms, config.max_delay_ms.max(config.min_delay_ms)))
+    }
+}
+
+/// 对冲竞速的胜者
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgeWinner {
+    Primary,
+    Hedge,
+}
+
+impl HedgeWinner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HedgeWinner::Primary => "primary",
+            HedgeWinner::Hedge => "hedge",
+        }
+    }
+}
+
+/// 对冲竞速结果
+pub struct HedgeOutcome<T, E> {
+    pub result: Result<T, E>,
+    pub winner: HedgeWinner,
+    /// 是否发出了对冲请求
+    pub hedged: bool,
+    /// 另一方是否因落败而被取消 (而非自身失败)
+    pub loser_cancelled: bool,
+    /// 另一方自身失败时的错误 (交由调用方按普通失败记录)
+    pub loser_error: Option<E>,
+}
+
+/// 执行对冲竞速
+///
+/// - `primary` 在 `delay` 内完成 (无论成败) 时直接返回，不发出对冲请求
+/// - 否则调用 `start_hedge` 发出对冲请求，取最先成功的一方
+/// - 一方失败时继续等待另一方；双方都失败时返回主请求的错误
+/// - 落败方自身失败时其错误通过 `loser_error` 返回，调用方需同样记录 (例如账号限流)
+pub async fn race<T, E, P, H, HF>(primary: P, delay: Duration, start_hedge: H) -> HedgeOutcome<T, E>
+where
+    P: Future<Output = Result<T, E>>,
+    H: FnOnce() -> HF,
+    HF: Future<Output = Result<T, E>>,
+{
+    tokio::pin!(primary);
+
+    tokio::select! {
+        result = &mut primary => {
+            return HedgeOutcome { result, winner: HedgeWinner::Primary, hedged: false, loser_cancelled: false, loser_error: None };
+        }
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    let hedge = start_hedge();
+    tokio::pin!(hedge);
+
+    tokio::select! {
+        result = &mut primary => match result {
+            Ok(v) => HedgeOutcome { result: Ok(v), winner: HedgeWinner::Primary, hedged: true, loser_cancelled: true, loser_error: None },
+            Err(primary_err) => match hedge.await {
+                Ok(v) => HedgeOutcome { result: Ok(v), winner: HedgeWinner::Hedge, hedged: true, loser_cancelled: false, loser_error: Some(primary_err) },
+                Err(hedge_err) => HedgeOutcome { result: Err(primary_err), winner: HedgeWinner::Primary, hedged: true, loser_cancelled: false, loser_error: Some(hedge_err) },
+            },
+        },
+        result = &mut hedge => match result {
+            Ok(v) => HedgeOutcome { result: Ok(v), winner: HedgeWinner::Hedge, hedged: true, loser_cancelled: true, loser_error: None },
+            Err(hedge_err) => HedgeOutcome { result: primary.await, winner: HedgeWinner::Primary, hedged: true, loser_cancelled: false, loser_error: Some(hedge_err) },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn after(ms: u64, value: Result<&'static str, &'static str>) -> Result<&'static str, &'static str> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        value
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_skips_hedge() {
+        let outcome = race(after(5, Ok("primary")), Duration::from_millis(100), || async {
+            panic!("hedge should not start")
+        })
+        .await;
+        assert_eq!(outcome.result, Ok("primary"));
+        assert!(!outcome.hedged);
+    }
+
+    #[tokio::test]
+    async fn test_slow_primary_loses_to_hedge() {
+        let outcome = race(after(500, Ok("primary")), Duration::from_millis(10), || after(10, Ok("hedge"))).await;
+        assert_eq!(outcome.result, Ok("hedge"));
+        assert_eq!(outcome.winner, HedgeWinner::Hedge);
+        assert!(outcome.loser_cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_primary_failure_waits_for_hedge() {
+        let outcome = race(after(30, Err("429")), Duration::from_millis(10), || after(60, Ok("hedge"))).await;
+        assert_eq!(outcome.result, Ok("hedge"));
+        assert!(!outcome.loser_cancelled);
+        assert_eq!(outcome.loser_error, Some("429"));
+
+        let outcome = race(after(30, Err("503")), Duration::from_millis(10), || after(5, Err("429"))).await;
+        assert_eq!(outcome.result, Err("503"));
+        assert_eq!(outcome.winner, HedgeWinner::Primary);
+        assert_eq!(outcome.loser_error, Some("429"));
+    }
+
+    #[test]
+    fn test_hedge_delay_uses_percentile() {
+        let tracker = LatencyTracker::new();
+        let config = HedgingConfig {
+            min_samples: 10,
+            ..Default::default()
+        };
+        // 样本不足: 使用默认延迟
+        tracker.record("gemini-2.5-flash-lite", Duration::from_millis(100));
+        assert_eq!(
+            tracker.hedge_delay("gemini-2.5-flash-lite", &config),
+            Duration::from_millis(config.default_delay_ms)
+        );
+
+        for ms in 1..=100 {
+            tracker.record("gemini-2.5-flash-lite", Duration::from_millis(ms * 10));
+        }
+        let (p95, _) = tracker.percentile("gemini-2.5-flash-lite", 0.95).unwrap();
+        assert_eq!(p95, 950);
+        assert_eq!(
+            tracker.hedge_delay("gemini-2.5-flash-lite", &config),
+            Duration::from_millis(950)
+        );
+    }
+}
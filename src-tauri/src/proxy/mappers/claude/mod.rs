@@ -22,9 +22,28 @@ use std::pin::Pin;
 
 /// 创建从 Gemini SSE 流到 Claude SSE 流的转换
 pub fn create_claude_sse_stream(
-    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     trace_id: String,
     email: String,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    create_claude_sse_stream_with_failover(gemini_stream, trace_id, email, None, None, None)
+}
+
+/// 创建支持断线续传的 Claude SSE 流
+///
+/// 上游流中途断开 (传输错误或未收到结束事件) 时，通过 `resumer` 在其他账号上以已输出正文为
+/// prefill 重新请求，续写内容沿用同一个 `StreamingState` 拼接，不会重复发送 message_start，
+/// content block 的 index 保持连续。
+///
+/// `session_id` 为请求的会话指纹，流中捕获的 thoughtSignature 按会话缓存，供同一会话后续请求回填。
+/// `cache_plan` 为提示词缓存计划，用于在 usage 中报告 cache_creation_input_tokens。
+pub fn create_claude_sse_stream_with_failover(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+    mut email: String,
+    session_id: Option<String>,
+    cache_plan: Option<crate::proxy::prompt_cache::CachePlan>,
+    mut resumer: Option<crate::proxy::stream_failover::StreamResumer>,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     use async_stream::stream;
     use bytes::BytesMut;
@@ -32,33 +51,66 @@ pub fn create_claude_sse_stream(
 
     Box::pin(stream! {
         let mut state = StreamingState::new();
+        state.session_id = session_id;
+        state.cache_plan = cache_plan;
         let mut buffer = BytesMut::new();
 
-        while let Some(chunk_result) = gemini_stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    buffer.extend_from_slice(&chunk);
-
-                    // Process complete lines
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() { continue; }
-
-                            if let Some(sse_chunks) = process_sse_line(line, &mut state, &trace_id, &email) {
-                                for sse_chunk in sse_chunks {
-                                    yield Ok(sse_chunk);
+        'upstream: loop {
+            let mut stream_error = None;
+
+            while let Some(chunk_result) = gemini_stream.next().await {
+                match chunk_result {
+                    Ok(chunk) => {
+                        buffer.extend_from_slice(&chunk);
+
+                        // Process complete lines
+                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                            let line_raw = buffer.split_to(pos + 1);
+                            if let Ok(line_str) = std::str::from_utf8(&line_raw) {
+                                let line = line_str.trim();
+                                if line.is_empty() { continue; }
+
+                                if let Some(sse_chunks) = process_sse_line(line, &mut state, &trace_id, &email) {
+                                    for sse_chunk in sse_chunks {
+                                        yield Ok(sse_chunk);
+                                    }
                                 }
                             }
                         }
                     }
+                    Err(e) => {
+                        stream_error = Some(format!("Stream error: {}", e));
+                        break;
+                    }
                 }
-                Err(e) => {
-                    yield Err(format!("Stream error: {}", e));
-                    break;
+            }
+
+            // 断流判定: 传输错误，或已开始输出但未收到结束事件
+            let broken = stream_error.is_some() || (state.message_start_sent && !state.message_stop_sent);
+            if broken {
+                if let Some(resume) = resumer.as_mut() {
+                    if state.prepare_resume() {
+                        tracing::warn!(
+                            "[{}] Upstream stream broken on {} ({}), attempting failover",
+                            trace_id,
+                            email,
+                            stream_error.as_deref().unwrap_or("unexpected end of stream")
+                        );
+                        if let Some(resumed) = resume(state.emitted_text.clone()).await {
+                            gemini_stream = resumed.stream;
+                            email = resumed.email;
+                            buffer.clear();
+                            continue 'upstream;
+                        }
+                        state.resuming = false;
+                    }
                 }
             }
+
+            if let Some(err) = stream_error {
+                yield Err(err);
+            }
+            break;
         }
 
         // Ensure termination events are sent
@@ -134,6 +186,20 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     {
         for part_value in parts {
             if let Ok(part) = serde_json::from_value::<GeminiPart>(part_value.clone()) {
+                let is_thought = part.thought.unwrap_or(false);
+                if state.resuming {
+                    // 续写阶段: 跳过新账号重新产生的思考内容，直到出现正文或工具调用
+                    let has_text = part.text.as_deref().is_some_and(|t| !t.is_empty());
+                    if is_thought || !(has_text || part.function_call.is_some()) {
+                        continue;
+                    }
+                    state.resuming = false;
+                }
+                if !is_thought {
+                    if let Some(text) = &part.text {
+                        state.emitted_text.push_str(text);
+                    }
+                }
                 let mut processor = PartProcessor::new(state);
                 chunks.extend(processor.process(&part));
             }
@@ -373,4 +439,49 @@ mod tests {
         assert!(all_text.contains("content_block_start"));
         assert!(all_text.contains("Hello"));
     }
+
+    fn mock_upstream(lines: &[&str]) -> crate::proxy::stream_failover::GeminiByteStream {
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = lines
+            .iter()
+            .map(|l| Ok(Bytes::from(format!("data: {}\n\n", l))))
+            .collect();
+        Box::pin(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_stream_failover_splices_continuation() {
+        use futures::StreamExt;
+        use std::sync::{Arc, Mutex};
+
+        let first = mock_upstream(&[
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hello"}]}}],"modelVersion":"test","responseId":"1"}"#,
+        ]);
+        let prefill = Arc::new(Mutex::new(None));
+        let captured = prefill.clone();
+        let resumer: crate::proxy::stream_failover::StreamResumer = Box::new(move |partial| {
+            *captured.lock().unwrap() = Some(partial);
+            Box::pin(async {
+                Some(crate::proxy::stream_failover::ResumedStream {
+                    stream: mock_upstream(&[
+                        r#"{"candidates":[{"content":{"parts":[{"text":"re-thinking","thought":true}]}}],"responseId":"2"}"#,
+                        r#"{"candidates":[{"content":{"parts":[{"text":" world"}]},"finishReason":"STOP"}],"usageMetadata":{},"responseId":"2"}"#,
+                    ]),
+                    email: "b@example.com".to_string(),
+                })
+            })
+        });
+
+        let output: String = create_claude_sse_stream_with_failover(first, "t".into(), "a@example.com".into(), None, None, Some(resumer))
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+
+        assert_eq!(prefill.lock().unwrap().as_deref(), Some("Hello"));
+        assert_eq!(output.matches("event: message_start").count(), 1);
+        assert_eq!(output.matches("event: content_block_start").count(), 1);
+        assert_eq!(output.matches("event: message_stop").count(), 1);
+        assert!(output.contains(" world"));
+        assert!(!output.contains("re-thinking"));
+    }
 }
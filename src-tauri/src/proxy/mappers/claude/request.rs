@@ -2,65 +2,18 @@
 // 对应 transformClaudeRequestIn
 
 use super::models::*;
-use crate::proxy::mappers::signature_store::get_thought_signature;
+use crate::proxy::session_manager::SessionManager;
+use crate::proxy::SignatureCache;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
 // ===== Safety Settings Configuration =====
 
-/// Safety threshold levels for Gemini API
-/// Can be configured via GEMINI_SAFETY_THRESHOLD environment variable
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum SafetyThreshold {
-    /// Disable all safety filters (default for proxy compatibility)
-    Off,
-    /// Block low probability and above
-    BlockLowAndAbove,
-    /// Block medium probability and above
-    BlockMediumAndAbove,
-    /// Only block high probability content
-    BlockOnlyHigh,
-    /// Don't block anything (BLOCK_NONE)
-    BlockNone,
-}
-
-impl SafetyThreshold {
-    /// Get threshold from environment variable or default to Off
-    pub fn from_env() -> Self {
-        match std::env::var("GEMINI_SAFETY_THRESHOLD").as_deref() {
-            Ok("OFF") | Ok("off") => SafetyThreshold::Off,
-            Ok("LOW") | Ok("low") => SafetyThreshold::BlockLowAndAbove,
-            Ok("MEDIUM") | Ok("medium") => SafetyThreshold::BlockMediumAndAbove,
-            Ok("HIGH") | Ok("high") => SafetyThreshold::BlockOnlyHigh,
-            Ok("NONE") | Ok("none") => SafetyThreshold::BlockNone,
-            _ => SafetyThreshold::Off, // Default: maintain current behavior
-        }
-    }
-
-    /// Convert to Gemini API threshold string
-    pub fn to_gemini_threshold(&self) -> &'static str {
-        match self {
-            SafetyThreshold::Off => "OFF",
-            SafetyThreshold::BlockLowAndAbove => "BLOCK_LOW_AND_ABOVE",
-            SafetyThreshold::BlockMediumAndAbove => "BLOCK_MEDIUM_AND_ABOVE",
-            SafetyThreshold::BlockOnlyHigh => "BLOCK_ONLY_HIGH",
-            SafetyThreshold::BlockNone => "BLOCK_NONE",
-        }
-    }
-}
+pub use crate::proxy::config::SafetyThreshold;
 
 /// Build safety settings based on configuration
 fn build_safety_settings() -> Value {
-    let threshold = SafetyThreshold::from_env();
-    let threshold_str = threshold.to_gemini_threshold();
-
-    json!([
-        { "category": "HARM_CATEGORY_HARASSMENT", "threshold": threshold_str },
-        { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": threshold_str },
-        { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": threshold_str },
-        { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": threshold_str },
-        { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": threshold_str },
-    ])
+    crate::proxy::prompt_policy::safety_settings(SafetyThreshold::from_env())
 }
 
 /// 清理消息中的 cache_control 字段
@@ -70,6 +23,8 @@ fn build_safety_settings() -> Value {
 /// 1. VS Code 等客户端会将历史消息(包含 cache_control)原封不动发回
 /// 2. Anthropic API 不接受请求中包含 cache_control 字段
 /// 3. 即使是转发到 Gemini,也应该清理以保持协议纯净性
+///
+/// 缓存断点在 handler 解析请求前已由 `prompt_cache::breakpoints` 提取，这里清理不影响提示词缓存
 fn clean_cache_control_from_messages(messages: &mut [Message]) {
     for msg in messages.iter_mut() {
         if let MessageContent::Array(blocks) = &mut msg.content {
@@ -118,6 +73,9 @@ pub fn transform_claude_request_in(
     clean_cache_control_from_messages(&mut cleaned_req.messages);
     let claude_req = &cleaned_req; // 后续使用清理后的请求
 
+    // 当前会话最近一次的 thoughtSignature (按会话指纹隔离，避免跨会话串用)
+    let session_sig = SignatureCache::global().get_session_signature(&SessionManager::extract_session_id(claude_req));
+
     // 检测是否有联网工具 (server tool or built-in tool)
     let has_web_search_tool = claude_req
         .tools
@@ -180,8 +138,8 @@ pub fn transform_claude_request_in(
     // [NEW FIX] Check if target model supports thinking
     // Only models with "-thinking" suffix or Claude models support thinking
     // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT support thinking
-    let target_model_supports_thinking = mapped_model.contains("-thinking") 
-        || mapped_model.starts_with("claude-");
+    let target_model_supports_thinking =
+        crate::proxy::common::model_capabilities::lookup(&mapped_model).thinking;
     
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -204,8 +162,6 @@ pub fn transform_claude_request_in(
     // [FIX #295 & #298] If thinking enabled but no signature available,
     // disable thinking to prevent Gemini 3 Pro rejection
     if is_thinking_enabled {
-        let global_sig = get_thought_signature();
-        
         // Check if there are any thinking blocks in message history
         let has_thinking_history = claude_req.messages.iter().any(|m| {
             if m.role == "assistant" {
@@ -240,7 +196,7 @@ pub fn transform_claude_request_in(
         }
 
         if needs_signature_check
-            && !has_valid_signature_for_function_calls(&claude_req.messages, &global_sig)
+            && !has_valid_signature_for_function_calls(&claude_req.messages, &session_sig)
         {
             tracing::warn!(
                 "[Thinking-Mode] [FIX #295] No valid signature found for function calls. \
@@ -260,6 +216,7 @@ pub fn transform_claude_request_in(
         is_thinking_enabled,
         allow_dummy_thought,
         &mapped_model,
+        session_sig.as_deref(),
     )?;
 
     // 3. Tools
@@ -404,10 +361,10 @@ const MIN_SIGNATURE_LENGTH: usize = 50;
 /// This prevents Gemini 3 Pro from rejecting requests due to missing thought_signature
 fn has_valid_signature_for_function_calls(
     messages: &[Message],
-    global_sig: &Option<String>,
+    session_sig: &Option<String>,
 ) -> bool {
-    // 1. Check global store
-    if let Some(sig) = global_sig {
+    // 1. Check session store
+    if let Some(sig) = session_sig {
         if sig.len() >= MIN_SIGNATURE_LENGTH {
             return true;
         }
@@ -439,10 +396,7 @@ fn build_system_instruction(system: &Option<SystemPrompt>, _model_name: &str) ->
     let mut parts = Vec::new();
 
     // [NEW] Antigravity 身份指令 (原始简化版)
-    let antigravity_identity = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.\n\
-    You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.\n\
-    **Absolute paths only**\n\
-    **Proactiveness**";
+    let antigravity_identity = crate::proxy::prompt_policy::ANTIGRAVITY_IDENTITY;
     
     // [HYBRID] 检查用户是否已提供 Antigravity 身份
     let mut user_has_antigravity = false;
@@ -487,7 +441,7 @@ fn build_system_instruction(system: &Option<SystemPrompt>, _model_name: &str) ->
 
     // 如果用户没有提供任何系统提示词,添加结束标记
     if !user_has_antigravity {
-        parts.push(json!({"text": "\n--- [SYSTEM_PROMPT_END] ---"}));
+        parts.push(json!({"text": crate::proxy::prompt_policy::SYSTEM_PROMPT_END_MARKER}));
     }
 
     Some(json!({
@@ -503,6 +457,7 @@ fn build_contents(
     is_thinking_enabled: bool,
     allow_dummy_thought: bool,
     mapped_model: &str,
+    session_sig: Option<&str>,
 ) -> Result<Value, String> {
     let mut contents = Vec::new();
     let mut last_thought_signature: Option<String> = None;
@@ -648,7 +603,7 @@ fn build_contents(
                             // 存储 id -> name 映射
                             tool_id_to_name.insert(id.clone(), name.clone());
 
-                            // Signature resolution logic (Priority: Client -> Context -> Cache -> Global Store)
+                            // Signature resolution logic (Priority: Client -> Context -> Tool Cache -> Session Store)
                             // [CRITICAL FIX] Do NOT use skip_thought_signature_validator for Vertex AI
                             // Vertex AI rejects this sentinel value, so we only add thoughtSignature if we have a real one
                             let final_sig = signature.as_ref()
@@ -656,19 +611,17 @@ fn build_contents(
                                 .cloned()
                                 .or_else(|| {
                                     // [NEW] Try layer 1 cache (Tool ID -> Signature)
-                                    crate::proxy::SignatureCache::global().get_tool_signature(id)
+                                    SignatureCache::global().get_tool_signature(id)
                                         .map(|s| {
                                             tracing::info!("[Claude-Request] Recovered signature from cache for tool_id: {}", id);
                                             s
                                         })
                                 })
                                 .or_else(|| {
-                                    let global_sig = get_thought_signature();
-                                    if global_sig.is_some() {
-                                        tracing::info!("[Claude-Request] Using global thought_signature fallback (length: {})", 
-                                            global_sig.as_ref().unwrap().len());
-                                    }
-                                    global_sig
+                                    session_sig.map(|s| {
+                                        tracing::info!("[Claude-Request] Using session thought_signature fallback (length: {})", s.len());
+                                        s.to_string()
+                                    })
                                 });
                             // Only add thoughtSignature if we have a valid one
                             // Do NOT add skip_thought_signature_validator - Vertex AI rejects it
@@ -991,8 +944,9 @@ fn build_generation_config(
         config["candidateCount"] = json!(1);
     }*/
 
-    // max_tokens 映射为 maxOutputTokens
-    config["maxOutputTokens"] = json!(64000);
+    // max_tokens 映射为 maxOutputTokens (不超过目标模型的输出上限)
+    let max_output = crate::proxy::common::model_capabilities::lookup(&claude_req.model).max_output_tokens;
+    config["maxOutputTokens"] = json!(max_output.min(64000));
 
     // [优化] 设置全局停止序列,防止流式输出冗余
     config["stopSequences"] = json!([
@@ -3,7 +3,6 @@
 
 use super::models::*;
 use super::utils::to_claude_usage;
-// use crate::proxy::mappers::signature_store::store_thought_signature; // Deprecated
 use crate::proxy::SignatureCache;
 use bytes::Bytes;
 use serde_json::json;
@@ -156,6 +155,13 @@ pub struct StreamingState {
     last_valid_state: Option<BlockType>,
     // [NEW] Model tracking for signature cache
     pub model_name: Option<String>,
+    // 会话指纹，用于按会话缓存 thoughtSignature
+    pub session_id: Option<String>,
+    // 提示词缓存计划，用于拆分 cache_creation_input_tokens
+    pub cache_plan: Option<crate::proxy::prompt_cache::CachePlan>,
+    // 流式断线续传: 已输出的正文 (用作续写 prefill) 与续写中标记
+    pub emitted_text: String,
+    pub resuming: bool,
 }
 
 impl StreamingState {
@@ -174,9 +180,32 @@ impl StreamingState {
             parse_error_count: 0,
             last_valid_state: None,
             model_name: None,
+            session_id: None,
+            cache_plan: None,
+            emitted_text: String::new(),
+            resuming: false,
         }
     }
 
+    /// 断流后准备续写: 已输出工具调用时无法以文本 prefill 续写，返回 false
+    pub fn prepare_resume(&mut self) -> bool {
+        if self.used_tool || self.message_stop_sent {
+            return false;
+        }
+        self.trailing_signature = None;
+        self.resuming = true;
+        true
+    }
+
+    /// 转换上游 usage，并按缓存计划拆分新写入缓存的 token
+    fn claude_usage(&self, usage_metadata: &UsageMetadata) -> Usage {
+        let mut usage = to_claude_usage(usage_metadata);
+        if let Some(plan) = &self.cache_plan {
+            plan.apply(&mut usage);
+        }
+        usage
+    }
+
     /// 发送 SSE 事件
     pub fn emit(&self, event_type: &str, data: serde_json::Value) -> Bytes {
         let sse = format!(
@@ -196,7 +225,7 @@ impl StreamingState {
         let usage = raw_json
             .get("usageMetadata")
             .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
-            .map(|u| to_claude_usage(&u));
+            .map(|u| self.claude_usage(&u));
 
         let mut message = json!({
             "id": raw_json.get("responseId")
@@ -390,7 +419,7 @@ impl StreamingState {
         };
 
         let usage = usage_metadata
-            .map(|u| to_claude_usage(u))
+            .map(|u| self.claude_usage(u))
             .unwrap_or(Usage {
                 input_tokens: 0,
                 output_tokens: 0,
@@ -633,6 +662,10 @@ impl<'a> PartProcessor<'a> {
             if let Some(model) = &self.state.model_name {
                  SignatureCache::global().cache_thinking_family(sig.clone(), model.clone());
             }
+            // 2. Cache as the session's latest signature
+            if let Some(session_id) = &self.state.session_id {
+                SignatureCache::global().cache_session_signature(session_id, sig);
+            }
             
             tracing::debug!(
                 "[Claude-SSE] Captured thought_signature from thinking block (length: {})",
@@ -758,6 +791,9 @@ impl<'a> PartProcessor<'a> {
             
             // 2. Cache tool signature (Layer 1 recovery)
             SignatureCache::global().cache_tool_signature(&tool_id, sig.clone());
+            if let Some(session_id) = &self.state.session_id {
+                SignatureCache::global().cache_session_signature(session_id, sig);
+            }
             
              tracing::debug!(
                 "[Claude-SSE] Captured thought_signature for function call (length: {})",
@@ -87,10 +87,7 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
          }
     } else {
         // [NEW] 只在非图像生成模式下注入 Antigravity 身份 (原始简化版)
-        let antigravity_identity = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.\n\
-        You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.\n\
-        **Absolute paths only**\n\
-        **Proactiveness**";
+        let antigravity_identity = crate::proxy::prompt_policy::ANTIGRAVITY_IDENTITY;
         
         // [HYBRID] 检查是否已有 systemInstruction
         if let Some(system_instruction) = inner_request.get_mut("systemInstruction") {
@@ -5,4 +5,3 @@ pub mod claude;
 pub mod common_utils;
 pub mod gemini;
 pub mod openai;
-pub mod signature_store;
@@ -1,7 +1,8 @@
 // OpenAI → Gemini 请求转换
 use super::models::*;
 use serde_json::{json, Value};
-use super::streaming::get_thought_signature;
+use crate::proxy::session_manager::SessionManager;
+use crate::proxy::SignatureCache;
 
 pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
     // 将 OpenAI 工具转为 Value 数组以便探测
@@ -48,10 +49,10 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         }
     }
 
-    // 从全局存储获取 thoughtSignature (PR #93 支持)
-    let global_thought_sig = get_thought_signature();
-    if global_thought_sig.is_some() {
-        tracing::debug!("从全局存储获取到 thoughtSignature (长度: {})", global_thought_sig.as_ref().unwrap().len());
+    // 从会话存储获取 thoughtSignature (PR #93 支持，按会话指纹隔离)
+    let session_thought_sig = SignatureCache::global().get_session_signature(&SessionManager::extract_openai_session_id(request));
+    if let Some(sig) = &session_thought_sig {
+        tracing::debug!("从会话存储获取到 thoughtSignature (长度: {})", sig.len());
     }
 
     // 2. 构建 Gemini contents (过滤掉 system)
@@ -168,7 +169,11 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                     });
 
                     // [修复] 为该消息内的所有工具调用注入 thoughtSignature (PR #114 优化)
-                    if let Some(ref sig) = global_thought_sig {
+                    // 优先使用该工具调用 ID 对应的签名，其次回退到会话最近的签名
+                    let sig = SignatureCache::global()
+                        .get_tool_signature(&tc.id)
+                        .or_else(|| session_thought_sig.clone());
+                    if let Some(sig) = sig {
                         func_call_part["thoughtSignature"] = json!(sig);
                     }
 
@@ -257,13 +262,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     let mut inner_request = json!({
         "contents": contents,
         "generationConfig": gen_config,
-        "safetySettings": [
-            { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
-        ]
+        "safetySettings": crate::proxy::prompt_policy::safety_settings(crate::proxy::config::SafetyThreshold::Off),
     });
 
     // 深度清理 [undefined] 字符串 (Cherry Studio 等客户端常见注入)
@@ -331,10 +330,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     }
     
     // [NEW] Antigravity 身份指令 (原始简化版)
-    let antigravity_identity = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.\n\
-    You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.\n\
-    **Absolute paths only**\n\
-    **Proactiveness**";
+    let antigravity_identity = crate::proxy::prompt_policy::ANTIGRAVITY_IDENTITY;
 
     // [HYBRID] 检查用户是否已提供 Antigravity 身份
     let user_has_antigravity = system_instructions.iter()
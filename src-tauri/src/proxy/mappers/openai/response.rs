@@ -1,8 +1,10 @@
 // OpenAI 协议响应转换模块
 use super::models::*;
+use crate::proxy::SignatureCache;
 use serde_json::Value;
 
-pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
+/// 转换 Gemini 响应为 OpenAI 格式，thoughtSignature 按 `session_id` 与工具调用 ID 缓存
+pub fn transform_openai_response(gemini_response: &Value, session_id: &str) -> OpenAIResponse {
     // 解包 response 字段
     let raw = gemini_response.get("response").unwrap_or(gemini_response);
 
@@ -10,7 +12,8 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
 
     // 支持多候选结果 (n > 1)
     if let Some(candidates) = raw.get("candidates").and_then(|c| c.as_array()) {
-        for (idx, candidate) in candidates.iter().enumerate() {
+        for (position, candidate) in candidates.iter().enumerate() {
+            let idx = super::streaming::candidate_index(candidate, position);
             let mut content_out = String::new();
             let mut thought_out = String::new();
             let mut tool_calls = Vec::new();
@@ -23,12 +26,12 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             {
                 for part in parts {
                     // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                    if let Some(sig) = part
+                    let part_sig = part
                         .get("thoughtSignature")
                         .or(part.get("thought_signature"))
-                        .and_then(|s| s.as_str())
-                    {
-                        super::streaming::store_thought_signature(sig);
+                        .and_then(|s| s.as_str());
+                    if let Some(sig) = part_sig {
+                        SignatureCache::global().cache_session_signature(session_id, sig);
                     }
 
                     // 检查该 part 是否是思考内容 (thought: true)
@@ -59,6 +62,9 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                             .and_then(|v| v.as_str())
                             .map(|s| s.to_string())
                             .unwrap_or_else(|| format!("{}-{}", name, uuid::Uuid::new_v4()));
+                        if let Some(sig) = part_sig {
+                            SignatureCache::global().cache_tool_signature(&id, sig.to_string());
+                        }
 
                         tool_calls.push(ToolCall {
                             id,
@@ -197,7 +203,7 @@ mod tests {
             "responseId": "resp_123"
         });
 
-        let result = transform_openai_response(&gemini_resp);
+        let result = transform_openai_response(&gemini_resp, "sid-test");
         assert_eq!(result.object, "chat.completion");
         let content = match result.choices[0].message.content.as_ref().unwrap() {
             OpenAIContent::String(s) => s,
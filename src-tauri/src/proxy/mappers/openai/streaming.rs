@@ -3,56 +3,26 @@ use bytes::{Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use serde_json::{json, Value};
 use std::pin::Pin;
-use std::sync::{Mutex, OnceLock};
 use chrono::Utc;
 use uuid::Uuid;
 use tracing::debug;
 use rand::Rng;
 
-// === 全局 ThoughtSignature 存储 ===
-// 用于在流式响应和后续请求之间传递签名，避免嵌入到用户可见的文本中
-static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+use crate::proxy::SignatureCache;
 
-fn get_thought_sig_storage() -> &'static Mutex<Option<String>> {
-    GLOBAL_THOUGHT_SIG.get_or_init(|| Mutex::new(None))
-}
-
-/// 保存 thoughtSignature 到全局存储
-/// 注意：只在新签名比现有签名更长时才存储，避免短签名覆盖有效签名
-pub fn store_thought_signature(sig: &str) {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        let should_store = match &*guard {
-            None => true, // 没有签名，直接存储
-            Some(existing) => sig.len() > existing.len(), // 只有新签名更长才存储
-        };
-        
-        if should_store {
-            tracing::debug!("[ThoughtSig] 存储新签名 (长度: {}，替换旧长度: {:?})", 
-                sig.len(), 
-                guard.as_ref().map(|s| s.len())
-            );
-            *guard = Some(sig.to_string());
-        } else {
-            tracing::debug!("[ThoughtSig] 跳过短签名 (新长度: {}，现有长度: {})", 
-                sig.len(), 
-                guard.as_ref().map(|s| s.len()).unwrap_or(0)
-            );
-        }
-    }
-}
-
-/// 获取全局存储的 thoughtSignature（不清除）
-pub fn get_thought_signature() -> Option<String> {
-    if let Ok(guard) = get_thought_sig_storage().lock() {
-        guard.clone()
-    } else {
-        None
-    }
+/// 候选结果的 index (上游未携带时按数组位置)
+pub fn candidate_index(candidate: &Value, position: usize) -> usize {
+    candidate
+        .get("index")
+        .and_then(|i| i.as_u64())
+        .map(|i| i as usize)
+        .unwrap_or(position)
 }
 
 pub fn create_openai_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    session_id: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
     
@@ -94,7 +64,9 @@ pub fn create_openai_sse_stream(
 
                                     // Extract candidates
                                     if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        for (idx, candidate) in candidates.iter().enumerate() {
+                                        for (position, candidate) in candidates.iter().enumerate() {
+                                            // n > 1 时每个事件可能只包含部分候选，以候选自带的 index 为准
+                                            let idx = candidate_index(candidate, position);
                                             let parts = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array());
 
                                             let mut content_out = String::new();
@@ -115,7 +87,7 @@ pub fn create_openai_sse_stream(
                                                     }
                                                     // 捕获 thoughtSignature (Gemini 3 工具调用必需)
                                                     if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                        store_thought_signature(sig);
+                                                        SignatureCache::global().cache_session_signature(&session_id, sig);
                                                     }
 
                                                     if let Some(img) = part.get("inlineData") {
@@ -246,9 +218,29 @@ pub fn create_openai_sse_stream(
     Box::pin(stream)
 }
 
+/// 创建支持断线续传的 OpenAI SSE 流
+///
+/// 上游流中途断开 (传输错误或未收到 finishReason) 时，通过 `resumer` 在其他账号上以已输出正文为
+/// prefill 重新请求，续写内容在转换前拼接到同一条上游流中，客户端看到的仍是同一个 chunk id。
+/// 续传只跟踪单个候选结果，调用方应仅在 n = 1 时提供 `resumer`。
+/// 流中捕获的 thoughtSignature 按 `session_id` (会话指纹) 缓存。
+pub fn create_openai_sse_stream_with_failover(
+    gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    model: String,
+    session_id: String,
+    resumer: Option<crate::proxy::stream_failover::StreamResumer>,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    let gemini_stream = match resumer {
+        Some(resumer) => crate::proxy::stream_failover::resumable_stream(gemini_stream, resumer),
+        None => gemini_stream,
+    };
+    create_openai_sse_stream(gemini_stream, model, session_id)
+}
+
 pub fn create_legacy_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    session_id: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
     
@@ -282,9 +274,13 @@ pub fn create_legacy_sse_stream(
                                 if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
                                     let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
                                     
-                                    let mut content_out = String::new();
-                                    if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
+                                    let empty = Vec::new();
+                                    let candidates = actual_data.get("candidates").and_then(|c| c.as_array()).unwrap_or(&empty);
+                                    // 每个候选结果输出为独立的 choice (n > 1)
+                                    let mut choices = Vec::new();
+                                    for (position, candidate) in candidates.iter().enumerate() {
+                                        let mut content_out = String::new();
+                                        if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
                                             for part in parts {
                                                 if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
                                                     content_out.push_str(text);
@@ -294,26 +290,37 @@ pub fn create_legacy_sse_stream(
                                                     // // content_out.push_str(thought_text);
                                                 }
                                                 */
-                                                // 捕获 thoughtSignature
-                                                // 捕获 thoughtSignature 到全局存储
+                                                // 捕获 thoughtSignature 到会话存储
                                                 if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                    store_thought_signature(sig);
+                                                    SignatureCache::global().cache_session_signature(&session_id, sig);
                                                 }
                                             }
                                         }
-                                    }
 
-                                    let finish_reason = actual_data.get("candidates")
-                                        .and_then(|c| c.as_array())
-                                        .and_then(|c| c.get(0))
-                                        .and_then(|c| c.get("finishReason"))
-                                        .and_then(|f| f.as_str())
-                                        .map(|f| match f {
-                                            "STOP" => "stop",
-                                            "MAX_TOKENS" => "length",
-                                            "SAFETY" => "content_filter",
-                                            _ => f,
-                                        });
+                                        let finish_reason = candidate.get("finishReason")
+                                            .and_then(|f| f.as_str())
+                                            .map(|f| match f {
+                                                "STOP" => "stop",
+                                                "MAX_TOKENS" => "length",
+                                                "SAFETY" => "content_filter",
+                                                _ => f,
+                                            });
+
+                                        choices.push(json!({
+                                            "text": content_out,
+                                            "index": candidate_index(candidate, position),
+                                            "logprobs": null,
+                                            "finish_reason": finish_reason // Will be null if None
+                                        }));
+                                    }
+                                    if choices.is_empty() {
+                                        choices.push(json!({
+                                            "text": "",
+                                            "index": 0,
+                                            "logprobs": null,
+                                            "finish_reason": null
+                                        }));
+                                    }
 
                                     // Construct LEGACY completion chunk - STRICT VERSION
                                     let legacy_chunk = json!({
@@ -321,14 +328,7 @@ pub fn create_legacy_sse_stream(
                                         "object": "text_completion",
                                         "created": created_ts,
                                         "model": &model,
-                                        "choices": [
-                                            {
-                                                "text": content_out,
-                                                "index": 0,
-                                                "logprobs": null,
-                                                "finish_reason": finish_reason // Will be null if None
-                                            }
-                                        ]
+                                        "choices": choices
                                     });
 
                                     let json_str = serde_json::to_string(&legacy_chunk).unwrap_or_default();
@@ -355,6 +355,7 @@ pub fn create_legacy_sse_stream(
 pub fn create_codex_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     _model: String,
+    session_id: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
     
@@ -431,10 +432,11 @@ pub fn create_codex_sse_stream(
                                                 }
                                                 */
                                                 // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                                                // 存储到全局状态，不再嵌入到用户可见的文本中
-                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                // 按会话存储，不再嵌入到用户可见的文本中
+                                                let part_sig = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str());
+                                                if let Some(sig) = part_sig {
                                                     tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
-                                                    store_thought_signature(sig);
+                                                    SignatureCache::global().cache_session_signature(&session_id, sig);
                                                 }
                                                 // Handle function call in chunk with deduplication
                                                 if let Some(func_call) = part.get("functionCall") {
@@ -449,6 +451,9 @@ pub fn create_codex_sse_stream(
                                                         use std::hash::{Hash, Hasher};
                                                         serde_json::to_string(func_call).unwrap_or_default().hash(&mut hasher);
                                                         let call_id = format!("call_{:x}", hasher.finish());
+                                                        if let Some(sig) = part_sig {
+                                                            SignatureCache::global().cache_tool_signature(&call_id, sig.to_string());
+                                                        }
                                                         
                                                         // Parse args once
                                                         let fallback_args = json!({});
@@ -61,6 +61,12 @@ pub async fn auth_middleware(
         return Err(StatusCode::UNAUTHORIZED);
     }
 
+    // 配置中的 API Key 解密失败 (主密钥缺失或不匹配)，任何请求都不可能通过认证
+    if crate::modules::config::is_sealed(&security.api_key) {
+        tracing::error!("Proxy api_key could not be decrypted with the master key; denying request");
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
     let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
 
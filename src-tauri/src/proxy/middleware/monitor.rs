@@ -18,11 +18,12 @@ pub async fn monitor_middleware(
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.monitor.is_enabled() {
-        return next.run(request).await;
-    }
+    state.monitor.record_request();
+
+    // 日志关闭时仍需经过响应统计 (仪表盘增量推送)，但不再缓存请求/响应内容
+    let logging_enabled = state.monitor.is_enabled();
 
-    let log_stream_content = {
+    let log_stream_content = logging_enabled && {
         let config = state.config.read().await;
         config.proxy.log_stream_content
     };
@@ -45,7 +46,7 @@ pub async fn monitor_middleware(
     };
 
     let request_body_str;
-    let request = if method == "POST" {
+    let request = if method == "POST" && logging_enabled {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
             Ok(bytes) => {
@@ -95,6 +96,19 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
+    // Extract model chain hops from X-Model-Hops header if present
+    let model_hops = response
+        .headers()
+        .get("X-Model-Hops")
+        .and_then(|v| v.to_str().ok())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+
+    let cache_hit = response
+        .headers()
+        .get(crate::proxy::response_cache::HIT_HEADER)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"HIT"));
+
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
@@ -111,6 +125,8 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        model_hops,
+        cache_hit,
     };
 
     if content_type.contains("text/event-stream") {
@@ -19,6 +19,21 @@ pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
 pub mod sticky_config;     // 粘性调度配置
+pub mod quota_predictor;   // 配额预测调度
+pub mod concurrency;       // 并发限制与排队
+pub mod circuit_breaker;   // 端点/账号熔断器
+pub mod events;            // 实时事件推送 (EventSink)
+pub mod hedging;           // 请求对冲
+pub mod stream_failover;   // 流式断线续传
+pub mod account_watcher;   // 账号热重载
+pub mod account_health;    // 账号健康度与自动隔离
+pub mod prompt_policy;     // 系统提示词注入与请求改写策略
+pub mod background_tasks;  // 后台任务检测与降级路由
+pub mod prompt_cache;      // cache_control 提示词缓存 (隐式缓存亲和)
+pub mod response_cache;    // 确定性请求的响应缓存
+pub mod trace_capture;     // 协议转换调试抓取与回放
+pub mod context_window;    // 上下文窗口管理 (超长对话截断/摘要)
+pub mod candidates;        // 多候选结果 (n > 1) 的并行拆分与合并
 pub mod session_manager;   // 会话指纹管理
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
@@ -4,6 +4,45 @@ use tokio::sync::RwLock;
 #[cfg(feature = "desktop")]
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::proxy::circuit_breaker::{CircuitTransition, TransitionListener};
+use crate::proxy::concurrency::{QueueStats, QueueStatsSnapshot};
+use crate::proxy::events::{self, topics, ProxyEvent};
+
+/// 内存中保留的熔断状态变更事件数量
+const MAX_CIRCUIT_EVENTS: usize = 200;
+
+/// 请求速率统计窗口 (毫秒)
+const RATE_WINDOW_MS: i64 = 60_000;
+
+/// 滑动窗口请求计数 (用于计算每分钟请求数)
+#[derive(Debug, Default)]
+pub struct RequestRateWindow {
+    timestamps: VecDeque<i64>,
+}
+
+impl RequestRateWindow {
+    pub fn record(&mut self, now_ms: i64) {
+        self.timestamps.push_back(now_ms);
+        self.evict(now_ms);
+    }
+
+    /// 最近 60 秒内的请求数
+    pub fn requests_per_minute(&mut self, now_ms: i64) -> f64 {
+        self.evict(now_ms);
+        self.timestamps.len() as f64
+    }
+
+    fn evict(&mut self, now_ms: i64) {
+        while let Some(&front) = self.timestamps.front() {
+            if now_ms - front >= RATE_WINDOW_MS {
+                self.timestamps.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
@@ -21,6 +60,12 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// 模型链回退经过的模型 (X-Model-Hops)
+    #[serde(default)]
+    pub model_hops: Option<String>,
+    /// 是否由响应缓存直接返回
+    #[serde(default)]
+    pub cache_hit: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -28,6 +73,12 @@ pub struct ProxyStats {
     pub total_requests: u64,
     pub success_count: u64,
     pub error_count: u64,
+    /// 并发排队统计 (队列深度/等待时间)
+    #[serde(default)]
+    pub queue: QueueStatsSnapshot,
+    /// 按类型统计的后台任务降级次数
+    #[serde(default)]
+    pub background_tasks: std::collections::HashMap<String, u64>,
 }
 
 pub struct ProxyMonitor {
@@ -35,6 +86,10 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
+    queue_stats: RwLock<Option<Arc<QueueStats>>>,
+    circuit_events: std::sync::Mutex<VecDeque<CircuitTransition>>,
+    request_rate: std::sync::Mutex<RequestRateWindow>,
+    background_tasks: std::sync::Mutex<std::collections::HashMap<String, u64>>,
     #[cfg(feature = "desktop")]
     app_handle: Option<tauri::AppHandle>,
 }
@@ -51,6 +106,10 @@ impl ProxyMonitor {
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
+            queue_stats: RwLock::new(None),
+            circuit_events: std::sync::Mutex::new(VecDeque::with_capacity(MAX_CIRCUIT_EVENTS)),
+            request_rate: std::sync::Mutex::new(RequestRateWindow::default()),
+            background_tasks: std::sync::Mutex::new(std::collections::HashMap::new()),
             #[cfg(feature = "desktop")]
             app_handle,
         }
@@ -65,6 +124,9 @@ impl ProxyMonitor {
     }
 
     pub async fn log_request(&self, log: ProxyRequestLog) {
+        // 仪表盘增量不受日志开关影响
+        self.publish_stats_delta(&log);
+
         if !self.is_enabled() {
             return;
         }
@@ -102,8 +164,49 @@ impl ProxyMonitor {
         if let Some(app) = &self.app_handle {
              let _ = app.emit("proxy://request", &log);
         }
+
+        // 推送到 Web Admin (去除请求/响应体，避免大包广播)
+        events::publish(ProxyEvent::RequestLog(ProxyRequestLog {
+            request_body: None,
+            response_body: None,
+            ..log
+        }));
+    }
+
+    /// 推送仪表盘增量 (Web Admin)
+    fn publish_stats_delta(&self, log: &ProxyRequestLog) {
+        events::publish_raw(
+            topics::DASHBOARD,
+            "dashboard_stats_delta",
+            serde_json::json!({
+                "requests": 1,
+                "success": log.status >= 200 && log.status < 400,
+                "tokens": log.input_tokens.unwrap_or(0) as u64 + log.output_tokens.unwrap_or(0) as u64,
+                "requests_per_minute": self.requests_per_minute(),
+                "model": log.mapped_model.as_ref().or(log.model.as_ref()),
+                "account_email": log.account_email,
+            }),
+        );
+    }
+
+    /// 记录一次入站请求 (用于滑动窗口 RPM，不受日志开关影响)
+    pub fn record_request(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.request_rate.lock().unwrap().record(now);
+    }
+
+    /// 最近 60 秒内的请求数
+    pub fn requests_per_minute(&self) -> f64 {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.request_rate.lock().unwrap().requests_per_minute(now)
+    }
+
+    /// 记录一次后台任务降级 (不受日志开关影响)
+    pub fn record_background_task(&self, name: &str) {
+        *self.background_tasks.lock().unwrap().entry(name.to_string()).or_insert(0) += 1;
     }
 
+
     pub async fn get_logs(&self, limit: usize) -> Vec<ProxyRequestLog> {
         // Try to get from DB first for true history
         match crate::modules::proxy_db::get_logs(limit) {
@@ -117,24 +220,87 @@ impl ProxyMonitor {
         }
     }
 
+    /// 关联 TokenManager 的并发排队统计
+    pub async fn attach_queue_stats(&self, stats: Arc<QueueStats>) {
+        *self.queue_stats.write().await = Some(stats);
+    }
+
     pub async fn get_stats(&self) -> ProxyStats {
-        match crate::modules::proxy_db::get_stats() {
+        let mut stats = match crate::modules::proxy_db::get_stats() {
             Ok(stats) => stats,
             Err(e) => {
                 tracing::error!("Failed to get stats from DB: {}", e);
                 self.stats.read().await.clone()
             }
+        };
+        if let Some(queue) = self.queue_stats.read().await.as_ref() {
+            stats.queue = queue.snapshot();
         }
+        stats.background_tasks = self.background_tasks.lock().unwrap().clone();
+        stats
     }
     
+    /// 记录熔断状态变更，并推送到前端 (Tauri 事件) 与 Web Admin WebSocket
+    pub fn record_circuit_transition(&self, transition: &CircuitTransition) {
+        {
+            let mut events = self.circuit_events.lock().unwrap();
+            if events.len() >= MAX_CIRCUIT_EVENTS {
+                events.pop_back();
+            }
+            events.push_front(transition.clone());
+        }
+
+        #[cfg(feature = "desktop")]
+        if let Some(app) = &self.app_handle {
+            let _ = app.emit("proxy://circuit", transition);
+        }
+        events::publish_raw(
+            topics::CIRCUITS,
+            "circuit_breaker_transition",
+            serde_json::to_value(transition).unwrap_or(serde_json::Value::Null),
+        );
+    }
+
+    /// 生成熔断器状态监听器 (注册到各个 CircuitBreakerRegistry)
+    pub fn circuit_listener(self: &Arc<Self>) -> TransitionListener {
+        let monitor = Arc::downgrade(self);
+        Arc::new(move |transition: &CircuitTransition| {
+            if let Some(monitor) = monitor.upgrade() {
+                monitor.record_circuit_transition(transition);
+            }
+        })
+    }
+
+    /// 最近的熔断状态变更事件 (最新在前)
+    pub fn get_circuit_events(&self, limit: usize) -> Vec<CircuitTransition> {
+        self.circuit_events.lock().unwrap().iter().take(limit).cloned().collect()
+    }
+
     pub async fn clear(&self) {
         let mut logs = self.logs.write().await;
         logs.clear();
         let mut stats = self.stats.write().await;
         *stats = ProxyStats::default();
+        self.background_tasks.lock().unwrap().clear();
 
         if let Err(e) = crate::modules::proxy_db::clear_logs() {
             tracing::error!("Failed to clear logs in DB: {}", e);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_rate_window_slides() {
+        let mut window = RequestRateWindow::default();
+        window.record(0);
+        window.record(30_000);
+        window.record(59_999);
+        assert_eq!(window.requests_per_minute(59_999), 3.0);
+        assert_eq!(window.requests_per_minute(60_000), 2.0);
+        assert_eq!(window.requests_per_minute(200_000), 0.0);
+    }
+}
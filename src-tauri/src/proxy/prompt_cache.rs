@@ -0,0 +1,292 @@
+// Claude cache_control 提示词缓存
+//
+// v1internal 不提供 cachedContents 接口，这里借助 Gemini 的隐式前缀缓存: 记录每个 cache_control
+// 断点对应的前缀指纹由哪个账号写入，后续相同前缀的请求通过会话粘性绑定回该账号，
+// 并据此向客户端报告 cache_creation_input_tokens (cache_read_input_tokens 取上游真实值)。
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::PromptCacheConfig;
+use crate::proxy::mappers::claude::models::Usage;
+
+/// 一个 cache_control 断点 (从请求开头到该内容块为止的前缀)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheBreakpoint {
+    /// 前缀指纹
+    pub hash: String,
+    /// 前缀的估算 token 数
+    pub tokens: u32,
+    pub ttl: Duration,
+}
+
+/// 单次请求的缓存计划
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CachePlan {
+    /// 已由同一账号写入过的最长前缀 (估算 token 数)
+    pub hit_tokens: u32,
+    /// 本次新写入缓存的 token 数 (最后一个断点减去已命中的前缀)
+    pub creation_tokens: u32,
+}
+
+impl CachePlan {
+    /// 将新写入缓存的部分从 input_tokens 中拆分到 cache_creation_input_tokens
+    pub fn apply(&self, usage: &mut Usage) {
+        let creation = self.creation_tokens.min(usage.input_tokens);
+        usage.input_tokens -= creation;
+        usage.cache_creation_input_tokens = Some(creation);
+    }
+}
+
+/// 按 Claude 的缓存顺序 (tools -> system -> messages) 提取所有 cache_control 断点
+/// 估算 token 数不足 `min_prefix_tokens` 的断点会被忽略 (与 Anthropic 的最小可缓存长度一致)
+pub fn breakpoints(body: &Value, config: &PromptCacheConfig) -> Vec<CacheBreakpoint> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    let mut walker = PrefixWalker {
+        hasher: Sha256::new(),
+        chars: 0,
+        found: Vec::new(),
+        default_ttl: Duration::from_secs(config.ttl_seconds),
+    };
+
+    if let Some(model) = body.get("model").and_then(|m| m.as_str()) {
+        walker.feed_str(model);
+    }
+    if let Some(tools) = body.get("tools").and_then(|t| t.as_array()) {
+        tools.iter().for_each(|tool| walker.feed_block(tool));
+    }
+    match body.get("system") {
+        Some(Value::String(text)) => walker.feed_str(text),
+        Some(Value::Array(blocks)) => blocks.iter().for_each(|block| walker.feed_block(block)),
+        _ => {}
+    }
+    if let Some(messages) = body.get("messages").and_then(|m| m.as_array()) {
+        for message in messages {
+            walker.feed_str(message.get("role").and_then(|r| r.as_str()).unwrap_or_default());
+            match message.get("content") {
+                Some(Value::String(text)) => walker.feed_str(text),
+                Some(Value::Array(blocks)) => blocks.iter().for_each(|block| walker.feed_block(block)),
+                _ => {}
+            }
+        }
+    }
+
+    walker.found.retain(|bp| bp.tokens >= config.min_prefix_tokens);
+    walker.found
+}
+
+struct PrefixWalker {
+    hasher: Sha256,
+    chars: usize,
+    found: Vec<CacheBreakpoint>,
+    default_ttl: Duration,
+}
+
+impl PrefixWalker {
+    fn feed_str(&mut self, text: &str) {
+        self.hasher.update(text.as_bytes());
+        self.hasher.update([0u8]);
+        self.chars += text.chars().count();
+    }
+
+    fn feed_block(&mut self, block: &Value) {
+        let cache_control = block.get("cache_control").filter(|c| !c.is_null()).cloned();
+        let mut content = block.clone();
+        if let Some(obj) = content.as_object_mut() {
+            obj.remove("cache_control");
+        }
+        self.feed_str(&content.to_string());
+
+        if let Some(cache_control) = cache_control {
+            let ttl = match cache_control.get("ttl").and_then(|t| t.as_str()) {
+                Some("1h") => Duration::from_secs(3600),
+                Some("5m") => Duration::from_secs(300),
+                _ => self.default_ttl,
+            };
+            self.found.push(CacheBreakpoint {
+                hash: format!("{:x}", self.hasher.clone().finalize()),
+                tokens: self.chars.div_ceil(4) as u32,
+                ttl,
+            });
+        }
+    }
+}
+
+struct CacheEntry {
+    email: String,
+    tokens: u32,
+    expires_at: Instant,
+}
+
+/// 记录各前缀缓存所在的账号
+#[derive(Default)]
+pub struct PromptCacheTracker {
+    entries: DashMap<String, CacheEntry>,
+}
+
+impl PromptCacheTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(model: &str, hash: &str) -> String {
+        format!("{}:{}", model, hash)
+    }
+
+    /// 持有最长有效前缀缓存的账号 (用于会话亲和)
+    pub fn preferred_account(&self, model: &str, breakpoints: &[CacheBreakpoint]) -> Option<String> {
+        let now = Instant::now();
+        breakpoints.iter().rev().find_map(|bp| {
+            self.entries
+                .get(&Self::key(model, &bp.hash))
+                .filter(|entry| entry.expires_at > now)
+                .map(|entry| entry.email.clone())
+        })
+    }
+
+    /// 计算在指定账号上执行时的缓存命中与写入量
+    pub fn plan(&self, model: &str, breakpoints: &[CacheBreakpoint], email: &str) -> CachePlan {
+        let Some(last) = breakpoints.last() else {
+            return CachePlan::default();
+        };
+        let now = Instant::now();
+        let hit_tokens = breakpoints
+            .iter()
+            .rev()
+            .find_map(|bp| {
+                self.entries
+                    .get(&Self::key(model, &bp.hash))
+                    .filter(|entry| entry.expires_at > now && entry.email == email)
+                    .map(|entry| entry.tokens)
+            })
+            .unwrap_or(0);
+        CachePlan {
+            hit_tokens,
+            creation_tokens: last.tokens.saturating_sub(hit_tokens),
+        }
+    }
+
+    /// 请求成功后记录 (或续期) 各断点的缓存
+    pub fn commit(&self, model: &str, breakpoints: &[CacheBreakpoint], email: &str, max_entries: usize) {
+        if breakpoints.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        if self.entries.len() + breakpoints.len() > max_entries {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        while !self.entries.is_empty() && self.entries.len() + breakpoints.len() > max_entries {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|entry| entry.expires_at)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+
+        for bp in breakpoints {
+            self.entries.insert(
+                Self::key(model, &bp.hash),
+                CacheEntry {
+                    email: email.to_string(),
+                    tokens: bp.tokens,
+                    expires_at: now + bp.ttl,
+                },
+            );
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> PromptCacheConfig {
+        PromptCacheConfig {
+            min_prefix_tokens: 10,
+            ..Default::default()
+        }
+    }
+
+    fn body(question: &str) -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "system": [{"type": "text", "text": "You are a helpful assistant. ".repeat(20), "cache_control": {"type": "ephemeral"}}],
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "Here is a long document. ".repeat(40), "cache_control": {"type": "ephemeral", "ttl": "1h"}}]},
+                {"role": "user", "content": question}
+            ]
+        })
+    }
+
+    #[test]
+    fn breakpoints_ignore_content_after_the_marker() {
+        let a = breakpoints(&body("first question"), &config());
+        let b = breakpoints(&body("a different question"), &config());
+        assert_eq!(a.len(), 2);
+        assert_eq!(a, b);
+        assert!(a[0].tokens < a[1].tokens);
+        assert_eq!(a[1].ttl, Duration::from_secs(3600));
+
+        // 未启用或低于最小长度时不产生断点
+        let disabled = PromptCacheConfig { enabled: false, ..config() };
+        assert!(breakpoints(&body("q"), &disabled).is_empty());
+        let strict = PromptCacheConfig { min_prefix_tokens: 100_000, ..config() };
+        assert!(breakpoints(&body("q"), &strict).is_empty());
+    }
+
+    #[test]
+    fn tracker_plans_creation_and_affinity() {
+        let tracker = PromptCacheTracker::new();
+        let bps = breakpoints(&body("q"), &config());
+        let model = "gemini-3-pro-high";
+
+        let first = tracker.plan(model, &bps, "a@example.com");
+        assert_eq!(first, CachePlan { hit_tokens: 0, creation_tokens: bps[1].tokens });
+        tracker.commit(model, &bps, "a@example.com", 100);
+
+        assert_eq!(tracker.preferred_account(model, &bps).as_deref(), Some("a@example.com"));
+        assert_eq!(tracker.plan(model, &bps, "a@example.com").creation_tokens, 0);
+        // 其他账号上没有缓存，需要重新写入
+        assert_eq!(tracker.plan(model, &bps, "b@example.com").creation_tokens, bps[1].tokens);
+        assert!(tracker.preferred_account("other-model", &bps).is_none());
+
+        let mut usage = Usage {
+            input_tokens: 50,
+            output_tokens: 5,
+            cache_read_input_tokens: None,
+            cache_creation_input_tokens: Some(0),
+            server_tool_use: None,
+        };
+        CachePlan { hit_tokens: 0, creation_tokens: 30 }.apply(&mut usage);
+        assert_eq!(usage.input_tokens, 20);
+        assert_eq!(usage.cache_creation_input_tokens, Some(30));
+    }
+
+    #[test]
+    fn tracker_evicts_when_full() {
+        let tracker = PromptCacheTracker::new();
+        let bps = breakpoints(&body("q"), &config());
+        tracker.commit("m1", &bps, "a@example.com", 3);
+        tracker.commit("m2", &bps, "a@example.com", 3);
+        assert!(tracker.len() <= 3);
+        assert_eq!(tracker.preferred_account("m2", &bps).as_deref(), Some("a@example.com"));
+    }
+}
@@ -0,0 +1,346 @@
+// 系统提示词注入与请求改写策略
+//
+// 在各协议 mapper 生成 v1internal 请求体之后统一执行: 身份提示词开关、前后缀模板、
+// 安全阈值覆盖与出站脱敏，保证 Claude / OpenAI / Gemini 三条链路行为一致。
+use axum::http::HeaderMap;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::common::model_router::cached_regex;
+use crate::proxy::config::{InboundProtocol, PromptPolicyConfig, PromptRoutePolicy, SafetyThreshold};
+
+/// 各 mapper 默认注入的 Antigravity 身份提示词
+pub const ANTIGRAVITY_IDENTITY: &str = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.\n\
+You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.\n\
+**Absolute paths only**\n\
+**Proactiveness**";
+
+/// Claude 链路在系统提示词末尾追加的结束标记
+pub const SYSTEM_PROMPT_END_MARKER: &str = "\n--- [SYSTEM_PROMPT_END] ---";
+
+const SAFETY_CATEGORIES: [&str; 5] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+    "HARM_CATEGORY_CIVIC_INTEGRITY",
+];
+
+/// 按阈值生成 safetySettings
+pub fn safety_settings(threshold: SafetyThreshold) -> Value {
+    let threshold_str = threshold.to_gemini_threshold();
+    Value::Array(
+        SAFETY_CATEGORIES
+            .iter()
+            .map(|category| json!({ "category": category, "threshold": threshold_str }))
+            .collect(),
+    )
+}
+
+/// 单次请求生效的策略 (全局设置 + 路由覆盖 + 请求头覆盖)
+#[derive(Debug, Clone)]
+pub struct ResolvedPromptPolicy {
+    pub route: Option<String>,
+    pub inject_identity: bool,
+    pub system_prefix: Option<String>,
+    pub system_suffix: Option<String>,
+    pub safety_threshold: Option<SafetyThreshold>,
+    redactions: Vec<(Regex, String)>,
+    model: String,
+    protocol: InboundProtocol,
+}
+
+impl ResolvedPromptPolicy {
+    pub fn resolve(config: &PromptPolicyConfig, model: &str, protocol: InboundProtocol, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            if !config.allow_header_overrides {
+                return None;
+            }
+            headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string())
+        };
+
+        let route = match header("x-prompt-policy") {
+            Some(name) => {
+                let found = config.routes.iter().find(|r| r.name == name);
+                if found.is_none() {
+                    tracing::warn!("[PromptPolicy] Unknown policy '{}' requested via header, ignoring", name);
+                }
+                found
+            }
+            None => config.routes.iter().find(|r| route_matches(r, model, protocol)),
+        };
+
+        let mut policy = Self {
+            route: route.map(|r| r.name.clone()),
+            inject_identity: route.and_then(|r| r.inject_identity).unwrap_or(config.inject_identity),
+            system_prefix: route.and_then(|r| r.system_prefix.clone()).or_else(|| config.system_prefix.clone()),
+            system_suffix: route.and_then(|r| r.system_suffix.clone()).or_else(|| config.system_suffix.clone()),
+            safety_threshold: route.and_then(|r| r.safety_threshold).or(config.safety_threshold),
+            redactions: config
+                .redactions
+                .iter()
+                .filter_map(|rule| match cached_regex(&rule.pattern) {
+                    Ok(re) => Some((re, rule.replacement.clone())),
+                    Err(e) => {
+                        tracing::warn!("[PromptPolicy] Skipping redaction rule '{}': {}", rule.name, e);
+                        None
+                    }
+                })
+                .collect(),
+            model: model.to_string(),
+            protocol,
+        };
+
+        if let Some(value) = header("x-prompt-identity") {
+            match value.to_lowercase().as_str() {
+                "on" | "true" | "1" => policy.inject_identity = true,
+                "off" | "false" | "0" => policy.inject_identity = false,
+                _ => {}
+            }
+        }
+        if let Some(threshold) = header("x-safety-threshold").and_then(|v| SafetyThreshold::parse(&v)) {
+            policy.safety_threshold = Some(threshold);
+        }
+
+        policy
+    }
+
+    /// 改写 v1internal 请求体 (`{"model": ..., "request": {...}}`)
+    pub fn apply(&self, body: &mut Value) {
+        let mapped_model = body.get("model").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let Some(request) = body.get_mut("request").and_then(|r| r.as_object_mut()) else {
+            return;
+        };
+
+        // 1. 出站脱敏 (在追加管理员模板之前执行)
+        if !self.redactions.is_empty() {
+            for key in ["contents", "systemInstruction"] {
+                if let Some(value) = request.get_mut(key) {
+                    self.redact(value);
+                }
+            }
+        }
+
+        // 图像生成模型不支持系统提示词
+        let is_image_request = request
+            .get("generationConfig")
+            .is_some_and(|g| g.get("imageConfig").is_some());
+
+        if !is_image_request {
+            let mut parts: Vec<Value> = request
+                .get("systemInstruction")
+                .and_then(|s| s.get("parts"))
+                .and_then(|p| p.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let original_len = parts.len();
+
+            // 2. 身份提示词开关
+            if !self.inject_identity {
+                parts.retain(|p| {
+                    let text = p.get("text").and_then(|t| t.as_str());
+                    text != Some(ANTIGRAVITY_IDENTITY) && text != Some(SYSTEM_PROMPT_END_MARKER)
+                });
+            }
+
+            // 3. 前后缀模板
+            if let Some(prefix) = &self.system_prefix {
+                parts.insert(0, json!({ "text": self.render(prefix, &mapped_model) }));
+            }
+            if let Some(suffix) = &self.system_suffix {
+                parts.push(json!({ "text": self.render(suffix, &mapped_model) }));
+            }
+
+            let changed = parts.len() != original_len || self.system_prefix.is_some() || self.system_suffix.is_some();
+            if changed {
+                if parts.is_empty() {
+                    request.remove("systemInstruction");
+                } else {
+                    request.insert("systemInstruction".to_string(), json!({ "role": "user", "parts": parts }));
+                }
+            }
+        }
+
+        // 4. 安全阈值
+        if let Some(threshold) = self.safety_threshold {
+            request.insert("safetySettings".to_string(), safety_settings(threshold));
+        }
+    }
+
+    fn render(&self, template: &str, mapped_model: &str) -> String {
+        let protocol = match self.protocol {
+            InboundProtocol::Claude => "claude",
+            InboundProtocol::OpenAI => "openai",
+            InboundProtocol::Gemini => "gemini",
+        };
+        template
+            .replace("{{model}}", &self.model)
+            .replace("{{mapped_model}}", mapped_model)
+            .replace("{{protocol}}", protocol)
+            .replace("{{date}}", &chrono::Utc::now().format("%Y-%m-%d").to_string())
+    }
+
+    /// 递归替换所有 text 字段以及工具调用参数/工具结果中的敏感信息
+    fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    match key.as_str() {
+                        "text" => {
+                            if let Value::String(text) = v {
+                                self.redact_str(text);
+                            }
+                        }
+                        // 工具结果与工具参数为任意 JSON，替换其中所有字符串
+                        "functionResponse" => {
+                            if let Some(response) = v.get_mut("response") {
+                                self.redact_leaves(response);
+                            }
+                        }
+                        "functionCall" => {
+                            if let Some(args) = v.get_mut("args") {
+                                self.redact_leaves(args);
+                            }
+                        }
+                        _ => self.redact(v),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact(v)),
+            _ => {}
+        }
+    }
+
+    fn redact_leaves(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => self.redact_str(text),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_leaves(v)),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_leaves(v)),
+            _ => {}
+        }
+    }
+
+    fn redact_str(&self, text: &mut String) {
+        for (re, replacement) in &self.redactions {
+            if re.is_match(text) {
+                *text = re.replace_all(text, replacement.as_str()).into_owned();
+            }
+        }
+    }
+}
+
+fn route_matches(route: &PromptRoutePolicy, model: &str, protocol: InboundProtocol) -> bool {
+    (route.model.is_empty() || wildcard_match(&route.model, model))
+        && (route.protocols.is_empty() || route.protocols.contains(&protocol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::RedactionRule;
+
+    fn body() -> Value {
+        json!({
+            "model": "gemini-3-pro-high",
+            "request": {
+                "contents": [{"role": "user", "parts": [{"text": "my key is sk-abcdefghijklmnop1234"}]}],
+                "systemInstruction": {
+                    "role": "user",
+                    "parts": [
+                        {"text": ANTIGRAVITY_IDENTITY},
+                        {"text": "Be brief."},
+                        {"text": SYSTEM_PROMPT_END_MARKER}
+                    ]
+                },
+                "safetySettings": safety_settings(SafetyThreshold::Off)
+            }
+        })
+    }
+
+    fn texts(body: &Value) -> Vec<String> {
+        body["request"]["systemInstruction"]["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["text"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn default_policy_leaves_body_untouched() {
+        let policy = ResolvedPromptPolicy::resolve(&PromptPolicyConfig::default(), "claude-opus-4-5", InboundProtocol::Claude, &HeaderMap::new());
+        let mut b = body();
+        policy.apply(&mut b);
+        assert_eq!(b, body());
+    }
+
+    #[test]
+    fn route_policy_rewrites_system_prompt_and_safety() {
+        let config = PromptPolicyConfig {
+            system_suffix: Some("global suffix".to_string()),
+            routes: vec![PromptRoutePolicy {
+                name: "team-a".to_string(),
+                model: "claude-*".to_string(),
+                protocols: vec![InboundProtocol::Claude],
+                inject_identity: Some(false),
+                system_prefix: Some("Model {{model}} via {{mapped_model}} ({{protocol}})".to_string()),
+                system_suffix: None,
+                safety_threshold: Some(SafetyThreshold::BlockOnlyHigh),
+            }],
+            redactions: vec![RedactionRule {
+                name: "api-key".to_string(),
+                pattern: r"sk-[A-Za-z0-9]{16,}".to_string(),
+                replacement: "[REDACTED]".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let policy = ResolvedPromptPolicy::resolve(&config, "claude-opus-4-5", InboundProtocol::Claude, &HeaderMap::new());
+        assert_eq!(policy.route.as_deref(), Some("team-a"));
+        let mut b = body();
+        policy.apply(&mut b);
+        assert_eq!(
+            texts(&b),
+            vec!["Model claude-opus-4-5 via gemini-3-pro-high (claude)", "Be brief.", "global suffix"]
+        );
+        assert_eq!(b["request"]["safetySettings"][0]["threshold"], "BLOCK_ONLY_HIGH");
+        assert_eq!(b["request"]["contents"][0]["parts"][0]["text"], "my key is [REDACTED]");
+
+        // 工具调用参数与工具结果中的字符串同样替换
+        let mut b = body();
+        b["request"]["contents"] = json!([
+            {"role": "model", "parts": [{"functionCall": {"name": "read_env", "args": {"path": ".env sk-abcdefghijklmnop1234"}}}]},
+            {"role": "user", "parts": [{"functionResponse": {"name": "read_env", "response": {"result": {"lines": ["OPENAI_KEY=sk-abcdefghijklmnop1234"]}}}}]}
+        ]);
+        policy.apply(&mut b);
+        let contents = &b["request"]["contents"];
+        assert_eq!(contents[0]["parts"][0]["functionCall"]["args"]["path"], ".env [REDACTED]");
+        assert_eq!(contents[1]["parts"][0]["functionResponse"]["response"]["result"]["lines"][0], "OPENAI_KEY=[REDACTED]");
+        assert_eq!(contents[1]["parts"][0]["functionResponse"]["name"], "read_env");
+
+        // 协议不匹配时回退到全局设置
+        let policy = ResolvedPromptPolicy::resolve(&config, "claude-opus-4-5", InboundProtocol::OpenAI, &HeaderMap::new());
+        assert!(policy.route.is_none());
+        assert!(policy.inject_identity);
+    }
+
+    #[test]
+    fn header_overrides_require_opt_in() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-prompt-identity", "off".parse().unwrap());
+        headers.insert("x-safety-threshold", "medium".parse().unwrap());
+
+        let mut config = PromptPolicyConfig::default();
+        let policy = ResolvedPromptPolicy::resolve(&config, "gemini-2.5-flash", InboundProtocol::Gemini, &headers);
+        assert!(policy.inject_identity);
+        assert!(policy.safety_threshold.is_none());
+
+        config.allow_header_overrides = true;
+        let policy = ResolvedPromptPolicy::resolve(&config, "gemini-2.5-flash", InboundProtocol::Gemini, &headers);
+        let mut b = body();
+        policy.apply(&mut b);
+        assert_eq!(texts(&b), vec!["Be brief."]);
+        assert_eq!(b["request"]["safetySettings"][0]["threshold"], "BLOCK_MEDIUM_AND_ABOVE");
+    }
+}
@@ -0,0 +1,195 @@
+// 配额预测调度 (Predictive quota-aware scheduling)
+//
+// 根据模型配额的 reset_time 与 proxy_db 中的历史请求速率，估算每个账号在重置前
+// 是否还会剩余配额 ("use it or lose it")，并为重置时间较远的账号保留储备。
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::proxy::sticky_config::PredictiveConfig;
+use crate::proxy::token_manager::ProxyToken;
+
+/// 单个账号针对某个模型的配额预测结果
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaForecast {
+    pub account_id: String,
+    pub email: String,
+    pub subscription_tier: Option<String>,
+    /// 当前剩余配额 (0.0-1.0)，未知时为 None
+    pub remaining: Option<f64>,
+    /// 距离配额重置的小时数，未知时为 None
+    pub hours_to_reset: Option<f64>,
+    /// 回溯窗口内的请求速率 (次/小时)
+    pub requests_per_hour: f64,
+    /// 估算的配额消耗速率 (占比/小时)
+    pub burn_rate: f64,
+    /// 按当前速率估算到重置时仍剩余的配额 (会被浪费的部分)
+    pub projected_leftover: Option<f64>,
+    /// 是否因重置较远且配额低于储备线而被保护
+    pub reserve_protected: bool,
+}
+
+impl QuotaForecast {
+    pub fn build(
+        token: &ProxyToken,
+        model: &str,
+        requests_per_hour: f64,
+        config: &PredictiveConfig,
+        now: i64,
+    ) -> Self {
+        let remaining = token.model_quotas.get(model).copied();
+        let hours_to_reset = token
+            .model_reset_times
+            .get(model)
+            .map(|&reset| ((reset - now).max(0) as f64) / 3600.0);
+        let burn_rate = requests_per_hour * config.request_cost;
+
+        let projected_leftover = match (remaining, hours_to_reset) {
+            (Some(r), Some(h)) => Some((r - burn_rate * h).max(0.0)),
+            _ => None,
+        };
+
+        let reserve_protected = match (remaining, hours_to_reset) {
+            (Some(r), Some(h)) => h > config.reserve_horizon_hours && r < config.reserve_fraction,
+            _ => false,
+        };
+
+        Self {
+            account_id: token.account_id.clone(),
+            email: token.email.clone(),
+            subscription_tier: token.subscription_tier.clone(),
+            remaining,
+            hours_to_reset,
+            requests_per_hour,
+            burn_rate,
+            projected_leftover,
+            reserve_protected,
+        }
+    }
+
+    /// 排序键 (升序越优先):
+    /// 1. 未被储备保护的账号优先
+    /// 2. 预计重置时仍有剩余 (会被浪费) 的账号优先
+    /// 3. 重置时间越近越优先，未知重置时间排在最后
+    /// 4. 预计浪费越多越优先
+    fn rank_key(&self) -> (bool, bool, f64, f64) {
+        let wastes = self.projected_leftover.map(|l| l > 0.0).unwrap_or(false);
+        (
+            self.reserve_protected,
+            !wastes,
+            self.hours_to_reset.unwrap_or(f64::MAX),
+            -self.projected_leftover.unwrap_or(0.0),
+        )
+    }
+}
+
+/// 对候选账号按预测优先级排序
+pub fn rank_forecasts(forecasts: &mut [QuotaForecast]) {
+    forecasts.sort_by(|a, b| {
+        let (ka, kb) = (a.rank_key(), b.rank_key());
+        ka.0.cmp(&kb.0)
+            .then(ka.1.cmp(&kb.1))
+            .then(ka.2.partial_cmp(&kb.2).unwrap_or(std::cmp::Ordering::Equal))
+            .then(ka.3.partial_cmp(&kb.3).unwrap_or(std::cmp::Ordering::Equal))
+    });
+}
+
+/// 按预测优先级对 token 快照重新排序
+pub fn sort_tokens(
+    tokens: &mut [ProxyToken],
+    model: &str,
+    request_rates: &HashMap<String, f64>,
+    config: &PredictiveConfig,
+    now: i64,
+) {
+    let mut forecasts: Vec<QuotaForecast> = tokens
+        .iter()
+        .map(|t| {
+            let rate = request_rates.get(&t.email).copied().unwrap_or(0.0);
+            QuotaForecast::build(t, model, rate, config, now)
+        })
+        .collect();
+    rank_forecasts(&mut forecasts);
+
+    let order: HashMap<String, usize> = forecasts
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.account_id.clone(), i))
+        .collect();
+    tokens.sort_by_key(|t| order.get(&t.account_id).copied().unwrap_or(usize::MAX));
+}
+
+/// 将 ISO 8601 格式的 reset_time 解析为 Unix 时间戳 (秒)
+pub fn parse_reset_time(reset_time: &str) -> Option<i64> {
+    if reset_time.is_empty() {
+        return None;
+    }
+    chrono::DateTime::parse_from_rfc3339(reset_time)
+        .ok()
+        .map(|dt| dt.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn token(id: &str, remaining: f64, reset_in_hours: f64, now: i64) -> ProxyToken {
+        let model = "gemini-3-pro-high".to_string();
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: String::new(),
+            refresh_token: String::new(),
+            expires_in: 3600,
+            timestamp: now + 3600,
+            email: format!("{}@example.com", id),
+            project_id: None,
+            subscription_tier: Some("PRO".to_string()),
+            model_quotas: HashMap::from([(model.clone(), remaining)]),
+            model_reset_times: HashMap::from([(model, now + (reset_in_hours * 3600.0) as i64)]),
+        }
+    }
+
+    #[test]
+    fn test_prefers_soonest_reset() {
+        let now = 1_700_000_000;
+        let config = PredictiveConfig::default();
+        let mut tokens = vec![token("far", 0.8, 100.0, now), token("soon", 0.8, 1.0, now)];
+
+        sort_tokens(&mut tokens, "gemini-3-pro-high", &HashMap::new(), &config, now);
+
+        assert_eq!(tokens[0].account_id, "soon");
+    }
+
+    #[test]
+    fn test_reserve_protects_distant_reset() {
+        let now = 1_700_000_000;
+        let config = PredictiveConfig::default();
+        // 重置很远且低于储备线 → 排在最后，即便没有其他可以浪费的账号
+        let mut tokens = vec![token("reserve", 0.1, 200.0, now), token("normal", 0.5, 150.0, now)];
+
+        sort_tokens(&mut tokens, "gemini-3-pro-high", &HashMap::new(), &config, now);
+
+        assert_eq!(tokens[0].account_id, "normal");
+        assert_eq!(tokens[1].account_id, "reserve");
+    }
+
+    #[test]
+    fn test_burn_rate_reduces_projected_leftover() {
+        let now = 1_700_000_000;
+        let config = PredictiveConfig::default();
+        // busy 的消耗速率足以在重置前用完剩余配额，不再属于 "use it or lose it"
+        let mut tokens = vec![token("busy", 0.2, 2.0, now), token("idle", 0.2, 3.0, now)];
+        let rates = HashMap::from([("busy@example.com".to_string(), 100.0)]);
+
+        sort_tokens(&mut tokens, "gemini-3-pro-high", &rates, &config, now);
+
+        assert_eq!(tokens[0].account_id, "idle");
+    }
+
+    #[test]
+    fn test_parse_reset_time() {
+        assert_eq!(parse_reset_time("2026-01-08T17:00:00Z"), Some(1767891600));
+        assert_eq!(parse_reset_time(""), None);
+        assert_eq!(parse_reset_time("invalid"), None);
+    }
+}
@@ -2,6 +2,25 @@ use dashmap::DashMap;
 use std::time::{SystemTime, Duration};
 use regex::Regex;
 
+use crate::proxy::events::{self, ProxyEvent};
+
+/// 推送限流锁定事件到 Web Admin
+fn publish_lockout(account_id: &str, info: &RateLimitInfo) {
+    events::publish(ProxyEvent::RateLimitSet {
+        account: account_id.to_string(),
+        reason: format!("{:?}", info.reason),
+        model: info.model.clone(),
+        retry_after_sec: info.retry_after_sec,
+    });
+}
+
+/// 推送限流解除事件到 Web Admin
+fn publish_cleared(account_id: Option<&str>) {
+    events::publish(ProxyEvent::RateLimitCleared {
+        account: account_id.map(|s| s.to_string()),
+    });
+}
+
 /// 限流原因类型
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RateLimitReason {
@@ -70,7 +89,9 @@ impl RateLimitTracker {
             tracing::debug!("账号 {} 请求成功，已重置失败计数", account_id);
         }
         // 同时清除限流记录（如果有）
-        self.limits.remove(account_id);
+        if self.limits.remove(account_id).is_some() {
+            publish_cleared(Some(account_id));
+        }
     }
     
     /// 精确锁定账号到指定时间点
@@ -95,6 +116,7 @@ impl RateLimitTracker {
             model: model.clone(),  // 🆕 支持模型级别限流
         };
         
+        publish_lockout(account_id, &info);
         self.limits.insert(account_id.to_string(), info);
         
         if let Some(m) = &model {
@@ -251,6 +273,7 @@ impl RateLimitTracker {
         };
         
         // 存储
+        publish_lockout(account_id, &info);
         self.limits.insert(account_id.to_string(), info.clone());
         
         tracing::warn!(
@@ -485,7 +508,11 @@ impl RateLimitTracker {
     /// 清除指定账号的限流记录
     #[allow(dead_code)]
     pub fn clear(&self, account_id: &str) -> bool {
-        self.limits.remove(account_id).is_some()
+        let removed = self.limits.remove(account_id).is_some();
+        if removed {
+            publish_cleared(Some(account_id));
+        }
+        removed
     }
     
     /// 清除所有限流记录 (乐观重置策略)
@@ -495,6 +522,7 @@ impl RateLimitTracker {
     pub fn clear_all(&self) {
         let count = self.limits.len();
         self.limits.clear();
+        publish_cleared(None);
         tracing::warn!("🔄 Optimistic reset: Cleared all {} rate limit record(s)", count);
     }
 }
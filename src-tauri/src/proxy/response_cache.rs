@@ -0,0 +1,336 @@
+// 确定性请求的响应缓存
+//
+// 以映射后的 v1internal 请求体 (去除 project/requestId 等易变字段) 的规范化哈希为键，缓存上游原始响应
+// (SSE 或 JSON)。命中时把原始字节重新送入各协议现有的流式转换器，流式与非流式请求共用同一份缓存。
+// 内存层保存热点条目，SQLite 层 (proxy_logs.db) 跨重启持久化。
+use axum::http::HeaderMap;
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::common::model_router::request_api_key;
+use crate::proxy::config::ResponseCacheConfig;
+
+/// 请求头: `bypass` 跳过缓存 (既不读取也不写入)
+pub const BYPASS_HEADER: &str = "x-response-cache";
+
+/// 命中缓存时写入响应的标记头 (监控中间件据此标记日志)
+pub const HIT_HEADER: &str = "X-Cache";
+
+struct CachedEntry {
+    body: Bytes,
+    created_at: i64,
+    expires_at: i64,
+}
+
+pub struct ResponseCache {
+    memory: DashMap<String, CachedEntry>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self { memory: DashMap::new() }
+    }
+
+    /// 计算缓存键; 未启用、路由/API Key 不匹配、非确定性请求或客户端要求跳过时返回 None
+    pub fn key_for(
+        config: &ResponseCacheConfig,
+        model: &str,
+        headers: &HeaderMap,
+        upstream_body: &Value,
+        stream: bool,
+    ) -> Option<String> {
+        if !config.enabled {
+            return None;
+        }
+        let bypass = headers
+            .get(BYPASS_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.eq_ignore_ascii_case("bypass") || v.eq_ignore_ascii_case("off"));
+        if bypass {
+            return None;
+        }
+        if !config.models.is_empty() && !config.models.iter().any(|pattern| wildcard_match(pattern, model)) {
+            return None;
+        }
+        if !config.api_keys.is_empty() {
+            let key = request_api_key(headers);
+            if !key.is_some_and(|k| config.api_keys.iter().any(|allowed| allowed == k)) {
+                return None;
+            }
+        }
+        if config.deterministic_only && !is_deterministic(upstream_body) {
+            return None;
+        }
+
+        let mut canonical = upstream_body.clone();
+        if let Some(obj) = canonical.as_object_mut() {
+            obj.remove("project");
+            obj.remove("requestId");
+            if let Some(request) = obj.get_mut("request").and_then(|r| r.as_object_mut()) {
+                request.remove("sessionId");
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(if stream { b"stream\0" as &[u8] } else { b"json\0" });
+        write_canonical(&canonical, &mut hasher);
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    /// 读取缓存 (内存优先，未命中时在阻塞线程池中回源 SQLite 并回填内存)
+    pub async fn get(&self, key: &str, config: &ResponseCacheConfig) -> Option<Bytes> {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(entry) = self.memory.get(key) {
+            if entry.expires_at > now {
+                return Some(entry.body.clone());
+            }
+        }
+        self.memory.remove_if(key, |_, entry| entry.expires_at <= now);
+
+        if !config.persist {
+            return None;
+        }
+        let db_key = key.to_string();
+        let persisted = tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_cached_response(&db_key, now))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        match persisted {
+            Ok(Some((body, expires_at))) => {
+                let body = Bytes::from(body);
+                self.insert_memory(key.to_string(), body.clone(), now, expires_at, config.max_entries);
+                Some(body)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("[ResponseCache] Failed to read persisted entry: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 写入缓存
+    pub fn put(&self, key: String, model: &str, body: Bytes, config: &ResponseCacheConfig) {
+        if body.is_empty() || body.len() > config.max_entry_bytes {
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let expires_at = now + config.ttl_seconds as i64;
+        self.insert_memory(key.clone(), body.clone(), now, expires_at, config.max_entries);
+
+        if config.persist {
+            let model = model.to_string();
+            let max_persisted = config.max_persisted_entries;
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = crate::modules::proxy_db::save_cached_response(&key, &model, &body, now, expires_at, max_persisted) {
+                    tracing::warn!("[ResponseCache] Failed to persist entry: {}", e);
+                }
+            });
+        }
+    }
+
+    /// 包装上游字节流: 完整结束 (无错误且包含 finishReason) 后写入缓存
+    pub fn record<S, E>(
+        self: &Arc<Self>,
+        stream: S,
+        key: String,
+        model: String,
+        config: ResponseCacheConfig,
+    ) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        let cache = self.clone();
+        async_stream::stream! {
+            let mut stream = Box::pin(stream);
+            let mut recorded: Option<Vec<u8>> = Some(Vec::new());
+            while let Some(item) = stream.next().await {
+                match &item {
+                    Ok(bytes) => {
+                        if let Some(buf) = recorded.as_mut() {
+                            buf.extend_from_slice(bytes);
+                            if buf.len() > config.max_entry_bytes {
+                                recorded = None;
+                            }
+                        }
+                    }
+                    Err(_) => recorded = None,
+                }
+                yield item;
+            }
+            if let Some(buf) = recorded {
+                if is_complete(&buf) {
+                    cache.put(key, &model, Bytes::from(buf), &config);
+                }
+            }
+        }
+    }
+
+    fn insert_memory(&self, key: String, body: Bytes, created_at: i64, expires_at: i64, max_entries: usize) {
+        if max_entries == 0 {
+            return;
+        }
+        if self.memory.len() >= max_entries {
+            let now = chrono::Utc::now().timestamp();
+            self.memory.retain(|_, entry| entry.expires_at > now);
+        }
+        while self.memory.len() >= max_entries {
+            let oldest = self
+                .memory
+                .iter()
+                .min_by_key(|entry| entry.created_at)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(k) => self.memory.remove(&k),
+                None => break,
+            };
+        }
+        self.memory.insert(key, CachedEntry { body, created_at, expires_at });
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+}
+
+/// 将缓存的原始响应还原为上游字节流，供各协议的流式转换器复用
+pub fn replay_stream(body: Bytes) -> Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> {
+    Box::pin(futures::stream::once(async move { Ok(body) }))
+}
+
+/// temperature 显式为 0 的请求才视为确定性请求
+fn is_deterministic(upstream_body: &Value) -> bool {
+    upstream_body
+        .get("request")
+        .and_then(|r| r.get("generationConfig"))
+        .and_then(|g| g.get("temperature"))
+        .and_then(|t| t.as_f64())
+        .is_some_and(|t| t == 0.0)
+}
+
+/// 只缓存正常结束的响应，避免把中途断开的流写入缓存
+fn is_complete(body: &[u8]) -> bool {
+    const MARKER: &[u8] = b"\"finishReason\"";
+    body.windows(MARKER.len()).any(|w| w == MARKER)
+}
+
+/// 按键名排序写入哈希，保证与 JSON 字段顺序无关
+fn write_canonical(value: &Value, hasher: &mut Sha256) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            hasher.update(b"{");
+            for key in keys {
+                hasher.update(key.as_bytes());
+                hasher.update(b":");
+                write_canonical(&map[key], hasher);
+                hasher.update(b",");
+            }
+            hasher.update(b"}");
+        }
+        Value::Array(items) => {
+            hasher.update(b"[");
+            for item in items {
+                write_canonical(item, hasher);
+                hasher.update(b",");
+            }
+            hasher.update(b"]");
+        }
+        other => hasher.update(other.to_string().as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> ResponseCacheConfig {
+        ResponseCacheConfig {
+            enabled: true,
+            persist: false,
+            ..Default::default()
+        }
+    }
+
+    fn body(project: &str, temperature: f64) -> Value {
+        json!({
+            "project": project,
+            "requestId": format!("agent-{}", project),
+            "model": "gemini-2.5-flash",
+            "request": {
+                "contents": [{"role": "user", "parts": [{"text": "2+2?"}]}],
+                "generationConfig": {"temperature": temperature, "maxOutputTokens": 64}
+            }
+        })
+    }
+
+    #[test]
+    fn key_ignores_volatile_fields_and_requires_determinism() {
+        let headers = HeaderMap::new();
+        let a = ResponseCache::key_for(&config(), "claude-sonnet-4-5", &headers, &body("p1", 0.0), true).unwrap();
+        let b = ResponseCache::key_for(&config(), "claude-sonnet-4-5", &headers, &body("p2", 0.0), true).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, ResponseCache::key_for(&config(), "claude-sonnet-4-5", &headers, &body("p1", 0.0), false).unwrap());
+        assert!(ResponseCache::key_for(&config(), "claude-sonnet-4-5", &headers, &body("p1", 0.7), true).is_none());
+
+        let mut bypass = HeaderMap::new();
+        bypass.insert(BYPASS_HEADER, "bypass".parse().unwrap());
+        assert!(ResponseCache::key_for(&config(), "claude-sonnet-4-5", &bypass, &body("p1", 0.0), true).is_none());
+
+        let scoped = ResponseCacheConfig {
+            models: vec!["gpt-*".to_string()],
+            ..config()
+        };
+        assert!(ResponseCache::key_for(&scoped, "claude-sonnet-4-5", &headers, &body("p1", 0.0), true).is_none());
+        assert!(ResponseCache::key_for(&scoped, "gpt-4o", &headers, &body("p1", 0.0), true).is_some());
+    }
+
+    #[tokio::test]
+    async fn record_stores_only_complete_streams() {
+        let cache = Arc::new(ResponseCache::new());
+        let chunks: Vec<Result<Bytes, String>> = vec![
+            Ok(Bytes::from("data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"4\"}]},")),
+            Ok(Bytes::from("\"finishReason\":\"STOP\"}]}}\n\n")),
+        ];
+        let out: Vec<_> = cache
+            .record(futures::stream::iter(chunks), "k1".to_string(), "m".to_string(), config())
+            .collect()
+            .await;
+        assert_eq!(out.len(), 2);
+        assert!(cache.get("k1", &config()).await.is_some_and(|b| b.ends_with(b"}}\n\n")));
+
+        let broken: Vec<Result<Bytes, String>> = vec![Ok(Bytes::from("data: {\"candidates\":[")), Err("reset".to_string())];
+        let _: Vec<_> = cache
+            .record(futures::stream::iter(broken), "k2".to_string(), "m".to_string(), config())
+            .collect()
+            .await;
+        assert!(cache.get("k2", &config()).await.is_none());
+    }
+
+    #[test]
+    fn memory_tier_is_bounded() {
+        let cache = ResponseCache::new();
+        let cfg = ResponseCacheConfig { max_entries: 2, ..config() };
+        for i in 0..5 {
+            cache.put(format!("k{}", i), "m", Bytes::from("x"), &cfg);
+        }
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -31,6 +31,9 @@ pub struct AppState {
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
     pub config: Arc<RwLock<crate::models::config::AppConfig>>,
+    pub latency: Arc<crate::proxy::hedging::LatencyTracker>, // 请求耗时统计 (对冲延迟)
+    pub prompt_cache: Arc<crate::proxy::prompt_cache::PromptCacheTracker>, // cache_control 前缀缓存所在账号
+    pub response_cache: Arc<crate::proxy::response_cache::ResponseCache>, // 确定性请求的响应缓存
 }
 
 /// Axum 服务器实例
@@ -41,12 +44,16 @@ pub struct AxumServer {
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
     config_state: Arc<RwLock<crate::models::config::AppConfig>>,
+    upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
 }
 
 impl AxumServer {
     pub async fn update_config(&self, proxy_config: &crate::proxy::config::ProxyConfig) {
         let mut config = self.config_state.write().await;
         config.proxy = proxy_config.clone();
+        self.upstream
+            .circuit_breakers()
+            .update_config(proxy_config.circuit_breaker.clone());
         tracing::info!("代理配置已热更新 (包括 log_stream_content 等字段)");
     }
 
@@ -76,20 +83,30 @@ impl AxumServer {
         *zai = config.zai.clone();
         tracing::info!("z.ai 配置已热更新");
     }
-    /// 启动 Axum 服务器
+
+    /// 上游客户端 (供后台任务复用)
+    pub fn upstream(&self) -> Arc<crate::proxy::upstream::client::UpstreamClient> {
+        self.upstream.clone()
+    }
+
+    /// 上游端点熔断器状态
+    pub fn endpoint_circuits(&self) -> Vec<crate::proxy::circuit_breaker::CircuitSnapshot> {
+        self.upstream.circuit_breakers().snapshot()
+    }
+
+    /// 启动 Axum 服务器 (监听地址、映射、上游代理等均取自 `app_config.proxy`)
     pub async fn start(
-        host: String,
-        port: u16,
         token_manager: Arc<TokenManager>,
-        custom_mapping: std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>,
-        _request_timeout: u64,
-        upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
-        security_config: crate::proxy::ProxySecurityConfig,
-        zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
-        experimental_config: crate::proxy::config::ExperimentalConfig,
         app_config: crate::models::config::AppConfig,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
+        let host = app_config.proxy.get_bind_address().to_string();
+        let port = app_config.proxy.port;
+        let custom_mapping = app_config.proxy.custom_mapping.clone();
+        let upstream_proxy = app_config.proxy.upstream_proxy.clone();
+        let security_config = crate::proxy::ProxySecurityConfig::from_proxy_config(&app_config.proxy);
+        let zai_config = app_config.proxy.zai.clone();
+        let experimental_config = app_config.proxy.experimental.clone();
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
@@ -98,6 +115,13 @@ impl AxumServer {
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let upstream = Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
+	            upstream_proxy.clone(),
+	        )));
+	        upstream
+	            .circuit_breakers()
+	            .update_config(app_config.proxy.circuit_breaker.clone());
+	        upstream.circuit_breakers().set_listener(monitor.circuit_listener());
 	        let config_state = Arc::new(RwLock::new(app_config));
 
 	        let state = AppState {
@@ -108,15 +132,16 @@ impl AxumServer {
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: upstream.clone(),
             zai: zai_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state,
             config: config_state.clone(),
+            latency: Arc::new(crate::proxy::hedging::LatencyTracker::new()),
+            prompt_cache: Arc::new(crate::proxy::prompt_cache::PromptCacheTracker::new()),
+            response_cache: Arc::new(crate::proxy::response_cache::ResponseCache::new()),
         };
 
 
@@ -182,6 +207,7 @@ impl AxumServer {
                 post(handlers::gemini::handle_count_tokens),
             ) // Specific route priority
             .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+            .route("/v1/models/route", post(handlers::common::handle_explain_route))
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
@@ -213,6 +239,7 @@ impl AxumServer {
             security_state,
             zai_state,
             config_state: config_state.clone(),
+            upstream,
         };
 
         // 在新任务中启动服务器
@@ -5,6 +5,8 @@ use std::time::{Duration, SystemTime};
 // Node.js proxy uses 2 hours TTL
 const SIGNATURE_TTL: Duration = Duration::from_secs(2 * 60 * 60);
 const MIN_SIGNATURE_LENGTH: usize = 50;
+/// Upper bound per layer; oldest entries are evicted beyond this
+const MAX_ENTRIES: usize = 1000;
 
 /// Cache entry with timestamp for TTL
 #[derive(Clone, Debug)]
@@ -26,9 +28,27 @@ impl<T> CacheEntry<T> {
     }
 }
 
-/// Double-layer signature cache to handle:
+/// Insert into a layer, dropping expired entries and then the oldest ones once it grows past `MAX_ENTRIES`
+fn insert_bounded(cache: &mut HashMap<String, CacheEntry<String>>, key: String, value: String) {
+    cache.insert(key, CacheEntry::new(value));
+    if cache.len() <= MAX_ENTRIES {
+        return;
+    }
+    cache.retain(|_, v| !v.is_expired());
+    if cache.len() > MAX_ENTRIES {
+        let mut by_age: Vec<(String, SystemTime)> = cache.iter().map(|(k, v)| (k.clone(), v.timestamp)).collect();
+        by_age.sort_by_key(|(_, ts)| *ts);
+        let excess = cache.len() - MAX_ENTRIES;
+        for (key, _) in by_age.into_iter().take(excess) {
+            cache.remove(&key);
+        }
+    }
+}
+
+/// Layered signature cache to handle:
 /// 1. Signature recovery for tool calls (when clients strip them)
 /// 2. Cross-model compatibility checks (preventing Claude signatures on Gemini models)
+/// 3. Per-session fallback signature, so one client's signature is never replayed into another session
 pub struct SignatureCache {
     /// Layer 1: Tool Use ID -> Thinking Signature
     /// Key: tool_use_id (e.g., "toolu_01...")
@@ -39,6 +59,11 @@ pub struct SignatureCache {
     /// Key: thought signature string
     /// Value: Model family identifier (e.g., "claude-3-5-sonnet", "gemini-2.0-flash")
     thinking_families: Mutex<HashMap<String, CacheEntry<String>>>,
+
+    /// Layer 3: Session ID -> Latest Thought Signature
+    /// Key: session fingerprint from `SessionManager`
+    /// Value: The most recent signature produced for that session
+    session_signatures: Mutex<HashMap<String, CacheEntry<String>>>,
 }
 
 impl SignatureCache {
@@ -46,6 +71,7 @@ impl SignatureCache {
         Self {
             tool_signatures: Mutex::new(HashMap::new()),
             thinking_families: Mutex::new(HashMap::new()),
+            session_signatures: Mutex::new(HashMap::new()),
         }
     }
 
@@ -63,13 +89,7 @@ impl SignatureCache {
         
         if let Ok(mut cache) = self.tool_signatures.lock() {
             tracing::debug!("[SignatureCache] Caching tool signature for id: {}", tool_use_id);
-            cache.insert(tool_use_id.to_string(), CacheEntry::new(signature));
-            
-            // Clean up expired entries occasionally (simple approach: unexpected check)
-            // In a production system we might want a dedicated background task
-            if cache.len() > 1000 {
-                cache.retain(|_, v| !v.is_expired());
-            }
+            insert_bounded(&mut cache, tool_use_id.to_string(), signature);
         }
     }
 
@@ -94,11 +114,7 @@ impl SignatureCache {
 
         if let Ok(mut cache) = self.thinking_families.lock() {
             tracing::debug!("[SignatureCache] Caching thinking family for sig (len={}): {}", signature.len(), family);
-            cache.insert(signature, CacheEntry::new(family));
-            
-            if cache.len() > 1000 {
-                cache.retain(|_, v| !v.is_expired());
-            }
+            insert_bounded(&mut cache, signature, family);
         }
     }
 
@@ -116,6 +132,30 @@ impl SignatureCache {
         None
     }
 
+    /// Store the latest signature seen for a session (newer turns replace older ones)
+    pub fn cache_session_signature(&self, session_id: &str, signature: &str) {
+        if signature.len() < MIN_SIGNATURE_LENGTH || session_id.is_empty() {
+            return;
+        }
+
+        if let Ok(mut cache) = self.session_signatures.lock() {
+            tracing::debug!("[SignatureCache] Caching session signature for {} (len={})", session_id, signature.len());
+            insert_bounded(&mut cache, session_id.to_string(), signature.to_string());
+        }
+    }
+
+    /// Retrieve the latest signature for a session
+    pub fn get_session_signature(&self, session_id: &str) -> Option<String> {
+        if let Ok(cache) = self.session_signatures.lock() {
+            if let Some(entry) = cache.get(session_id) {
+                if !entry.is_expired() {
+                    return Some(entry.data.clone());
+                }
+            }
+        }
+        None
+    }
+
     /// Clear all caches (for testing or manual reset)
     pub fn clear(&self) {
         if let Ok(mut cache) = self.tool_signatures.lock() {
@@ -124,6 +164,9 @@ impl SignatureCache {
         if let Ok(mut cache) = self.thinking_families.lock() {
             cache.clear();
         }
+        if let Ok(mut cache) = self.session_signatures.lock() {
+            cache.clear();
+        }
     }
 }
 
@@ -157,4 +200,35 @@ mod tests {
         cache.cache_thinking_family(sig.clone(), "claude".to_string());
         assert_eq!(cache.get_signature_family(&sig), Some("claude".to_string()));
     }
+
+    #[test]
+    fn test_session_signatures_are_isolated() {
+        let cache = SignatureCache::new();
+        let sig_a = "a".repeat(60);
+        let sig_b = "b".repeat(80);
+
+        cache.cache_session_signature("sid-a", &sig_a);
+        cache.cache_session_signature("sid-b", &sig_b);
+        assert_eq!(cache.get_session_signature("sid-a"), Some(sig_a));
+        assert_eq!(cache.get_session_signature("sid-b"), Some(sig_b));
+        assert_eq!(cache.get_session_signature("sid-c"), None);
+
+        // Newer signature replaces the older one even when shorter
+        let newer = "c".repeat(55);
+        cache.cache_session_signature("sid-b", &newer);
+        assert_eq!(cache.get_session_signature("sid-b"), Some(newer));
+    }
+
+    #[test]
+    fn test_size_bound_evicts_oldest() {
+        let cache = SignatureCache::new();
+        let sig = "z".repeat(60);
+        for i in 0..MAX_ENTRIES + 5 {
+            cache.cache_session_signature(&format!("sid-{}", i), &sig);
+            sleep(Duration::from_nanos(1));
+        }
+        let len = cache.session_signatures.lock().unwrap().len();
+        assert_eq!(len, MAX_ENTRIES);
+        assert!(cache.get_session_signature(&format!("sid-{}", MAX_ENTRIES + 4)).is_some());
+    }
 }
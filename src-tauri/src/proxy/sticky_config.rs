@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// 调度模式枚举
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -9,6 +10,9 @@ pub enum SchedulingMode {
     Balance,
     /// 性能优先 (Performance-first): 纯轮询模式 (Round-robin)，账号负载最均衡，但不利用缓存
     PerformanceFirst,
+    /// 配额预测 (Quota-predictive): 根据配额重置时间与消耗速率，优先消耗即将重置的账号，
+    /// 并为重置时间较远的账号保留储备
+    QuotaPredictive,
 }
 
 impl Default for SchedulingMode {
@@ -17,6 +21,79 @@ impl Default for SchedulingMode {
     }
 }
 
+/// 配额预测调度参数 (仅在 QuotaPredictive 模式下生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictiveConfig {
+    /// 估算消耗速率时回溯的请求历史窗口 (分钟)
+    #[serde(default = "default_lookback_minutes")]
+    pub lookback_minutes: u64,
+    /// 单次请求平均消耗的配额占比 (0.01 = 1%)
+    #[serde(default = "default_request_cost")]
+    pub request_cost: f64,
+    /// 重置时间超过该小时数的账号视为 "远期重置"
+    #[serde(default = "default_reserve_horizon_hours")]
+    pub reserve_horizon_hours: f64,
+    /// 远期重置账号的储备配额线，低于此值时仅作兜底使用
+    #[serde(default = "default_reserve_fraction")]
+    pub reserve_fraction: f64,
+}
+
+impl Default for PredictiveConfig {
+    fn default() -> Self {
+        Self {
+            lookback_minutes: default_lookback_minutes(),
+            request_cost: default_request_cost(),
+            reserve_horizon_hours: default_reserve_horizon_hours(),
+            reserve_fraction: default_reserve_fraction(),
+        }
+    }
+}
+
+fn default_lookback_minutes() -> u64 {
+    60
+}
+
+fn default_request_cost() -> f64 {
+    0.005
+}
+
+fn default_reserve_horizon_hours() -> f64 {
+    24.0
+}
+
+fn default_reserve_fraction() -> f64 {
+    0.2
+}
+
+/// 并发限制配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcurrencyConfig {
+    /// 每个账号的最大并发请求数 (0 表示不限制)
+    #[serde(default)]
+    pub max_per_account: usize,
+    /// 每个账号下各模型族的最大并发请求数
+    /// Key: 模型族 ("claude" | "gemini-pro" | "gemini-flash" | "gemini-image" | "other")
+    #[serde(default)]
+    pub max_per_model_family: HashMap<String, usize>,
+    /// 槽位已满时的排队超时时间 (秒)；缓存优先模式下改用 `max_wait_seconds`
+    #[serde(default = "default_queue_timeout_seconds")]
+    pub queue_timeout_seconds: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_per_account: 0,
+            max_per_model_family: HashMap::new(),
+            queue_timeout_seconds: default_queue_timeout_seconds(),
+        }
+    }
+}
+
+fn default_queue_timeout_seconds() -> u64 {
+    30
+}
+
 /// 粘性会话配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StickySessionConfig {
@@ -24,6 +101,12 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 配额预测调度参数
+    #[serde(default)]
+    pub predictive: PredictiveConfig,
+    /// 并发限制与排队
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +114,8 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            predictive: PredictiveConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
         }
     }
 }
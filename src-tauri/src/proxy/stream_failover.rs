@@ -0,0 +1,365 @@
+// 流式断线续传 (Streaming failover)
+//
+// 上游 SSE 流中途断开时，将已输出给客户端的正文作为 assistant prefill 追加到原请求末尾，
+// 在号池中的另一个账号上重新发起请求，并把续写内容拼接到同一个客户端流中。
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+
+/// 上游 Gemini SSE 字节流
+pub type GeminiByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// 续传成功后的新上游流
+pub struct ResumedStream {
+    pub stream: GeminiByteStream,
+    pub email: String,
+}
+
+pub type ResumeFuture = Pin<Box<dyn Future<Output = Option<ResumedStream>> + Send>>;
+
+/// 续传回调: 接收已输出的正文，返回新账号上的上游流 (无法续传时返回 None)
+pub type StreamResumer = Box<dyn FnMut(String) -> ResumeFuture + Send>;
+
+/// 续传请求所需的调度上下文
+pub struct ResumeContext {
+    pub token_manager: Arc<TokenManager>,
+    pub upstream: Arc<UpstreamClient>,
+    pub request_type: String,
+    /// 用于账号选择与并发槽位的模型名
+    pub model: String,
+    pub quota_threshold: f64,
+    pub quota_priority: bool,
+    pub max_resumes: usize,
+    pub trace_id: String,
+}
+
+/// 构建续传回调
+///
+/// `build_body(partial, project_id)` 负责将已输出的正文作为 prefill 追加到原请求，
+/// 并生成对应账号的 v1internal 请求体。
+pub fn build_resumer<F>(ctx: ResumeContext, broken_email: String, build_body: F) -> StreamResumer
+where
+    F: Fn(&str, &str) -> Result<Value, String> + Send + Sync + 'static,
+{
+    let ctx = Arc::new(ctx);
+    let build_body = Arc::new(build_body);
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let last_email = Arc::new(Mutex::new(broken_email));
+
+    Box::new(move |partial: String| {
+        let ctx = ctx.clone();
+        let build_body = build_body.clone();
+        let attempts = attempts.clone();
+        let last_email = last_email.clone();
+
+        Box::pin(async move {
+            if attempts.fetch_add(1, Ordering::SeqCst) >= ctx.max_resumes {
+                tracing::warn!("[{}] Stream failover limit ({}) reached", ctx.trace_id, ctx.max_resumes);
+                return None;
+            }
+
+            // 轮换到与断流账号不同的账号
+            let broken = last_email.lock().unwrap().clone();
+            let mut selected = None;
+            for _ in 0..2 {
+                match ctx
+                    .token_manager
+                    .get_token(&ctx.request_type, Some(&ctx.model), ctx.quota_threshold, true, None, ctx.quota_priority)
+                    .await
+                {
+                    Ok(token) if token.2 != broken => {
+                        selected = Some(token);
+                        break;
+                    }
+                    Ok(_) => continue,
+                    Err(e) => {
+                        tracing::warn!("[{}] Stream failover: no account available: {}", ctx.trace_id, e);
+                        return None;
+                    }
+                }
+            }
+            let Some((access_token, project_id, email)) = selected else {
+                tracing::warn!("[{}] Stream failover: no alternative account to resume on", ctx.trace_id);
+                return None;
+            };
+
+            let slot = match ctx.token_manager.acquire_slot(&email, &ctx.model).await {
+                Ok(slot) => slot,
+                Err(e) => {
+                    tracing::warn!("[{}] Stream failover: {}", ctx.trace_id, e);
+                    return None;
+                }
+            };
+
+            let body = match build_body(&partial, &project_id) {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::warn!("[{}] Stream failover: failed to build resume request: {}", ctx.trace_id, e);
+                    return None;
+                }
+            };
+
+            let response = match ctx
+                .upstream
+                .call_v1_internal("streamGenerateContent", &access_token, body, Some("alt=sse"))
+                .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    tracing::warn!("[{}] Stream failover request failed: {}", ctx.trace_id, e);
+                    return None;
+                }
+            };
+
+            let status = response.status();
+            if !status.is_success() {
+                let status_code = status.as_u16();
+                let retry_after = response
+                    .headers()
+                    .get("Retry-After")
+                    .and_then(|h| h.to_str().ok())
+                    .map(|s| s.to_string());
+                let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
+                ctx.token_manager.record_upstream_error(&email, status_code, &error_text);
+                if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
+                    ctx.token_manager
+                        .mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&ctx.model))
+                        .await;
+                }
+                tracing::warn!("[{}] Stream failover on {} returned HTTP {}", ctx.trace_id, email, status_code);
+                return None;
+            }
+
+            ctx.token_manager.mark_account_success(&email);
+            *last_email.lock().unwrap() = email.clone();
+            tracing::info!(
+                "[{}] ↻ Resuming broken stream on account {} ({} chars of prefill)",
+                ctx.trace_id,
+                email,
+                partial.chars().count()
+            );
+
+            let stream = crate::proxy::concurrency::guard_stream(response.bytes_stream(), slot);
+            Some(ResumedStream {
+                stream: Box::pin(stream),
+                email,
+            })
+        })
+    })
+}
+
+/// 为上游字节流加上断线续传 (用于在转换前拼接续写内容的协议，如 OpenAI)
+///
+/// 跟踪第一个候选结果已输出的正文与结束原因；断流时以已输出正文调用 `resumer`，
+/// 续写阶段丢弃新账号重新产生的思考内容，直到出现正文或工具调用。
+/// 已输出过工具调用的流不再续传 (续写会让客户端重复收到同一工具调用)，与 Claude 流一致。
+/// 只跟踪 index 0 的候选结果，多候选 (n > 1) 请求不应使用。
+pub fn resumable_stream(stream: GeminiByteStream, mut resumer: StreamResumer) -> GeminiByteStream {
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let mut buffer = BytesMut::new();
+        let mut progress = StreamProgress::default();
+
+        'upstream: loop {
+            let mut stream_error = None;
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(bytes) => {
+                        buffer.extend_from_slice(&bytes);
+                        let mut out = BytesMut::new();
+                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                            let line = buffer.split_to(pos + 1);
+                            if let Some(line) = track_line(&line, &mut progress) {
+                                out.extend_from_slice(&line);
+                            }
+                        }
+                        if !out.is_empty() {
+                            yield Ok(out.freeze());
+                        }
+                    }
+                    Err(e) => {
+                        stream_error = Some(e);
+                        break;
+                    }
+                }
+            }
+
+            // 断流判定: 传输错误，或已开始输出但未收到结束原因
+            let broken = !progress.finished && (stream_error.is_some() || !progress.emitted_text.is_empty());
+            if broken && progress.used_tool {
+                tracing::warn!("[StreamFailover] Upstream stream broken after a tool call, not resuming");
+            } else if broken {
+                tracing::warn!(
+                    "[StreamFailover] Upstream stream broken ({}), attempting failover",
+                    stream_error.as_ref().map(|e| e.to_string()).unwrap_or_else(|| "unexpected end of stream".to_string())
+                );
+                if let Some(resumed) = resumer(progress.emitted_text.clone()).await {
+                    stream = resumed.stream;
+                    buffer.clear();
+                    progress.resuming = true;
+                    continue 'upstream;
+                }
+            }
+
+            match stream_error {
+                Some(e) => yield Err(e),
+                None if !buffer.is_empty() => yield Ok(buffer.split().freeze()),
+                None => {}
+            }
+            break;
+        }
+    })
+}
+
+/// 已向客户端输出的进度 (仅 index 0 的候选结果)
+#[derive(Default)]
+struct StreamProgress {
+    /// 已输出的正文 (不含思考内容)
+    emitted_text: String,
+    /// 是否已收到结束原因
+    finished: bool,
+    /// 是否已输出工具调用
+    used_tool: bool,
+    /// 续写阶段: 尚未出现正文或工具调用
+    resuming: bool,
+}
+
+/// 记录单行 SSE 事件的输出进度；续写阶段改写或丢弃仅含思考内容的事件
+fn track_line(line: &[u8], progress: &mut StreamProgress) -> Option<Bytes> {
+    let Some(payload) = std::str::from_utf8(line)
+        .ok()
+        .and_then(|text| text.trim().strip_prefix("data:"))
+        .map(str::trim)
+    else {
+        return Some(Bytes::copy_from_slice(line));
+    };
+    let Ok(mut event) = serde_json::from_str::<Value>(payload) else {
+        return Some(Bytes::copy_from_slice(line));
+    };
+
+    let raw = match event.get_mut("response") {
+        Some(inner) => inner,
+        None => &mut event,
+    };
+    let Some(candidate) = raw
+        .get_mut("candidates")
+        .and_then(|c| c.as_array_mut())
+        .and_then(|c| c.first_mut())
+    else {
+        return Some(Bytes::copy_from_slice(line));
+    };
+
+    let has_finish = candidate.get("finishReason").is_some();
+    let parts = candidate
+        .get_mut("content")
+        .and_then(|c| c.get_mut("parts"))
+        .and_then(|p| p.as_array_mut());
+    let mut rewritten = false;
+    if let Some(parts) = parts {
+        if progress.resuming {
+            let before = parts.len();
+            parts.retain(|part| !part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false));
+            rewritten = parts.len() != before;
+            let has_output = parts.iter().any(|part| {
+                part.get("text").and_then(|t| t.as_str()).is_some_and(|t| !t.is_empty())
+                    || part.get("functionCall").is_some()
+            });
+            if !has_output && !has_finish {
+                return None;
+            }
+            progress.resuming = false;
+        }
+        for part in parts.iter() {
+            let is_thought = part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false);
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()).filter(|_| !is_thought) {
+                progress.emitted_text.push_str(text);
+            }
+            if part.get("functionCall").is_some() {
+                progress.used_tool = true;
+            }
+        }
+    } else if progress.resuming && !has_finish {
+        return None;
+    }
+    if has_finish {
+        progress.finished = true;
+    }
+
+    if rewritten {
+        Some(Bytes::from(format!("data: {}\n\n", event)))
+    } else {
+        Some(Bytes::copy_from_slice(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(chunks: &[&str]) -> GeminiByteStream {
+        let items: Vec<Result<Bytes, reqwest::Error>> =
+            chunks.iter().map(|c| Ok(Bytes::from(format!("data: {}\n\n", c)))).collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    #[tokio::test]
+    async fn test_resumable_stream_continues_with_prefill() {
+        let prefills = Arc::new(Mutex::new(Vec::new()));
+        let seen = prefills.clone();
+        let resumer: StreamResumer = Box::new(move |partial: String| {
+            seen.lock().unwrap().push(partial);
+            Box::pin(async {
+                Some(ResumedStream {
+                    stream: upstream(&[
+                        r#"{"candidates":[{"content":{"parts":[{"text":"thinking","thought":true}]}}]}"#,
+                        r#"{"candidates":[{"content":{"parts":[{"text":"again","thought":true},{"text":" world"}]},"finishReason":"STOP"}]}"#,
+                    ]),
+                    email: "b@example.com".to_string(),
+                })
+            })
+        });
+
+        // 首个上游输出部分正文后未收到 finishReason 即结束
+        let first = upstream(&[r#"{"response":{"candidates":[{"content":{"parts":[{"text":"Hello"}]}}]}}"#]);
+        let bytes: Vec<u8> = resumable_stream(first, resumer)
+            .map(|item| item.unwrap().to_vec())
+            .concat()
+            .await;
+        let output = String::from_utf8(bytes).unwrap();
+
+        assert_eq!(prefills.lock().unwrap().as_slice(), ["Hello"]);
+        assert!(output.contains("Hello"));
+        assert!(output.contains(" world"));
+        assert!(!output.contains("thinking"));
+        assert!(!output.contains("again"));
+    }
+
+    #[tokio::test]
+    async fn test_no_resume_after_tool_call() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let resumer: StreamResumer = Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { None })
+        });
+
+        let first = upstream(&[
+            r#"{"candidates":[{"content":{"parts":[{"text":"Let me check"}]}}]}"#,
+            r#"{"candidates":[{"content":{"parts":[{"functionCall":{"name":"ls","args":{}}}]}}]}"#,
+        ]);
+        let bytes: Vec<u8> = resumable_stream(first, resumer)
+            .map(|item| item.unwrap().to_vec())
+            .concat()
+            .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(String::from_utf8(bytes).unwrap().contains("functionCall"));
+    }
+}
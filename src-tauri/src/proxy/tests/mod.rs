@@ -1,2 +1,3 @@
 pub mod comprehensive;
 pub mod model_chain_tests;
+pub mod trace_replay_tests;
@@ -40,6 +40,19 @@ mod tests {
         assert_eq!(chain, vec!["gemini-pro", "gemini-flash"]);
     }
 
+    #[test]
+    fn test_overlapping_wildcards_prefer_most_specific() {
+        let mut mapping = HashMap::new();
+        mapping.insert("gpt-*".to_string(), ModelMappingTarget::Single("gemini-flash".to_string()));
+        mapping.insert("gpt-4*".to_string(), ModelMappingTarget::Single("gemini-pro".to_string()));
+        mapping.insert("*-turbo".to_string(), ModelMappingTarget::Single("gemini-lite".to_string()));
+
+        // "gpt-4*" 与 "*-turbo" 同样具体 (5 个非通配字符)，按字典序取 "*-turbo"
+        assert_eq!(resolve_model_chain("gpt-4-turbo", &mapping), vec!["gemini-lite"]);
+        assert_eq!(resolve_model_chain("gpt-4o", &mapping), vec!["gemini-pro"]);
+        assert_eq!(resolve_model_chain("gpt-3.5", &mapping), vec!["gemini-flash"]);
+    }
+
     #[test]
     fn test_default_fallback() {
         let mapping = HashMap::new();
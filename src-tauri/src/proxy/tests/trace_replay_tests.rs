@@ -0,0 +1,36 @@
+// 协议转换回归测试: 回放 fixtures/traces 下的抓取文件
+// 新增夹具: 开启 trace_capture 抓取问题请求，确认行为修复后将抓取文件复制到该目录
+#[cfg(test)]
+mod tests {
+    use crate::proxy::trace_capture::{replay, TraceFile};
+    use std::path::PathBuf;
+
+    fn fixture_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/proxy/tests/fixtures/traces")
+    }
+
+    #[tokio::test]
+    async fn test_recorded_traces_replay_without_differences() {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(fixture_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no trace fixtures found");
+
+        let mut failures = Vec::new();
+        for path in &paths {
+            let trace = TraceFile::load(path).unwrap();
+            let report = replay(&trace).await.unwrap();
+            if !report.is_clean() {
+                failures.push(format!(
+                    "{}:\n{}",
+                    path.display(),
+                    report.request_diff.iter().chain(&report.response_diff).cloned().collect::<Vec<_>>().join("\n")
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "mapper output changed:\n{}", failures.join("\n\n"));
+    }
+}
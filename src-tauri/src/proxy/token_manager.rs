@@ -5,10 +5,23 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::proxy::concurrency::{ConcurrencyLimiter, ConcurrencySlot, QueueStats};
+use crate::proxy::circuit_breaker::CircuitBreakerRegistry;
+use crate::proxy::account_health::{AccountHealthRegistry, HealthOutcome};
 use crate::proxy::rate_limit::RateLimitTracker;
 use crate::proxy::sticky_config::StickySessionConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::models::Account;
+use crate::modules::account_store;
+use crate::proxy::events::{self, ProxyEvent};
 
-#[derive(Debug, Clone)]
+/// 健康探测使用的模型 (最便宜的文本模型)
+const HEALTH_PROBE_MODEL: &str = "gemini-2.5-flash-lite";
+
+/// 模型 -> (查询时间, 账号请求速率 email -> 次/小时)
+type RequestRateCache = DashMap<String, (std::time::Instant, HashMap<String, f64>)>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProxyToken {
     pub account_id: String,
     pub access_token: String,
@@ -16,10 +29,39 @@ pub struct ProxyToken {
     pub expires_in: i64,
     pub timestamp: i64,
     pub email: String,
-    pub account_path: PathBuf,  // 账号文件路径，用于更新
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
     pub model_quotas: HashMap<String, f64>, // 新增: 模型名称 -> 剩余百分比 (0.0-1.0)
+    pub model_reset_times: HashMap<String, i64>, // 模型名称 -> 配额重置时间 (Unix 秒)
+}
+
+/// 账号池增量同步结果
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AccountSyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+impl AccountSyncReport {
+    pub fn has_changes(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+}
+
+/// 单次 Token 选择的参数
+struct TokenQuery<'a> {
+    quota_group: &'a str,
+    model_name: Option<&'a str>,
+    quota_threshold: f64,
+    force_rotate: bool,
+    session_id: Option<&'a str>,
+    quota_priority_enabled: bool,
+    /// 限定的账号组 (邮箱列表)
+    accounts: Option<&'a [String]>,
+    /// 调度模拟: 走完整选择逻辑但不修改任何状态 (不绑定会话、不推进轮询、不占用探测名额、不刷新 token)
+    dry_run: bool,
 }
 
 pub struct TokenManager {
@@ -30,6 +72,10 @@ pub struct TokenManager {
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
     session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    request_rates: Arc<RequestRateCache>, // 请求速率缓存 (配额预测调度)
+    concurrency: Arc<ConcurrencyLimiter>, // 并发限制 (按账号 email 计数)
+    circuit_breakers: Arc<CircuitBreakerRegistry>, // 账号熔断器 (按账号 email)
+    account_health: Arc<AccountHealthRegistry>, // 账号健康度与隔离 (按账号 email)
 }
 
 impl TokenManager {
@@ -43,153 +89,151 @@ impl TokenManager {
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
+            request_rates: Arc::new(DashMap::new()),
+            concurrency: Arc::new(ConcurrencyLimiter::new(Default::default())),
+            circuit_breakers: Arc::new(CircuitBreakerRegistry::new("account", Default::default())),
+            account_health: Arc::new(AccountHealthRegistry::new(Default::default())),
         }
     }
     
-    /// 从主应用账号目录加载所有账号
+    /// 从账号存储加载所有账号 (增量同步，返回当前账号池大小)
     pub async fn load_accounts(&self) -> Result<usize, String> {
-        let accounts_dir = self.data_dir.join("accounts");
-        
-        if !accounts_dir.exists() {
-            return Err(format!("账号目录不存在: {:?}", accounts_dir));
-        }
+        self.sync_accounts().await?;
+        Ok(self.tokens.len())
+    }
 
-        // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
-        self.tokens.clear();
-        self.current_index.store(0, Ordering::SeqCst);
-        {
-            let mut last_used = self.last_used_account.lock().await;
-            *last_used = None;
-        }
-        
-        let entries = std::fs::read_dir(&accounts_dir)
-            .map_err(|e| format!("读取账号目录失败: {}", e))?;
-        
-        let mut count = 0;
-        
-        for entry in entries {
-            let entry = entry.map_err(|e| format!("读取目录项失败: {}", e))?;
-            let path = entry.path();
-            
-            if path.extension().and_then(|s| s.to_str()) != Some("json") {
-                continue;
-            }
-            
-            // 尝试加载账号
-            match self.load_single_account(&path).await {
+    /// 将账号池与账号存储做增量同步
+    ///
+    /// 只新增/替换/移除有变化的账号，未变化账号的会话绑定、限流与轮询位置保持不变。
+    pub async fn sync_accounts(&self) -> Result<AccountSyncReport, String> {
+        let data_dir = self.data_dir.clone();
+        let listing = tokio::task::spawn_blocking(move || account_store::for_dir(&data_dir)?.list())
+            .await
+            .map_err(|e| format!("账号读取任务失败: {}", e))??;
+
+        let mut desired: HashMap<String, ProxyToken> = HashMap::new();
+        for account in &listing.accounts {
+            match Self::proxy_token_from_account(account) {
                 Ok(Some(token)) => {
-                    let account_id = token.account_id.clone();
-                    self.tokens.insert(account_id, token);
-                    count += 1;
+                    desired.insert(token.account_id.clone(), token);
                 },
                 Ok(None) => {
                     // 跳过无效账号
                 },
                 Err(e) => {
-                    tracing::debug!("加载账号失败 {:?}: {}", path, e);
+                    tracing::debug!("加载账号失败 {}: {}", account.email, e);
                 }
             }
         }
-        
-        Ok(count)
-    }
-    
-    /// 加载单个账号
-    async fn load_single_account(&self, path: &PathBuf) -> Result<Option<ProxyToken>, String> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| format!("读取文件失败: {}", e))?;
-        
-        let account: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| format!("解析 JSON 失败: {}", e))?;
-
-        if account
-            .get("disabled")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
-            tracing::debug!(
-                "Skipping disabled account file: {:?} (email={})",
-                path,
-                account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
+
+        let mut report = AccountSyncReport::default();
+
+        // 1. 移除已删除/已禁用的账号，并解除指向它们的会话绑定
+        let removed: Vec<String> = self
+            .tokens
+            .iter()
+            .filter(|e| !desired.contains_key(e.key()))
+            .map(|e| e.key().clone())
+            .collect();
+        for account_id in &removed {
+            if let Some((_, token)) = self.tokens.remove(account_id) {
+                self.account_health.restore(&token.email);
+            }
+            let bound_sessions: Vec<String> = self
+                .session_accounts
+                .iter()
+                .filter(|e| e.value() == account_id)
+                .map(|e| e.key().clone())
+                .collect();
+            for session_id in bound_sessions {
+                self.unbind_session(&session_id);
+            }
+        }
+        if !removed.is_empty() {
+            let mut last_used = self.last_used_account.lock().await;
+            if last_used.as_ref().is_some_and(|(id, _)| removed.contains(id)) {
+                *last_used = None;
+            }
+        }
+        report.removed = removed.len();
+
+        // 2. 新增或替换有变化的账号
+        for (account_id, token) in desired {
+            // 注意: 不能在持有 get_mut 引用时 insert (DashMap 分片锁)
+            let existed = match self.tokens.get_mut(&account_id) {
+                Some(mut existing) => {
+                    if *existing == token {
+                        report.unchanged += 1;
+                    } else {
+                        *existing = token.clone();
+                        report.updated += 1;
+                    }
+                    true
+                }
+                None => false,
+            };
+            if !existed {
+                self.tokens.insert(account_id, token);
+                report.added += 1;
+            }
+        }
+
+        if report.has_changes() {
+            tracing::info!(
+                "Account pool synced: +{} ~{} -{} ({} unchanged)",
+                report.added, report.updated, report.removed, report.unchanged
             );
+        }
+        Ok(report)
+    }
+
+    /// 账号存储的变更指纹 (用于热重载检测，文件元数据在阻塞线程池中读取)
+    pub async fn store_fingerprint(&self) -> Result<u64, String> {
+        let data_dir = self.data_dir.clone();
+        tokio::task::spawn_blocking(move || account_store::for_dir(&data_dir)?.fingerprint())
+            .await
+            .map_err(|e| format!("账号存储指纹任务失败: {}", e))?
+    }
+
+    /// 由账号数据构建 ProxyToken (已禁用的账号返回 None)
+    fn proxy_token_from_account(account: &Account) -> Result<Option<ProxyToken>, String> {
+        if account.disabled {
+            tracing::debug!("Skipping disabled account: {} (email={})", account.id, account.email);
             return Ok(None);
         }
 
         // 检查主动禁用状态
-        if account
-            .get("proxy_disabled")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
-            tracing::debug!(
-                "Skipping proxy-disabled account file: {:?} (email={})",
-                path,
-                account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
-            );
+        if account.proxy_disabled {
+            tracing::debug!("Skipping proxy-disabled account: {} (email={})", account.id, account.email);
             return Ok(None);
         }
 
-        let account_id = account["id"].as_str()
-            .ok_or("缺少 id 字段")?
-            .to_string();
-        
-        let email = account["email"].as_str()
-            .ok_or("缺少 email 字段")?
-            .to_string();
-        
-        let token_obj = account["token"].as_object()
-            .ok_or("缺少 token 字段")?;
-        
-        let access_token = token_obj["access_token"].as_str()
-            .ok_or("缺少 access_token")?
-            .to_string();
-        
-        let refresh_token = token_obj["refresh_token"].as_str()
-            .ok_or("缺少 refresh_token")?
-            .to_string();
-        
-        let expires_in = token_obj["expires_in"].as_i64()
-            .ok_or("缺少 expires_in")?;
-        
-        let timestamp = token_obj["expiry_timestamp"].as_i64()
-            .ok_or("缺少 expiry_timestamp")?;
-        
-        // project_id 是可选的
-        let project_id = token_obj.get("project_id")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
-        
-        // 【新增】提取订阅等级 (subscription_tier 为 "FREE" | "PRO" | "ULTRA")
-        let subscription_tier = account.get("quota")
-            .and_then(|q| q.get("subscription_tier"))
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+        // 加密存储的 Token 在此解密
+        let access_token = account.token.get_access_token()?;
+        let refresh_token = account.token.get_refresh_token()?;
 
-        // 【新增】提取模型配额信息
+        // 【新增】提取订阅等级与模型配额信息 (配额存储为 0.0-1.0 格式)
+        let subscription_tier = account.quota.as_ref().and_then(|q| q.subscription_tier.clone());
         let mut model_quotas = HashMap::new();
-        if let Some(models) = account.get("quota").and_then(|q| q.get("models")).and_then(|m| m.as_array()) {
-            for m in models {
-                if let (Some(name), Some(percentage)) = (
-                    m.get("name").and_then(|n| n.as_str()),
-                    m.get("percentage").and_then(|p| p.as_f64())
-                ) {
-                    // 存储为 0.0-1.0 格式
-                    model_quotas.insert(name.to_string(), percentage / 100.0);
-                }
+        let mut model_reset_times = HashMap::new();
+        for m in account.quota.iter().flat_map(|q| q.models.iter()) {
+            model_quotas.insert(m.name.clone(), m.percentage as f64 / 100.0);
+            if let Some(reset) = crate::proxy::quota_predictor::parse_reset_time(&m.reset_time) {
+                model_reset_times.insert(m.name.clone(), reset);
             }
         }
 
         Ok(Some(ProxyToken {
-            account_id,
+            account_id: account.id.clone(),
             access_token,
             refresh_token,
-            expires_in,
-            timestamp,
-            email,
-            account_path: path.clone(),
-            project_id,
+            expires_in: account.token.expires_in,
+            timestamp: account.token.expiry_timestamp,
+            email: account.email.clone(),
+            project_id: account.token.project_id.clone(),
             subscription_tier,
             model_quotas,
+            model_reset_times,
         }))
     }
     
@@ -208,37 +252,102 @@ impl TokenManager {
         session_id: Option<&str>,
         quota_priority_enabled: bool,
     ) -> Result<(String, String, String), String> {
-        // 【优化 Issue #284】添加 5 秒超时，防止死锁
-        let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(
-            timeout_duration,
-            self.get_token_internal(quota_group, model_name, quota_threshold, force_rotate, session_id, quota_priority_enabled),
-        )
+        self.get_token_with_timeout(TokenQuery {
+            quota_group,
+            model_name,
+            quota_threshold,
+            force_rotate,
+            session_id,
+            quota_priority_enabled,
+            accounts: None,
+            dry_run: false,
+        })
         .await
-        {
-            Ok(result) => result,
-            Err(_) => Err(
-                "Token acquisition timeout (5s) - system too busy or deadlock detected".to_string(),
-            ),
-        }
     }
 
-    /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(
+    /// 仅在指定账号组 (邮箱列表) 内选择 Token，用于后台任务专用账号
+    /// 账号组内没有可用账号 (不在池中、限流、隔离或配额不足) 时回退到完整账号池；后台任务不使用会话粘性
+    pub async fn get_token_in_group(
         &self,
+        accounts: &[String],
         quota_group: &str,
         model_name: Option<&str>,
         quota_threshold: f64,
         force_rotate: bool,
-        session_id: Option<&str>,
         quota_priority_enabled: bool,
     ) -> Result<(String, String, String), String> {
+        let query = |accounts| TokenQuery {
+            quota_group,
+            model_name,
+            quota_threshold,
+            force_rotate,
+            session_id: None,
+            quota_priority_enabled,
+            accounts,
+            dry_run: false,
+        };
+        match self.get_token_with_timeout(query(Some(accounts))).await {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                tracing::warn!("No grouped account available ({}), using full pool", e);
+                self.get_token_with_timeout(query(None)).await
+            }
+        }
+    }
+
+    async fn get_token_with_timeout(&self, query: TokenQuery<'_>) -> Result<(String, String, String), String> {
+        // 【优化 Issue #284】添加 5 秒超时，防止死锁
+        let timeout_duration = std::time::Duration::from_secs(5);
+        match tokio::time::timeout(timeout_duration, self.get_token_internal(query)).await {
+            Ok(result) => result,
+            Err(_) => Err(
+                "Token acquisition timeout (5s) - system too busy or deadlock detected".to_string(),
+            ),
+        }
+    }
+
+    /// 内部实现：获取 Token 的核心逻辑
+    async fn get_token_internal(&self, query: TokenQuery<'_>) -> Result<(String, String, String), String> {
+        let TokenQuery {
+            quota_group,
+            model_name,
+            quota_threshold,
+            force_rotate,
+            session_id,
+            quota_priority_enabled,
+            accounts,
+            dry_run,
+        } = query;
+
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+        if tokens_snapshot.is_empty() {
             return Err("Token pool is empty".to_string());
         }
 
+        // 限定账号组 (无可用账号时由 get_token_in_group 回退到完整账号池)
+        if let Some(accounts) = accounts.filter(|a| !a.is_empty()) {
+            tokens_snapshot.retain(|t| accounts.iter().any(|a| a.eq_ignore_ascii_case(&t.email)));
+            if tokens_snapshot.is_empty() {
+                return Err(format!("None of the {} grouped accounts are in the pool", accounts.len()));
+            }
+        }
+
+        // 跳过被隔离的账号 (全部隔离时仍使用完整账号池，避免所有请求直接失败)
+        let healthy: Vec<ProxyToken> = tokens_snapshot
+            .iter()
+            .filter(|t| !self.account_health.is_quarantined(&t.email))
+            .cloned()
+            .collect();
+        if healthy.is_empty() {
+            if accounts.is_some_and(|a| !a.is_empty()) {
+                return Err("All grouped accounts are quarantined".to_string());
+            }
+            tracing::warn!("All {} accounts are quarantined, ignoring health quarantine", tokens_snapshot.len());
+        } else {
+            tokens_snapshot = healthy;
+        }
+        let total = tokens_snapshot.len();
+
         // ===== 【优化】根据订阅等级排序 (优先级: ULTRA > PRO > FREE) =====
         // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
         // 如果启用配额优先，则在同等级内按配额升序排序
@@ -276,6 +385,22 @@ impl TokenManager {
         let scheduling = self.sticky_config.read().await.clone();
         use crate::proxy::sticky_config::SchedulingMode;
 
+        // 配额预测模式: 按重置时间与消耗速率重新排序，并总是从最优账号开始尝试
+        let predictive = scheduling.mode == SchedulingMode::QuotaPredictive && model_name.is_some();
+        if predictive {
+            let model = model_name.unwrap();
+            let rates = self.get_request_rates(model, scheduling.predictive.lookback_minutes).await;
+            crate::proxy::quota_predictor::sort_tokens(
+                &mut tokens_snapshot,
+                model,
+                &rates,
+                &scheduling.predictive,
+                chrono::Utc::now().timestamp(),
+            );
+        }
+        let greedy = quota_priority_enabled || predictive;
+        let slot_model = model_name.unwrap_or_default();
+
         // 【优化 Issue #284】将锁操作移到循环外，避免重复获取锁
         // 预先获取 last_used_account 的快照，避免在循环中多次加锁
         let last_used_account_id = if quota_group != "image_gen" {
@@ -303,11 +428,22 @@ impl TokenManager {
                 if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
                     // 2. 检查绑定的账号是否限流 (使用精准的剩余时间接口)
                     let reset_sec = self.rate_limit_tracker.get_remaining_wait(&bound_id);
-                    if reset_sec > 0 {
+                    let bound_email = tokens_snapshot
+                        .iter()
+                        .find(|t| t.account_id == bound_id)
+                        .map(|t| t.email.clone());
+                    if bound_email.as_deref().is_some_and(|e| self.circuit_breakers.is_open(e)) {
+                        tracing::warn!("Session {} bound account {} is circuit-open. Unbinding and switching to next available account.", sid, bound_id);
+                        if !dry_run {
+                            self.unbind_session(sid);
+                        }
+                    } else if reset_sec > 0 {
                         // 【修复 Issue #284】立即解绑并切换账号，不再阻塞等待
                         // 原因：阻塞等待会导致并发请求时客户端 socket 超时 (UND_ERR_SOCKET)
                         tracing::warn!("Session {} bound account {} is rate-limited ({}s remaining). Unbinding and switching to next available account.", sid, bound_id, reset_sec);
-                        self.session_accounts.remove(sid);
+                        if !dry_run {
+                            self.unbind_session(sid);
+                        }
                     } else if !attempted.contains(&bound_id) {
                         // 【新增】主动检查配额
                         let mut quota_ok = true;
@@ -316,7 +452,9 @@ impl TokenManager {
                                 if let Some(&remaining) = token.model_quotas.get(model) {
                                     if remaining < quota_threshold {
                                         tracing::warn!("Sticky Session: Account {} has low quota for {} ({:.2}% < {:.2}%). Unbinding.", token.email, model, remaining * 100.0, quota_threshold * 100.0);
-                                        self.session_accounts.remove(sid);
+                                        if !dry_run {
+                                            self.unbind_session(sid);
+                                        }
                                         quota_ok = false;
                                     }
                                 }
@@ -326,8 +464,15 @@ impl TokenManager {
                         if quota_ok {
                             // 3. 账号可用且未被标记为尝试失败，优先复用
                             if let Some(found) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
-                                tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", found.email, sid);
-                                target_token = Some(found.clone());
+                                // 并发槽位已满: 缓存优先模式下排队等待绑定账号，其他模式切换到空闲账号
+                                if scheduling.mode != SchedulingMode::CacheFirst
+                                    && !self.concurrency.has_capacity(&found.email, slot_model)
+                                {
+                                    tracing::debug!("Sticky Session: Bound account {} is at its concurrency limit, picking another account", found.email);
+                                } else {
+                                    tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", found.email, sid);
+                                    target_token = Some(found.clone());
+                                }
                             }
                         }
                     }
@@ -351,7 +496,10 @@ impl TokenManager {
                                 }
                             }
 
-                            if quota_ok {
+                            if quota_ok
+                                && self.concurrency.has_capacity(&found.email, slot_model)
+                                && !self.circuit_breakers.is_open(&found.email)
+                            {
                                 tracing::debug!("60s Window: Force reusing last account: {}", found.email);
                                 target_token = Some(found.clone());
                             }
@@ -361,15 +509,18 @@ impl TokenManager {
 
                 // 若无锁定，则轮询选择新账号
                 if target_token.is_none() {
-                    // 如果启用了配额优先，则总是从排序后的第一个(即最优)账号开始尝试(Greedy)
+                    // 如果启用了配额优先或配额预测，则总是从排序后的第一个(即最优)账号开始尝试(Greedy)
                     // 否则使用 Round-Robin
-                    let start_idx = if quota_priority_enabled {
+                    let start_idx = if greedy {
                         0
+                    } else if dry_run {
+                        self.current_index.load(Ordering::SeqCst) % total
                     } else {
                         self.current_index.fetch_add(1, Ordering::SeqCst) % total
                     };
 
                     let mut best_fallback: Option<(ProxyToken, f64)> = None;
+                    let mut busy_fallback: Option<ProxyToken> = None;
 
                     for offset in 0..total {
                         let idx = (start_idx + offset) % total;
@@ -383,6 +534,11 @@ impl TokenManager {
                             continue;
                         }
 
+                        // 跳过熔断中的账号 (半开且仍有探测名额时可选，选定后再占用名额)
+                        if self.circuit_breakers.is_open(&candidate.email) {
+                            continue;
+                        }
+
                         // 【新增】主动配额预检
                         if let Some(model) = model_name {
                             if let Some(&remaining) = candidate.model_quotas.get(model) {
@@ -396,20 +552,37 @@ impl TokenManager {
                             }
                         }
 
+                        // 并发槽位已满的账号暂时跳过，全部繁忙时再排队
+                        if !self.concurrency.has_capacity(&candidate.email, slot_model) {
+                            if busy_fallback.is_none() {
+                                busy_fallback = Some(candidate.clone());
+                            }
+                            continue;
+                        }
+
                         target_token = Some(candidate.clone());
                         // 【优化】标记需要更新，稍后统一写回
                         need_update_last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
 
                         // 如果是会话首次分配且需要粘性，在此建立绑定
-                        if let Some(sid) = session_id {
+                        if let Some(sid) = session_id.filter(|_| !dry_run) {
                             if scheduling.mode != SchedulingMode::PerformanceFirst {
-                                self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
+                                self.bind_session(sid, &candidate.account_id);
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
                         }
                         break;
                     }
 
+                    // 所有可用账号的并发槽位均已满: 选择第一个繁忙账号，在其队列中排队
+                    if target_token.is_none() {
+                        if let Some(busy) = busy_fallback {
+                            tracing::debug!("All accounts at concurrency limit, queueing on {}", busy.email);
+                            need_update_last_used = Some((busy.account_id.clone(), std::time::Instant::now()));
+                            target_token = Some(busy);
+                        }
+                    }
+
                         // 【新增】智能降级：如果所有账号都低于阈值，则使用配额最多的账号
                     // Hard Floor: 0.0001 (0.01%) - 绝对枯竭线
                     if target_token.is_none() && best_fallback.is_some() {
@@ -425,15 +598,18 @@ impl TokenManager {
                 }
             } else if target_token.is_none() {
                 // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换
-                // 如果启用了配额优先，则总是从排序后的第一个(即最优)账号开始尝试(Greedy)
+                // 如果启用了配额优先或配额预测，则总是从排序后的第一个(即最优)账号开始尝试(Greedy)
                 // 否则使用 Round-Robin
-                let start_idx = if quota_priority_enabled {
+                let start_idx = if greedy {
                     0
+                } else if dry_run {
+                    self.current_index.load(Ordering::SeqCst) % total
                 } else {
                     self.current_index.fetch_add(1, Ordering::SeqCst) % total
                 };
 
                 let mut best_fallback: Option<(ProxyToken, f64)> = None;
+                let mut busy_fallback: Option<ProxyToken> = None;
 
                 for offset in 0..total {
                     let idx = (start_idx + offset) % total;
@@ -447,6 +623,11 @@ impl TokenManager {
                         continue;
                     }
 
+                    // 跳过熔断中的账号 (半开且仍有探测名额时可选，选定后再占用名额)
+                    if self.circuit_breakers.is_open(&candidate.email) {
+                        continue;
+                    }
+
                     // 【新增】主动配额预检
                     if let Some(model) = model_name {
                         if let Some(&remaining) = candidate.model_quotas.get(model) {
@@ -460,6 +641,14 @@ impl TokenManager {
                         }
                     }
 
+                    // 并发槽位已满的账号暂时跳过，全部繁忙时再排队
+                    if !self.concurrency.has_capacity(&candidate.email, slot_model) {
+                        if busy_fallback.is_none() {
+                            busy_fallback = Some(candidate.clone());
+                        }
+                        continue;
+                    }
+
                     target_token = Some(candidate.clone());
 
                     if rotate {
@@ -477,6 +666,14 @@ impl TokenManager {
                     break;
                 }
 
+                // 所有可用账号的并发槽位均已满: 选择第一个繁忙账号，在其队列中排队
+                if target_token.is_none() {
+                    if let Some(busy) = busy_fallback {
+                        tracing::debug!("All accounts at concurrency limit, queueing on {}", busy.email);
+                        target_token = Some(busy);
+                    }
+                }
+
                 // 【新增】智能降级
                 if target_token.is_none() && best_fallback.is_some() {
                     let (best_token, remaining) = best_fallback.unwrap();
@@ -506,6 +703,14 @@ impl TokenManager {
                     let min_wait = tokens_snapshot.iter()
                         .filter_map(|t| self.rate_limit_tracker.get_reset_seconds(&t.account_id))
                         .min();
+
+                    // 调度模拟不执行缓冲等待与乐观重置 (会清除限流记录)
+                    if dry_run {
+                        return Err(match min_wait {
+                            Some(wait_sec) => format!("All accounts are currently limited. Please wait {}s.", wait_sec),
+                            None => "All accounts failed or unhealthy.".to_string(),
+                        });
+                    }
                     
                     // Layer 1: 如果最短等待时间 <= 2秒,执行缓冲延迟
                     if let Some(wait_sec) = min_wait {
@@ -560,7 +765,18 @@ impl TokenManager {
                 }
             };
 
-        
+            // 熔断半开的账号在选定后才占用探测名额；名额已被并发请求占用时换下一个账号。
+            // 之后刷新 token / 获取 project_id 失败而换号时，守卫会归还名额
+            // 调度模拟到此为止: 半开账号是否还有探测名额已由 is_open 判断
+            if dry_run {
+                return Ok((String::new(), token.project_id.unwrap_or_default(), token.email));
+            }
+            let Some(probe) = self.circuit_breakers.acquire_probe(&token.email) else {
+                tracing::debug!("Account {} probe slot taken, trying next account", token.email);
+                attempted.insert(token.account_id.clone());
+                continue;
+            };
+
             // 3. 检查 token 是否过期（提前5分钟刷新）
             let now = chrono::Utc::now().timestamp();
             if now >= token.timestamp - 300 {
@@ -657,6 +873,7 @@ impl TokenManager {
                 }
             }
 
+            probe.commit();
             return Ok((token.access_token, project_id, token.email));
         }
 
@@ -664,71 +881,44 @@ impl TokenManager {
     }
 
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
-        let path = if let Some(entry) = self.tokens.get(account_id) {
-            entry.account_path.clone()
-        } else {
-            self.data_dir
-                .join("accounts")
-                .join(format!("{}.json", account_id))
-        };
-
-        let mut content: serde_json::Value = serde_json::from_str(
-            &std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?,
-        )
-        .map_err(|e| format!("解析 JSON 失败: {}", e))?;
-
-        let now = chrono::Utc::now().timestamp();
-        content["disabled"] = serde_json::Value::Bool(true);
-        content["disabled_at"] = serde_json::Value::Number(now.into());
-        content["disabled_reason"] = serde_json::Value::String(truncate_reason(reason, 800));
-
-        std::fs::write(&path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-
-        tracing::warn!("Account disabled: {} ({:?})", account_id, path);
+        let reason = truncate_reason(reason, 800);
+        account_store::for_dir(&self.data_dir)?.update(account_id, |account| {
+            account.disabled = true;
+            account.disabled_at = Some(chrono::Utc::now().timestamp());
+            account.disabled_reason = Some(reason.clone());
+            Ok(())
+        })?;
+
+        tracing::warn!("Account disabled: {}", account_id);
+        events::publish(ProxyEvent::AccountStateChanged {
+            account_id: account_id.to_string(),
+            field: "disabled",
+            enabled: false,
+            reason: Some(reason),
+        });
         Ok(())
     }
 
-    /// 保存 project_id 到账号文件
+    /// 保存 project_id 到账号存储
     async fn save_project_id(&self, account_id: &str, project_id: &str) -> Result<(), String> {
-        let entry = self.tokens.get(account_id)
-            .ok_or("账号不存在")?;
-        
-        let path = &entry.account_path;
-        
-        let mut content: serde_json::Value = serde_json::from_str(
-            &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?
-        ).map_err(|e| format!("解析 JSON 失败: {}", e))?;
-        
-        content["token"]["project_id"] = serde_json::Value::String(project_id.to_string());
-        
-        std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-        
+        account_store::for_dir(&self.data_dir)?.update(account_id, |account| {
+            account.token.project_id = Some(project_id.to_string());
+            Ok(())
+        })?;
+
         tracing::debug!("已保存 project_id 到账号 {}", account_id);
         Ok(())
     }
     
-    /// 保存刷新后的 token 到账号文件
+    /// 保存刷新后的 token 到账号存储
     async fn save_refreshed_token(&self, account_id: &str, token_response: &crate::modules::oauth::TokenResponse) -> Result<(), String> {
-        let entry = self.tokens.get(account_id)
-            .ok_or("账号不存在")?;
-        
-        let path = &entry.account_path;
-        
-        let mut content: serde_json::Value = serde_json::from_str(
-            &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?
-        ).map_err(|e| format!("解析 JSON 失败: {}", e))?;
-        
-        let now = chrono::Utc::now().timestamp();
-        
-        content["token"]["access_token"] = serde_json::Value::String(token_response.access_token.clone());
-        content["token"]["expires_in"] = serde_json::Value::Number(token_response.expires_in.into());
-        content["token"]["expiry_timestamp"] = serde_json::Value::Number((now + token_response.expires_in).into());
-        
-        std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-        
+        account_store::for_dir(&self.data_dir)?.update(account_id, |account| {
+            // update_tokens 会按账号的加密状态重新加密
+            let refresh_token = account.token.get_refresh_token()?;
+            account.token.expires_in = token_response.expires_in;
+            account.token.update_tokens(token_response.access_token.clone(), refresh_token)
+        })?;
+
         tracing::debug!("已保存刷新后的 token 到账号 {}", account_id);
         Ok(())
     }
@@ -747,6 +937,7 @@ impl TokenManager {
         retry_after_header: Option<&str>,
         error_body: &str,
     ) {
+        self.record_account_failure(account_id, status);
         self.rate_limit_tracker.parse_from_error(
             account_id,
             status,
@@ -784,49 +975,155 @@ impl TokenManager {
     /// 下次失败时从最短的锁定时间开始（智能限流）。
     pub fn mark_account_success(&self, account_id: &str) {
         self.rate_limit_tracker.mark_success(account_id);
+        self.circuit_breakers.record_success(account_id);
+        self.account_health.record(account_id, HealthOutcome::Success, "");
     }
-    
-    /// 从账号文件获取配额刷新时间
-    /// 
-    /// 返回该账号最近的配额刷新时间字符串（ISO 8601 格式）
-    pub fn get_quota_reset_time(&self, email: &str) -> Option<String> {
-        // 尝试从账号文件读取配额信息
-        let accounts_dir = self.data_dir.join("accounts");
-        
-        // 遍历账号文件查找对应的 email
-        if let Ok(entries) = std::fs::read_dir(&accounts_dir) {
-            for entry in entries.flatten() {
-                if let Ok(content) = std::fs::read_to_string(entry.path()) {
-                    if let Ok(account) = serde_json::from_str::<serde_json::Value>(&content) {
-                        // 检查 email 是否匹配
-                        if account.get("email").and_then(|e| e.as_str()) == Some(email) {
-                            // 获取 quota.models 中最早的 reset_time
-                            if let Some(models) = account
-                                .get("quota")
-                                .and_then(|q| q.get("models"))
-                                .and_then(|m| m.as_array()) 
-                            {
-                                // 找到最早的 reset_time（最保守的锁定策略）
-                                let mut earliest_reset: Option<&str> = None;
-                                for model in models {
-                                    if let Some(reset_time) = model.get("reset_time").and_then(|r| r.as_str()) {
-                                        if !reset_time.is_empty() {
-                                            if earliest_reset.is_none() || reset_time < earliest_reset.unwrap() {
-                                                earliest_reset = Some(reset_time);
-                                            }
-                                        }
-                                    }
-                                }
-                                if let Some(reset) = earliest_reset {
-                                    return Some(reset.to_string());
-                                }
-                            }
-                        }
+
+    /// 记录上游错误对账号健康度的影响 (限流类错误不计入)
+    /// 4xx 响应说明账号可达，同时结束该账号熔断器的半开探测
+    pub fn record_upstream_error(&self, email: &str, status: u16, error_text: &str) {
+        if status < 500 {
+            self.circuit_breakers.resolve_probe(email, &format!("HTTP {}", status));
+        }
+        if let Some(outcome) = HealthOutcome::from_upstream_error(status, error_text) {
+            self.account_health
+                .record(email, outcome, &format!("HTTP {}: {}", status, error_text));
+        }
+    }
+
+    /// 流式请求收到 HTTP 200: 重置限流与熔断状态，健康度结果待流结束后由 `record_stream_outcome` 记录
+    pub fn mark_stream_opened(&self, account_id: &str) {
+        self.rate_limit_tracker.mark_success(account_id);
+        self.circuit_breakers.record_success(account_id);
+    }
+
+    /// 记录一次流式请求的最终结果 (是否返回过候选结果)
+    pub fn record_stream_outcome(&self, email: &str, produced_candidates: bool) {
+        if produced_candidates {
+            self.account_health.record(email, HealthOutcome::Success, "");
+        } else {
+            self.account_health
+                .record(email, HealthOutcome::EmptyStream, "upstream stream ended without data");
+        }
+    }
+
+    /// 账号健康度 (用于探测任务与管理接口)
+    pub fn account_health(&self) -> Arc<AccountHealthRegistry> {
+        self.account_health.clone()
+    }
+
+    /// 更新账号健康度配置
+    pub fn update_account_health_config(&self, config: crate::proxy::config::AccountHealthConfig) {
+        self.account_health.update_config(config);
+    }
+
+    /// 向被隔离账号发送一次探测请求 (必要时刷新 token，再发送一次最小的 generateContent 请求)
+    pub async fn probe_account(&self, email: &str, upstream: &UpstreamClient) -> Result<(), String> {
+        let Some(mut token) = self
+            .tokens
+            .iter()
+            .find(|e| e.value().email == email)
+            .map(|e| e.value().clone())
+        else {
+            // 账号已从账号池移除，不再需要探测
+            self.account_health.restore(email);
+            return Err("account is no longer in the pool".to_string());
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        if now >= token.timestamp - 300 {
+            match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
+                Ok(token_response) => {
+                    token.access_token = token_response.access_token.clone();
+                    token.expires_in = token_response.expires_in;
+                    token.timestamp = now + token_response.expires_in;
+                    if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                        entry.access_token = token.access_token.clone();
+                        entry.expires_in = token.expires_in;
+                        entry.timestamp = token.timestamp;
+                    }
+                    if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
+                        tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
+                    }
+                }
+                Err(e) => {
+                    if e.contains("invalid_grant") {
+                        let _ = self
+                            .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
+                            .await;
+                        self.tokens.remove(&token.account_id);
+                        self.account_health.restore(email);
                     }
+                    return Err(format!("token refresh failed: {}", e));
                 }
             }
         }
-        None
+
+        let project_id = match &token.project_id {
+            Some(pid) => pid.clone(),
+            None => crate::proxy::project_resolver::fetch_project_id(&token.access_token).await?,
+        };
+        let body = serde_json::json!({
+            "project": project_id,
+            "requestId": format!("probe-{}", uuid::Uuid::new_v4()),
+            "request": {
+                "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+                "generationConfig": {"maxOutputTokens": 1}
+            },
+            "model": HEALTH_PROBE_MODEL,
+            "userAgent": "antigravity",
+            "requestType": "agent"
+        });
+        let response = upstream
+            .call_v1_internal("generateContent", &token.access_token, body, None)
+            .await?;
+        let status = response.status();
+        // 限流与上游故障与账号本身无关，不影响探测结果
+        if status.is_success() || status.as_u16() == 429 || status.is_server_error() {
+            return Ok(());
+        }
+        let error_text = response.text().await.unwrap_or_default();
+        match HealthOutcome::from_upstream_error(status.as_u16(), &error_text) {
+            Some(outcome) => Err(format!("HTTP {} ({:?}): {}", status.as_u16(), outcome, error_text)),
+            None => Ok(()),
+        }
+    }
+
+    /// 5xx 错误计入账号熔断器 (429 等限流由 RateLimitTracker 单独处理)
+    /// 4xx/429 说明账号可达，若为半开探测请求则结束半开状态
+    fn record_account_failure(&self, account_id: &str, status: u16) {
+        if status >= 500 {
+            self.circuit_breakers.record_failure(account_id, &format!("HTTP {}", status));
+        } else {
+            self.circuit_breakers.resolve_probe(account_id, &format!("HTTP {}", status));
+        }
+    }
+
+    /// 账号熔断器 (用于注册状态监听与监控展示)
+    pub fn circuit_breakers(&self) -> Arc<CircuitBreakerRegistry> {
+        self.circuit_breakers.clone()
+    }
+
+    /// 更新账号熔断配置
+    pub fn update_circuit_breaker_config(&self, config: crate::proxy::config::CircuitBreakerConfig) {
+        self.circuit_breakers.update_config(config);
+    }
+    
+    /// 从账号存储获取配额刷新时间
+    /// 
+    /// 返回该账号最近的配额刷新时间字符串（ISO 8601 格式）
+    pub fn get_quota_reset_time(&self, email: &str) -> Option<String> {
+        let listing = account_store::for_dir(&self.data_dir).ok()?.list().ok()?;
+        let account = listing.accounts.into_iter().find(|a| a.email == email)?;
+
+        // 找到最早的 reset_time（最保守的锁定策略）
+        account
+            .quota?
+            .models
+            .into_iter()
+            .map(|m| m.reset_time)
+            .filter(|reset_time| !reset_time.is_empty())
+            .min()
     }
     
     /// 使用配额刷新时间精确锁定账号
@@ -931,6 +1228,8 @@ impl TokenManager {
         error_body: &str,
         model: Option<&str>,  // 🆕 新增模型参数
     ) {
+        self.record_account_failure(account_id, status);
+
         // 检查 API 是否返回了精确的重试时间
         let has_explicit_retry_time = retry_after_header.is_some() || 
             error_body.contains("quotaResetDelay");
@@ -997,30 +1296,177 @@ impl TokenManager {
 
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
+        self.concurrency.update_config(new_config.concurrency.clone());
         let mut config = self.sticky_config.write().await;
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    // ===== 并发限制相关方法 =====
+
+    /// 为账号获取并发槽位，槽位已满时按 FIFO 排队
+    ///
+    /// 缓存优先模式下最多等待 `max_wait_seconds` (尽量留在绑定账号上)，
+    /// 其他模式使用 `concurrency.queue_timeout_seconds`。返回的槽位需持有到响应结束。
+    pub async fn acquire_slot(&self, email: &str, model: &str) -> Result<ConcurrencySlot, String> {
+        let timeout = {
+            let config = self.sticky_config.read().await;
+            if config.mode == crate::proxy::sticky_config::SchedulingMode::CacheFirst {
+                std::time::Duration::from_secs(config.max_wait_seconds)
+            } else {
+                self.concurrency.queue_timeout()
+            }
+        };
+        self.concurrency.acquire(email, model, timeout).await
+    }
+
+    /// 单个账号在该模型上的并发上限 (不限制时为 None)
+    pub fn slot_limit(&self, model: &str) -> Option<usize> {
+        self.concurrency.slot_limit(model)
+    }
+
+    /// 排队统计 (供监控使用)
+    pub fn queue_stats(&self) -> Arc<QueueStats> {
+        self.concurrency.stats()
+    }
+
     /// 清除特定会话的粘性映射
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
-        self.session_accounts.remove(session_id);
+        self.unbind_session(session_id);
     }
 
     /// 清除所有会话的粘性映射
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
+        events::publish(ProxyEvent::SessionUnbound { session_id: None });
+    }
+
+    /// 建立会话与账号的粘性绑定
+    fn bind_session(&self, session_id: &str, account_id: &str) {
+        self.session_accounts.insert(session_id.to_string(), account_id.to_string());
+        events::publish(ProxyEvent::SessionBound {
+            session_id: session_id.to_string(),
+            account_id: account_id.to_string(),
+        });
+    }
+
+    /// 将会话绑定到指定邮箱的账号 (用于提示词缓存亲和)，账号不在池中时忽略
+    pub fn prefer_account_for_session(&self, session_id: &str, email: &str) {
+        let Some(account_id) = self
+            .tokens
+            .iter()
+            .find(|t| t.email == email)
+            .map(|t| t.account_id.clone())
+        else {
+            return;
+        };
+        if self.session_accounts.get(session_id).is_some_and(|bound| *bound == account_id) {
+            return;
+        }
+        tracing::debug!("Prompt cache affinity: binding session {} to {}", session_id, email);
+        self.bind_session(session_id, &account_id);
+    }
+
+    /// 解除会话的粘性绑定
+    fn unbind_session(&self, session_id: &str) {
+        if self.session_accounts.remove(session_id).is_some() {
+            events::publish(ProxyEvent::SessionUnbound {
+                session_id: Some(session_id.to_string()),
+            });
+        }
+    }
+
+    /// 获取各账号针对某模型的请求速率 (email -> 次/小时)，结果缓存 30 秒
+    /// SQLite 查询在阻塞线程池中执行，避免占用异步运行时
+    async fn get_request_rates(&self, model: &str, lookback_minutes: u64) -> HashMap<String, f64> {
+        if let Some(entry) = self.request_rates.get(model) {
+            if entry.0.elapsed().as_secs() < 30 {
+                return entry.1.clone();
+            }
+        }
+
+        let lookback_minutes = lookback_minutes.max(1);
+        let since = chrono::Utc::now().timestamp_millis() - (lookback_minutes as i64) * 60_000;
+        let query_model = model.to_string();
+        let counts = tokio::task::spawn_blocking(move || {
+            crate::modules::proxy_db::get_account_request_counts(&query_model, since)
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("request history task failed: {}", e)));
+        let rates: HashMap<String, f64> = match counts {
+            Ok(counts) => counts
+                .into_iter()
+                .map(|(email, count)| (email, count as f64 * 60.0 / lookback_minutes as f64))
+                .collect(),
+            Err(e) => {
+                tracing::debug!("Failed to load request history for {}: {}", model, e);
+                HashMap::new()
+            }
+        };
+
+        self.request_rates.insert(model.to_string(), (std::time::Instant::now(), rates.clone()));
+        rates
+    }
+
+    /// 调度模拟 (Dry-run): 以 dry-run 方式执行与真实请求相同的账号选择逻辑，不修改任何状态
+    ///
+    /// 调度模式、会话粘性、60s 锁定、并发槽位、熔断与隔离均与真实请求一致。
+    /// 返回按配额预测排序的账号列表 (用于展示)，以及实际会被选中的账号 email 或无法选择的原因
+    pub async fn simulate_selection(
+        &self,
+        model: &str,
+        quota_threshold: f64,
+        session_id: Option<&str>,
+        quota_priority_enabled: bool,
+    ) -> (Vec<crate::proxy::quota_predictor::QuotaForecast>, Result<String, String>) {
+        use crate::proxy::quota_predictor::{rank_forecasts, QuotaForecast};
+
+        let scheduling = self.sticky_config.read().await.clone();
+        let rates = self.get_request_rates(model, scheduling.predictive.lookback_minutes).await;
+        let now = chrono::Utc::now().timestamp();
+
+        let mut forecasts: Vec<QuotaForecast> = self
+            .tokens
+            .iter()
+            .map(|e| {
+                let token = e.value();
+                let rate = rates.get(&token.email).copied().unwrap_or(0.0);
+                QuotaForecast::build(token, model, rate, &scheduling.predictive, now)
+            })
+            .collect();
+        rank_forecasts(&mut forecasts);
+
+        let selected = self
+            .get_token_with_timeout(TokenQuery {
+                quota_group: "agent",
+                model_name: Some(model),
+                quota_threshold,
+                force_rotate: false,
+                session_id,
+                quota_priority_enabled,
+                accounts: None,
+                dry_run: true,
+            })
+            .await
+            .map(|(_, _, email)| email);
+
+        (forecasts, selected)
     }
 
     /// 更新内存中特定账号的模型配额信息
     pub fn update_token_quota(&self, account_id: &str, quota_data: &crate::models::quota::QuotaData) {
         if let Some(mut entry) = self.tokens.get_mut(account_id) {
             let mut model_quotas = HashMap::new();
+            let mut model_reset_times = HashMap::new();
             for m in &quota_data.models {
                 model_quotas.insert(m.name.clone(), m.percentage as f64 / 100.0);
+                if let Some(reset) = crate::proxy::quota_predictor::parse_reset_time(&m.reset_time) {
+                    model_reset_times.insert(m.name.clone(), reset);
+                }
             }
             entry.model_quotas = model_quotas;
+            entry.model_reset_times = model_reset_times;
             tracing::debug!("Updated in-memory quotas for account {}", account_id);
         }
     }
@@ -1049,10 +1495,10 @@ mod tests {
             expires_in: 3600,
             timestamp: chrono::Utc::now().timestamp() + 3600,
             email: email.to_string(),
-            account_path: PathBuf::from("/tmp/mock_account.json"),
             project_id: Some("mock_project".to_string()),
             subscription_tier: Some("FREE".to_string()),
             model_quotas,
+            model_reset_times: HashMap::new(),
         }
     }
 
@@ -1099,6 +1545,38 @@ mod tests {
         assert!(err.contains("Quota < 0.01%"));
     }
 
+    #[tokio::test]
+    async fn test_account_group_restricts_selection() {
+        let manager = TokenManager::new(PathBuf::from("/tmp"));
+        let mut token_a = create_mock_token("a", "a@example.com", "claude-3-sonnet", 0.9);
+        token_a.subscription_tier = Some("ULTRA".to_string());
+        manager.tokens.insert("a".to_string(), token_a);
+        manager.tokens.insert("b".to_string(), create_mock_token("b", "b@example.com", "claude-3-sonnet", 0.9));
+
+        // 组内只有 B，即使 A 等级更高也只能选 B
+        let group = vec!["B@example.com".to_string()];
+        let (_, _, email) = manager
+            .get_token_in_group(&group, "claude", Some("claude-3-sonnet"), 0.01, false, false)
+            .await
+            .unwrap();
+        assert_eq!(email, "b@example.com");
+
+        // 组内账号均不在池中时回退到完整账号池
+        let missing = vec!["missing@example.com".to_string()];
+        assert!(manager
+            .get_token_in_group(&missing, "claude", Some("claude-3-sonnet"), 0.01, false, false)
+            .await
+            .is_ok());
+
+        // 组内账号配额耗尽时同样回退到完整账号池
+        manager.tokens.insert("b".to_string(), create_mock_token("b", "b@example.com", "claude-3-sonnet", 0.00005));
+        let (_, _, email) = manager
+            .get_token_in_group(&group, "claude", Some("claude-3-sonnet"), 0.01, false, false)
+            .await
+            .unwrap();
+        assert_eq!(email, "a@example.com");
+    }
+
     #[tokio::test]
     async fn test_quota_priority_sorting() {
         let manager = TokenManager::new(PathBuf::from("/tmp"));
@@ -1206,4 +1684,108 @@ mod tests {
 
         assert_eq!(seen.len(), 2, "Should have rotated through both accounts");
     }
+
+    #[tokio::test]
+    async fn test_predictive_prefers_soonest_reset() {
+        let manager = TokenManager::new(PathBuf::from("/tmp"));
+        let now = chrono::Utc::now().timestamp();
+
+        // A: 80%, resets in 5 days
+        let mut token_a = create_mock_token("a", "a@example.com", "claude-3-sonnet", 0.8);
+        token_a.model_reset_times.insert("claude-3-sonnet".to_string(), now + 5 * 86400);
+        // B: 80%, resets in 1 hour ("use it or lose it")
+        let mut token_b = create_mock_token("b", "b@example.com", "claude-3-sonnet", 0.8);
+        token_b.model_reset_times.insert("claude-3-sonnet".to_string(), now + 3600);
+
+        manager.tokens.insert("a".to_string(), token_a);
+        manager.tokens.insert("b".to_string(), token_b);
+
+        let mut config = StickySessionConfig::default();
+        config.mode = crate::proxy::sticky_config::SchedulingMode::QuotaPredictive;
+        manager.update_sticky_config(config).await;
+
+        for _ in 0..3 {
+            let result = manager.get_token("claude", Some("claude-3-sonnet"), 0.01, true, None, false).await;
+            assert_eq!(result.unwrap().2, "b@example.com");
+        }
+
+        let (forecasts, selected) = manager.simulate_selection("claude-3-sonnet", 0.01, None, false).await;
+        assert_eq!(forecasts.len(), 2);
+        assert_eq!(selected.as_deref(), Ok("b@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_simulation_follows_sticky_session_without_side_effects() {
+        let manager = TokenManager::new(PathBuf::from("/tmp"));
+        manager.tokens.insert("a".to_string(), create_mock_token("a", "a@example.com", "claude-3-sonnet", 0.9));
+        manager.tokens.insert("b".to_string(), create_mock_token("b", "b@example.com", "claude-3-sonnet", 0.3));
+        manager.bind_session("session-1", "b");
+        let index_before = manager.current_index.load(Ordering::SeqCst);
+
+        // 与真实请求一致: 会话绑定的账号优先
+        let (_, selected) = manager.simulate_selection("claude-3-sonnet", 0.1, Some("session-1"), false).await;
+        assert_eq!(selected.as_deref(), Ok("b@example.com"));
+
+        // 绑定账号配额不足时换号，但模拟不会解除绑定
+        let (_, selected) = manager.simulate_selection("claude-3-sonnet", 0.5, Some("session-1"), false).await;
+        assert_eq!(selected.as_deref(), Ok("a@example.com"));
+        assert_eq!(manager.session_accounts.get("session-1").map(|v| v.clone()), Some("b".to_string()));
+
+        // 新会话不会被绑定，轮询位置不变
+        manager.simulate_selection("claude-3-sonnet", 0.1, Some("session-2"), false).await;
+        assert!(manager.session_accounts.get("session-2").is_none());
+        assert_eq!(manager.current_index.load(Ordering::SeqCst), index_before);
+    }
+
+    fn store_account(data_dir: &std::path::Path, id: &str, proxy_disabled: bool) {
+        let token = crate::models::TokenData::new("at".into(), "rt".into(), 3600, None, None, None);
+        let mut account = Account::new(id.to_string(), format!("{}@example.com", id), token);
+        account.proxy_disabled = proxy_disabled;
+        account_store::for_dir(data_dir)
+            .unwrap()
+            .transact(|txn| {
+                txn.save(&account)?;
+                let index = txn.index_mut();
+                if !index.accounts.iter().any(|s| s.id == account.id) {
+                    index.accounts.push(crate::models::AccountSummary {
+                        id: account.id.clone(),
+                        email: account.email.clone(),
+                        name: None,
+                        created_at: account.created_at,
+                        last_used: account.last_used,
+                    });
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sync_accounts_is_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        store_account(dir.path(), "a", false);
+        store_account(dir.path(), "b", false);
+
+        let manager = TokenManager::new(dir.path().to_path_buf());
+        assert_eq!(manager.load_accounts().await.unwrap(), 2);
+        manager.bind_session("session-a", "a");
+        manager.bind_session("session-b", "b");
+
+        // b 被禁用反代，新增 c
+        store_account(dir.path(), "b", true);
+        store_account(dir.path(), "c", false);
+
+        let report = manager.sync_accounts().await.unwrap();
+        assert_eq!(
+            report,
+            AccountSyncReport { added: 1, updated: 0, removed: 1, unchanged: 1 }
+        );
+        assert!(manager.tokens.contains_key("c"));
+        assert!(!manager.tokens.contains_key("b"));
+        // 未变化账号的会话绑定保留，被移除账号的绑定解除
+        assert_eq!(manager.session_accounts.get("session-a").map(|v| v.clone()), Some("a".to_string()));
+        assert!(manager.session_accounts.get("session-b").is_none());
+
+        assert!(!manager.sync_accounts().await.unwrap().has_changes());
+    }
 }
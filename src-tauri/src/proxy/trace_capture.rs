@@ -0,0 +1,618 @@
+// 协议转换调试: 请求/响应抓取与离线回放
+//
+// 抓取文件 (JSON) 记录客户端原始请求、映射器输入/输出、实际发送的 v1internal 请求体、上游原始 SSE 行
+// 以及映射器输出给客户端的字节。`replay` 用当前代码中的映射器离线重跑抓取文件并输出差异
+// (屏蔽随机 id、时间戳等易变字段)，抓取文件同时作为回归测试夹具 (src/proxy/tests/fixtures/traces)。
+//
+// 目前覆盖 Claude Messages、OpenAI Chat Completions 以及 Codex/Legacy Completions 的流式路径;
+// 对冲请求与断线续写的续接部分不在抓取范围内。
+use axum::http::HeaderMap;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::config::TraceCaptureConfig;
+use crate::proxy::prompt_cache::CachePlan;
+
+/// 请求头: `on` 抓取本次请求 (需开启 header_trigger)
+pub const CAPTURE_HEADER: &str = "x-trace-capture";
+
+/// 抓取文件格式版本
+pub const TRACE_VERSION: u32 = 1;
+
+/// 被屏蔽的响应字段 (随机 id 与时间戳)
+const VOLATILE_RESPONSE_KEYS: &[&str] = &["id", "created", "created_at"];
+
+/// LCS 差异计算的规模上限，超过时退化为逐行比较
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// 抓取的入口与响应转换器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceKind {
+    ClaudeMessages,
+    OpenaiChat,
+    OpenaiCodex,
+    OpenaiLegacy,
+}
+
+impl TraceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraceKind::ClaudeMessages => "claude_messages",
+            TraceKind::OpenaiChat => "openai_chat",
+            TraceKind::OpenaiCodex => "openai_codex",
+            TraceKind::OpenaiLegacy => "openai_legacy",
+        }
+    }
+}
+
+/// 抓取文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceFile {
+    pub version: u32,
+    pub id: String,
+    pub created_at: i64,
+    pub kind: TraceKind,
+    /// 客户端请求的模型
+    pub model: String,
+    #[serde(default)]
+    pub mapped_model: String,
+    #[serde(default)]
+    pub project_id: String,
+    /// 响应转换器使用的会话指纹
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// 响应转换器使用的提示词缓存计划 (Claude)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_plan: Option<CachePlan>,
+    /// 客户端原始请求体
+    pub inbound_request: Value,
+    /// 映射器输入 (经过过滤、能力调整后的类型化请求)
+    #[serde(default)]
+    pub mapper_input: Value,
+    /// 映射器输出 (提示词策略之前的 v1internal 请求体)
+    #[serde(default)]
+    pub mapper_output: Value,
+    /// 实际发送的 v1internal 请求体
+    #[serde(default)]
+    pub upstream_request: Value,
+    /// 上游原始 SSE 行
+    #[serde(default)]
+    pub upstream_lines: Vec<String>,
+    /// 响应转换器输出给客户端的字节
+    #[serde(default)]
+    pub client_output: String,
+}
+
+impl TraceFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let trace: TraceFile =
+            serde_json::from_str(&content).map_err(|e| format!("Invalid trace file {}: {}", path.display(), e))?;
+        if trace.version > TRACE_VERSION {
+            return Err(format!("Unsupported trace version {} in {}", trace.version, path.display()));
+        }
+        Ok(trace)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}-{}-{}.json", self.created_at, self.kind.as_str(), self.id)
+    }
+}
+
+struct TraceSink {
+    trace: Mutex<TraceFile>,
+    upstream: Mutex<Vec<u8>>,
+    client: Mutex<Vec<u8>>,
+    dir: PathBuf,
+    max_files: usize,
+}
+
+impl Drop for TraceSink {
+    // 请求处理与两个被抓取的流全部结束 (或被客户端中断) 后写入文件
+    fn drop(&mut self) {
+        let mut trace = match self.trace.lock() {
+            Ok(t) => t.clone(),
+            Err(_) => return,
+        };
+        if trace.upstream_request.is_null() {
+            return;
+        }
+        if let Ok(upstream) = self.upstream.lock() {
+            trace.upstream_lines = String::from_utf8_lossy(&upstream)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(client) = self.client.lock() {
+            trace.client_output = String::from_utf8_lossy(&client).into_owned();
+        }
+
+        let dir = self.dir.clone();
+        let max_files = self.max_files;
+        let write = move || {
+            if let Err(e) = write_trace(&dir, &trace, max_files) {
+                tracing::warn!("[Trace] Failed to write trace {}: {}", trace.id, e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(write);
+            }
+            Err(_) => write(),
+        }
+    }
+}
+
+/// 单个请求的抓取句柄 (可克隆，所有克隆释放后写入文件)
+#[derive(Clone)]
+pub struct TraceRecorder {
+    sink: Arc<TraceSink>,
+}
+
+impl TraceRecorder {
+    /// 按配置与请求头决定是否抓取本次请求
+    pub fn start(config: &TraceCaptureConfig, headers: &HeaderMap, kind: TraceKind, inbound: &Value) -> Option<Self> {
+        let model = inbound.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+        let requested = config.header_trigger
+            && headers
+                .get(CAPTURE_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "on" | "1" | "true"));
+        let matched =
+            config.enabled && (config.models.is_empty() || config.models.iter().any(|pattern| wildcard_match(pattern, model)));
+        if !requested && !matched {
+            return None;
+        }
+
+        let dir = match &config.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => match crate::modules::account::get_data_dir() {
+                Ok(data_dir) => data_dir.join("traces"),
+                Err(e) => {
+                    tracing::warn!("[Trace] Capture disabled: {}", e);
+                    return None;
+                }
+            },
+        };
+
+        let trace = TraceFile {
+            version: TRACE_VERSION,
+            id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            kind,
+            model: model.to_string(),
+            mapped_model: String::new(),
+            project_id: String::new(),
+            session_id: None,
+            cache_plan: None,
+            inbound_request: inbound.clone(),
+            mapper_input: Value::Null,
+            mapper_output: Value::Null,
+            upstream_request: Value::Null,
+            upstream_lines: Vec::new(),
+            client_output: String::new(),
+        };
+        tracing::debug!("[Trace] Capturing {} request {}", kind.as_str(), trace.id);
+        Some(Self {
+            sink: Arc::new(TraceSink {
+                trace: Mutex::new(trace),
+                upstream: Mutex::new(Vec::new()),
+                client: Mutex::new(Vec::new()),
+                dir,
+                max_files: config.max_files,
+            }),
+        })
+    }
+
+    /// 记录映射器的输入与输出 (每次重试覆盖)
+    pub fn record_mapping<T: Serialize>(&self, mapped_model: &str, project_id: &str, input: &T, output: &Value) {
+        if let Ok(mut trace) = self.sink.trace.lock() {
+            trace.mapped_model = mapped_model.to_string();
+            trace.project_id = project_id.to_string();
+            trace.mapper_input = serde_json::to_value(input).unwrap_or(Value::Null);
+            trace.mapper_output = output.clone();
+        }
+    }
+
+    /// 记录实际发送的上游请求体
+    pub fn record_upstream_request(&self, body: &Value) {
+        if let Ok(mut trace) = self.sink.trace.lock() {
+            trace.upstream_request = body.clone();
+        }
+    }
+
+    /// 记录响应转换器的参数
+    pub fn record_stream_context(&self, session_id: Option<String>, cache_plan: Option<CachePlan>) {
+        if let Ok(mut trace) = self.sink.trace.lock() {
+            trace.session_id = session_id;
+            trace.cache_plan = cache_plan;
+        }
+    }
+}
+
+/// 抓取上游原始字节流
+pub fn tee_upstream<S, E>(
+    recorder: Option<&TraceRecorder>,
+    stream: S,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    match recorder {
+        Some(recorder) => {
+            let sink = recorder.sink.clone();
+            Box::pin(stream.inspect(move |item| {
+                if let (Ok(bytes), Ok(mut buf)) = (item, sink.upstream.lock()) {
+                    buf.extend_from_slice(bytes);
+                }
+            }))
+        }
+        None => Box::pin(stream),
+    }
+}
+
+/// 抓取响应转换器输出给客户端的字节流
+pub fn tee_client<S, E>(
+    recorder: Option<&TraceRecorder>,
+    stream: S,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    match recorder {
+        Some(recorder) => {
+            let sink = recorder.sink.clone();
+            Box::pin(stream.inspect(move |item| {
+                if let (Ok(bytes), Ok(mut buf)) = (item, sink.client.lock()) {
+                    buf.extend_from_slice(bytes);
+                }
+            }))
+        }
+        None => Box::pin(stream),
+    }
+}
+
+fn write_trace(dir: &Path, trace: &TraceFile, max_files: usize) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let content = serde_json::to_string_pretty(trace).map_err(|e| e.to_string())?;
+    let path = dir.join(trace.file_name());
+    // 先写临时文件再重命名，避免读到写了一半的抓取文件
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, content).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, &path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    tracing::info!("[Trace] Captured {} -> {}", trace.id, path.display());
+
+    // 文件名以毫秒时间戳开头，按名称排序即按时间排序; 只清理本模块写入的抓取文件 (抓取目录可由用户指定)
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(is_trace_file_name))
+        .collect();
+    if files.len() > max_files {
+        files.sort();
+        for old in &files[..files.len() - max_files] {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+    Ok(())
+}
+
+/// 是否为抓取文件名 (`<毫秒时间戳>-<kind>-<id>.json`)
+fn is_trace_file_name(name: &str) -> bool {
+    const KINDS: [TraceKind; 4] = [
+        TraceKind::ClaudeMessages,
+        TraceKind::OpenaiChat,
+        TraceKind::OpenaiCodex,
+        TraceKind::OpenaiLegacy,
+    ];
+    let Some(stem) = name.strip_suffix(".json") else {
+        return false;
+    };
+    let mut parts = stem.splitn(3, '-');
+    let (Some(ts), Some(kind), Some(id)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    !ts.is_empty()
+        && ts.bytes().all(|b| b.is_ascii_digit())
+        && KINDS.iter().any(|k| k.as_str() == kind)
+        && !id.is_empty()
+        && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// 回放结果 (空差异表示当前映射器与抓取时行为一致)
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub request_diff: Vec<String>,
+    pub response_diff: Vec<String>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.request_diff.is_empty() && self.response_diff.is_empty()
+    }
+}
+
+/// 用当前映射器离线重跑抓取文件，并与抓取时的输出比较
+pub async fn replay(trace: &TraceFile) -> Result<ReplayReport, String> {
+    let mut report = ReplayReport::default();
+    if !trace.mapper_input.is_null() {
+        let replayed = replay_request(trace)?;
+        report.request_diff = diff_lines(
+            &pretty_lines(&normalize_request(trace.mapper_output.clone())),
+            &pretty_lines(&normalize_request(replayed)),
+        );
+    }
+    if !trace.upstream_lines.is_empty() {
+        let output = replay_response(trace).await;
+        report.response_diff = diff_lines(&normalize_sse(&trace.client_output), &normalize_sse(&output));
+    }
+    Ok(report)
+}
+
+fn replay_request(trace: &TraceFile) -> Result<Value, String> {
+    match trace.kind {
+        TraceKind::ClaudeMessages => {
+            let request: crate::proxy::mappers::claude::ClaudeRequest = serde_json::from_value(trace.mapper_input.clone())
+                .map_err(|e| format!("Invalid mapper input: {}", e))?;
+            crate::proxy::mappers::claude::transform_claude_request_in(&request, &trace.project_id)
+        }
+        TraceKind::OpenaiChat | TraceKind::OpenaiCodex | TraceKind::OpenaiLegacy => {
+            let request: crate::proxy::mappers::openai::OpenAIRequest = serde_json::from_value(trace.mapper_input.clone())
+                .map_err(|e| format!("Invalid mapper input: {}", e))?;
+            Ok(crate::proxy::mappers::openai::transform_openai_request(
+                &request,
+                &trace.project_id,
+                &trace.mapped_model,
+            ))
+        }
+    }
+}
+
+async fn replay_response(trace: &TraceFile) -> String {
+    use crate::proxy::mappers::openai::streaming::{
+        create_codex_sse_stream, create_legacy_sse_stream, create_openai_sse_stream,
+    };
+
+    let raw: String = trace.upstream_lines.iter().map(|line| format!("{}\n\n", line)).collect();
+    let upstream = crate::proxy::response_cache::replay_stream(Bytes::from(raw));
+    // OpenAI 转换器以映射器输入中的模型名 (客户端模型) 构造响应
+    let model = trace
+        .mapper_input
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or(&trace.model)
+        .to_string();
+    let session_id = trace.session_id.clone().unwrap_or_default();
+
+    let stream = match trace.kind {
+        TraceKind::ClaudeMessages => crate::proxy::mappers::claude::create_claude_sse_stream_with_failover(
+            upstream,
+            "replay".to_string(),
+            "replay".to_string(),
+            trace.session_id.clone(),
+            trace.cache_plan,
+            None,
+        ),
+        TraceKind::OpenaiChat => create_openai_sse_stream(upstream, model, session_id),
+        TraceKind::OpenaiCodex => create_codex_sse_stream(upstream, model, session_id),
+        TraceKind::OpenaiLegacy => create_legacy_sse_stream(upstream, model, session_id),
+    };
+
+    let chunks: Vec<Result<Bytes, String>> = stream.collect().await;
+    let bytes: Vec<u8> = chunks.into_iter().filter_map(Result::ok).flat_map(|b| b.to_vec()).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// 去除请求体中的易变字段 (project/requestId/sessionId 与来自全局缓存的签名)
+fn normalize_request(mut body: Value) -> Value {
+    if let Some(obj) = body.as_object_mut() {
+        obj.remove("project");
+        obj.remove("requestId");
+        if let Some(request) = obj.get_mut("request").and_then(|r| r.as_object_mut()) {
+            request.remove("sessionId");
+        }
+    }
+    mask_keys(&mut body, &["thoughtSignature"]);
+    body
+}
+
+/// 将 SSE 文本按行规范化，data 行中的 JSON 屏蔽随机 id 与时间戳
+fn normalize_sse(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.strip_prefix("data: ").map(serde_json::from_str::<Value>) {
+            Some(Ok(mut value)) => {
+                mask_keys(&mut value, VOLATILE_RESPONSE_KEYS);
+                format!("data: {}", value)
+            }
+            _ => line.to_string(),
+        })
+        .collect()
+}
+
+fn mask_keys(value: &mut Value, keys: &[&str]) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if keys.contains(&key.as_str()) && !v.is_null() {
+                    *v = Value::String("<masked>".to_string());
+                } else {
+                    mask_keys(v, keys);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| mask_keys(v, keys)),
+        _ => {}
+    }
+}
+
+fn pretty_lines(value: &Value) -> Vec<String> {
+    serde_json::to_string_pretty(value)
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// 逐行差异: `- ` 为抓取时的输出，`+ ` 为回放输出
+fn diff_lines(expected: &[String], actual: &[String]) -> Vec<String> {
+    let (n, m) = (expected.len(), actual.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        let mut diff = Vec::new();
+        for i in 0..n.max(m) {
+            match (expected.get(i), actual.get(i)) {
+                (Some(a), Some(b)) if a == b => {}
+                (a, b) => {
+                    diff.extend(a.map(|a| format!("- {}", a)));
+                    diff.extend(b.map(|b| format!("+ {}", b)));
+                }
+            }
+        }
+        return diff;
+    }
+
+    // lcs[i][j] = expected[i..] 与 actual[j..] 的最长公共子序列长度
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lines(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn diff_reports_only_changed_lines() {
+        let diff = diff_lines(&lines(&["a", "b", "c", "d"]), &lines(&["a", "x", "c", "d", "e"]));
+        assert_eq!(diff, lines(&["- b", "+ x", "+ e"]));
+        assert!(diff_lines(&lines(&["a"]), &lines(&["a"])).is_empty());
+    }
+
+    #[test]
+    fn normalization_masks_volatile_fields() {
+        let a = normalize_sse("data: {\"id\":\"chatcmpl-1\",\"created\":1,\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\n");
+        let b = normalize_sse("data: {\"id\":\"chatcmpl-2\",\"created\":2,\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\n");
+        assert_eq!(a, b);
+        assert_eq!(a[1], "data: [DONE]");
+
+        let request = normalize_request(json!({
+            "project": "p", "requestId": "agent-1",
+            "request": {"sessionId": "s", "contents": [{"parts": [{"thoughtSignature": "sig", "text": "t"}]}]}
+        }));
+        assert_eq!(
+            request,
+            json!({"request": {"contents": [{"parts": [{"thoughtSignature": "<masked>", "text": "t"}]}]}})
+        );
+    }
+
+    #[test]
+    fn pruning_only_touches_trace_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("1-notes-x.json"), "{}").unwrap();
+        assert!(is_trace_file_name("1718000000000-claude_messages-0a1b2c3d4e5f.json"));
+
+        let trace = |created_at: i64, id: &str| -> TraceFile {
+            serde_json::from_value(json!({
+                "version": TRACE_VERSION,
+                "id": id,
+                "created_at": created_at,
+                "kind": "openai_chat",
+                "model": "gpt-4o",
+                "inbound_request": {}
+            }))
+            .unwrap()
+        };
+        for (i, id) in ["aaaaaaaaaaaa", "bbbbbbbbbbbb", "cccccccccccc"].iter().enumerate() {
+            write_trace(dir.path(), &trace(1_000 + i as i64, id), 2).unwrap();
+        }
+
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok().map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["1-notes-x.json", "1001-openai_chat-bbbbbbbbbbbb.json", "1002-openai_chat-cccccccccccc.json", "package.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn recorder_writes_trace_after_streams_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TraceCaptureConfig {
+            header_trigger: true,
+            dir: Some(dir.path().to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let inbound = json!({"model": "gpt-4o", "messages": []});
+        assert!(TraceRecorder::start(&config, &HeaderMap::new(), TraceKind::OpenaiChat, &inbound).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CAPTURE_HEADER, "on".parse().unwrap());
+        let recorder = TraceRecorder::start(&config, &headers, TraceKind::OpenaiChat, &inbound).unwrap();
+        recorder.record_mapping("gemini-2.5-flash", "proj", &inbound, &json!({"model": "gemini-2.5-flash"}));
+        recorder.record_upstream_request(&json!({"model": "gemini-2.5-flash", "project": "proj"}));
+
+        let upstream: Vec<Result<Bytes, String>> = vec![Ok(Bytes::from("data: {\"a\":1}\r\n\r\ndata: {\"b\"")), Ok(Bytes::from(":2}\n\n"))];
+        let client: Vec<Result<Bytes, String>> = vec![Ok(Bytes::from("data: [DONE]\n\n"))];
+        let _: Vec<_> = tee_upstream(Some(&recorder), futures::stream::iter(upstream)).collect().await;
+        let _: Vec<_> = tee_client(Some(&recorder), futures::stream::iter(client)).collect().await;
+        drop(recorder);
+
+        let mut written = None;
+        for _ in 0..50 {
+            written = std::fs::read_dir(dir.path()).unwrap().filter_map(|e| e.ok()).map(|e| e.path()).next();
+            if written.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let trace = TraceFile::load(&written.expect("trace file written")).unwrap();
+        assert_eq!(trace.kind, TraceKind::OpenaiChat);
+        assert_eq!(trace.upstream_lines, lines(&["data: {\"a\":1}", "data: {\"b\":2}"]));
+        assert_eq!(trace.client_output, "data: [DONE]\n\n");
+        assert_eq!(trace.project_id, "proj");
+    }
+}
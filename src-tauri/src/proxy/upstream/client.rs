@@ -3,8 +3,11 @@
 
 use reqwest::{header, Client, Response, StatusCode};
 use serde_json::Value;
+use std::sync::Arc;
 use tokio::time::Duration;
 
+use crate::proxy::circuit_breaker::CircuitBreakerRegistry;
+
 // Cloud Code v1internal endpoints (fallback order: prod → daily)
 // 优先使用稳定的 prod 端点，避免影响缓存命中率
 const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
@@ -16,6 +19,7 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
 
 pub struct UpstreamClient {
     http_client: Client,
+    circuit_breakers: Arc<CircuitBreakerRegistry>, // 端点熔断器 (按 base URL)
 }
 
 impl UpstreamClient {
@@ -40,7 +44,40 @@ impl UpstreamClient {
 
         let http_client = builder.build().expect("Failed to create HTTP client");
 
-        Self { http_client }
+        Self {
+            http_client,
+            circuit_breakers: Arc::new(CircuitBreakerRegistry::new("endpoint", Default::default())),
+        }
+    }
+
+    /// 端点熔断器 (用于热更新配置、注册状态监听与监控展示)
+    pub fn circuit_breakers(&self) -> Arc<CircuitBreakerRegistry> {
+        self.circuit_breakers.clone()
+    }
+
+    /// 判断端点是否可以尝试
+    ///
+    /// 熔断中的端点直接跳过；若所有端点都已熔断，则仍强制尝试最后一个端点，
+    /// 避免请求在没有任何上游调用的情况下全部失败。
+    fn should_attempt_endpoint(&self, base_url: &str, is_last: bool, attempted: usize) -> bool {
+        if self.circuit_breakers.try_acquire_probe(base_url) {
+            return true;
+        }
+        if is_last && attempted == 0 {
+            tracing::warn!("All upstream endpoints are circuit-open, forcing request to {}", base_url);
+            return true;
+        }
+        tracing::debug!("Skipping upstream endpoint {} (circuit open)", base_url);
+        false
+    }
+
+    /// 根据响应状态更新端点熔断器 (仅超时与 5xx 视为端点故障)
+    fn record_endpoint_status(&self, base_url: &str, status: StatusCode) {
+        if status == StatusCode::REQUEST_TIMEOUT || status.is_server_error() {
+            self.circuit_breakers.record_failure(base_url, &format!("HTTP {}", status.as_u16()));
+        } else {
+            self.circuit_breakers.record_success(base_url);
+        }
     }
 
     /// 构建 v1internal URL
@@ -95,11 +132,17 @@ impl UpstreamClient {
         );
 
         let mut last_err: Option<String> = None;
+        let mut last_resp: Option<Response> = None;
+        let mut attempted = 0;
 
-        // 遍历所有端点，失败时自动切换
+        // 遍历所有端点，失败时自动切换 (跳过已熔断的端点)
         for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
             let url = Self::build_url(base_url, method, query_string);
             let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+            if !self.should_attempt_endpoint(base_url, !has_next, attempted) {
+                continue;
+            }
+            attempted += 1;
 
             let response = self
                 .http_client
@@ -112,6 +155,7 @@ impl UpstreamClient {
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    self.record_endpoint_status(base_url, status);
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -136,6 +180,8 @@ impl UpstreamClient {
                             method
                         );
                         last_err = Some(format!("Upstream {} returned {}", base_url, status));
+                        // 保留响应: 后续端点均被熔断跳过时返回给调用方处理 (如 429 限流解析)
+                        last_resp = Some(resp);
                         continue;
                     }
 
@@ -145,17 +191,15 @@ impl UpstreamClient {
                 Err(e) => {
                     let msg = format!("HTTP request failed at {}: {}", base_url, e);
                     tracing::debug!("{}", msg);
+                    self.circuit_breakers.record_failure(base_url, &e.to_string());
                     last_err = Some(msg);
-
-                    // 如果是最后一个端点，退出循环
-                    if !has_next {
-                        break;
-                    }
-                    continue;
                 }
             }
         }
 
+        if let Some(resp) = last_resp {
+            return Ok(resp);
+        }
         Err(last_err.unwrap_or_else(|| "All endpoints failed".to_string()))
     }
 
@@ -198,10 +242,16 @@ impl UpstreamClient {
         );
 
         let mut last_err: Option<String> = None;
+        let mut attempted = 0;
 
-        // 遍历所有端点，失败时自动切换
+        // 遍历所有端点，失败时自动切换 (跳过已熔断的端点)
         for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
             let url = Self::build_url(base_url, "fetchAvailableModels", None);
+            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+            if !self.should_attempt_endpoint(base_url, !has_next, attempted) {
+                continue;
+            }
+            attempted += 1;
 
             let response = self
                 .http_client
@@ -214,6 +264,7 @@ impl UpstreamClient {
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    self.record_endpoint_status(base_url, status);
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -232,7 +283,6 @@ impl UpstreamClient {
                     }
 
                     // 如果有下一个端点且当前错误可重试，则切换
-                    let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
                     if has_next && Self::should_try_next_endpoint(status) {
                         tracing::warn!(
                             "fetchAvailableModels returned {} at {}, trying next endpoint",
@@ -249,13 +299,8 @@ impl UpstreamClient {
                 Err(e) => {
                     let msg = format!("Request failed at {}: {}", base_url, e);
                     tracing::debug!("{}", msg);
+                    self.circuit_breakers.record_failure(base_url, &e.to_string());
                     last_err = Some(msg);
-
-                    // 如果是最后一个端点，退出循环
-                    if idx + 1 >= V1_INTERNAL_BASE_URL_FALLBACKS.len() {
-                        break;
-                    }
-                    continue;
                 }
             }
         }
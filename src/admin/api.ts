@@ -7,13 +7,40 @@ export interface LoginResponse {
   expires_at: number;
 }
 
+export interface ModelFamilyHeadroom {
+  family: string;
+  accounts: number;
+  avg_percentage: number;
+  min_percentage: number;
+  total_headroom: number;
+}
+
 export interface DashboardStats {
   total_accounts: number;
   active_accounts: number;
+  disabled_accounts: number;
+  proxy_disabled_accounts: number;
+  forbidden_accounts: number;
   proxy_status: string;
   total_requests: number;
   total_tokens: number;
   requests_per_minute: number;
+  quota_headroom: ModelFamilyHeadroom[];
+}
+
+export interface WebSocketEvent<T = unknown> {
+  topic: string;
+  event_type: string;
+  data: T;
+}
+
+export interface DashboardStatsDelta {
+  requests: number;
+  success: boolean;
+  tokens: number;
+  requests_per_minute: number;
+  model: string | null;
+  account_email: string | null;
 }
 
 export interface ProxyStatus {
@@ -36,6 +63,28 @@ export interface AccountListResponse {
   current_account_id: string | null;
 }
 
+export interface RefreshStats {
+  total: number;
+  success: number;
+  failed: number;
+  details: string[];
+}
+
+export type OAuthMode = 'paste' | 'callback';
+
+export interface RemoteOAuthStart {
+  auth_url: string;
+  state: string;
+  redirect_uri: string;
+  mode: OAuthMode;
+  expires_at: number;
+}
+
+export type OAuthFlowStatus =
+  | { status: 'pending' }
+  | { status: 'completed'; account_id: string; email: string }
+  | { status: 'failed'; error: string };
+
 export interface LogFileEntry {
   name: string;
   size: number;
@@ -130,6 +179,23 @@ export class WebAdminClient {
     );
   }
 
+  async getAccountHealth(): Promise<any[]> {
+    const response = await axios.get(
+      `${API_BASE_URL}/proxy/health`,
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async restoreAccountHealth(email: string): Promise<{ was_quarantined: boolean }> {
+    const response = await axios.post(
+      `${API_BASE_URL}/proxy/health/restore`,
+      { email },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
   async getAccounts(): Promise<AccountListResponse> {
     const response = await axios.get<AccountListResponse>(
       `${API_BASE_URL}/accounts`,
@@ -164,6 +230,184 @@ export class WebAdminClient {
     return response.data;
   }
 
+  async addAccount(refreshToken: string, name?: string): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts`,
+      { refresh_token: refreshToken, name },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async deleteAccount(id: string): Promise<void> {
+    await axios.delete(
+      `${API_BASE_URL}/accounts/${id}`,
+      { headers: this.getHeaders() }
+    );
+  }
+
+  async deleteAccounts(accountIds: string[]): Promise<void> {
+    await axios.post(
+      `${API_BASE_URL}/accounts/delete`,
+      { account_ids: accountIds },
+      { headers: this.getHeaders() }
+    );
+  }
+
+  async reorderAccounts(accountIds: string[]): Promise<void> {
+    await axios.put(
+      `${API_BASE_URL}/accounts/order`,
+      { account_ids: accountIds },
+      { headers: this.getHeaders() }
+    );
+  }
+
+  async setAccountProxyEnabled(id: string, enabled: boolean, reason?: string): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/${id}/proxy`,
+      { enabled, reason },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async refreshAllAccounts(): Promise<RefreshStats> {
+    const response = await axios.post<RefreshStats>(
+      `${API_BASE_URL}/accounts/refresh`,
+      {},
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async getCurrentAccount(): Promise<any | null> {
+    const response = await axios.get(
+      `${API_BASE_URL}/accounts/current`,
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async switchAccount(accountId: string): Promise<void> {
+    await axios.put(
+      `${API_BASE_URL}/accounts/current`,
+      { account_id: accountId },
+      { headers: this.getHeaders() }
+    );
+  }
+
+  async importV1Accounts(): Promise<any[]> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/import/v1`,
+      {},
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async importFromDb(path?: string): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/import/db`,
+      { path },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  /** `callback` mode requires the server's public URL (web_admin_public_url / ANTIGRAVITY_PUBLIC_URL) */
+  async startOAuth(mode: OAuthMode = 'paste'): Promise<RemoteOAuthStart> {
+    const response = await axios.post<RemoteOAuthStart>(
+      `${API_BASE_URL}/accounts/oauth/start`,
+      { mode },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async getOAuthStatus(state: string): Promise<OAuthFlowStatus> {
+    const response = await axios.get<OAuthFlowStatus>(
+      `${API_BASE_URL}/accounts/oauth/${state}`,
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  /** `callback` is the full redirect URL copied from the browser (or just the code) */
+  async completeOAuth(callback: string, state?: string): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/oauth/complete`,
+      { callback, state },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  /** Returns the encrypted bundle file content */
+  async exportAccountBundle(passphrase: string, includeConfig = false): Promise<string> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/bundle/export`,
+      { passphrase, include_config: includeConfig },
+      { headers: this.getHeaders(), responseType: 'text' }
+    );
+    return response.data;
+  }
+
+  async previewAccountBundle(bundle: string, passphrase: string): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/bundle/preview`,
+      { bundle, passphrase },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async importAccountBundle(
+    bundle: string,
+    passphrase: string,
+    options?: { emails?: string[]; conflict?: 'skip' | 'overwrite' | 'keep_newer'; config?: 'none' | 'mappings' | 'full' }
+  ): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/accounts/bundle/import`,
+      { bundle, passphrase, options: options ?? {} },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async getEncryptionStatus(): Promise<any> {
+    const response = await axios.get(
+      `${API_BASE_URL}/system/encryption`,
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async migrateEncryption(mode: 'rotate' | 'encrypt' | 'decrypt'): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/system/encryption/migrate`,
+      { mode },
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async getAccountStore(): Promise<{ backend: 'file' | 'sqlite'; accounts: number }> {
+    const response = await axios.get(
+      `${API_BASE_URL}/system/account-store`,
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
+  async migrateAccountStore(): Promise<any> {
+    const response = await axios.post(
+      `${API_BASE_URL}/system/account-store/migrate`,
+      {},
+      { headers: this.getHeaders() }
+    );
+    return response.data;
+  }
+
   async getLogFiles(): Promise<LogFileEntry[]> {
     const response = await axios.get<LogFileEntry[]>(
       `${API_BASE_URL}/system/logs/files`,
@@ -184,11 +428,19 @@ export class WebAdminClient {
     return response.data;
   }
 
-  createWebSocket(): WebSocket | null {
+  /**
+   * Open the admin event stream. `topics` limits server-side delivery
+   * (requests, accounts, rate_limits, quota, sessions, config, proxy, circuits, dashboard);
+   * omit it to receive everything.
+   */
+  createWebSocket(topics?: string[]): WebSocket | null {
     if (!this.token) {
       return null;
     }
-    const wsUrl = `ws://127.0.0.1:8046/api/v1/ws?token=${this.token}`;
+    let wsUrl = `ws://127.0.0.1:8046/api/v1/ws?token=${this.token}`;
+    if (topics && topics.length > 0) {
+      wsUrl += `&topics=${encodeURIComponent(topics.join(','))}`;
+    }
     return new WebSocket(wsUrl);
   }
 }
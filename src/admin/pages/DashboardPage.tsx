@@ -1,21 +1,40 @@
 import React, { useEffect } from 'react';
 import { useDashboardStore, useProxyStore } from '../store';
+import { apiClient } from '../api';
 import { Activity, Users, Zap, TrendingUp, Server, Power, RefreshCw } from 'lucide-react';
 
 export const DashboardPage: React.FC = () => {
-  const { stats, loading, error, fetchStats } = useDashboardStore();
-  const { status: proxyStatus, fetchStatus } = useProxyStore();
+  const { stats, loading, error, fetchStats, applyDelta } = useDashboardStore();
+  const { status: proxyStatus, fetchStatus, setStatus } = useProxyStore();
 
   useEffect(() => {
     fetchStats();
     fetchStatus();
-    const interval = setInterval(() => {
-      fetchStats();
-      fetchStatus();
-    }, 5000); // Refresh every 5 seconds
 
-    return () => clearInterval(interval);
-  }, [fetchStats, fetchStatus]);
+    // Live updates are pushed over the WebSocket; polling only refreshes account counts / quota
+    const ws = apiClient.createWebSocket(['dashboard', 'proxy']);
+    if (ws) {
+      ws.onmessage = (message) => {
+        try {
+          const event = JSON.parse(message.data);
+          if (event.event_type === 'dashboard_stats_delta') {
+            applyDelta(event.data);
+          } else if (event.event_type === 'proxy_status_update') {
+            setStatus(event.data);
+            fetchStats();
+          }
+        } catch (err) {
+          console.error('Failed to parse WebSocket event:', err);
+        }
+      };
+    }
+    const interval = setInterval(fetchStats, 30000);
+
+    return () => {
+      clearInterval(interval);
+      ws?.close();
+    };
+  }, [fetchStats, fetchStatus, applyDelta, setStatus]);
 
   const handleRefresh = () => {
     fetchStats();
@@ -73,6 +92,15 @@ export const DashboardPage: React.FC = () => {
           </div>
           <div className="mt-4 flex items-center gap-2 text-sm">
             <span className="text-green-400">{stats?.active_accounts || 0} active</span>
+            {!!stats?.disabled_accounts && (
+              <span className="text-red-400">{stats.disabled_accounts} disabled</span>
+            )}
+            {!!stats?.proxy_disabled_accounts && (
+              <span className="text-gray-400">{stats.proxy_disabled_accounts} proxy off</span>
+            )}
+            {!!stats?.forbidden_accounts && (
+              <span className="text-yellow-400">{stats.forbidden_accounts} forbidden</span>
+            )}
           </div>
         </div>
 
@@ -139,6 +167,24 @@ export const DashboardPage: React.FC = () => {
         </div>
       </div>
 
+      {/* Quota Headroom */}
+      {stats && stats.quota_headroom.length > 0 && (
+        <div className="bg-gray-800 rounded-xl p-6 border border-gray-700">
+          <h2 className="text-xl font-semibold text-white mb-4">Quota Headroom</h2>
+          <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-4 gap-4">
+            {stats.quota_headroom.map((h) => (
+              <div key={h.family} className="p-4 bg-gray-700 rounded-lg">
+                <p className="font-medium text-white">{h.family}</p>
+                <p className="text-2xl font-bold text-white mt-1">{h.avg_percentage.toFixed(0)}%</p>
+                <p className="text-sm text-gray-400">
+                  {h.accounts} accounts · min {h.min_percentage}% · {h.total_headroom.toFixed(1)} full
+                </p>
+              </div>
+            ))}
+          </div>
+        </div>
+      )}
+
       {/* Quick Actions */}
       <div className="bg-gray-800 rounded-xl p-6 border border-gray-700">
         <h2 className="text-xl font-semibold text-white mb-4">Quick Actions</h2>
@@ -1,5 +1,5 @@
 import { create } from 'zustand';
-import { apiClient, DashboardStats, ProxyStatus, AccountListResponse } from './api';
+import { apiClient, DashboardStats, DashboardStatsDelta, ProxyStatus, AccountListResponse } from './api';
 
 interface AuthState {
   isAuthenticated: boolean;
@@ -14,6 +14,7 @@ interface DashboardState {
   loading: boolean;
   error: string | null;
   fetchStats: () => Promise<void>;
+  applyDelta: (delta: DashboardStatsDelta) => void;
 }
 
 interface ProxyState {
@@ -21,6 +22,7 @@ interface ProxyState {
   loading: boolean;
   error: string | null;
   fetchStatus: () => Promise<void>;
+  setStatus: (status: ProxyStatus) => void;
   startProxy: (config: any) => Promise<void>;
   stopProxy: () => Promise<void>;
 }
@@ -69,6 +71,16 @@ export const useDashboardStore = create<DashboardState>((set) => ({
       set({ error: error.message, loading: false });
     }
   },
+  applyDelta: (delta: DashboardStatsDelta) => {
+    set((state) => state.stats ? {
+      stats: {
+        ...state.stats,
+        total_requests: state.stats.total_requests + delta.requests,
+        total_tokens: state.stats.total_tokens + delta.tokens,
+        requests_per_minute: delta.requests_per_minute,
+      },
+    } : {});
+  },
 }));
 
 export const useProxyStore = create<ProxyState>((set) => ({
@@ -84,6 +96,7 @@ export const useProxyStore = create<ProxyState>((set) => ({
       set({ error: error.message, loading: false });
     }
   },
+  setStatus: (status: ProxyStatus) => set({ status }),
   startProxy: async (config: any) => {
     set({ loading: true, error: null });
     try {
@@ -22,12 +22,15 @@ interface ProxyRequestLog {
     input_tokens?: number;
     output_tokens?: number;
     account_email?: string;
+    model_hops?: string;
+    cache_hit?: boolean;
 }
 
 interface ProxyStats {
     total_requests: number;
     success_count: number;
     error_count: number;
+    background_tasks?: Record<string, number>;
 }
 
 interface ProxyMonitorProps {
@@ -86,6 +89,7 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                 setStats((prev: ProxyStats) => {
                     const isSuccess = newLog.status >= 200 && newLog.status < 400;
                     return {
+                        ...prev,
                         total_requests: prev.total_requests + 1,
                         success_count: prev.success_count + (isSuccess ? 1 : 0),
                         error_count: prev.error_count + (isSuccess ? 0 : 1),
@@ -106,6 +110,8 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
         )
         .sort((a, b) => b.timestamp - a.timestamp);
 
+    const backgroundCount = Object.values(stats.background_tasks || {}).reduce((sum, n) => sum + n, 0);
+
     const quickFilters = [
         { label: t('monitor.filters.all'), value: '' },
         { label: t('monitor.filters.error'), value: '40' },
@@ -170,6 +176,11 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                         <span className="text-blue-500">{formatCompactNumber(stats.total_requests)} REQS</span>
                         <span className="text-green-500">{formatCompactNumber(stats.success_count)} OK</span>
                         <span className="text-red-500">{formatCompactNumber(stats.error_count)} ERR</span>
+                        {backgroundCount > 0 && (
+                            <span className="text-gray-400" title={Object.entries(stats.background_tasks || {}).map(([k, v]) => `${k}: ${v}`).join('\n')}>
+                                {formatCompactNumber(backgroundCount)} BG
+                            </span>
+                        )}
                     </div>
 
                     <button onClick={clearLogs} className="btn btn-sm btn-ghost text-gray-400">
@@ -205,7 +216,10 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                     <tbody className="font-mono text-gray-700 dark:text-gray-300">
                         {filteredLogs.map(log => (
                             <tr key={log.id} className="hover:bg-blue-50 dark:hover:bg-blue-900/20 cursor-pointer" onClick={() => setSelectedLog(log)}>
-                                <td><span className={`badge badge-xs text-white border-none ${log.status >= 200 && log.status < 400 ? 'badge-success' : 'badge-error'}`}>{log.status}</span></td>
+                                <td>
+                                    <span className={`badge badge-xs text-white border-none ${log.status >= 200 && log.status < 400 ? 'badge-success' : 'badge-error'}`}>{log.status}</span>
+                                    {log.cache_hit && <span className="badge badge-xs badge-info text-white border-none ml-1">CACHE</span>}
+                                </td>
                                 <td className="font-bold">{log.method}</td>
                                 <td className="text-blue-600 truncate max-w-[180px]">
                                     {log.mapped_model && log.model !== log.mapped_model
@@ -276,6 +290,12 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                         )}
                                     </div>
                                 </div>
+                                {selectedLog.model_hops && (
+                                    <div className="mt-5 pt-5 border-t border-gray-200 dark:border-slate-700">
+                                        <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest mb-2">{t('monitor.details.model_hops')}</span>
+                                        <span className="font-mono font-semibold text-gray-900 dark:text-white text-xs break-all">{selectedLog.model_hops}</span>
+                                    </div>
+                                )}
                                 {selectedLog.account_email && (
                                     <div className="mt-5 pt-5 border-t border-gray-200 dark:border-slate-700">
                                         <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest mb-2">{t('monitor.details.account_used')}</span>
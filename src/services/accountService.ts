@@ -107,6 +107,69 @@ export async function syncAccountFromDb(): Promise<Account | null> {
     return await invoke('sync_account_from_db');
 }
 
+// 加密账号包
+export type BundleConflictStrategy = 'skip' | 'overwrite' | 'keep_newer';
+export type BundleConfigImportMode = 'none' | 'mappings' | 'full';
+
+export interface BundleImportOptions {
+    emails?: string[];
+    conflict?: BundleConflictStrategy;
+    config?: BundleConfigImportMode;
+}
+
+export interface BundlePreview {
+    created_at: number;
+    accounts: { email: string; name: string | null; disabled: boolean; proxy_disabled: boolean; exists: boolean }[];
+    has_config: boolean;
+}
+
+export interface BundleImportReport {
+    added: string[];
+    updated: string[];
+    skipped: string[];
+    failed: [string, string][];
+    config_imported: boolean;
+    current_account: string | null;
+}
+
+export async function exportAccountBundle(path: string, passphrase: string, includeConfig: boolean): Promise<void> {
+    return await invoke('export_account_bundle', { path, passphrase, includeConfig });
+}
+
+export async function previewAccountBundle(path: string, passphrase: string): Promise<BundlePreview> {
+    return await invoke('preview_account_bundle', { path, passphrase });
+}
+
+export async function importAccountBundle(path: string, passphrase: string, options?: BundleImportOptions): Promise<BundleImportReport> {
+    return await invoke('import_account_bundle', { path, passphrase, options });
+}
+
+export interface EncryptionStatus {
+    encryption_enabled: boolean;
+    current_key_id: string | null;
+    plain: number;
+    legacy: number;
+    by_key_id: Record<string, number>;
+    unreadable: number;
+}
+
+export interface EncryptionMigrationReport {
+    backup_dir: string | null;
+    key_id: string | null;
+    key_generated: boolean;
+    migrated: number;
+    unchanged: number;
+    failed: [string, string][];
+}
+
+export async function getEncryptionStatus(): Promise<EncryptionStatus> {
+    return await invoke('get_encryption_status');
+}
+
+export async function migrateAccountEncryption(mode: 'rotate' | 'encrypt' | 'decrypt'): Promise<EncryptionMigrationReport> {
+    return await invoke('migrate_account_encryption', { mode });
+}
+
 export async function toggleProxyStatus(accountId: string, enable: boolean, reason?: string): Promise<void> {
     return await invoke('toggle_proxy_status', { accountId, enable, reason });
 }
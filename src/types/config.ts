@@ -11,19 +11,206 @@ export interface ProxyConfig {
     api_key: string;
     auto_start: boolean;
     custom_mapping?: Record<string, string | string[]>;
+    routing_rules?: RoutingRule[];
     request_timeout: number;
     enable_logging: boolean;
     log_stream_content: boolean;
     upstream_proxy: UpstreamProxyConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    circuit_breaker?: CircuitBreakerConfig;
+    hedging?: HedgingConfig; // 请求对冲，仅作用于 Claude 协议 (/v1/messages)
+    stream_failover?: StreamFailoverConfig;
+    account_watch?: AccountWatchConfig;
+    account_health?: AccountHealthConfig;
+    prompt_policy?: PromptPolicyConfig;
+    background_tasks?: BackgroundTaskConfig;
+    prompt_cache?: PromptCacheConfig;
+    response_cache?: ResponseCacheConfig;
+    context_window?: ContextWindowConfig;
+    trace_capture?: TraceCaptureConfig;
+    multi_candidate?: MultiCandidateConfig;
 }
 
-export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
+export type InboundProtocol = 'claude' | 'openai' | 'gemini';
+
+export interface RouteConditions {
+    protocols?: InboundProtocol[];
+    api_keys?: string[];
+    headers?: Record<string, string>;
+    min_prompt_tokens?: number;
+    max_prompt_tokens?: number;
+    has_tools?: boolean;
+    has_images?: boolean;
+    has_thinking?: boolean;
+}
+
+export interface RouteSplit {
+    percent: number;
+    target: string | string[];
+}
+
+export interface RoutingRule {
+    name?: string;
+    enabled?: boolean;
+    model: string;
+    match_type?: 'glob' | 'regex' | 'exact';
+    when?: RouteConditions;
+    target: string | string[];
+    splits?: RouteSplit[];
+}
+
+export type SafetyThreshold = 'off' | 'low' | 'medium' | 'high' | 'none';
+
+export interface PromptRoutePolicy {
+    name?: string;
+    model?: string;
+    protocols?: InboundProtocol[];
+    inject_identity?: boolean;
+    system_prefix?: string;
+    system_suffix?: string;
+    safety_threshold?: SafetyThreshold;
+}
+
+export interface RedactionRule {
+    name?: string;
+    pattern: string;
+    replacement?: string;
+}
+
+export interface PromptPolicyConfig {
+    inject_identity: boolean;
+    system_prefix?: string;
+    system_suffix?: string;
+    safety_threshold?: SafetyThreshold;
+    routes?: PromptRoutePolicy[];
+    redactions?: RedactionRule[];
+    allow_header_overrides: boolean;
+}
+
+export interface BackgroundClassifier {
+    name: string;
+    enabled?: boolean;
+    keywords?: string[];
+    patterns?: string[];
+    required_keywords?: string[];
+    max_length?: number;
+    has_tools?: boolean;
+    target: string | string[];
+    account_group?: string;
+}
+
+export interface BackgroundTaskConfig {
+    enabled: boolean;
+    protocols: InboundProtocol[];
+    classifiers: BackgroundClassifier[];
+    account_groups?: Record<string, string[]>;
+}
+
+export interface PromptCacheConfig {
+    enabled: boolean;
+    ttl_seconds: number;
+    min_prefix_tokens: number;
+    max_entries: number;
+}
+
+export interface ResponseCacheConfig {
+    enabled: boolean;
+    ttl_seconds: number;
+    max_entries: number;
+    persist: boolean;
+    max_persisted_entries: number;
+    max_entry_bytes: number;
+    deterministic_only: boolean;
+    models?: string[];
+    api_keys?: string[];
+}
+
+export type ContextStrategy = 'truncate' | 'summarize';
+
+export interface ContextWindowConfig {
+    enabled: boolean;
+    strategy: ContextStrategy;
+    protocols?: InboundProtocol[];
+    target_ratio: number;
+    keep_recent_turns: number;
+    summary_model: string;
+    summary_max_tokens: number;
+}
+
+export interface MultiCandidateConfig {
+    fan_out_models?: string[];
+    max_fan_out: number;
+}
+
+export interface TraceCaptureConfig {
+    enabled: boolean;
+    header_trigger: boolean;
+    dir?: string;
+    max_files: number;
+    models?: string[];
+}
+
+export interface AccountWatchConfig {
+    enabled: boolean;
+    interval_ms: number; // polling interval; external account changes apply within one interval
+}
+
+export interface AccountHealthConfig {
+    enabled: boolean;
+    window_seconds: number;
+    window_size: number;
+    min_samples: number;
+    quarantine_threshold: number;
+    probe_interval_seconds: number;
+    restore_after_probes: number;
+}
+
+export interface StreamFailoverConfig {
+    enabled: boolean;
+    max_resumes: number;
+}
+
+export interface HedgingConfig {
+    enabled: boolean;
+    background_only: boolean;
+    percentile: number;
+    default_delay_ms: number;
+    min_delay_ms: number;
+    max_delay_ms: number;
+    min_samples: number;
+}
+
+export interface CircuitBreakerConfig {
+    enabled: boolean;
+    window_seconds: number;
+    min_requests: number;
+    failure_rate_threshold: number;
+    consecutive_failures: number;
+    open_seconds: number;
+    half_open_max_probes: number;
+}
+
+export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst' | 'QuotaPredictive';
+
+export interface PredictiveConfig {
+    lookback_minutes: number;
+    request_cost: number;
+    reserve_horizon_hours: number;
+    reserve_fraction: number;
+}
+
+export interface ConcurrencyConfig {
+    max_per_account: number;
+    max_per_model_family: Record<string, number>;
+    queue_timeout_seconds: number;
+}
 
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    predictive?: PredictiveConfig;
+    concurrency?: ConcurrencyConfig;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -65,5 +252,6 @@ export interface AppConfig {
     accounts_page_size?: number; // 账号列表每页显示数量,默认 0 表示自动计算
     model_quota_threshold?: number; // 模型配额跳过阈值 (0.01 = 1%)
     web_admin_lan_access?: boolean; // 是否允许局域网访问 Web Admin
+    web_admin_public_url?: string | null; // Web Admin 公网地址 (远程 OAuth 回调)
     proxy: ProxyConfig;
 }